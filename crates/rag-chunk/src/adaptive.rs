@@ -2,17 +2,39 @@
 
 use std::sync::Arc;
 
-use rag_core::{ChunkConfig, ChunkData, Chunker, ContentType, Result};
+use rag_core::{ChunkConfig, ChunkData, ChunkedDocument, Chunker, ChunkingConfig, ContentType, Result};
 
-use crate::RecursiveChunker;
+use crate::{AstChunker, ChatChunker, HtmlChunker, MarkdownChunker, RecursiveChunker, StructuredChunker};
 
 /// Adaptive chunker that dispatches to specialized chunkers based on content type.
 ///
-/// Falls back to RecursiveChunker for unsupported types.
+/// Code in a language with a tree-sitter grammar goes to [`AstChunker`]
+/// unless AST-aware chunking is disabled, Markdown to [`MarkdownChunker`],
+/// HTML to [`HtmlChunker`], JSON, YAML and TOML to [`StructuredChunker`],
+/// and chat transcripts to [`ChatChunker`].
+/// Falls back to RecursiveChunker for other types.
 pub struct AdaptiveChunker {
     /// Fallback recursive chunker.
     recursive: RecursiveChunker,
 
+    /// Chunker for code.
+    ast: AstChunker,
+
+    /// Chunker for Markdown.
+    markdown: MarkdownChunker,
+
+    /// Chunker for HTML.
+    html: HtmlChunker,
+
+    /// Chunker for structured data.
+    structured: StructuredChunker,
+
+    /// Chunker for chat transcripts.
+    chat: ChatChunker,
+
+    /// Route code to the AST chunker.
+    ast_aware: bool,
+
     /// Optional custom token counter.
     token_counter: Option<Arc<dyn Fn(&str) -> usize + Send + Sync>>,
 }
@@ -22,23 +44,61 @@ impl AdaptiveChunker {
     pub fn new() -> Self {
         Self {
             recursive: RecursiveChunker::new(),
+            ast: AstChunker::new(),
+            markdown: MarkdownChunker::new(),
+            html: HtmlChunker::new(),
+            structured: StructuredChunker::new(),
+            chat: ChatChunker::new(),
+            ast_aware: true,
             token_counter: None,
         }
     }
 
+    /// Create an adaptive chunker from the `[chunking]` configuration.
+    pub fn from_config(config: &ChunkingConfig) -> Self {
+        Self::new()
+            .with_ast_aware(config.ast_aware)
+            .with_hierarchy(config.hierarchical)
+    }
+
     /// Create an adaptive chunker with a custom token counter.
     pub fn with_token_counter<F>(counter: F) -> Self
     where
         F: Fn(&str) -> usize + Send + Sync + 'static,
     {
         let counter = Arc::new(counter);
-        let counter_clone = counter.clone();
+        let recursive_counter = counter.clone();
+        let ast_counter = counter.clone();
+        let markdown_counter = counter.clone();
+        let html_counter = counter.clone();
+        let structured_counter = counter.clone();
+        let chat_counter = counter.clone();
 
         Self {
-            recursive: RecursiveChunker::with_token_counter(move |s| counter_clone(s)),
+            recursive: RecursiveChunker::with_token_counter(move |s| recursive_counter(s)),
+            ast: AstChunker::with_token_counter(move |s| ast_counter(s)),
+            markdown: MarkdownChunker::with_token_counter(move |s| markdown_counter(s)),
+            html: HtmlChunker::with_token_counter(move |s| html_counter(s)),
+            structured: StructuredChunker::with_token_counter(move |s| structured_counter(s)),
+            chat: ChatChunker::with_token_counter(move |s| chat_counter(s)),
+            ast_aware: true,
             token_counter: Some(counter),
         }
     }
+
+    /// Enable or disable AST-aware chunking of code.
+    pub fn with_ast_aware(mut self, ast_aware: bool) -> Self {
+        self.ast_aware = ast_aware;
+        self
+    }
+
+    /// Enable or disable parent chunks for code, Markdown and HTML.
+    pub fn with_hierarchy(mut self, hierarchical: bool) -> Self {
+        self.ast = self.ast.with_hierarchy(hierarchical);
+        self.markdown = self.markdown.with_hierarchy(hierarchical);
+        self.html = self.html.with_hierarchy(hierarchical);
+        self
+    }
 }
 
 impl Default for AdaptiveChunker {
@@ -54,13 +114,45 @@ impl Chunker for AdaptiveChunker {
         content_type: ContentType,
         config: &ChunkConfig,
     ) -> Result<Vec<ChunkData>> {
-        // For now, use recursive chunker for all types
-        // In the future, we can add specialized chunkers:
-        // - AstChunker for code (tree-sitter)
-        // - SemanticChunker for markdown (pulldown-cmark)
+        if self.ast_aware && AstChunker::supports(content_type) {
+            return self.ast.chunk(content, content_type, config);
+        }
+
+        if content_type == ContentType::Markdown {
+            return self.markdown.chunk(content, content_type, config);
+        }
+
+        if content_type == ContentType::Html {
+            return self.html.chunk(content, content_type, config);
+        }
+
+        if StructuredChunker::supports(content_type) {
+            return self.structured.chunk(content, content_type, config);
+        }
+
+        if content_type == ContentType::Chat {
+            return self.chat.chunk(content, content_type, config);
+        }
+
         self.recursive.chunk(content, content_type, config)
     }
 
+    fn chunk_with_metadata(
+        &self,
+        content: &str,
+        content_type: ContentType,
+        config: &ChunkConfig,
+    ) -> Result<ChunkedDocument> {
+        if content_type == ContentType::Html {
+            return self.html.chunk_with_metadata(content, content_type, config);
+        }
+
+        Ok(ChunkedDocument {
+            chunks: self.chunk(content, content_type, config)?,
+            ..Default::default()
+        })
+    }
+
     fn supported_types(&self) -> Vec<ContentType> {
         self.recursive.supported_types()
     }
@@ -108,6 +200,34 @@ fn helper() {
         assert!(!chunks.is_empty());
     }
 
+    #[test]
+    fn test_adaptive_routes_code_to_ast_chunker() {
+        let config = ChunkConfig {
+            max_tokens: 12,
+            min_tokens: 1,
+            overlap_tokens: 0,
+        };
+        let code = "fn a() {}\n\nfn b() {\n    let y = 2;\n\n    let z = 3;\n}\n";
+        let words = |s: &str| s.split_whitespace().count();
+
+        let chunks = AdaptiveChunker::with_token_counter(words)
+            .chunk(code, ContentType::Rust, &config)
+            .unwrap();
+        let starts: Vec<_> = chunks.iter().map(|c| c.start_line).collect();
+        assert_eq!(starts, vec![1, 3]);
+
+        let settings = ChunkingConfig {
+            ast_aware: false,
+            ..Default::default()
+        };
+        assert!(!AdaptiveChunker::from_config(&settings).ast_aware);
+        let chunks = AdaptiveChunker::with_token_counter(words)
+            .with_ast_aware(false)
+            .chunk(code, ContentType::Rust, &config)
+            .unwrap();
+        assert_eq!(chunks.iter().map(|c| c.start_line).collect::<Vec<_>>(), vec![1, 6]);
+    }
+
     #[test]
     fn test_adaptive_with_token_counter() {
         // Custom counter: 1 token per word
@@ -0,0 +1,517 @@
+//! AST-aware chunker for source code.
+//!
+//! Parses code with tree-sitter and chunks it at item boundaries, so a
+//! function, impl or class is never cut in half unless it alone exceeds the
+//! token limit.
+
+use tree_sitter::{Language, Node, Parser};
+
+use rag_core::{ChunkConfig, ChunkData, Chunker, ContentType, Result};
+
+use crate::hierarchy::{build_hierarchy, Span};
+use crate::lines::Lines;
+use crate::markdown::PATH_SEPARATOR;
+use crate::merge::merge_undersized;
+use crate::RecursiveChunker;
+
+/// Node kinds that belong to the item that follows them.
+const PREFIX_KINDS: &[&str] = &[
+    "comment",
+    "line_comment",
+    "block_comment",
+    "attribute_item",
+    "inner_attribute_item",
+    "decorator",
+    "attribute_list",
+];
+
+/// Node kinds with a signature but no `name` field.
+const UNNAMED_ITEM_KINDS: &[&str] = &["function_definition", "media_statement", "supports_statement"];
+
+/// Longest signature recorded in a heading path, in characters.
+const MAX_SIGNATURE_CHARS: usize = 80;
+
+/// Chunker that splits code at syntax tree item boundaries.
+///
+/// - Top-level items (functions, impls, classes, ...) are chunk boundaries;
+///   adjacent small items are merged up to `max_tokens`.
+/// - An item larger than `max_tokens` is split into its children (e.g. the
+///   methods of an impl or class), recursively, and the pieces are merged
+///   again up to `max_tokens`.
+/// - Comments, doc comments, attributes and decorators directly above an
+///   item stay with it.
+/// - Pieces of a split item record the items enclosing them (e.g.
+///   `impl Point`) as their heading path.
+/// - Chunks below `min_tokens` are merged into a neighbour if the result
+///   fits, and kept as they are otherwise.
+/// - Chunks always consist of whole lines, with exact line numbers.
+///
+/// Nodes without children that still exceed `max_tokens` (e.g. a huge
+/// string literal) are split with [`RecursiveChunker`].
+///
+/// With [`with_hierarchy`](Self::with_hierarchy), the file and every item
+/// that had to be split are emitted as well, as parents of the chunks
+/// inside them.
+pub struct AstChunker {
+    /// Fallback for oversized leaves and unsupported languages; also counts
+    /// tokens.
+    recursive: RecursiveChunker,
+
+    /// Emit parent chunks for the file and split items.
+    hierarchical: bool,
+}
+
+/// A run of whole lines covering one or more sibling nodes.
+struct Segment<'tree> {
+    /// First line (0-based).
+    start_row: usize,
+
+    /// Last line (0-based, inclusive).
+    end_row: usize,
+
+    /// The nodes on these lines.
+    nodes: Vec<Node<'tree>>,
+
+    /// Whether the segment only holds comments or attributes, which join
+    /// the next item if it starts on the following line.
+    prefix: bool,
+
+    /// Signatures of the items enclosing the segment, outermost first.
+    path: Vec<String>,
+}
+
+impl AstChunker {
+    /// Create a new AST chunker with default token estimation.
+    pub fn new() -> Self {
+        Self {
+            recursive: RecursiveChunker::new(),
+            hierarchical: false,
+        }
+    }
+
+    /// Create a chunker with a custom token counter.
+    pub fn with_token_counter<F>(counter: F) -> Self
+    where
+        F: Fn(&str) -> usize + Send + Sync + 'static,
+    {
+        Self {
+            recursive: RecursiveChunker::with_token_counter(counter),
+            hierarchical: false,
+        }
+    }
+
+    /// Enable or disable parent chunks for the file and split items.
+    pub fn with_hierarchy(mut self, hierarchical: bool) -> Self {
+        self.hierarchical = hierarchical;
+        self
+    }
+
+    /// Check whether a content type has a grammar.
+    pub fn supports(content_type: ContentType) -> bool {
+        Self::language(content_type).is_some()
+    }
+
+    /// Tree-sitter grammar for a content type.
+    fn language(content_type: ContentType) -> Option<Language> {
+        match content_type {
+            ContentType::Rust => Some(tree_sitter_rust::LANGUAGE.into()),
+            ContentType::Python => Some(tree_sitter_python::LANGUAGE.into()),
+            ContentType::TypeScript => Some(tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into()),
+            ContentType::JavaScript => Some(tree_sitter_javascript::LANGUAGE.into()),
+            ContentType::Go => Some(tree_sitter_go::LANGUAGE.into()),
+            ContentType::Java => Some(tree_sitter_java::LANGUAGE.into()),
+            ContentType::CSharp => Some(tree_sitter_c_sharp::LANGUAGE.into()),
+            ContentType::Cpp => Some(tree_sitter_cpp::LANGUAGE.into()),
+            ContentType::C => Some(tree_sitter_c::LANGUAGE.into()),
+            ContentType::Ruby => Some(tree_sitter_ruby::LANGUAGE.into()),
+            ContentType::Shell => Some(tree_sitter_bash::LANGUAGE.into()),
+            ContentType::Css => Some(tree_sitter_css::LANGUAGE.into()),
+            _ => None,
+        }
+    }
+
+    /// Group sibling nodes into segments of whole lines.
+    ///
+    /// Nodes sharing a line end up in the same segment, and comments or
+    /// attributes join the item directly below them.
+    fn segments<'tree>(nodes: impl IntoIterator<Item = Node<'tree>>) -> Vec<Segment<'tree>> {
+        let mut segments: Vec<Segment<'tree>> = Vec::new();
+
+        // Zero-width nodes (e.g. inserted by error recovery) cover no lines
+        for node in nodes.into_iter().filter(|node| node.start_byte() < node.end_byte()) {
+            let start_row = node.start_position().row;
+            let end = node.end_position();
+            // A node ending at the start of a line (e.g. a line comment
+            // including its newline) does not cover that line
+            let end_row = if end.column == 0 && end.row > start_row {
+                end.row - 1
+            } else {
+                end.row
+            };
+            let prefix = PREFIX_KINDS.contains(&node.kind());
+
+            match segments.last_mut() {
+                Some(last) if start_row <= last.end_row || (last.prefix && start_row == last.end_row + 1) => {
+                    last.end_row = last.end_row.max(end_row);
+                    last.nodes.push(node);
+                    last.prefix &= prefix;
+                }
+                _ => segments.push(Segment {
+                    start_row,
+                    end_row,
+                    nodes: vec![node],
+                    prefix,
+                    path: Vec::new(),
+                }),
+            }
+        }
+
+        segments
+    }
+
+    /// Split a segment into pieces that each fit in `max_tokens`, descending
+    /// into the children of its nodes as needed. Segments that were split
+    /// are recorded in `spans`.
+    fn pieces<'tree>(
+        &self,
+        segment: Segment<'tree>,
+        lines: &Lines<'_>,
+        config: &ChunkConfig,
+        out: &mut Vec<Segment<'tree>>,
+        spans: &mut Vec<Span>,
+    ) {
+        let text = lines.text(segment.start_row, segment.end_row);
+        if self.recursive.count_tokens(text) <= config.max_tokens {
+            out.push(segment);
+            return;
+        }
+
+        if segment.prefix {
+            // Only comments; chunked as text later
+            out.push(segment);
+            return;
+        }
+
+        let children: Vec<Node<'tree>> = segment
+            .nodes
+            .iter()
+            .flat_map(|node| {
+                // Keep comments and attributes whole so they can still join their item
+                if PREFIX_KINDS.contains(&node.kind()) {
+                    return vec![*node];
+                }
+                let mut cursor = node.walk();
+                node.children(&mut cursor).collect::<Vec<_>>()
+            })
+            .collect();
+        if children.is_empty() {
+            // An oversized leaf, e.g. a string literal; chunked as text later
+            out.push(segment);
+            return;
+        }
+
+        let mut path = segment.path.clone();
+        let signature = segment.nodes.iter().find_map(|node| Self::signature(*node, lines));
+        if let Some(signature) = signature.filter(|signature| path.last() != Some(signature)) {
+            path.push(signature);
+        }
+
+        spans.push(Span {
+            start_row: segment.start_row,
+            end_row: segment.end_row,
+            heading_path: Self::heading_path(&segment.path),
+        });
+        for mut child in Self::segments(children) {
+            child.path = path.clone();
+            self.pieces(child, lines, config, out, spans);
+        }
+    }
+
+    /// Merge consecutive pieces up to `max_tokens` and emit them as chunks.
+    fn merge(&self, pieces: &[Segment<'_>], lines: &Lines<'_>, config: &ChunkConfig, chunks: &mut Vec<ChunkData>) {
+        let runs = pieces.iter().map(|piece| (piece.start_row, piece.end_row, piece.path.as_slice()));
+        // Merged pieces keep the items they share
+        let common = |path: &[String], next: &[String]| path.iter().zip(next).take_while(|(a, b)| a == b).count();
+        lines.merge(
+            runs,
+            |path, next| &path[..common(path, next)],
+            |path| Self::heading_path(path),
+            &self.recursive,
+            config,
+            chunks,
+        );
+    }
+
+    /// First line of an item that has a name, e.g. `impl Point` or
+    /// `def get(self, key)`, without its opening brace or colon.
+    fn signature(node: Node<'_>, lines: &Lines<'_>) -> Option<String> {
+        // Python wraps decorated functions and classes
+        let node = node.child_by_field_name("definition").unwrap_or(node);
+        let named = node.child_by_field_name("name").is_some()
+            || (node.kind() == "impl_item" && node.child_by_field_name("type").is_some())
+            || UNNAMED_ITEM_KINDS.contains(&node.kind());
+        if !named {
+            return None;
+        }
+
+        let row = node.start_position().row;
+        let line = lines
+            .text(row, row)
+            .trim()
+            .trim_end_matches(|c: char| c == '{' || c == ':' || c.is_whitespace());
+        Some(line.chars().take(MAX_SIGNATURE_CHARS).collect())
+    }
+
+    /// Join signatures into a heading path.
+    fn heading_path(path: &[String]) -> Option<String> {
+        if path.is_empty() {
+            None
+        } else {
+            Some(path.join(PATH_SEPARATOR))
+        }
+    }
+}
+
+impl Default for AstChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunker for AstChunker {
+    fn chunk(
+        &self,
+        content: &str,
+        content_type: ContentType,
+        config: &ChunkConfig,
+    ) -> Result<Vec<ChunkData>> {
+        if content.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let tree = Self::language(content_type).and_then(|language| {
+            let mut parser = Parser::new();
+            parser.set_language(&language).ok()?;
+            parser.parse(content, None)
+        });
+        let Some(tree) = tree else {
+            return self.recursive.chunk(content, content_type, config);
+        };
+
+        let lines = Lines::new(content);
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let items = Self::segments(root.children(&mut cursor));
+
+        // Small top-level items are merged with each other; an oversized one
+        // is split on its own
+        let mut chunks = Vec::new();
+        let mut spans = Vec::new();
+        let mut small = Vec::new();
+        for item in items {
+            let mut pieces = Vec::new();
+            self.pieces(item, &lines, config, &mut pieces, &mut spans);
+            if pieces.len() == 1 {
+                small.append(&mut pieces);
+            } else {
+                self.merge(&small, &lines, config, &mut chunks);
+                small.clear();
+                self.merge(&pieces, &lines, config, &mut chunks);
+            }
+        }
+        self.merge(&small, &lines, config, &mut chunks);
+
+        // Error recovery can leave text outside of any node; keep it too
+        lines.fill_gaps(&mut chunks, &self.recursive, config);
+
+        let chunks = merge_undersized(chunks, &lines, config, |text| self.recursive.count_tokens(text));
+        if self.hierarchical {
+            return Ok(build_hierarchy(chunks, spans, &lines, |text| self.recursive.count_tokens(text)));
+        }
+
+        Ok(chunks)
+    }
+
+    fn supported_types(&self) -> Vec<ContentType> {
+        ContentType::ALL
+            .iter()
+            .copied()
+            .filter(|content_type| Self::language(*content_type).is_some())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_exact_lines, config, count_lines};
+
+    /// Chunker counting one token per line.
+    fn line_chunker() -> AstChunker {
+        AstChunker::with_token_counter(count_lines)
+    }
+
+    const RUST: &str = r#"use std::fmt;
+
+/// Adds one.
+#[inline]
+fn add_one(x: i32) -> i32 {
+    x + 1
+}
+
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl Point {
+    fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    fn norm(&self) -> i32 {
+        self.x * self.x + self.y * self.y
+    }
+}
+"#;
+
+    #[test]
+    fn test_rust_items_with_doc_comments() {
+        let chunks = line_chunker().chunk(RUST, ContentType::Rust, &config(5)).unwrap();
+        assert_exact_lines(RUST, &chunks);
+
+        // The doc comment and attribute stay with their function
+        let add_one = chunks.iter().find(|c| c.content.contains("fn add_one")).unwrap();
+        assert!(add_one.content.starts_with("/// Adds one.\n#[inline]"));
+        assert_eq!((add_one.start_line, add_one.end_line), (3, 7));
+
+        // No function is cut in half
+        for function in [
+            "fn add_one(x: i32) -> i32 {\n    x + 1\n}",
+            "    fn new(x: i32, y: i32) -> Self {\n        Self { x, y }\n    }",
+            "    fn norm(&self) -> i32 {\n        self.x * self.x + self.y * self.y\n    }",
+        ] {
+            assert!(chunks.iter().any(|c| c.content.contains(function)), "{}", function);
+        }
+    }
+
+    #[test]
+    fn test_oversized_impl_is_split_into_methods() {
+        let chunks = line_chunker().chunk(RUST, ContentType::Rust, &config(6)).unwrap();
+        assert_exact_lines(RUST, &chunks);
+
+        // The impl header merges with the first method, the closing brace with the last
+        let new = chunks.iter().find(|c| c.content.contains("fn new")).unwrap();
+        assert!(new.content.starts_with("impl Point {"));
+        let norm = chunks.iter().find(|c| c.content.contains("fn norm")).unwrap();
+        assert!(!norm.content.contains("fn new"));
+        assert!(norm.content.ends_with("}\n}"));
+
+        // Pieces of the impl record it as their enclosing item
+        assert_eq!(norm.heading_path.as_deref(), Some("impl Point"));
+        let add_one = chunks.iter().find(|c| c.content.contains("fn add_one")).unwrap();
+        assert_eq!(add_one.heading_path, None);
+    }
+
+    #[test]
+    fn test_small_items_are_merged() {
+        let chunks = line_chunker().chunk(RUST, ContentType::Rust, &config(100)).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 22));
+    }
+
+    #[test]
+    fn test_python_classes_and_decorators() {
+        let source = r#"import os
+
+
+@cache
+def load(path):
+    return open(path).read()
+
+
+class Store:
+    """A store."""
+
+    def get(self, key):
+        return self.data[key]
+
+    def put(self, key, value):
+        self.data[key] = value
+"#;
+        let chunks = line_chunker().chunk(source, ContentType::Python, &config(4)).unwrap();
+        assert_exact_lines(source, &chunks);
+
+        assert!(chunks.iter().any(|c| c.content.starts_with("@cache\ndef load")));
+        let put = chunks.iter().find(|c| c.content.contains("def put")).unwrap();
+        assert_eq!((put.start_line, put.end_line), (15, 16));
+        assert!(!put.content.contains("def get"));
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let chunks = line_chunker()
+            .with_hierarchy(true)
+            .chunk(RUST, ContentType::Rust, &config(6))
+            .unwrap();
+
+        // The file, then the split impl, then its methods
+        let file = &chunks[0];
+        assert_eq!((file.start_line, file.end_line, file.parent, file.level), (1, 22, None, 0));
+        let (index, imp) = chunks
+            .iter()
+            .enumerate()
+            .find(|(_, c)| c.content.starts_with("impl Point {") && c.content.ends_with("}\n}"))
+            .unwrap();
+        assert_eq!((imp.parent, imp.level), (Some(0), 1));
+
+        let norm = chunks.iter().find(|c| c.content.contains("fn norm") && c.level == 2).unwrap();
+        assert_eq!(norm.parent, Some(index));
+        assert!(chunks.iter().all(|c| c.parent.map_or(true, |p| p < chunks.len() && chunks[p].level + 1 == c.level)));
+
+        // Without a hierarchy, only the leaves are emitted
+        let leaves = line_chunker().chunk(RUST, ContentType::Rust, &config(6)).unwrap();
+        let parents: Vec<usize> = chunks.iter().filter_map(|c| c.parent).collect();
+        let hierarchy_leaves = (0..chunks.len()).filter(|i| !parents.contains(i)).count();
+        assert_eq!(hierarchy_leaves, leaves.len());
+    }
+
+    #[test]
+    fn test_other_languages() {
+        let cases = [
+            (ContentType::Go, "package main\n\n// Add adds.\nfunc Add(a, b int) int {\n\treturn a + b\n}\n\nfunc Sub(a, b int) int {\n\treturn a - b\n}\n"),
+            (ContentType::TypeScript, "// Adds.\nexport function add(a: number, b: number): number {\n  return a + b;\n}\n\nexport function sub(a: number, b: number): number {\n  return a - b;\n}\n"),
+            (ContentType::JavaScript, "// Adds.\nfunction add(a, b) {\n  return a + b;\n}\n\nfunction sub(a, b) {\n  return a - b;\n}\n"),
+            (ContentType::Java, "class Calc {\n    // Adds.\n    int add(int a, int b) {\n        return a + b;\n    }\n\n    int sub(int a, int b) {\n        return a - b;\n    }\n}\n"),
+            (ContentType::CSharp, "class Calc\n{\n    // Adds.\n    [Pure]\n    int add(int a, int b) {\n        return a + b;\n    }\n\n    int sub(int a, int b) {\n        return a - b;\n    }\n}\n"),
+            (ContentType::C, "#include <stdio.h>\n\n// Adds.\nint add(int a, int b) {\n    return a + b;\n}\n\nint sub(int a, int b) {\n    return a - b;\n}\n"),
+            (ContentType::Cpp, "namespace calc {\n// Adds.\nint add(int a, int b) {\n    return a + b;\n}\n\nint sub(int a, int b) {\n    return a - b;\n}\n}\n"),
+            (ContentType::Ruby, "# Adds.\ndef add(a, b)\n  a + b\nend\n\ndef sub(a, b)\n  a - b\nend\n"),
+            (ContentType::Shell, "#!/bin/sh\n\n# Adds.\nadd() {\n  echo $(($1 + $2))\n}\n\nsub() {\n  echo $(($1 - $2))\n}\n"),
+            (ContentType::Css, "/* Adds. */\n.add {\n  color: red;\n}\n\n.sub {\n  color: blue;\n}\n"),
+        ];
+
+        for (content_type, source) in cases {
+            let chunks = line_chunker().chunk(source, content_type, &config(4)).unwrap();
+            assert_exact_lines(source, &chunks);
+            let add = chunks.iter().find(|c| c.content.contains("add") || c.content.contains("Add")).unwrap();
+            let first_line = add.content.trim_start().lines().next().unwrap();
+            assert!(first_line.contains("Add"), "{:?}: {}", content_type, add.content);
+            assert!(chunks.iter().any(|c| c.content.contains("sub") || c.content.contains("Sub")));
+            assert!(chunks.iter().all(|c| !(c.content.contains("add(") && c.content.contains("sub("))));
+        }
+    }
+
+    #[test]
+    fn test_supported_types_match_content_types() {
+        let supported = AstChunker::new().supported_types();
+        for content_type in ContentType::ALL {
+            assert_eq!(
+                supported.contains(content_type),
+                content_type.supports_ast_chunking(),
+                "{:?}",
+                content_type
+            );
+        }
+    }
+}
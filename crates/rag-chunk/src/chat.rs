@@ -0,0 +1,468 @@
+//! Sliding-window chunker for conversation transcripts.
+//!
+//! Parses a transcript into messages and chunks it into windows of whole
+//! turns, each overlapping the previous one, labelled with the speakers and
+//! time range they cover.
+
+use serde_json::Value;
+
+use rag_core::{ChunkConfig, ChunkData, Chunker, ContentType, Result};
+
+use crate::lines::Lines;
+use crate::RecursiveChunker;
+
+/// Longest speaker name recognized in `Speaker: text` logs.
+const MAX_SPEAKER_CHARS: usize = 32;
+
+/// Keys naming the speaker of a JSON message, in order of preference.
+const SPEAKER_KEYS: &[&str] = &["role", "speaker", "author", "name", "sender", "from", "user"];
+
+/// Keys holding the text of a JSON message.
+const TEXT_KEYS: &[&str] = &["content", "text", "message", "body"];
+
+/// Keys holding the time of a JSON message.
+const TIME_KEYS: &[&str] = &["timestamp", "time", "created_at", "ts", "date"];
+
+/// Chunker for conversation transcripts.
+///
+/// - Chunks are windows of consecutive messages up to `max_tokens`. A
+///   message is never split: one larger than `max_tokens` is a chunk of
+///   its own.
+/// - Each window starts with the last messages of the previous one that
+///   fit in `overlap_tokens`; the overlap counts toward `max_tokens`.
+/// - Each chunk records the speakers and the time range it covers.
+///
+/// `Speaker: text` logs are chunked as exact line ranges. JSONL messages
+/// are rendered as `speaker: text` lines, with the line numbers and byte
+/// offsets of the messages they came from.
+///
+/// Transcripts without any recognizable message are split with
+/// [`RecursiveChunker`].
+pub struct ChatChunker {
+    /// Fallback for unrecognized transcripts; also counts tokens.
+    recursive: RecursiveChunker,
+}
+
+/// A message of a transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    /// Who sent the message; empty for text before the first message of a
+    /// log.
+    pub speaker: String,
+
+    /// Message text.
+    pub text: String,
+
+    /// Time the message was sent, as written in the transcript.
+    pub timestamp: Option<String>,
+
+    /// Start line (1-based).
+    pub start_line: u32,
+
+    /// End line (1-based, inclusive).
+    pub end_line: u32,
+}
+
+impl ChatChunker {
+    /// Create a new chat chunker with default token estimation.
+    pub fn new() -> Self {
+        Self {
+            recursive: RecursiveChunker::new(),
+        }
+    }
+
+    /// Create a chunker with a custom token counter.
+    pub fn with_token_counter<F>(counter: F) -> Self
+    where
+        F: Fn(&str) -> usize + Send + Sync + 'static,
+    {
+        Self {
+            recursive: RecursiveChunker::with_token_counter(counter),
+        }
+    }
+
+    /// Split messages into overlapping windows, as `start..end` ranges.
+    fn windows(&self, tokens: &[usize], config: &ChunkConfig) -> Vec<(usize, usize)> {
+        let mut windows = Vec::new();
+        let mut start = 0;
+
+        while start < tokens.len() {
+            let mut end = start;
+            let mut total = 0;
+            while end < tokens.len() && (end == start || total + tokens[end] <= config.max_tokens) {
+                total += tokens[end];
+                end += 1;
+            }
+            windows.push((start, end));
+            if end == tokens.len() {
+                break;
+            }
+
+            // Carry over the last messages, leaving room for the next one
+            let mut next = end;
+            let mut overlap = 0;
+            while next > start + 1
+                && overlap + tokens[next - 1] <= config.overlap_tokens
+                && overlap + tokens[next - 1] + tokens[end] <= config.max_tokens
+            {
+                overlap += tokens[next - 1];
+                next -= 1;
+            }
+            start = next;
+        }
+
+        windows
+    }
+}
+
+impl Default for ChatChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunker for ChatChunker {
+    fn chunk(
+        &self,
+        content: &str,
+        content_type: ContentType,
+        config: &ChunkConfig,
+    ) -> Result<Vec<ChunkData>> {
+        let (messages, jsonl) = parse(content);
+        if messages.iter().all(|message| message.speaker.is_empty()) {
+            return self.recursive.chunk(content, content_type, config);
+        }
+
+        let lines = Lines::new(content);
+        let texts: Vec<String> = messages
+            .iter()
+            .map(|message| {
+                if jsonl {
+                    format!("{}: {}", message.speaker, message.text)
+                } else {
+                    lines
+                        .text(message.start_line as usize - 1, message.end_line as usize - 1)
+                        .to_string()
+                }
+            })
+            .collect();
+        let tokens: Vec<usize> = texts.iter().map(|text| self.recursive.count_tokens(text)).collect();
+
+        let chunks = self
+            .windows(&tokens, config)
+            .into_iter()
+            .map(|(start, end)| {
+                let window = &messages[start..end];
+                let (first, last) = (&window[0], &window[window.len() - 1]);
+                let (start_row, end_row) = (first.start_line as usize - 1, last.end_line as usize - 1);
+                let mut chunk = lines.chunk(start_row, end_row, 0);
+                if jsonl {
+                    chunk.content = texts[start..end].join("\n");
+                }
+
+                ChunkData {
+                    token_count: self.recursive.count_tokens(&chunk.content),
+                    speakers: speakers(window),
+                    time_range: time_range(window),
+                    ..chunk
+                }
+            })
+            .collect();
+
+        Ok(chunks)
+    }
+
+    fn supported_types(&self) -> Vec<ContentType> {
+        vec![ContentType::Chat]
+    }
+}
+
+impl ChatMessage {
+    /// Parse a transcript: JSONL messages if its first line is a JSON
+    /// object, `Speaker: text` lines otherwise.
+    pub fn parse(content: &str) -> Vec<Self> {
+        parse(content).0
+    }
+}
+
+/// Parse a transcript, and tell whether it was JSONL.
+fn parse(content: &str) -> (Vec<ChatMessage>, bool) {
+    let jsonl = content.trim_start().starts_with('{');
+    let messages = if jsonl {
+        parse_jsonl(content)
+    } else {
+        parse_log(content)
+    };
+    (messages, jsonl)
+}
+
+/// Speakers of messages, in order of appearance.
+fn speakers(messages: &[ChatMessage]) -> Vec<String> {
+    let mut speakers: Vec<String> = Vec::new();
+    for message in messages {
+        if !message.speaker.is_empty() && !speakers.contains(&message.speaker) {
+            speakers.push(message.speaker.clone());
+        }
+    }
+    speakers
+}
+
+/// Times of the first and last message with a time.
+fn time_range(messages: &[ChatMessage]) -> Option<(String, String)> {
+    let mut times = messages.iter().filter_map(|message| message.timestamp.as_deref());
+    let first = times.next()?;
+    let last = times.next_back().unwrap_or(first);
+    Some((first.to_string(), last.to_string()))
+}
+
+/// Parse JSONL messages, e.g. `{"role": "user", "content": "..."}`.
+///
+/// The message may be nested under a `message` key, and its content may be
+/// a list of parts, of which the text parts are kept. Lines that aren't
+/// messages with text are skipped.
+fn parse_jsonl(content: &str) -> Vec<ChatMessage> {
+    let mut messages = Vec::new();
+
+    for (row, line) in content.lines().enumerate() {
+        let Ok(Value::Object(record)) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        let message = match record.get("message") {
+            Some(Value::Object(message)) => message,
+            _ => &record,
+        };
+
+        let text = TEXT_KEYS
+            .iter()
+            .filter_map(|key| message.get(*key))
+            .find_map(text_of)
+            .filter(|text| !text.trim().is_empty());
+        let Some(text) = text else {
+            continue;
+        };
+        let speaker = [message, &record]
+            .iter()
+            .find_map(|object| SPEAKER_KEYS.iter().filter_map(|key| object.get(*key)).find_map(name_of))
+            .unwrap_or_else(|| "unknown".to_string());
+        let timestamp = [message, &record].iter().find_map(|object| {
+            TIME_KEYS.iter().filter_map(|key| object.get(*key)).find_map(|value| match value {
+                Value::String(time) if !time.is_empty() => Some(time.clone()),
+                Value::Number(time) => Some(time.to_string()),
+                _ => None,
+            })
+        });
+
+        messages.push(ChatMessage {
+            speaker,
+            text: text.trim().to_string(),
+            timestamp,
+            start_line: row as u32 + 1,
+            end_line: row as u32 + 1,
+        });
+    }
+
+    messages
+}
+
+/// Text of a message: a string, or the text parts of a list.
+fn text_of(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(parts) => {
+            let texts: Vec<&str> = parts
+                .iter()
+                .filter_map(|part| match part {
+                    Value::String(text) => Some(text.as_str()),
+                    Value::Object(part) => part.get("text").and_then(Value::as_str),
+                    _ => None,
+                })
+                .collect();
+            (!texts.is_empty()).then(|| texts.join("\n"))
+        }
+        _ => None,
+    }
+}
+
+/// Speaker name: a string, or the name or role of an object.
+fn name_of(value: &Value) -> Option<String> {
+    match value {
+        Value::String(name) if !name.trim().is_empty() => Some(name.trim().to_string()),
+        Value::Object(object) => ["name", "role"]
+            .iter()
+            .find_map(|key| object.get(*key).and_then(Value::as_str))
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+/// Parse a `Speaker: text` log, optionally with `[time]` prefixes and IRC
+/// style `<speaker> text` lines. Lines that don't start a message belong
+/// to the previous one.
+fn parse_log(content: &str) -> Vec<ChatMessage> {
+    let mut messages: Vec<ChatMessage> = Vec::new();
+
+    for (row, line) in content.lines().enumerate() {
+        let line_number = row as u32 + 1;
+        if let Some((timestamp, speaker, text)) = turn(line) {
+            messages.push(ChatMessage {
+                speaker: speaker.to_string(),
+                text: text.to_string(),
+                timestamp: timestamp.map(str::to_string),
+                start_line: line_number,
+                end_line: line_number,
+            });
+            continue;
+        }
+
+        // Blank lines only count once the message continues
+        if line.trim().is_empty() {
+            continue;
+        }
+        match messages.last_mut() {
+            Some(message) => {
+                if !message.text.is_empty() {
+                    message.text.push('\n');
+                }
+                message.text.push_str(line.trim());
+                message.end_line = line_number;
+            }
+            None => messages.push(ChatMessage {
+                speaker: String::new(),
+                text: line.trim().to_string(),
+                timestamp: None,
+                start_line: line_number,
+                end_line: line_number,
+            }),
+        }
+    }
+
+    messages
+}
+
+/// Split a line starting a message into its time, speaker and text.
+fn turn(line: &str) -> Option<(Option<&str>, &str, &str)> {
+    let mut rest = line.trim_start();
+    let mut timestamp = None;
+    if let Some(stamped) = rest.strip_prefix('[') {
+        let end = stamped.find(']')?;
+        timestamp = Some(stamped[..end].trim());
+        rest = stamped[end + 1..].trim_start();
+    }
+
+    let (speaker, text) = if let Some(nick) = rest.strip_prefix('<') {
+        let end = nick.find('>')?;
+        (&nick[..end], &nick[end + 1..])
+    } else {
+        let end = rest.find(':')?;
+        let text = &rest[end + 1..];
+        // `10:30`, `https://...`
+        if !(text.is_empty() || text.starts_with(char::is_whitespace)) {
+            return None;
+        }
+        (&rest[..end], text)
+    };
+
+    let speaker = speaker.trim();
+    let valid = !speaker.is_empty()
+        && speaker.chars().count() <= MAX_SPEAKER_CHARS
+        && speaker.split_whitespace().count() <= 3
+        && speaker.starts_with(char::is_alphanumeric)
+        && speaker
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '.' | '_' | '-' | '\'' | '@' | '(' | ')'));
+    valid.then(|| (timestamp, speaker, text.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words() -> ChatChunker {
+        ChatChunker::with_token_counter(|s: &str| s.split_whitespace().count())
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let log = "Support chat export\n\
+                   [10:02] Alice: the build fails\n  on CI only\n\n\
+                   [10:03] Bob: which job?\n\
+                   <carol> see https://ci.example.com\n\
+                   Note that this is not: a turn\n";
+        let messages = ChatMessage::parse(log);
+
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].speaker, "");
+        assert_eq!(messages[1].speaker, "Alice");
+        assert_eq!(messages[1].text, "the build fails\non CI only");
+        assert_eq!(messages[1].timestamp.as_deref(), Some("10:02"));
+        assert_eq!((messages[1].start_line, messages[1].end_line), (2, 3));
+        assert_eq!(messages[3].speaker, "carol");
+        assert_eq!(messages[3].text, "see https://ci.example.com\nNote that this is not: a turn");
+    }
+
+    #[test]
+    fn test_parse_jsonl() {
+        let jsonl = r#"{"role": "user", "content": "Fix the cache", "timestamp": "2024-05-01T10:00:00Z"}
+{"type": "summary", "summary": "not a message"}
+{"type": "assistant", "timestamp": "2024-05-01T10:00:05Z", "message": {"role": "assistant", "content": [{"type": "text", "text": "Looking"}, {"type": "tool_use", "name": "read"}]}}
+not json
+{"author": {"name": "bot"}, "text": "done", "ts": 1714557610}
+"#;
+        let messages = ChatMessage::parse(jsonl);
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].speaker, "user");
+        assert_eq!(messages[1].speaker, "assistant");
+        assert_eq!(messages[1].text, "Looking");
+        assert_eq!(messages[1].start_line, 3);
+        assert_eq!(messages[2].speaker, "bot");
+        assert_eq!(messages[2].timestamp.as_deref(), Some("1714557610"));
+    }
+
+    #[test]
+    fn test_windows_overlap_whole_turns() {
+        let jsonl = (0..6)
+            .map(|i| {
+                let speaker = if i % 2 == 0 { "user" } else { "assistant" };
+                format!(r#"{{"role": "{}", "content": "one two three", "timestamp": "10:0{}"}}"#, speaker, i)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let config = ChunkConfig {
+            max_tokens: 12,
+            min_tokens: 1,
+            overlap_tokens: 4,
+        };
+        let chunks = words().chunk(&jsonl, ContentType::Chat, &config).unwrap();
+
+        // Three turns of four words per window, the last one carried over
+        let lines: Vec<_> = chunks.iter().map(|c| (c.start_line, c.end_line)).collect();
+        assert_eq!(lines, vec![(1, 3), (3, 5), (5, 6)]);
+        assert_eq!(chunks[0].content, "user: one two three\nassistant: one two three\nuser: one two three");
+        assert_eq!(chunks[0].speakers, vec!["user", "assistant"]);
+        assert_eq!(chunks[0].time_range, Some(("10:00".to_string(), "10:02".to_string())));
+        assert_eq!(chunks[2].time_range, Some(("10:04".to_string(), "10:05".to_string())));
+        assert!(chunks.iter().all(|c| c.heading_path.is_none()));
+    }
+
+    #[test]
+    fn test_long_message_not_split() {
+        let log = format!("alice: hi\nbob: {}\nalice: thanks", "word ".repeat(50));
+        let config = ChunkConfig {
+            max_tokens: 10,
+            min_tokens: 1,
+            overlap_tokens: 5,
+        };
+        let chunks = words().chunk(&log, ContentType::Chat, &config).unwrap();
+
+        let lines: Vec<_> = chunks.iter().map(|c| (c.start_line, c.end_line)).collect();
+        assert_eq!(lines, vec![(1, 1), (2, 2), (3, 3)]);
+        assert!(chunks[1].content.starts_with("bob: word"));
+        assert_eq!(chunks[1].speakers, vec!["bob"]);
+        assert_eq!(chunks[1].time_range, None);
+
+        // Not a transcript
+        let chunks = words().chunk("just some notes", ContentType::Chat, &config).unwrap();
+        assert!(chunks[0].speakers.is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+//! Parent chunks enclosing the chunks of a document.
+
+use std::cmp::Reverse;
+
+use rag_core::ChunkData;
+
+use crate::lines::Lines;
+
+/// A structural unit (item, section, split paragraph, ...) that may become
+/// the parent of the chunks inside it.
+pub(crate) struct Span {
+    /// First line (0-based).
+    pub(crate) start_row: usize,
+
+    /// Last line (0-based, inclusive).
+    pub(crate) end_row: usize,
+
+    pub(crate) heading_path: Option<String>,
+}
+
+/// Add parent chunks for `spans` and for the whole document above `leaves`.
+///
+/// A span becomes a parent if it holds more than one leaf, or a leaf smaller
+/// than itself; spans cutting through a leaf or another span are skipped.
+/// The result lists parents before their children, and each chunk's
+/// `parent` is the index of the smallest parent containing it.
+pub(crate) fn build_hierarchy<F>(
+    leaves: Vec<ChunkData>,
+    mut spans: Vec<Span>,
+    lines: &Lines<'_>,
+    count_tokens: F,
+) -> Vec<ChunkData>
+where
+    F: Fn(&str) -> usize,
+{
+    let rows = |chunk: &ChunkData| (chunk.start_line as usize - 1, chunk.end_line as usize - 1);
+    let contains = |outer: (usize, usize), inner: (usize, usize)| outer.0 <= inner.0 && inner.1 <= outer.1;
+    let crosses = |a: (usize, usize), b: (usize, usize)| {
+        a.0 <= b.1 && b.0 <= a.1 && !contains(a, b) && !contains(b, a)
+    };
+
+    let (Some(first), Some(last)) = (
+        leaves.iter().map(|chunk| chunk.start_line).min(),
+        leaves.iter().map(|chunk| chunk.end_line).max(),
+    ) else {
+        return leaves;
+    };
+    spans.push(Span {
+        start_row: first as usize - 1,
+        end_row: last as usize - 1,
+        heading_path: None,
+    });
+
+    // Outer spans first; of two identical spans, the one given first wins
+    spans.sort_by_key(|span| (span.start_row, Reverse(span.end_row)));
+    spans.dedup_by_key(|span| (span.start_row, span.end_row));
+
+    let mut parents: Vec<Span> = Vec::new();
+    for span in spans {
+        let range = (span.start_row, span.end_row);
+        if leaves.iter().any(|leaf| crosses(range, rows(leaf)))
+            || parents.iter().any(|parent| crosses(range, (parent.start_row, parent.end_row)))
+        {
+            continue;
+        }
+        let inside: Vec<_> = leaves.iter().map(rows).filter(|&leaf| contains(range, leaf)).collect();
+        if inside.len() > 1 || inside.first().is_some_and(|&leaf| leaf != range) {
+            parents.push(span);
+        }
+    }
+
+    // Parents are sorted outermost first, so the last one containing a range
+    // is the smallest
+    let smallest_parent = |range: (usize, usize), strict: bool| {
+        parents.iter().rposition(|parent| {
+            let outer = (parent.start_row, parent.end_row);
+            contains(outer, range) && !(strict && outer == range)
+        })
+    };
+
+    let mut order: Vec<(usize, usize, bool, usize)> = parents
+        .iter()
+        .enumerate()
+        .map(|(i, parent)| (parent.start_row, parent.end_row, false, i))
+        .chain(leaves.iter().enumerate().map(|(i, leaf)| {
+            let (start, end) = rows(leaf);
+            (start, end, true, i)
+        }))
+        .collect();
+    order.sort_by_key(|&(start, end, leaf, _)| (start, Reverse(end), leaf));
+
+    let mut leaves: Vec<Option<ChunkData>> = leaves.into_iter().map(Some).collect();
+    let mut positions = vec![0; parents.len()];
+    let mut chunks: Vec<ChunkData> = Vec::with_capacity(order.len());
+
+    for (start, end, leaf, i) in order {
+        let parent = smallest_parent((start, end), !leaf).map(|p| positions[p]);
+        let level = parent.map_or(0, |p| chunks[p].level + 1);
+
+        let chunk = if leaf {
+            leaves[i].take()
+        } else {
+            positions[i] = chunks.len();
+            Some(ChunkData {
+                heading_path: parents[i].heading_path.clone(),
+                ..lines.chunk(start, end, count_tokens(lines.text(start, end)))
+            })
+        };
+
+        if let Some(chunk) = chunk {
+            chunks.push(ChunkData { parent, level, ..chunk });
+        }
+    }
+
+    chunks
+}
@@ -0,0 +1,1125 @@
+//! HTML content extraction and chunking.
+//!
+//! Parses HTML leniently, drops boilerplate (scripts, navigation, footers,
+//! ...), finds the element holding the main content and renders it as
+//! Markdown, which is then chunked by section like any Markdown document.
+
+use std::collections::HashMap;
+
+use rag_core::{ChunkConfig, ChunkData, ChunkedDocument, Chunker, ContentType, Result};
+
+use crate::lines::Lines;
+use crate::MarkdownChunker;
+
+/// Elements whose content is not markup.
+const RAW_TEXT: &[&str] = &["script", "style", "title", "textarea", "xmp"];
+
+/// Elements without content or end tag.
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/// Elements rendered as part of the surrounding text.
+const INLINE: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "br", "cite", "code", "data", "del", "dfn", "em", "font", "i", "img", "ins",
+    "kbd", "label", "mark", "q", "s", "samp", "small", "span", "strong", "sub", "sup", "time", "u", "var", "wbr",
+];
+
+/// Elements that close an open `<p>`.
+const CLOSES_PARAGRAPH: &[&str] = &[
+    "address", "article", "aside", "blockquote", "details", "div", "dl", "fieldset", "figure", "footer", "form",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "main", "nav", "ol", "p", "pre", "section", "table", "ul",
+];
+
+/// Elements that never hold main content.
+const BOILERPLATE: &[&str] = &[
+    "aside", "button", "canvas", "dialog", "embed", "form", "head", "iframe", "menu", "nav", "noscript", "object",
+    "script", "select", "style", "svg", "template", "title",
+];
+
+/// ARIA roles of page furniture.
+const BOILERPLATE_ROLES: &[&str] = &[
+    "alert", "banner", "complementary", "contentinfo", "dialog", "menu", "navigation", "search",
+];
+
+/// Words in a class or id that mark page furniture...
+const UNLIKELY_WORDS: &[&str] = &[
+    "ad", "ads", "advert", "advertisement", "banner", "breadcrumb", "breadcrumbs", "comment", "comments", "cookie",
+    "cookies", "footer", "header", "masthead", "menu", "modal", "nav", "navbar", "newsletter", "pagination", "popup",
+    "promo", "related", "share", "sharing", "sidebar", "skip", "social", "subscribe",
+];
+
+/// ...unless it also has one of these.
+const LIKELY_WORDS: &[&str] = &["article", "body", "content", "entry", "main", "post", "story", "text"];
+
+/// Chunker for HTML pages.
+///
+/// Only the main content of the page is chunked: scripts, styles,
+/// navigation, headers, footers, sidebars and other page furniture are
+/// dropped, and if the page has a `<main>` element, a single `<article>`,
+/// or one element holding most of its text, everything outside of it is
+/// dropped too. Headings, lists, tables and `<pre>` blocks are rendered as
+/// Markdown and chunked with [`MarkdownChunker`], so chunks follow the
+/// sections of the page and record their heading path.
+///
+/// Chunk content is the rendered Markdown; line numbers and byte offsets
+/// cover the lines of the HTML elements the chunk was rendered from.
+pub struct HtmlChunker {
+    markdown: MarkdownChunker,
+}
+
+/// The main content of an HTML page, rendered as Markdown.
+#[derive(Debug, Clone, Default)]
+pub struct HtmlPage {
+    /// Text of the `<title>` element.
+    pub title: Option<String>,
+
+    /// Target of `<link rel="canonical">`.
+    pub canonical_url: Option<String>,
+
+    /// Main content as Markdown.
+    pub markdown: String,
+
+    /// HTML lines (0-based, inclusive) each Markdown line was rendered from.
+    rows: Vec<(usize, usize)>,
+}
+
+/// A parsed element.
+struct Element {
+    /// Lowercase tag name.
+    name: String,
+
+    /// Attributes with lowercase names, in source order.
+    attrs: Vec<(String, String)>,
+
+    children: Vec<Node>,
+
+    /// Byte offset of the start tag.
+    start: usize,
+
+    /// Byte offset just past the end tag, or where the element was closed.
+    end: usize,
+}
+
+enum Node {
+    Element(Element),
+
+    /// Decoded text, with the byte range of its non-whitespace part.
+    Text { text: String, start: usize, end: usize },
+}
+
+/// A rendered Markdown line.
+struct Line {
+    text: String,
+    start_row: usize,
+    end_row: usize,
+}
+
+impl HtmlChunker {
+    /// Create a new HTML chunker with default token estimation.
+    pub fn new() -> Self {
+        Self {
+            markdown: MarkdownChunker::new(),
+        }
+    }
+
+    /// Create a chunker with a custom token counter.
+    pub fn with_token_counter<F>(counter: F) -> Self
+    where
+        F: Fn(&str) -> usize + Send + Sync + 'static,
+    {
+        Self {
+            markdown: MarkdownChunker::with_token_counter(counter),
+        }
+    }
+
+    /// Enable or disable parent chunks for the page, sections and split
+    /// blocks.
+    pub fn with_hierarchy(mut self, hierarchical: bool) -> Self {
+        self.markdown = self.markdown.with_hierarchy(hierarchical);
+        self
+    }
+}
+
+impl Default for HtmlChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunker for HtmlChunker {
+    fn chunk(
+        &self,
+        content: &str,
+        content_type: ContentType,
+        config: &ChunkConfig,
+    ) -> Result<Vec<ChunkData>> {
+        Ok(self.chunk_with_metadata(content, content_type, config)?.chunks)
+    }
+
+    /// Chunk a page, returning its `title` and `canonical_url` as metadata.
+    fn chunk_with_metadata(
+        &self,
+        content: &str,
+        _content_type: ContentType,
+        config: &ChunkConfig,
+    ) -> Result<ChunkedDocument> {
+        let page = HtmlPage::parse(content);
+        let mut metadata = HashMap::new();
+        if let Some(title) = &page.title {
+            metadata.insert("title".to_string(), serde_json::Value::String(title.clone()));
+        }
+        if let Some(url) = &page.canonical_url {
+            metadata.insert("canonical_url".to_string(), serde_json::Value::String(url.clone()));
+        }
+        if page.markdown.trim().is_empty() {
+            return Ok(ChunkedDocument {
+                chunks: Vec::new(),
+                metadata,
+            });
+        }
+
+        let lines = Lines::new(content);
+        let mut chunks = self.markdown.chunk(&page.markdown, ContentType::Markdown, config)?;
+        for chunk in &mut chunks {
+            let (start_row, _) = page.rows[chunk.start_line as usize - 1];
+            let (_, end_row) = page.rows[chunk.end_line as usize - 1];
+            lines.locate(chunk, lines.range(start_row, end_row.max(start_row)));
+        }
+
+        Ok(ChunkedDocument { chunks, metadata })
+    }
+
+    fn supported_types(&self) -> Vec<ContentType> {
+        vec![ContentType::Html]
+    }
+}
+
+impl HtmlPage {
+    /// Extract the title, canonical link and main content of a page.
+    pub fn parse(html: &str) -> Self {
+        let document = parse(html);
+        let lines = Lines::new(html);
+
+        let title = document
+            .find(&|el| el.name == "title")
+            .map(|el| collapse(&el.text()))
+            .filter(|title| !title.is_empty());
+        let canonical_url = document
+            .find(&|el| {
+                el.name == "link"
+                    && el
+                        .attr("rel")
+                        .is_some_and(|rel| rel.split_ascii_whitespace().any(|r| r.eq_ignore_ascii_case("canonical")))
+            })
+            .and_then(|el| el.attr("href"))
+            .map(|href| href.trim().to_string())
+            .filter(|href| !href.is_empty());
+
+        let renderer = Renderer { lines: &lines };
+        let mut blocks = Vec::new();
+        renderer.blocks(main_content(&document), &mut blocks);
+
+        let mut markdown = Vec::new();
+        let mut rows = Vec::new();
+        for block in blocks {
+            if let Some(&(_, end_row)) = rows.last() {
+                markdown.push(String::new());
+                rows.push((end_row, end_row));
+            }
+            for line in block {
+                markdown.push(line.text);
+                rows.push((line.start_row, line.end_row));
+            }
+        }
+
+        Self {
+            title,
+            canonical_url,
+            markdown: markdown.join("\n"),
+            rows,
+        }
+    }
+}
+
+impl Element {
+    fn new(name: &str, attrs: Vec<(String, String)>, start: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            attrs,
+            children: Vec::new(),
+            start,
+            end: start,
+        }
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(el) => Some(el),
+            Node::Text { .. } => None,
+        })
+    }
+
+    /// First element in document order matching a predicate.
+    fn find(&self, predicate: &dyn Fn(&Element) -> bool) -> Option<&Element> {
+        if predicate(self) {
+            return Some(self);
+        }
+        self.elements().find_map(|el| el.find(predicate))
+    }
+
+    /// Text content, without scripts and styles.
+    fn text(&self) -> String {
+        let mut text = String::new();
+        self.push_text(&mut text);
+        text
+    }
+
+    fn push_text(&self, text: &mut String) {
+        for child in &self.children {
+            match child {
+                Node::Text { text: t, .. } => text.push_str(t),
+                Node::Element(el) if el.name == "script" || el.name == "style" => {}
+                Node::Element(el) => el.push_text(text),
+            }
+        }
+    }
+
+    /// Whether the element is page furniture rather than content.
+    fn is_boilerplate(&self) -> bool {
+        if BOILERPLATE.contains(&self.name.as_str())
+            || self.attr("hidden").is_some()
+            || self.attr("aria-hidden") == Some("true")
+            || self.attr("role").is_some_and(|role| BOILERPLATE_ROLES.contains(&role))
+        {
+            return true;
+        }
+        if matches!(self.name.as_str(), "body" | "main" | "article") {
+            return false;
+        }
+        if let Some(style) = self.attr("style") {
+            let style: String = style.chars().filter(|c| !c.is_whitespace()).collect();
+            if style.contains("display:none") {
+                return true;
+            }
+        }
+
+        let names = format!("{} {}", self.attr("class").unwrap_or(""), self.attr("id").unwrap_or(""))
+            .to_ascii_lowercase();
+        let words: Vec<&str> = names.split(|c: char| !c.is_ascii_alphanumeric()).collect();
+        let unlikely = self.name == "header"
+            || self.name == "footer"
+            || words.iter().any(|word| UNLIKELY_WORDS.contains(word));
+        unlikely && !words.iter().any(|word| LIKELY_WORDS.contains(word))
+    }
+
+    /// Amount of text in the content blocks inside the element.
+    fn weight(&self) -> usize {
+        if self.is_boilerplate() {
+            return 0;
+        }
+        if matches!(self.name.as_str(), "p" | "pre" | "li" | "td" | "blockquote" | "dd") {
+            return collapse(&self.text()).len();
+        }
+        self.children
+            .iter()
+            .map(|child| match child {
+                Node::Element(el) => el.weight(),
+                Node::Text { text, .. } => text.trim().len(),
+            })
+            .sum()
+    }
+}
+
+/// Element holding the main content: `<main>`, the only `<article>`, or the
+/// innermost element holding at least 80% of the text of the page.
+fn main_content(document: &Element) -> &Element {
+    let mut articles = Vec::new();
+    collect(document, &|el| el.name == "article", &mut articles);
+
+    if let Some(main) = document.find(&|el| el.name == "main" || el.attr("role") == Some("main")) {
+        return main;
+    }
+    if let [article] = articles[..] {
+        return article;
+    }
+
+    let mut root = document.find(&|el| el.name == "body").unwrap_or(document);
+    loop {
+        let total = root.weight();
+        let child = root
+            .elements()
+            .find(|child| child.weight() * 5 >= total * 4 && !is_leaf_block(child));
+        match child {
+            Some(child) if total > 0 => root = child,
+            _ => return root,
+        }
+    }
+}
+
+/// Whether an element is a block rendered as a whole, such as a paragraph or
+/// a heading, even when it holds all the text of the page.
+fn is_leaf_block(el: &Element) -> bool {
+    matches!(el.name.as_str(), "p" | "pre" | "table" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6")
+}
+
+/// Collect the elements matching a predicate, outside of boilerplate.
+fn collect<'a>(el: &'a Element, predicate: &dyn Fn(&Element) -> bool, out: &mut Vec<&'a Element>) {
+    if el.is_boilerplate() {
+        return;
+    }
+    if predicate(el) {
+        out.push(el);
+    }
+    for child in el.elements() {
+        collect(child, predicate, out);
+    }
+}
+
+/// Renders elements as Markdown blocks, each a list of lines.
+struct Renderer<'a> {
+    lines: &'a Lines<'a>,
+}
+
+impl Renderer<'_> {
+    /// HTML lines spanned by an element.
+    fn rows(&self, el: &Element) -> (usize, usize) {
+        (self.lines.row(el.start), self.lines.row(el.end.saturating_sub(1).max(el.start)))
+    }
+
+    fn line(&self, text: String, el: &Element) -> Line {
+        let (start_row, end_row) = self.rows(el);
+        Line {
+            text,
+            start_row,
+            end_row,
+        }
+    }
+
+    /// Render the content of a container element.
+    fn blocks(&self, container: &Element, out: &mut Vec<Vec<Line>>) {
+        // Text and inline elements between blocks form paragraphs
+        let mut inline = String::new();
+        let mut inline_rows: Option<(usize, usize)> = None;
+        let flush = |inline: &mut String, inline_rows: &mut Option<(usize, usize)>, out: &mut Vec<Vec<Line>>| {
+            let (start_row, end_row) = inline_rows.take().unwrap_or_default();
+            let paragraph: Vec<Line> = inline
+                .split('\n')
+                .map(collapse)
+                .filter(|line| !line.is_empty())
+                .map(|text| Line {
+                    text,
+                    start_row,
+                    end_row,
+                })
+                .collect();
+            if !paragraph.is_empty() {
+                out.push(paragraph);
+            }
+            inline.clear();
+        };
+
+        for child in &container.children {
+            match child {
+                Node::Text { text, start, end } => {
+                    if !text.trim().is_empty() {
+                        let start_row = self.lines.row(*start);
+                        let end_row = self.lines.row(end.saturating_sub(1).max(*start));
+                        let rows = inline_rows.get_or_insert((start_row, end_row));
+                        rows.1 = rows.1.max(end_row);
+                    }
+                    inline.push_str(text);
+                }
+                Node::Element(el) if el.is_boilerplate() => {}
+                Node::Element(el) if INLINE.contains(&el.name.as_str()) => {
+                    let (start_row, end_row) = self.rows(el);
+                    let rows = inline_rows.get_or_insert((start_row, end_row));
+                    rows.1 = rows.1.max(end_row);
+                    inline_text(el, &mut inline);
+                }
+                Node::Element(el) => {
+                    flush(&mut inline, &mut inline_rows, out);
+                    self.block(el, out);
+                }
+            }
+        }
+        flush(&mut inline, &mut inline_rows, out);
+    }
+
+    /// Render a block element.
+    fn block(&self, el: &Element, out: &mut Vec<Vec<Line>>) {
+        match el.name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = usize::from(el.name.as_bytes()[1] - b'0');
+                let mut text = String::new();
+                inline_text(el, &mut text);
+                let text = collapse(&text);
+                if !text.is_empty() {
+                    out.push(vec![self.line(format!("{} {}", "#".repeat(level), text), el)]);
+                }
+            }
+            "ul" | "ol" => {
+                let list = self.list(el);
+                if !list.is_empty() {
+                    out.push(list);
+                }
+            }
+            "pre" => out.push(self.pre(el)),
+            "table" => {
+                let table = self.table(el);
+                if !table.is_empty() {
+                    out.push(table);
+                }
+            }
+            "blockquote" => {
+                let mut inner = Vec::new();
+                self.blocks(el, &mut inner);
+                let mut quote = Vec::new();
+                for (i, block) in inner.into_iter().enumerate() {
+                    if i > 0 {
+                        quote.push(self.line(">".to_string(), el));
+                    }
+                    quote.extend(block.into_iter().map(|line| Line {
+                        text: format!("> {}", line.text),
+                        ..line
+                    }));
+                }
+                if !quote.is_empty() {
+                    out.push(quote);
+                }
+            }
+            "hr" => {}
+            _ => self.blocks(el, out),
+        }
+    }
+
+    /// A list, with nested content indented under its item.
+    fn list(&self, el: &Element) -> Vec<Line> {
+        let ordered = el.name == "ol";
+        let first: usize = el.attr("start").and_then(|start| start.trim().parse().ok()).unwrap_or(1);
+        let mut lines = Vec::new();
+
+        for (number, item) in (first..).zip(el.elements().filter(|item| item.name == "li")) {
+            let mut inner = Vec::new();
+            self.blocks(item, &mut inner);
+            let marker = if ordered {
+                format!("{}. ", number)
+            } else {
+                "- ".to_string()
+            };
+
+            let indent = " ".repeat(marker.len());
+            for (i, line) in inner.into_iter().flatten().enumerate() {
+                let prefix = if i == 0 { &marker } else { &indent };
+                lines.push(Line {
+                    text: format!("{}{}", prefix, line.text),
+                    ..line
+                });
+            }
+        }
+
+        lines
+    }
+
+    /// A fenced code block.
+    fn pre(&self, el: &Element) -> Vec<Line> {
+        let text = el.text();
+        let code = text.strip_prefix('\n').unwrap_or(&text).trim_end();
+        let fence = if code.contains("```") { "~~~" } else { "```" };
+
+        // `class="language-rust"` on the <pre> or its <code>
+        let language = el
+            .find(&|el| el.attr("class").is_some_and(|class| class.contains("language-") || class.contains("lang-")))
+            .and_then(|el| el.attr("class"))
+            .and_then(|class| {
+                class
+                    .split_ascii_whitespace()
+                    .find_map(|c| c.strip_prefix("language-").or_else(|| c.strip_prefix("lang-")))
+            })
+            .unwrap_or("");
+
+        let (start_row, end_row) = self.rows(el);
+        let mut lines = vec![Line {
+            text: format!("{}{}", fence, language),
+            start_row,
+            end_row: start_row,
+        }];
+        for (i, line) in code.lines().enumerate() {
+            // Code lines usually match source lines one to one
+            let row = (start_row + i).min(end_row);
+            lines.push(Line {
+                text: line.to_string(),
+                start_row: row,
+                end_row: row,
+            });
+        }
+        lines.push(Line {
+            text: fence.to_string(),
+            start_row: end_row,
+            end_row,
+        });
+        lines
+    }
+
+    /// A table, with its first row as header.
+    fn table(&self, el: &Element) -> Vec<Line> {
+        let mut rows = Vec::new();
+        collect_rows(el, &mut rows);
+
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                row.elements()
+                    .filter(|cell| cell.name == "td" || cell.name == "th")
+                    .map(|cell| collapse(&cell.text()).replace('|', "\\|"))
+                    .collect()
+            })
+            .collect();
+        let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return Vec::new();
+        }
+
+        let mut lines = Vec::new();
+        for (i, (row, cells)) in rows.iter().zip(&cells).enumerate() {
+            let mut text = String::from("|");
+            for column in 0..columns {
+                text.push(' ');
+                text.push_str(cells.get(column).map_or("", String::as_str));
+                text.push_str(" |");
+            }
+            lines.push(self.line(text, row));
+            if i == 0 {
+                lines.push(self.line(format!("|{}", " --- |".repeat(columns)), row));
+            }
+        }
+        lines
+    }
+}
+
+/// Rows of a table, outside of nested tables.
+fn collect_rows<'a>(el: &'a Element, rows: &mut Vec<&'a Element>) {
+    for child in el.elements() {
+        match child.name.as_str() {
+            "tr" => rows.push(child),
+            "table" => {}
+            _ => collect_rows(child, rows),
+        }
+    }
+}
+
+/// Append the text of an inline element, with `<br>` as a newline and
+/// `<code>` in backticks.
+fn inline_text(el: &Element, text: &mut String) {
+    match el.name.as_str() {
+        "br" => text.push('\n'),
+        "code" => {
+            let code = collapse(&el.text());
+            if !code.is_empty() {
+                text.push('`');
+                text.push_str(&code);
+                text.push('`');
+            }
+        }
+        "script" | "style" => {}
+        _ => {
+            for child in &el.children {
+                match child {
+                    Node::Text { text: t, .. } => text.push_str(t),
+                    Node::Element(child) if child.is_boilerplate() => {}
+                    Node::Element(child) => {
+                        // Blocks inside inline elements still get a line of their own
+                        let block = !INLINE.contains(&child.name.as_str());
+                        if block {
+                            text.push('\n');
+                        }
+                        inline_text(child, text);
+                        if block {
+                            text.push('\n');
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Collapse runs of whitespace into single spaces and trim.
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parse HTML into a tree under a `#document` element, recovering from
+/// unclosed and stray tags.
+fn parse(html: &str) -> Element {
+    let lower = html.to_ascii_lowercase();
+    let bytes = html.as_bytes();
+    let mut stack = vec![Element::new("#document", Vec::new(), 0)];
+    let mut pos = 0;
+    let mut text_start = 0;
+
+    fn push_text(stack: &mut [Element], html: &str, range: std::ops::Range<usize>) {
+        push_literal(stack, html, range, false);
+    }
+
+    /// Add text, with character references decoded unless it is `literal`.
+    fn push_literal(stack: &mut [Element], html: &str, range: std::ops::Range<usize>, literal: bool) {
+        if range.is_empty() {
+            return;
+        }
+        if let Some(top) = stack.last_mut() {
+            let raw = &html[range.clone()];
+            let start = range.start + (raw.len() - raw.trim_start().len());
+            top.children.push(Node::Text {
+                text: if literal { raw.to_string() } else { decode_entities(raw) },
+                start,
+                end: (range.start + raw.trim_end().len()).max(start),
+            });
+        }
+    }
+
+    while let Some(offset) = html[pos..].find('<') {
+        pos += offset;
+        let rest = &html[pos..];
+
+        if rest.starts_with("<!--") {
+            push_text(&mut stack, html, text_start..pos);
+            pos = html[pos + 4..].find("-->").map_or(html.len(), |end| pos + 4 + end + 3);
+            text_start = pos;
+        } else if rest.starts_with("<![CDATA[") {
+            // Kept as literal text, as in XHTML and SVG
+            push_text(&mut stack, html, text_start..pos);
+            let content_start = pos + "<![CDATA[".len();
+            let content_end = html[content_start..].find("]]>").map_or(html.len(), |end| content_start + end);
+            push_literal(&mut stack, html, content_start..content_end, true);
+            pos = (content_end + 3).min(html.len());
+            text_start = pos;
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            push_text(&mut stack, html, text_start..pos);
+            pos = html[pos..].find('>').map_or(html.len(), |end| pos + end + 1);
+            text_start = pos;
+        } else if rest.starts_with("</") && bytes.get(pos + 2).is_some_and(u8::is_ascii_alphabetic) {
+            push_text(&mut stack, html, text_start..pos);
+            let name = tag_name(&lower[pos + 2..]);
+            let end = html[pos..].find('>').map_or(html.len(), |end| pos + end + 1);
+            close(&mut stack, name, end);
+            pos = end;
+            text_start = pos;
+        } else if bytes.get(pos + 1).is_some_and(u8::is_ascii_alphabetic) {
+            push_text(&mut stack, html, text_start..pos);
+            let (element, self_closing, end) = start_tag(html, &lower, pos);
+            pos = end;
+            text_start = pos;
+
+            open(&mut stack, &element.name);
+            let name = element.name.clone();
+            stack.push(element);
+
+            if RAW_TEXT.contains(&name.as_str()) {
+                let content_end = raw_text_end(&lower, &name, pos);
+                if name != "script" && name != "style" {
+                    push_text(&mut stack, html, pos..content_end);
+                }
+                let end = html[content_end..].find('>').map_or(html.len(), |end| content_end + end + 1);
+                close(&mut stack, &name, end);
+                pos = end;
+                text_start = pos;
+            } else if self_closing || VOID.contains(&name.as_str()) {
+                close(&mut stack, &name, pos);
+            }
+        } else {
+            // A literal `<`
+            pos += 1;
+        }
+    }
+    push_text(&mut stack, html, text_start..html.len());
+
+    while stack.len() > 1 {
+        close_top(&mut stack, html.len());
+    }
+    let mut document = stack.pop().unwrap_or_else(|| Element::new("#document", Vec::new(), 0));
+    document.end = html.len();
+    document
+}
+
+/// Offset of the end tag of the raw text element `name` whose content starts
+/// at `pos`; `</script` in `</scripts>` does not end a script.
+fn raw_text_end(lower: &str, name: &str, pos: usize) -> usize {
+    let close_tag = format!("</{}", name);
+    let mut from = pos;
+    while let Some(offset) = lower[from..].find(&close_tag) {
+        let end = from + offset;
+        let next = lower.as_bytes().get(end + close_tag.len());
+        if next.map_or(true, |&b| b.is_ascii_whitespace() || b == b'/' || b == b'>') {
+            return end;
+        }
+        from = end + close_tag.len();
+    }
+    lower.len()
+}
+
+/// Close the elements an opening `name` tag implicitly ends.
+fn open(stack: &mut Vec<Element>, name: &str) {
+    let current = |stack: &Vec<Element>| stack.last().map(|el| el.name.clone()).unwrap_or_default();
+
+    if CLOSES_PARAGRAPH.contains(&name) && current(stack) == "p" {
+        let start = stack.last().map_or(0, |el| el.start);
+        close_top(stack, start);
+    }
+
+    // Siblings that can't contain each other, up to their container
+    let (siblings, containers): (&[&str], &[&str]) = match name {
+        "li" => (&["li"], &["ul", "ol", "menu"]),
+        "dt" | "dd" => (&["dt", "dd"], &["dl"]),
+        "tr" => (&["tr", "td", "th"], &["table", "thead", "tbody", "tfoot"]),
+        "td" | "th" => (&["td", "th"], &["tr", "table"]),
+        "thead" | "tbody" | "tfoot" => (&["thead", "tbody", "tfoot", "tr", "td", "th"], &["table"]),
+        "option" => (&["option"], &["select", "datalist", "optgroup"]),
+        _ => return,
+    };
+    // The outermost sibling inside the innermost container, so that `<tr>`
+    // closes the open row rather than just its last cell
+    let inside = stack
+        .iter()
+        .rposition(|el| containers.contains(&el.name.as_str()))
+        .map_or(0, |i| i + 1);
+    let open_sibling = stack[inside..]
+        .iter()
+        .position(|el| siblings.contains(&el.name.as_str()))
+        .map(|i| inside + i);
+    if let Some(i) = open_sibling {
+        let end = stack.last().map_or(0, |el| el.end.max(el.start));
+        while stack.len() > i {
+            close_top(stack, end);
+        }
+    }
+}
+
+/// Close the innermost open `name` element and everything inside it; a
+/// stray end tag is ignored.
+fn close(stack: &mut Vec<Element>, name: &str, end: usize) {
+    if let Some(i) = stack.iter().skip(1).rposition(|el| el.name == name) {
+        while stack.len() > i + 1 {
+            close_top(stack, end);
+        }
+    }
+}
+
+fn close_top(stack: &mut Vec<Element>, end: usize) {
+    if stack.len() < 2 {
+        return;
+    }
+    if let Some(mut el) = stack.pop() {
+        el.end = end.max(el.start);
+        if let Some(parent) = stack.last_mut() {
+            parent.children.push(Node::Element(el));
+        }
+    }
+}
+
+/// Name of a tag starting at `text`.
+fn tag_name(text: &str) -> &str {
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == ':'))
+        .unwrap_or(text.len());
+    &text[..end]
+}
+
+/// Parse a start tag at `pos`, returning the element, whether it is
+/// self-closing, and the offset past the tag.
+fn start_tag(html: &str, lower: &str, pos: usize) -> (Element, bool, usize) {
+    let name = tag_name(&lower[pos + 1..]);
+    let mut i = pos + 1 + name.len();
+    let bytes = html.as_bytes();
+    let mut attrs = Vec::new();
+    let mut self_closing = false;
+
+    while i < html.len() {
+        match bytes[i] {
+            b'>' => {
+                i += 1;
+                break;
+            }
+            b'/' => {
+                self_closing = bytes.get(i + 1) == Some(&b'>');
+                i += 1;
+            }
+            c if c.is_ascii_whitespace() => i += 1,
+            _ => {
+                let name_end = html[i..]
+                    .find(|c: char| c.is_ascii_whitespace() || matches!(c, '=' | '>' | '/'))
+                    .map_or(html.len(), |end| i + end);
+                let attr_name = lower[i..name_end].to_string();
+                i = name_end;
+                while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+                    i += 1;
+                }
+
+                let mut value = String::new();
+                if bytes.get(i) == Some(&b'=') {
+                    i += 1;
+                    while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+                        i += 1;
+                    }
+                    let value_end = match bytes.get(i) {
+                        Some(&quote @ (b'"' | b'\'')) => {
+                            i += 1;
+                            let end = html[i..].find(quote as char).map_or(html.len(), |end| i + end);
+                            value = decode_entities(&html[i..end]);
+                            (end + 1).min(html.len())
+                        }
+                        _ => {
+                            let end = html[i..]
+                                .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                                .map_or(html.len(), |end| i + end);
+                            value = decode_entities(&html[i..end]);
+                            end
+                        }
+                    };
+                    i = value_end;
+                }
+                attrs.push((attr_name, value));
+            }
+        }
+    }
+
+    (Element::new(name, attrs, pos), self_closing, i.min(html.len()))
+}
+
+/// Decode character references.
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let entity = rest[1..].find(';').filter(|&end| end > 0 && end <= 10).and_then(|end| {
+            let name = &rest[1..=end];
+            let c = match name {
+                "amp" | "AMP" => Some('&'),
+                "lt" | "LT" => Some('<'),
+                "gt" | "GT" => Some('>'),
+                "quot" | "QUOT" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                "ndash" => Some('–'),
+                "mdash" => Some('—'),
+                "hellip" => Some('…'),
+                "lsquo" => Some('‘'),
+                "rsquo" => Some('’'),
+                "ldquo" => Some('“'),
+                "rdquo" => Some('”'),
+                "laquo" => Some('«'),
+                "raquo" => Some('»'),
+                "copy" => Some('©'),
+                "reg" => Some('®'),
+                "trade" => Some('™'),
+                _ => match name.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => numeric_reference(&hex[1..], 16),
+                    Some(decimal) => numeric_reference(decimal, 10),
+                    None => None,
+                },
+            };
+            c.map(|c| (c, end + 2))
+        });
+
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Character of a numeric reference; NUL, surrogates and values beyond
+/// Unicode become U+FFFD.
+fn numeric_reference(digits: &str, radix: u32) -> Option<char> {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let c = u32::from_str_radix(digits, radix).ok().and_then(char::from_u32).filter(|&c| c != '\0');
+    Some(c.unwrap_or(char::REPLACEMENT_CHARACTER))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+  <title>Install guide &ndash; Demo</title>
+  <link rel="canonical" href="https://example.com/install">
+  <script>var tracking = "<p>not content</p>";</script>
+  <style>body { color: red; }</style>
+</head>
+<body>
+  <nav class="navbar"><a href="/">Home</a> <a href="/docs">Docs</a></nav>
+  <div class="cookie-banner">We use cookies.</div>
+  <div class="page">
+    <div class="sidebar"><ul><li><a href="/a">Other page</a></li></ul></div>
+    <div class="content">
+      <h1>Install</h1>
+      <p>Download the <code>demo</code> binary<br>and put it on your path.</p>
+      <h2>Linux</h2>
+      <ul>
+        <li>Debian &amp; Ubuntu
+        <li>Fedora
+          <ol><li>Enable the repo</li><li>Install it</li></ol>
+      </ul>
+      <pre><code class="language-sh">curl -O https://example.com/demo
+chmod +x demo</code></pre>
+      <table>
+        <tr><th>OS</th><th>Package</th></tr>
+        <tr><td>Linux</td><td>demo.tar.gz</td></tr>
+      </table>
+    </div>
+  </div>
+  <footer>Copyright 2024</footer>
+</body>
+</html>
+"#;
+
+    #[test]
+    fn test_extracts_main_content_as_markdown() {
+        let page = HtmlPage::parse(PAGE);
+        assert_eq!(page.title.as_deref(), Some("Install guide – Demo"));
+        assert_eq!(page.canonical_url.as_deref(), Some("https://example.com/install"));
+        assert_eq!(
+            page.markdown,
+            "# Install\n\n\
+             Download the `demo` binary\nand put it on your path.\n\n\
+             ## Linux\n\n\
+             - Debian & Ubuntu\n- Fedora\n  1. Enable the repo\n  2. Install it\n\n\
+             ```sh\ncurl -O https://example.com/demo\nchmod +x demo\n```\n\n\
+             | OS | Package |\n| --- | --- |\n| Linux | demo.tar.gz |"
+        );
+    }
+
+    #[test]
+    fn test_chunks_by_section_with_source_lines() {
+        let chunker = HtmlChunker::with_token_counter(|s: &str| s.split_whitespace().count());
+        let config = ChunkConfig {
+            max_tokens: 40,
+            min_tokens: 1,
+            overlap_tokens: 0,
+        };
+        let chunks = chunker.chunk(PAGE, ContentType::Html, &config).unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].content.starts_with("# Install"));
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (15, 16));
+        assert_eq!(chunks[1].heading_path.as_deref(), Some("Install > Linux"));
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (17, 27));
+        assert!(chunks.iter().all(|c| !c.content.contains("cookies") && !c.content.contains("Home")));
+    }
+
+    #[test]
+    fn test_main_element_and_fragments() {
+        let page = HtmlPage::parse("<header><h1>Site</h1></header><main><p>Body text</p></main>");
+        assert_eq!(page.markdown, "Body text");
+
+        // Unclosed tags, stray end tags and a bare `<`
+        let page = HtmlPage::parse("<p>a < b</span><p>second &#x41;&#66; &bogus;");
+        assert_eq!(page.markdown, "a < b\n\nsecond AB &bogus;");
+        assert_eq!(page.title, None);
+    }
+
+    #[test]
+    fn test_recovers_from_unclosed_and_misnested_tags() {
+        let page = HtmlPage::parse("<main><p>one <b>bold <i>both</b> italic</i> two<p>three</main>");
+        assert_eq!(page.markdown, "one bold both italic two\n\nthree");
+
+        // Items, rows and cells close their open siblings
+        let page = HtmlPage::parse("<ul><li>a<li>b<ul><li>c</ul><li>d</ul><table><tr><td>1<td>2<tr><td>3</table>");
+        assert_eq!(page.markdown, "- a\n- b\n  - c\n- d\n\n| 1 | 2 |\n| --- | --- |\n| 3 |  |");
+
+        // An unclosed heading keeps what follows, as in browsers
+        assert_eq!(HtmlPage::parse("<h1>Title<p>Body").markdown, "# Title Body");
+    }
+
+    #[test]
+    fn test_script_and_style_content() {
+        let page = HtmlPage::parse(
+            "<p>x</p><script>if (a </b) { document.write('</p><p>gone'); }</script>\
+             <style>p::after { content: '</style' }</style><p>y</p>",
+        );
+        assert_eq!(page.markdown, "x\n\ny");
+
+        let page = HtmlPage::parse("<p>x</p><script>var s = '</scripts>';</SCRIPT ><p>y</p>");
+        assert_eq!(page.markdown, "x\n\ny");
+    }
+
+    #[test]
+    fn test_character_references() {
+        let page = HtmlPage::parse("<p>&amp; &lt;&gt; &quot; &#65;&#x42;&#X43; &AMP; caf&eacute;</p>");
+        assert_eq!(page.markdown, "& <> \" ABC & caf&eacute;");
+
+        // Invalid numeric references become U+FFFD, malformed ones stay as text
+        let page = HtmlPage::parse("<p>&#0; &#xD800; &#1114112; &#; &#x; &#xZ; &amp &bogus;</p>");
+        assert_eq!(page.markdown, "\u{FFFD} \u{FFFD} \u{FFFD} &#; &#x; &#xZ; &amp &bogus;");
+
+        let page = HtmlPage::parse(r#"<link rel="canonical" href="/a?x=1&amp;y=2"><p>text</p>"#);
+        assert_eq!(page.canonical_url.as_deref(), Some("/a?x=1&y=2"));
+    }
+
+    #[test]
+    fn test_comments_and_cdata() {
+        let page = HtmlPage::parse("<p>a<!-- <p>hidden</p> --> b <!-- unterminated <p>gone</p>");
+        assert_eq!(page.markdown, "a b");
+
+        let page = HtmlPage::parse("<p>a <![CDATA[ x < y &amp; z ]]> b</p>");
+        assert_eq!(page.markdown, "a x < y &amp; z b");
+    }
+
+    #[test]
+    fn test_unquoted_attributes() {
+        let page = HtmlPage::parse(
+            "<title>T</title><link rel=canonical href=https://example.com/a?b=1>\
+             <main><div class=sidebar>menu</div>\
+             <p title=\"a > b\" data-x='1'>quoted</p><img alt=x/><p>after</p></main>",
+        );
+        assert_eq!(page.title.as_deref(), Some("T"));
+        assert_eq!(page.canonical_url.as_deref(), Some("https://example.com/a?b=1"));
+        assert_eq!(page.markdown, "quoted\n\nafter");
+    }
+
+    #[test]
+    fn test_legacy_charset() {
+        // Pages in a legacy charset are decoded before parsing
+        let bytes = b"<meta charset=windows-1252><title>Caf\xe9</title><p>\x93quoted\x94 na\xefve</p>";
+        let decoded = rag_core::decode_text(bytes).unwrap();
+        assert_eq!(decoded.encoding, rag_core::Encoding::Latin1);
+
+        let page = HtmlPage::parse(&decoded.text);
+        assert_eq!(page.title.as_deref(), Some("Café"));
+        assert_eq!(page.markdown, "\u{201C}quoted\u{201D} naïve");
+    }
+
+    #[test]
+    fn test_chunk_with_metadata() {
+        let chunked = HtmlChunker::new()
+            .chunk_with_metadata(PAGE, ContentType::Html, &ChunkConfig::default())
+            .unwrap();
+        assert_eq!(chunked.metadata["title"], "Install guide – Demo");
+        assert_eq!(chunked.metadata["canonical_url"], "https://example.com/install");
+        let chunks = HtmlChunker::new().chunk(PAGE, ContentType::Html, &ChunkConfig::default()).unwrap();
+        assert_eq!(chunked.chunks.len(), chunks.len());
+    }
+}
@@ -8,9 +8,30 @@
 //! - [`RecursiveChunker`]: Recursively splits text using progressively smaller
 //!   separators (paragraphs, lines, sentences, words).
 //!
+//! - [`AstChunker`]: Splits source code at syntax tree item boundaries
+//!   (functions, impls, classes) using tree-sitter.
+//!
+//! - [`MarkdownChunker`]: Splits Markdown by section, recording the heading
+//!   path of each chunk.
+//!
+//! - [`SemanticChunker`]: Splits prose where the topic shifts, judged by
+//!   the similarity of sentence embeddings.
+//!
+//! - [`StructuredChunker`]: Splits JSON, YAML and TOML at object and array
+//!   boundaries, recording the key path of each chunk.
+//!
+//! - [`HtmlChunker`]: Extracts the main content of HTML pages as Markdown
+//!   and splits it by section.
+//!
+//! - [`ChatChunker`]: Splits conversation transcripts into overlapping
+//!   windows of whole turns, recording their speakers and time range.
+//!
 //! - [`AdaptiveChunker`]: Automatically selects the best chunking strategy
 //!   based on content type.
 //!
+//! [`ChunkerRegistry`] picks a chunker for each document by glob, MIME
+//! type, collection or content type, and lets applications add their own.
+//!
 //! # Example
 //!
 //! ```rust
@@ -23,10 +44,33 @@
 //! ```
 
 mod adaptive;
+mod ast;
+mod chat;
+mod hierarchy;
+mod html;
+mod lines;
+mod markdown;
+mod merge;
 mod recursive;
+mod registry;
+mod semantic;
+mod structured;
+#[cfg(test)]
+mod testing;
 
 pub use adaptive::AdaptiveChunker;
+pub use ast::AstChunker;
+pub use chat::{ChatChunker, ChatMessage};
+pub use html::{HtmlChunker, HtmlPage};
+pub use lines::byte_ranges;
+pub use markdown::MarkdownChunker;
 pub use recursive::RecursiveChunker;
+pub use registry::{ChunkerRegistry, SEMANTIC_CHUNKER};
+pub use semantic::SemanticChunker;
+pub use structured::StructuredChunker;
 
 // Re-export types for convenience
 pub use rag_core::{ChunkConfig, ChunkData, Chunker, ContentType};
+
+/// Custom token counter of a chunker.
+pub type TokenCounter = Box<dyn Fn(&str) -> usize + Send + Sync>;
@@ -0,0 +1,256 @@
+//! Line index shared by the structure-aware chunkers.
+
+use std::ops::Range;
+
+use rag_core::{ChunkConfig, ChunkData, Chunker, ContentType};
+
+use crate::RecursiveChunker;
+
+/// Line index of a source text.
+pub(crate) struct Lines<'a> {
+    source: &'a str,
+
+    /// Byte offset of the start of each line.
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    pub(crate) fn new(source: &'a str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Self { source, starts }
+    }
+
+    /// Number of lines.
+    pub(crate) fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Line (0-based) containing a byte offset.
+    pub(crate) fn row(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    /// Text of lines `start..=end`, without the final newline.
+    pub(crate) fn text(&self, start: usize, end: usize) -> &'a str {
+        &self.source[self.range(start, end)]
+    }
+
+    /// Chunk of lines `start..=end`, without the final newline.
+    pub(crate) fn chunk(&self, start: usize, end: usize, token_count: usize) -> ChunkData {
+        let mut chunk = self.chunk_bytes(self.range(start, end), token_count);
+        // The last line may be empty
+        chunk.end_line = end as u32 + 1;
+        chunk.end_column = self.column(end, chunk.end_byte);
+        chunk
+    }
+
+    /// Chunk of the bytes `range`.
+    pub(crate) fn chunk_bytes(&self, range: Range<usize>, token_count: usize) -> ChunkData {
+        let mut chunk = ChunkData {
+            content: self.source[range.clone()].to_string(),
+            token_count,
+            start_line: 0,
+            end_line: 0,
+            start_byte: 0,
+            end_byte: 0,
+            start_column: 0,
+            end_column: 0,
+            heading_path: None,
+            speakers: Vec::new(),
+            time_range: None,
+            parent: None,
+            level: 0,
+        };
+        self.locate(&mut chunk, range);
+        chunk
+    }
+
+    /// Move a chunk of the text at byte `offset` of the source to its place
+    /// in the source.
+    pub(crate) fn shift(&self, mut chunk: ChunkData, offset: usize) -> ChunkData {
+        let range = chunk.start_byte + offset..chunk.end_byte + offset;
+        self.locate(&mut chunk, range);
+        chunk
+    }
+
+    /// Split line `row`, too large for one chunk, as text with `recursive`,
+    /// placing the pieces on that line.
+    pub(crate) fn split_line(&self, row: usize, recursive: &RecursiveChunker, config: &ChunkConfig) -> Vec<ChunkData> {
+        let range = self.range(row, row);
+        recursive
+            .chunk(&self.source[range.clone()], ContentType::PlainText, config)
+            .map(|parts| parts.into_iter().map(|part| self.shift(part, range.start)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Merge consecutive runs of lines `(start, end, path)` up to
+    /// `max_tokens` and emit them as chunks.
+    ///
+    /// Runs sharing a line are always merged, since chunks are whole lines.
+    /// `join` combines the paths of a merged run and the next one, and
+    /// `heading_path` renders the path of a chunk.
+    pub(crate) fn merge<P>(
+        &self,
+        runs: impl IntoIterator<Item = (usize, usize, P)>,
+        join: impl Fn(P, P) -> P,
+        heading_path: impl Fn(&P) -> Option<String>,
+        recursive: &RecursiveChunker,
+        config: &ChunkConfig,
+        chunks: &mut Vec<ChunkData>,
+    ) {
+        let mut current: Option<(usize, usize, P)> = None;
+
+        for (start, end, path) in runs {
+            current = match current.take() {
+                Some((first, last, merged))
+                    if start <= last || recursive.count_tokens(self.text(first, end)) <= config.max_tokens =>
+                {
+                    Some((first, last.max(end), join(merged, path)))
+                }
+                previous => {
+                    if let Some((first, last, merged)) = previous {
+                        self.emit(first, last, heading_path(&merged).as_deref(), recursive, config, chunks);
+                    }
+                    Some((start, end, path))
+                }
+            };
+        }
+
+        if let Some((first, last, merged)) = current {
+            self.emit(first, last, heading_path(&merged).as_deref(), recursive, config, chunks);
+        }
+    }
+
+    /// Emit lines `start..=end` as one chunk, or split them on line
+    /// boundaries if they are still too large.
+    pub(crate) fn emit(
+        &self,
+        start: usize,
+        end: usize,
+        path: Option<&str>,
+        recursive: &RecursiveChunker,
+        config: &ChunkConfig,
+        chunks: &mut Vec<ChunkData>,
+    ) {
+        let text = self.text(start, end);
+        let token_count = recursive.count_tokens(text);
+
+        if text.trim().is_empty() {
+            return;
+        }
+
+        if token_count <= config.max_tokens {
+            chunks.push(ChunkData {
+                heading_path: path.map(String::from),
+                ..self.chunk(start, end, token_count)
+            });
+            return;
+        }
+
+        if start == end {
+            // A single oversized line is split as text
+            chunks.extend(self.split_line(start, recursive, config).into_iter().map(|part| ChunkData {
+                heading_path: path.map(String::from),
+                ..part
+            }));
+            return;
+        }
+
+        let mut first = start;
+        for row in start + 1..=end {
+            if recursive.count_tokens(self.text(first, row)) > config.max_tokens {
+                self.emit(first, row - 1, path, recursive, config, chunks);
+                first = row;
+            }
+        }
+        self.emit(first, end, path, recursive, config, chunks);
+    }
+
+    /// Emit the lines outside of any chunk, without their leading and
+    /// trailing blank lines, and sort the chunks by line.
+    pub(crate) fn fill_gaps(&self, chunks: &mut Vec<ChunkData>, recursive: &RecursiveChunker, config: &ChunkConfig) {
+        let mut gaps = Vec::new();
+        let mut next_row = 0;
+        for chunk in chunks.iter() {
+            let start_row = chunk.start_line as usize - 1;
+            if start_row > next_row {
+                gaps.push((next_row, start_row - 1));
+            }
+            next_row = next_row.max(chunk.end_line as usize);
+        }
+        if next_row < self.len() {
+            gaps.push((next_row, self.len() - 1));
+        }
+        for (mut start, mut end) in gaps {
+            while start < end && self.text(start, start).trim().is_empty() {
+                start += 1;
+            }
+            while end > start && self.text(end, end).trim().is_empty() {
+                end -= 1;
+            }
+            self.emit(start, end, None, recursive, config, chunks);
+        }
+        chunks.sort_by_key(|chunk| chunk.start_line);
+    }
+
+    /// Place a chunk at the bytes `range`, ending on the line of its last
+    /// byte.
+    pub(crate) fn locate(&self, chunk: &mut ChunkData, range: Range<usize>) {
+        let start = self.row(range.start);
+        let end = self.row(range.end.saturating_sub(1).max(range.start));
+        chunk.start_line = start as u32 + 1;
+        chunk.end_line = end as u32 + 1;
+        chunk.start_byte = range.start;
+        chunk.end_byte = range.end;
+        chunk.start_column = self.column(start, range.start);
+        chunk.end_column = self.column(end, range.end);
+    }
+
+    /// Column (1-based) of a byte offset on a line.
+    fn column(&self, row: usize, offset: usize) -> u32 {
+        (offset - self.starts[row]) as u32 + 1
+    }
+
+    /// Byte range of lines `start..=end`, without the final newline.
+    pub(crate) fn range(&self, start: usize, end: usize) -> Range<usize> {
+        let from = self.starts[start];
+        let to = self.starts.get(end + 1).map_or(self.source.len(), |next| next - 1);
+        let to = to.max(from);
+        if self.source[from..to].ends_with('\r') {
+            from..to - 1
+        } else {
+            from..to
+        }
+    }
+}
+
+/// Byte ranges of chunks in the text they were chunked from.
+///
+/// A chunk whose `start_byte..end_byte` holds its content keeps that range.
+/// Other chunks, such as those stored without byte offsets, are looked up
+/// within their lines, so the pieces of a split line get their own range; a
+/// chunk not found there covers its whole lines.
+pub fn byte_ranges(source: &str, chunks: &[ChunkData]) -> Vec<Range<usize>> {
+    let lines = Lines::new(source);
+    let last = lines.len() - 1;
+
+    chunks
+        .iter()
+        .map(|chunk| {
+            let range = chunk.start_byte..chunk.end_byte;
+            if !range.is_empty() && source.get(range.clone()) == Some(chunk.content.as_str()) {
+                return range;
+            }
+            let start = (chunk.start_line as usize).saturating_sub(1).min(last);
+            let end = (chunk.end_line as usize).saturating_sub(1).clamp(start, last);
+            let range = lines.range(start, end);
+            match source[range.clone()].find(chunk.content.as_str()) {
+                Some(offset) if !chunk.content.is_empty() => {
+                    range.start + offset..range.start + offset + chunk.content.len()
+                }
+                _ => range,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,521 @@
+//! Heading-aware chunker for Markdown.
+//!
+//! Parses Markdown with pulldown-cmark and chunks it along the section
+//! hierarchy, recording the headings each chunk belongs to.
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+
+use rag_core::{ChunkConfig, ChunkData, Chunker, ContentType, Result};
+
+use crate::hierarchy::{build_hierarchy, Span};
+use crate::lines::Lines;
+use crate::merge::merge_undersized;
+use crate::RecursiveChunker;
+
+/// Separator between the headings of a heading path.
+pub(crate) const PATH_SEPARATOR: &str = " > ";
+
+/// Chunker that splits Markdown by section.
+///
+/// - Blocks of a section are merged up to `max_tokens`; a chunk never spans
+///   two sections unless it would otherwise be smaller than `min_tokens`.
+/// - Each chunk records its heading path (e.g. `Install > Linux`).
+/// - Fenced code blocks, tables and list items are never split, even if
+///   they exceed `max_tokens`. A list that is too large is split between
+///   its items.
+/// - Oversized paragraphs are split on line boundaries, and single lines
+///   that are still too large with [`RecursiveChunker`].
+/// - Chunks always consist of whole lines, with exact line numbers.
+///
+/// With [`with_hierarchy`](Self::with_hierarchy), the document, its
+/// sections and split paragraphs or lists are emitted as well, as parents of
+/// the chunks inside them.
+pub struct MarkdownChunker {
+    /// Fallback for oversized lines; also counts tokens.
+    recursive: RecursiveChunker,
+
+    /// Emit parent chunks for the document, sections and split blocks.
+    hierarchical: bool,
+}
+
+/// A top-level Markdown block.
+struct Block {
+    /// First line (0-based).
+    start_row: usize,
+
+    /// Last line (0-based, inclusive).
+    end_row: usize,
+
+    kind: BlockKind,
+}
+
+enum BlockKind {
+    /// A heading with its level and title.
+    Heading(HeadingLevel, String),
+
+    /// A list with the line ranges of its items.
+    List(Vec<(usize, usize)>),
+
+    /// A code block, table or HTML block, which must stay whole.
+    Atomic,
+
+    /// A paragraph, block quote or rule, which may be split on lines.
+    Text,
+}
+
+/// A run of lines that becomes a chunk or part of one.
+struct Unit {
+    start_row: usize,
+    end_row: usize,
+
+    /// Whether the unit is a heading, which starts a new section.
+    heading: bool,
+
+    /// Whether the unit may be split on line boundaries.
+    splittable: bool,
+}
+
+/// A chunk being built from consecutive units.
+struct Pending {
+    start_row: usize,
+    end_row: usize,
+    path: Vec<String>,
+
+    /// Whether the chunk is only a heading so far.
+    heading_only: bool,
+}
+
+impl MarkdownChunker {
+    /// Create a new Markdown chunker with default token estimation.
+    pub fn new() -> Self {
+        Self {
+            recursive: RecursiveChunker::new(),
+            hierarchical: false,
+        }
+    }
+
+    /// Create a chunker with a custom token counter.
+    pub fn with_token_counter<F>(counter: F) -> Self
+    where
+        F: Fn(&str) -> usize + Send + Sync + 'static,
+    {
+        Self {
+            recursive: RecursiveChunker::with_token_counter(counter),
+            hierarchical: false,
+        }
+    }
+
+    /// Enable or disable parent chunks for the document, sections and split
+    /// blocks.
+    pub fn with_hierarchy(mut self, hierarchical: bool) -> Self {
+        self.hierarchical = hierarchical;
+        self
+    }
+
+    /// Parse the top-level blocks of a document.
+    fn blocks(content: &str, lines: &Lines<'_>) -> Vec<Block> {
+        let options = Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS;
+
+        let rows = |range: std::ops::Range<usize>| {
+            let mut start_row = lines.row(range.start);
+            let mut end_row = lines.row(range.end.saturating_sub(1).max(range.start));
+            // Surrounding blank lines belong to no block
+            while end_row > start_row && lines.text(end_row, end_row).trim().is_empty() {
+                end_row -= 1;
+            }
+            while start_row < end_row && lines.text(start_row, start_row).trim().is_empty() {
+                start_row += 1;
+            }
+            (start_row, end_row)
+        };
+
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut depth = 0usize;
+
+        for (event, range) in Parser::new_ext(content, options).into_offset_iter() {
+            match event {
+                Event::Start(tag) => {
+                    if depth == 0 {
+                        let kind = match tag {
+                            Tag::Heading { level, .. } => BlockKind::Heading(level, String::new()),
+                            Tag::List(_) => BlockKind::List(Vec::new()),
+                            Tag::CodeBlock(_) | Tag::Table(_) | Tag::HtmlBlock => BlockKind::Atomic,
+                            _ => BlockKind::Text,
+                        };
+                        let (start_row, end_row) = rows(range);
+                        blocks.push(Block { start_row, end_row, kind });
+                    } else if depth == 1 && matches!(tag, Tag::Item) {
+                        if let Some(Block { kind: BlockKind::List(items), .. }) = blocks.last_mut() {
+                            items.push(rows(range));
+                        }
+                    }
+                    depth += 1;
+                }
+                Event::End(_) => depth = depth.saturating_sub(1),
+                Event::Text(text) | Event::Code(text) if depth > 0 => {
+                    if let Some(Block { kind: BlockKind::Heading(_, title), .. }) = blocks.last_mut() {
+                        title.push_str(&text);
+                    }
+                }
+                _ if depth == 0 => {
+                    let (start_row, end_row) = rows(range);
+                    blocks.push(Block { start_row, end_row, kind: BlockKind::Text });
+                }
+                _ => {}
+            }
+        }
+
+        blocks
+    }
+
+    /// Sections of a document: each heading up to the next heading of the
+    /// same or a higher level.
+    fn sections(blocks: &[Block]) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let mut open: Vec<(HeadingLevel, usize, Vec<String>)> = Vec::new();
+        let mut last_row = 0;
+
+        let mut close = |(_, start_row, path): (HeadingLevel, usize, Vec<String>), end_row| {
+            spans.push(Span {
+                start_row,
+                end_row,
+                heading_path: Self::heading_path(&path),
+            })
+        };
+
+        for block in blocks {
+            if let BlockKind::Heading(level, title) = &block.kind {
+                while open.last().is_some_and(|(outer, ..)| outer >= level) {
+                    if let Some(section) = open.pop() {
+                        close(section, last_row);
+                    }
+                }
+                let mut path = open.last().map(|(_, _, path)| path.clone()).unwrap_or_default();
+                path.push(title.clone());
+                open.push((*level, block.start_row, path));
+            }
+            last_row = block.end_row;
+        }
+        for section in open {
+            close(section, last_row);
+        }
+
+        spans
+    }
+
+    /// Units of a block: the block itself, or the items of a list that
+    /// does not fit.
+    fn units(&self, block: &Block, lines: &Lines<'_>, config: &ChunkConfig) -> Vec<Unit> {
+        let unit = |start_row, end_row, splittable| Unit {
+            start_row,
+            end_row,
+            heading: false,
+            splittable,
+        };
+
+        match &block.kind {
+            BlockKind::Heading(..) => vec![Unit {
+                heading: true,
+                ..unit(block.start_row, block.end_row, false)
+            }],
+            BlockKind::List(items)
+                if !items.is_empty()
+                    && self.recursive.count_tokens(lines.text(block.start_row, block.end_row)) > config.max_tokens =>
+            {
+                items.iter().map(|&(start, end)| unit(start, end, false)).collect()
+            }
+            BlockKind::List(_) | BlockKind::Atomic => vec![unit(block.start_row, block.end_row, false)],
+            BlockKind::Text => vec![unit(block.start_row, block.end_row, true)],
+        }
+    }
+
+    /// Push a finished chunk.
+    fn flush(&self, pending: Pending, lines: &Lines<'_>, chunks: &mut Vec<ChunkData>) {
+        let token_count = self.recursive.count_tokens(lines.text(pending.start_row, pending.end_row));
+        chunks.push(ChunkData {
+            heading_path: Self::heading_path(&pending.path),
+            ..lines.chunk(pending.start_row, pending.end_row, token_count)
+        });
+    }
+
+    /// Join headings into a path, skipping empty ones.
+    fn heading_path(path: &[String]) -> Option<String> {
+        let headings: Vec<&str> = path
+            .iter()
+            .map(|heading| heading.trim())
+            .filter(|heading| !heading.is_empty())
+            .collect();
+
+        if headings.is_empty() {
+            None
+        } else {
+            Some(headings.join(PATH_SEPARATOR))
+        }
+    }
+}
+
+impl Default for MarkdownChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunker for MarkdownChunker {
+    fn chunk(
+        &self,
+        content: &str,
+        _content_type: ContentType,
+        config: &ChunkConfig,
+    ) -> Result<Vec<ChunkData>> {
+        let lines = Lines::new(content);
+        let mut chunks = Vec::new();
+        let mut headings: Vec<(HeadingLevel, String)> = Vec::new();
+        let mut pending: Option<Pending> = None;
+
+        let blocks = Self::blocks(content, &lines);
+        let mut spans = Self::sections(&blocks);
+
+        for block in blocks {
+            if let BlockKind::Heading(level, title) = &block.kind {
+                while headings.last().is_some_and(|(open, _)| open >= level) {
+                    headings.pop();
+                }
+                headings.push((*level, title.clone()));
+            }
+            let path: Vec<String> = headings.iter().map(|(_, title)| title.clone()).collect();
+
+            let units = self.units(&block, &lines, config);
+            if units.len() > 1 {
+                spans.push(Span {
+                    start_row: block.start_row,
+                    end_row: block.end_row,
+                    heading_path: Self::heading_path(&path),
+                });
+            }
+
+            for unit in units {
+                if let Some(current) = pending.as_mut() {
+                    let same_section = !unit.heading && current.path == path;
+                    let small = current.heading_only
+                        || self.recursive.count_tokens(lines.text(current.start_row, current.end_row))
+                            < config.min_tokens;
+                    let fits = self.recursive.count_tokens(lines.text(current.start_row, unit.end_row))
+                        <= config.max_tokens;
+
+                    if (same_section || small) && fits {
+                        if current.heading_only {
+                            // A heading joins the section below it
+                            current.path = path.clone();
+                        } else {
+                            let common = current.path.iter().zip(&path).take_while(|(a, b)| a == b).count();
+                            current.path.truncate(common);
+                        }
+                        current.end_row = unit.end_row;
+                        current.heading_only &= unit.heading;
+                        continue;
+                    }
+                }
+
+                // A heading stays with the content below it, even if that
+                // content is too large to share a chunk with
+                let mut start_row = unit.start_row;
+                match pending.take() {
+                    Some(done) if done.heading_only && !unit.heading => start_row = done.start_row,
+                    Some(done) => self.flush(done, &lines, &mut chunks),
+                    None => {}
+                }
+
+                let oversized = self.recursive.count_tokens(lines.text(start_row, unit.end_row)) > config.max_tokens;
+                if unit.splittable && oversized {
+                    spans.push(Span {
+                        start_row,
+                        end_row: unit.end_row,
+                        heading_path: Self::heading_path(&path),
+                    });
+                    let heading_path = Self::heading_path(&path);
+                    lines.emit(start_row, unit.end_row, heading_path.as_deref(), &self.recursive, config, &mut chunks);
+                } else {
+                    pending = Some(Pending {
+                        start_row,
+                        end_row: unit.end_row,
+                        path: path.clone(),
+                        heading_only: unit.heading,
+                    });
+                }
+            }
+        }
+
+        if let Some(done) = pending {
+            self.flush(done, &lines, &mut chunks);
+        }
+
+        let chunks = merge_undersized(chunks, &lines, config, |text| self.recursive.count_tokens(text));
+        if self.hierarchical {
+            return Ok(build_hierarchy(chunks, spans, &lines, |text| self.recursive.count_tokens(text)));
+        }
+
+        Ok(chunks)
+    }
+
+    fn supported_types(&self) -> Vec<ContentType> {
+        vec![ContentType::Markdown]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::config;
+
+    const README: &str = "\
+Intro paragraph.
+
+# Install
+
+Download the release.
+
+## Linux
+
+```sh
+tar xzf rag.tar.gz
+
+./install.sh
+```
+
+### Troubleshooting
+
+| Error | Fix |
+|-------|-----|
+| EPERM | sudo |
+
+## macOS
+
+- brew install rag
+- rag --version
+";
+
+    fn words() -> MarkdownChunker {
+        MarkdownChunker::with_token_counter(|s| s.split_whitespace().count())
+    }
+
+    #[test]
+    fn test_heading_paths() {
+        let chunks = words().chunk(README, ContentType::Markdown, &config(14)).unwrap();
+
+        let paths: Vec<_> = chunks.iter().map(|c| c.heading_path.as_deref()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                None,
+                Some("Install"),
+                Some("Install > Linux"),
+                Some("Install > Linux > Troubleshooting"),
+                Some("Install > macOS"),
+            ]
+        );
+
+        let troubleshooting = &chunks[3];
+        assert!(troubleshooting.content.starts_with("### Troubleshooting\n\n| Error"));
+        assert_eq!((troubleshooting.start_line, troubleshooting.end_line), (15, 19));
+    }
+
+    #[test]
+    fn test_code_blocks_and_tables_are_never_split() {
+        let chunks = words().chunk(README, ContentType::Markdown, &config(2)).unwrap();
+
+        // Headings stay with the block below them
+        let fence = "## Linux\n\n```sh\ntar xzf rag.tar.gz\n\n./install.sh\n```";
+        assert!(chunks.iter().any(|c| c.content == fence));
+        assert!(chunks.iter().any(|c| c.content.contains("\n| Error") && c.content.ends_with("| EPERM | sudo |")));
+
+        // The list is split between its items only
+        let items: Vec<_> = chunks.iter().filter(|c| c.content.contains("- ")).collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].content, "## macOS\n\n- brew install rag");
+        assert_eq!(items[1].content, "- rag --version");
+        assert!(items.iter().all(|c| c.heading_path.as_deref() == Some("Install > macOS")));
+    }
+
+    #[test]
+    fn test_chunks_are_exact_lines() {
+        let lines: Vec<&str> = README.lines().collect();
+
+        for max_tokens in [3, 5, 14, 100] {
+            let chunks = words().chunk(README, ContentType::Markdown, &config(max_tokens)).unwrap();
+            let mut last = 0;
+            for chunk in &chunks {
+                assert!(chunk.start_line as usize > last);
+                last = chunk.end_line as usize;
+                let expected = lines[chunk.start_line as usize - 1..chunk.end_line as usize].join("\n");
+                assert_eq!(chunk.content, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_small_sections_are_merged() {
+        // Sections below min_tokens join their neighbours, under the
+        // headings they share
+        let config = ChunkConfig {
+            max_tokens: 100,
+            min_tokens: 9,
+            overlap_tokens: 0,
+        };
+        let text = "# Install\n\n## Linux\n\nRun it.\n\n## macOS\n\nBrew it.\n";
+        let chunks = words().chunk(text, ContentType::Markdown, &config).unwrap();
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].heading_path.as_deref(), Some("Install"));
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 9));
+    }
+
+    #[test]
+    fn test_oversized_paragraph_is_split() {
+        let text = "# Notes\n\none two three\nfour five six\nseven eight nine\n";
+        let chunks = words().chunk(text, ContentType::Markdown, &config(6)).unwrap();
+
+        let ranges: Vec<_> = chunks.iter().map(|c| (c.start_line, c.end_line)).collect();
+        assert_eq!(ranges, vec![(1, 3), (4, 5)]);
+        assert!(chunks.iter().all(|c| c.heading_path.as_deref() == Some("Notes")));
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let chunks = words()
+            .with_hierarchy(true)
+            .chunk(README, ContentType::Markdown, &config(5))
+            .unwrap();
+
+        let tree: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.start_line, c.end_line, c.parent, c.level, c.heading_path.as_deref()))
+            .collect();
+        assert_eq!(
+            tree,
+            vec![
+                (1, 24, None, 0, None),
+                (1, 1, Some(0), 1, None),
+                (3, 24, Some(0), 1, Some("Install")),
+                (3, 5, Some(2), 2, Some("Install")),
+                (7, 19, Some(2), 2, Some("Install > Linux")),
+                (7, 13, Some(4), 3, Some("Install > Linux")),
+                (15, 19, Some(4), 3, Some("Install > Linux > Troubleshooting")),
+                (21, 24, Some(2), 2, Some("Install > macOS")),
+                (21, 23, Some(7), 3, Some("Install > macOS")),
+                (24, 24, Some(7), 3, Some("Install > macOS")),
+            ]
+        );
+
+        // The leaves are the chunks produced without a hierarchy
+        let leaves = words().chunk(README, ContentType::Markdown, &config(5)).unwrap();
+        let parents: Vec<_> = chunks.iter().filter_map(|c| c.parent).collect();
+        let hierarchy_leaves: Vec<_> = (0..chunks.len())
+            .filter(|i| !parents.contains(i))
+            .map(|i| (chunks[i].start_line, chunks[i].end_line))
+            .collect();
+        assert_eq!(hierarchy_leaves, leaves.iter().map(|c| (c.start_line, c.end_line)).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,64 @@
+//! Merging of undersized chunks into their neighbours.
+
+use rag_core::{ChunkConfig, ChunkData};
+
+use crate::lines::Lines;
+use crate::markdown::PATH_SEPARATOR;
+
+/// Merge chunks below `min_tokens` into an adjacent chunk, as long as the
+/// result stays within `max_tokens`.
+///
+/// Only chunks of whole lines are merged; the pieces of a split line are
+/// left as they are. A chunk that cannot be merged is kept, never dropped.
+pub(crate) fn merge_undersized<F>(
+    chunks: Vec<ChunkData>,
+    lines: &Lines<'_>,
+    config: &ChunkConfig,
+    count_tokens: F,
+) -> Vec<ChunkData>
+where
+    F: Fn(&str) -> usize,
+{
+    let whole_lines =
+        |chunk: &ChunkData| chunk.content == lines.text(chunk.start_line as usize - 1, chunk.end_line as usize - 1);
+
+    let mut merged: Vec<ChunkData> = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        if let Some(last) = merged.last_mut() {
+            let small = last.token_count < config.min_tokens || chunk.token_count < config.min_tokens;
+            if small && chunk.start_line > last.end_line && whole_lines(last) && whole_lines(&chunk) {
+                let text = lines.text(last.start_line as usize - 1, chunk.end_line as usize - 1);
+                let token_count = count_tokens(text);
+                if token_count <= config.max_tokens {
+                    *last = ChunkData {
+                        heading_path: common_heading_path(&last.heading_path, &chunk.heading_path),
+                        parent: last.parent,
+                        level: last.level,
+                        ..lines.chunk(last.start_line as usize - 1, chunk.end_line as usize - 1, token_count)
+                    };
+                    continue;
+                }
+            }
+        }
+        merged.push(chunk);
+    }
+
+    merged
+}
+
+/// Headings two heading paths share.
+fn common_heading_path(a: &Option<String>, b: &Option<String>) -> Option<String> {
+    let (a, b) = (a.as_deref()?, b.as_deref()?);
+    let common: Vec<&str> = a
+        .split(PATH_SEPARATOR)
+        .zip(b.split(PATH_SEPARATOR))
+        .take_while(|(a, b)| a == b)
+        .map(|(heading, _)| heading)
+        .collect();
+
+    if common.is_empty() {
+        None
+    } else {
+        Some(common.join(PATH_SEPARATOR))
+    }
+}
@@ -3,8 +3,13 @@
 //! Splits text by trying progressively smaller separators until chunks
 //! fit within the token limit.
 
+use std::ops::Range;
+
 use rag_core::{ChunkConfig, ChunkData, Chunker, ContentType, Result};
 
+use crate::lines::Lines;
+use crate::TokenCounter;
+
 /// Recursive chunker that splits text by multiple separators.
 ///
 /// Tries each separator in order until chunks are small enough:
@@ -13,10 +18,15 @@ use rag_core::{ChunkConfig, ChunkData, Chunker, ContentType, Result};
 /// 3. Sentence boundaries (. ! ?)
 /// 4. Word boundaries (space)
 /// 5. Character (last resort)
+///
+/// Pieces smaller than `min_tokens` are merged into a neighbour rather than
+/// dropped. With `overlap_tokens`, each chunk also starts with the last
+/// words of the previous one; the overlap counts toward `max_tokens`.
+/// Chunks are exact slices of the input, with exact line numbers.
 pub struct RecursiveChunker {
     /// Function to count tokens in text.
     /// Uses simple word count approximation if None.
-    token_counter: Option<Box<dyn Fn(&str) -> usize + Send + Sync>>,
+    token_counter: Option<TokenCounter>,
 }
 
 impl RecursiveChunker {
@@ -36,7 +46,7 @@ impl RecursiveChunker {
     }
 
     /// Count tokens in text.
-    fn count_tokens(&self, text: &str) -> usize {
+    pub(crate) fn count_tokens(&self, text: &str) -> usize {
         match &self.token_counter {
             Some(counter) => counter(text),
             // Simple approximation: ~4 chars per token on average
@@ -56,6 +66,12 @@ impl RecursiveChunker {
             | ContentType::Java
             | ContentType::Cpp
             | ContentType::C => vec!["\n\n", "\nfn ", "\ndef ", "\nfunc ", "\nclass ", "\nimpl ", "\n", " "],
+            ContentType::Kotlin | ContentType::CSharp => vec!["\n\n", "\nfun ", "\nclass ", "\n", " "],
+            ContentType::Ruby | ContentType::Shell | ContentType::Makefile | ContentType::Dockerfile => {
+                vec!["\n\n", "\n", " "]
+            }
+            ContentType::Sql => vec!["\n\n", ";\n", "\n", " "],
+            ContentType::Css => vec!["\n\n", "}\n", "\n", " "],
             ContentType::Json | ContentType::Yaml | ContentType::Toml => {
                 vec!["\n\n", "\n", ", ", " "]
             }
@@ -63,176 +79,155 @@ impl RecursiveChunker {
         }
     }
 
-    /// Split text by a separator.
-    fn split_by_separator<'a>(&self, text: &'a str, separator: &str) -> Vec<&'a str> {
-        if separator.is_empty() {
-            // Character-level split as last resort
-            return text
-                .char_indices()
-                .map(|(i, c)| &text[i..i + c.len_utf8()])
-                .collect();
-        }
-
-        text.split(separator)
-            .filter(|s| !s.is_empty())
-            .collect()
-    }
-
-    /// Recursively chunk text.
-    fn chunk_recursive(
+    /// Split `range` into pieces of at most `budget` tokens, trying each
+    /// separator in turn and merging adjacent pieces greedily.
+    fn split(
         &self,
         text: &str,
+        range: Range<usize>,
         separators: &[&str],
-        config: &ChunkConfig,
-        start_line: u32,
-    ) -> Vec<ChunkData> {
-        let tokens = self.count_tokens(text);
-
-        // If text fits in one chunk, return it
-        if tokens <= config.max_tokens {
-            return vec![ChunkData {
-                content: text.to_string(),
-                token_count: tokens,
-                start_line,
-                end_line: start_line + text.lines().count().saturating_sub(1) as u32,
-            }];
+        budget: usize,
+        pieces: &mut Vec<Range<usize>>,
+    ) {
+        if self.count_tokens(&text[range.clone()]) <= budget {
+            pieces.push(range);
+            return;
         }
 
-        // Try each separator
-        for (sep_idx, separator) in separators.iter().enumerate() {
-            let parts = self.split_by_separator(text, separator);
+        let Some((separator, rest)) = separators.split_first() else {
+            self.split_by_size(text, range, budget, pieces);
+            return;
+        };
 
-            if parts.len() <= 1 {
-                continue;
+        let parts = Self::split_by_separator(text, range.clone(), separator);
+        if parts.len() <= 1 {
+            self.split(text, range, rest, budget, pieces);
+            return;
+        }
+
+        let mut current: Option<Range<usize>> = None;
+        for part in parts {
+            if let Some(open) = current.take() {
+                if self.count_tokens(&text[open.start..part.end]) <= budget {
+                    current = Some(open.start..part.end);
+                    continue;
+                }
+                pieces.push(open);
             }
 
-            let mut chunks = Vec::new();
-            let mut current_chunk = String::new();
-            let mut chunk_start_line = start_line;
-            let mut current_line = start_line;
+            if self.count_tokens(&text[part.clone()]) <= budget {
+                current = Some(part);
+            } else {
+                self.split(text, part, rest, budget, pieces);
+            }
+        }
+        pieces.extend(current);
+    }
 
-            for part in parts {
-                let part_with_sep = if current_chunk.is_empty() {
-                    part.to_string()
-                } else {
-                    format!("{}{}{}", current_chunk, separator, part)
-                };
+    /// Split a range at each occurrence of a separator.
+    ///
+    /// Leading newlines of the separator end the previous part, so parts
+    /// start at the beginning of a line; other separators stay with the
+    /// part before them.
+    fn split_by_separator(text: &str, range: Range<usize>, separator: &str) -> Vec<Range<usize>> {
+        let cut = match separator.trim_start_matches('\n') {
+            rest if rest.len() < separator.len() => separator.len() - rest.len(),
+            _ => separator.len(),
+        };
 
-                let combined_tokens = self.count_tokens(&part_with_sep);
+        let mut parts = Vec::new();
+        let mut start = range.start;
+        for (offset, _) in text[range.clone()].match_indices(separator) {
+            let boundary = range.start + offset + cut;
+            if boundary > start && boundary < range.end {
+                parts.push(start..boundary);
+                start = boundary;
+            }
+        }
+        parts.push(start..range.end);
+        parts
+    }
 
-                if combined_tokens <= config.max_tokens {
-                    // Add to current chunk
-                    if current_chunk.is_empty() {
-                        current_chunk = part.to_string();
-                    } else {
-                        current_chunk = part_with_sep;
-                    }
-                } else if current_chunk.is_empty() {
-                    // Part itself is too big, recurse with next separator
-                    let remaining_seps = &separators[sep_idx + 1..];
-                    if remaining_seps.is_empty() {
-                        // No more separators, just split by max chars
-                        chunks.extend(self.split_by_size(part, config, current_line));
-                    } else {
-                        chunks.extend(self.chunk_recursive(part, remaining_seps, config, current_line));
-                    }
-                    current_line += part.lines().count() as u32;
-                } else {
-                    // Save current chunk and start new one
-                    let tokens = self.count_tokens(&current_chunk);
-                    if tokens >= config.min_tokens {
-                        chunks.push(ChunkData {
-                            content: current_chunk.clone(),
-                            token_count: tokens,
-                            start_line: chunk_start_line,
-                            end_line: current_line.saturating_sub(1),
-                        });
-                    }
+    /// Split a range by size (last resort), preferring whitespace breaks.
+    fn split_by_size(&self, text: &str, range: Range<usize>, budget: usize, pieces: &mut Vec<Range<usize>>) {
+        let mut start = range.start;
 
-                    // Check if part itself fits
-                    let part_tokens = self.count_tokens(part);
-                    if part_tokens <= config.max_tokens {
-                        current_chunk = part.to_string();
-                        chunk_start_line = current_line;
-                    } else {
-                        // Part too big, recurse
-                        let remaining_seps = &separators[sep_idx + 1..];
-                        if remaining_seps.is_empty() {
-                            chunks.extend(self.split_by_size(part, config, current_line));
-                        } else {
-                            chunks.extend(self.chunk_recursive(part, remaining_seps, config, current_line));
-                        }
-                        current_chunk = String::new();
-                        chunk_start_line = current_line + part.lines().count() as u32;
-                    }
-                    current_line += part.lines().count() as u32;
-                }
+        while start < range.end {
+            let min_end = start + text[start..].chars().next().map_or(1, char::len_utf8);
+            // Approximate chars per chunk, shrunk until it fits
+            let mut end = floor_char_boundary(text, (start + budget * 4).min(range.end)).max(min_end);
+            while end > min_end && self.count_tokens(&text[start..end]) > budget {
+                end = floor_char_boundary(text, start + (end - start) / 2).max(min_end);
             }
 
-            // Don't forget the last chunk
-            if !current_chunk.is_empty() {
-                let tokens = self.count_tokens(&current_chunk);
-                if tokens >= config.min_tokens {
-                    chunks.push(ChunkData {
-                        content: current_chunk,
-                        token_count: tokens,
-                        start_line: chunk_start_line,
-                        end_line: chunk_start_line + current_line.saturating_sub(chunk_start_line),
-                    });
+            if end < range.end {
+                if let Some(offset) = text[start..end].rfind(char::is_whitespace).filter(|&i| i > 0) {
+                    end = start + offset + 1;
                 }
             }
 
-            if !chunks.is_empty() {
-                return chunks;
-            }
+            pieces.push(start..end);
+            start = end;
         }
-
-        // Fallback: split by size
-        self.split_by_size(text, config, start_line)
     }
 
-    /// Split text by size (last resort).
-    fn split_by_size(&self, text: &str, config: &ChunkConfig, start_line: u32) -> Vec<ChunkData> {
-        let mut chunks = Vec::new();
-        let chars: Vec<char> = text.chars().collect();
-        let target_chars = config.max_tokens * 4; // Approximate chars per chunk
-        let mut start = 0;
-        let mut current_line = start_line;
-
-        while start < chars.len() {
-            let end = (start + target_chars).min(chars.len());
-
-            // Try to break at word boundary
-            let mut actual_end = end;
-            if end < chars.len() {
-                // Look for space backwards
-                for i in (start..end).rev() {
-                    if chars[i] == ' ' || chars[i] == '\n' {
-                        actual_end = i + 1;
-                        break;
-                    }
+    /// Merge pieces below `min_tokens` into a neighbour, as long as the
+    /// result stays within `budget`.
+    fn merge_undersized(&self, text: &str, pieces: Vec<Range<usize>>, min_tokens: usize, budget: usize) -> Vec<Range<usize>> {
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(pieces.len());
+
+        for piece in pieces {
+            if let Some(last) = merged.last_mut() {
+                let small = self.count_tokens(&text[last.clone()]) < min_tokens
+                    || self.count_tokens(&text[piece.clone()]) < min_tokens;
+                if small && self.count_tokens(&text[last.start..piece.end]) <= budget {
+                    last.end = piece.end;
+                    continue;
                 }
             }
+            merged.push(piece);
+        }
 
-            let chunk_text: String = chars[start..actual_end].iter().collect();
-            let tokens = self.count_tokens(&chunk_text);
-            let lines_in_chunk = chunk_text.lines().count() as u32;
-
-            if tokens > 0 {
-                chunks.push(ChunkData {
-                    content: chunk_text,
-                    token_count: tokens,
-                    start_line: current_line,
-                    end_line: current_line + lines_in_chunk.saturating_sub(1),
-                });
+        merged
+    }
+
+    /// Start of the overlap with the previous piece: the longest run of
+    /// whole words at its end that fits in `overlap_tokens`.
+    fn overlap_start(&self, text: &str, previous: Range<usize>, overlap_tokens: usize) -> Option<usize> {
+        let mut words = Vec::new();
+        let mut after_space = false;
+        for (i, c) in text[previous.clone()].char_indices() {
+            if after_space && !c.is_whitespace() {
+                words.push(previous.start + i);
             }
+            after_space = c.is_whitespace();
+        }
 
-            current_line += lines_in_chunk;
-            start = actual_end;
+        let mut start = None;
+        for &word in words.iter().rev() {
+            if self.count_tokens(&text[word..previous.end]) > overlap_tokens {
+                break;
+            }
+            start = Some(word);
         }
+        start
+    }
+}
 
-        chunks
+/// Largest char boundary at or below `index`.
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
     }
+    index
+}
+
+/// Range without leading and trailing whitespace.
+pub(crate) fn trim(text: &str, range: Range<usize>) -> Range<usize> {
+    let slice = &text[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.end - (slice.len() - slice.trim_end().len());
+    start..end.max(start)
 }
 
 impl Default for RecursiveChunker {
@@ -248,17 +243,34 @@ impl Chunker for RecursiveChunker {
         content_type: ContentType,
         config: &ChunkConfig,
     ) -> Result<Vec<ChunkData>> {
-        if content.is_empty() {
-            return Ok(Vec::new());
-        }
-
         let separators = self.separators(&content_type);
-        let chunks = self.chunk_recursive(content, &separators, config, 1);
+        let overlap_tokens = config.overlap_tokens.min(config.max_tokens / 2);
+        // Overlap counts toward max_tokens
+        let budget = (config.max_tokens - overlap_tokens).max(1);
 
-        // Filter out chunks that are too small
-        let chunks: Vec<_> = chunks
+        let mut pieces = Vec::new();
+        self.split(content, 0..content.len(), &separators, budget, &mut pieces);
+
+        let pieces: Vec<_> = pieces
             .into_iter()
-            .filter(|c| c.token_count >= config.min_tokens)
+            .map(|piece| trim(content, piece))
+            .filter(|piece| !piece.is_empty())
+            .collect();
+        let pieces = self.merge_undersized(content, pieces, config.min_tokens, budget);
+
+        let lines = Lines::new(content);
+        let chunks = pieces
+            .iter()
+            .enumerate()
+            .map(|(i, piece)| {
+                let start = match i.checked_sub(1) {
+                    Some(previous) if overlap_tokens > 0 => self
+                        .overlap_start(content, pieces[previous].clone(), overlap_tokens)
+                        .unwrap_or(piece.start),
+                    _ => piece.start,
+                };
+                lines.chunk_bytes(start..piece.end, self.count_tokens(&content[start..piece.end]))
+            })
             .collect();
 
         Ok(chunks)
@@ -275,9 +287,16 @@ impl Chunker for RecursiveChunker {
             ContentType::JavaScript,
             ContentType::Go,
             ContentType::Java,
+            ContentType::Kotlin,
+            ContentType::CSharp,
             ContentType::Cpp,
             ContentType::C,
             ContentType::Ruby,
+            ContentType::Shell,
+            ContentType::Sql,
+            ContentType::Css,
+            ContentType::Dockerfile,
+            ContentType::Makefile,
             ContentType::Json,
             ContentType::Yaml,
             ContentType::Toml,
@@ -348,6 +367,120 @@ mod tests {
         assert!(chunks.is_empty());
     }
 
+    #[test]
+    fn test_small_pieces_are_merged_not_dropped() {
+        let chunker = RecursiveChunker::with_token_counter(|s| s.split_whitespace().count());
+        let config = ChunkConfig {
+            max_tokens: 8,
+            min_tokens: 3,
+            overlap_tokens: 0,
+        };
+
+        let text = "one two three four five\n\nsix seven eight nine ten\n\nend";
+        let chunks = chunker.chunk(text, ContentType::PlainText, &config).unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].content, "six seven eight nine ten\n\nend");
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (3, 5));
+    }
+
+    #[test]
+    fn test_overlap() {
+        let chunker = RecursiveChunker::with_token_counter(|s| s.split_whitespace().count());
+        let config = ChunkConfig {
+            max_tokens: 6,
+            min_tokens: 1,
+            overlap_tokens: 2,
+        };
+
+        let text = "a b c d\ne f g h\ni j k l";
+        let chunks = chunker.chunk(text, ContentType::PlainText, &config).unwrap();
+
+        let contents: Vec<_> = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(contents, vec!["a b c d", "c d\ne f g h", "g h\ni j k l"]);
+        let lines: Vec<_> = chunks.iter().map(|c| (c.start_line, c.end_line)).collect();
+        assert_eq!(lines, vec![(1, 1), (1, 2), (2, 3)]);
+        assert!(chunks.iter().all(|c| c.token_count <= config.max_tokens));
+    }
+
+    #[test]
+    fn test_chunks_cover_every_line() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        use crate::AdaptiveChunker;
+
+        const WORDS: &[&str] = &[
+            "the", "quick", "fn", "main()", "{", "}", "# Title", "- item", "```", "Sentence.", "end!",
+            "héllo", "日本語", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "\t",
+            "key:", "\"k\":", "= 1,", "[", "]", "[[t]]", "|",
+        ];
+
+        for seed in 0..500 {
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            let mut text = String::new();
+            for _ in 0..rng.gen_range(0..40) {
+                match rng.gen_range(0..10) {
+                    0 => {}
+                    1 => text.push_str("   "),
+                    _ => {
+                        let words: Vec<_> = (0..rng.gen_range(1..12))
+                            .map(|_| WORDS[rng.gen_range(0..WORDS.len())])
+                            .collect();
+                        text.push_str(&words.join(" "));
+                    }
+                }
+                text.push_str(if rng.gen_bool(0.1) { "\r\n" } else { "\n" });
+            }
+
+            let max_tokens = rng.gen_range(1..40);
+            let config = ChunkConfig {
+                max_tokens,
+                min_tokens: rng.gen_range(0..20),
+                overlap_tokens: rng.gen_range(0..10),
+            };
+            // The other chunkers must not lose lines either
+            let words = |s: &str| s.split_whitespace().count();
+            let chunker: Box<dyn Chunker> = match rng.gen_range(0..4) {
+                0 => Box::new(RecursiveChunker::new()),
+                1 => Box::new(RecursiveChunker::with_token_counter(words)),
+                2 => Box::new(AdaptiveChunker::new()),
+                _ => Box::new(AdaptiveChunker::with_token_counter(words)),
+            };
+            let content_type = [
+                ContentType::PlainText,
+                ContentType::Markdown,
+                ContentType::Rust,
+                ContentType::Json,
+                ContentType::Yaml,
+                ContentType::Toml,
+                ContentType::Shell,
+                ContentType::Sql,
+                ContentType::Css,
+            ][rng.gen_range(0..9)];
+
+            let chunks = chunker.chunk(&text, content_type, &config).unwrap();
+
+            let lines: Vec<&str> = text.lines().collect();
+            let mut covered = vec![false; lines.len()];
+            for chunk in &chunks {
+                assert!(!chunk.content.trim().is_empty(), "seed {}: empty chunk", seed);
+                assert!(chunk.start_line <= chunk.end_line, "seed {}: bad range", seed);
+                let span = lines[chunk.start_line as usize - 1..chunk.end_line as usize].join("\n");
+                let content: String = chunk.content.replace('\r', "");
+                assert!(span.contains(&content), "seed {}: {:?} not in lines {:?}", seed, content, span);
+                covered[chunk.start_line as usize - 1..chunk.end_line as usize].fill(true);
+            }
+            for (i, line) in lines.iter().enumerate() {
+                assert!(line.trim().is_empty() || covered[i], "seed {}: line {} not covered", seed, i + 1);
+            }
+            for (chunk, range) in chunks.iter().zip(crate::byte_ranges(&text, &chunks)) {
+                assert_eq!(&text[range], chunk.content, "seed {}: wrong byte range", seed);
+            }
+        }
+    }
+
     #[test]
     fn test_supported_types() {
         let chunker = RecursiveChunker::new();
@@ -0,0 +1,486 @@
+//! Registry routing documents to chunkers.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use rag_core::{
+    ChunkConfig, ChunkData, ChunkedDocument, Chunker, ChunkingConfig, CollectionChunkingConfig, ContentType, Document,
+    RagError, Result, SemanticChunkingConfig,
+};
+
+use crate::{
+    AdaptiveChunker, AstChunker, ChatChunker, HtmlChunker, MarkdownChunker, RecursiveChunker, SemanticChunker,
+    StructuredChunker,
+};
+
+/// Name of the chunker used when no rule matches.
+const DEFAULT_CHUNKER: &str = "adaptive";
+
+/// Name routing a document to the [`SemanticChunker`].
+///
+/// The semantic chunker embeds sentences, so it is not registered here:
+/// callers holding an embedder check [`ChunkerRegistry::route`] for this
+/// name and chunk such documents themselves.
+pub const SEMANTIC_CHUNKER: &str = "semantic";
+
+/// Named chunkers and the rules picking one for a document.
+///
+/// A document goes to the chunker of the first rule it matches, trying
+/// glob rules, then MIME type rules, then semantic chunking of its
+/// collection, then its collection's chunker, then content type rules, and
+/// finally the `adaptive` chunker. Rules refer to
+/// chunkers by name, so a rule from the configuration can use a chunker
+/// registered by the application:
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use rag_chunk::{ChunkerRegistry, RecursiveChunker};
+/// use rag_core::{ChunkingConfig, ContentType, Document};
+///
+/// let registry = ChunkerRegistry::from_config(&ChunkingConfig::default())
+///     .with_chunker("proto", Arc::new(RecursiveChunker::new()))
+///     .route_glob("**/*.proto", "proto");
+///
+/// let doc = Document::new("code", "file://api/user.proto", "message User {}", ContentType::Unknown);
+/// let chunks = registry.chunk(&doc).unwrap();
+/// ```
+///
+/// The configuration also sets the chunk sizes, with per-collection
+/// overrides.
+pub struct ChunkerRegistry {
+    /// Chunkers by name.
+    chunkers: HashMap<String, Arc<dyn Chunker>>,
+
+    /// Glob rules, in order.
+    globs: Vec<(String, String)>,
+
+    /// MIME type rules, in order.
+    mime_types: Vec<(String, String)>,
+
+    /// Content type rules.
+    content_types: HashMap<ContentType, String>,
+
+    /// Chunk sizes from the configuration.
+    config: ChunkConfig,
+
+    /// Overrides by collection.
+    collections: HashMap<String, CollectionChunkingConfig>,
+
+    /// Collections using semantic chunking.
+    semantic: SemanticChunkingConfig,
+}
+
+impl ChunkerRegistry {
+    /// Create a registry with the built-in chunkers, and the rules and
+    /// chunk sizes of the `[chunking]` configuration.
+    ///
+    /// The built-in chunkers are `adaptive`, `recursive`, `ast`,
+    /// `markdown`, `html`, `structured` and `chat`.
+    pub fn from_config(config: &ChunkingConfig) -> Self {
+        let hierarchical = config.hierarchical;
+        let mut registry = Self {
+            chunkers: HashMap::new(),
+            globs: Vec::new(),
+            mime_types: Vec::new(),
+            content_types: HashMap::new(),
+            config: ChunkConfig {
+                max_tokens: config.max_tokens,
+                min_tokens: config.min_tokens,
+                overlap_tokens: config.overlap_tokens,
+            },
+            collections: config.collections.clone(),
+            semantic: config.semantic.clone(),
+        }
+        .with_chunker(DEFAULT_CHUNKER, Arc::new(AdaptiveChunker::from_config(config)))
+        .with_chunker("recursive", Arc::new(RecursiveChunker::new()))
+        .with_chunker("ast", Arc::new(AstChunker::new().with_hierarchy(hierarchical)))
+        .with_chunker("markdown", Arc::new(MarkdownChunker::new().with_hierarchy(hierarchical)))
+        .with_chunker("html", Arc::new(HtmlChunker::new().with_hierarchy(hierarchical)))
+        .with_chunker("structured", Arc::new(StructuredChunker::new()))
+        .with_chunker("chat", Arc::new(ChatChunker::new()));
+
+        for route in &config.routes {
+            if let Some(glob) = &route.glob {
+                registry = registry.route_glob(glob, &route.chunker);
+            }
+            if let Some(mime_type) = &route.mime_type {
+                registry = registry.route_mime_type(mime_type, &route.chunker);
+            }
+            if let Some(content_type) = route.content_type {
+                registry = registry.route_content_type(content_type, &route.chunker);
+            }
+        }
+
+        registry
+    }
+
+    /// Register a chunker under a name, replacing any chunker of that name.
+    pub fn with_chunker(mut self, name: impl Into<String>, chunker: Arc<dyn Chunker>) -> Self {
+        self.chunkers.insert(name.into(), chunker);
+        self
+    }
+
+    /// Send documents whose path matches a glob to a chunker.
+    ///
+    /// `*` and `?` match within a path segment and `**` matches any number
+    /// of segments. A glob without `/` matches the file name, and one
+    /// with `/` matches the end of the path unless it starts with `/`.
+    pub fn route_glob(mut self, glob: &str, chunker: &str) -> Self {
+        self.globs.push((glob.to_string(), chunker.to_string()));
+        self
+    }
+
+    /// Send documents of a MIME type, such as `text/html` or `text/*`, to a
+    /// chunker.
+    pub fn route_mime_type(mut self, mime_type: &str, chunker: &str) -> Self {
+        self.mime_types.push((mime_type.to_lowercase(), chunker.to_string()));
+        self
+    }
+
+    /// Send documents of a content type to a chunker, replacing any
+    /// previous rule for it.
+    pub fn route_content_type(mut self, content_type: ContentType, chunker: &str) -> Self {
+        self.content_types.insert(content_type, chunker.to_string());
+        self
+    }
+
+    /// Get a chunker by name.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Chunker>> {
+        self.chunkers.get(name).cloned()
+    }
+
+    /// Chunk sizes for a collection.
+    pub fn config_for(&self, collection: &str) -> ChunkConfig {
+        let mut config = self.config.clone();
+        if let Some(overrides) = self.collections.get(collection) {
+            config.max_tokens = overrides.max_tokens.unwrap_or(config.max_tokens);
+            config.min_tokens = overrides.min_tokens.unwrap_or(config.min_tokens);
+            config.overlap_tokens = overrides.overlap_tokens.unwrap_or(config.overlap_tokens);
+        }
+        config
+    }
+
+    /// Name of the chunker for a document.
+    ///
+    /// The MIME type of a document is its `mime_type` metadata, the type of
+    /// a `data:` URI, or the one of its content type. Semantic chunking only
+    /// applies to the content types the [`SemanticChunker`] supports.
+    pub fn route(&self, doc: &Document) -> &str {
+        let path = doc.source_uri.strip_prefix("file://").unwrap_or(&doc.source_uri);
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        let mime_type = doc
+            .metadata
+            .get("mime_type")
+            .and_then(|value| value.as_str())
+            .or_else(|| {
+                let data = doc.source_uri.strip_prefix("data:")?;
+                data.split([';', ',']).next().filter(|mime| !mime.is_empty())
+            })
+            .unwrap_or_else(|| doc.content_type.mime_type())
+            .to_lowercase();
+        let mime_type = mime_type.split(';').next().unwrap_or("").trim();
+
+        let semantic = self.semantic.applies_to(&doc.collection) && SemanticChunker::supports(doc.content_type);
+        self.globs
+            .iter()
+            .find(|(glob, _)| glob_matches(glob, path))
+            .or_else(|| self.mime_types.iter().find(|(pattern, _)| mime_matches(pattern, mime_type)))
+            .map(|(_, name)| name.as_str())
+            .or_else(|| semantic.then_some(SEMANTIC_CHUNKER))
+            .or_else(|| self.collections.get(&doc.collection).and_then(|c| c.chunker.as_deref()))
+            .or_else(|| self.content_types.get(&doc.content_type).map(String::as_str))
+            .unwrap_or(DEFAULT_CHUNKER)
+    }
+
+    /// Pick the chunker for a document, as named by [`route`](Self::route).
+    pub fn resolve(&self, doc: &Document) -> Result<Arc<dyn Chunker>> {
+        let name = self.route(doc);
+        if name == SEMANTIC_CHUNKER && !self.chunkers.contains_key(name) {
+            return Err(RagError::chunking(format!(
+                "{} needs semantic chunking, which requires an embedder",
+                doc.source_uri
+            )));
+        }
+
+        self.get(name)
+            .ok_or_else(|| RagError::chunking(format!("Unknown chunker '{}' for {}", name, doc.source_uri)))
+    }
+
+    /// Chunk a document's content with its chunker and its collection's
+    /// chunk sizes.
+    pub fn chunk(&self, doc: &Document) -> Result<Vec<ChunkData>> {
+        Ok(self.chunk_with_metadata(doc)?.chunks)
+    }
+
+    /// Chunk a document like [`chunk`](Self::chunk), also returning the
+    /// metadata its chunker found in it.
+    pub fn chunk_with_metadata(&self, doc: &Document) -> Result<ChunkedDocument> {
+        let content = doc.raw_content.as_deref().unwrap_or("");
+        self.resolve(doc)?
+            .chunk_with_metadata(content, doc.content_type, &self.config_for(&doc.collection))
+    }
+}
+
+impl Default for ChunkerRegistry {
+    fn default() -> Self {
+        Self::from_config(&ChunkingConfig::default())
+    }
+}
+
+/// Check whether a MIME type matches a pattern such as `text/*`.
+fn mime_matches(pattern: &str, mime_type: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some("*") => true,
+        Some(kind) => mime_type.split('/').next() == Some(kind),
+        None => pattern == mime_type,
+    }
+}
+
+/// Check whether a path matches a glob.
+fn glob_matches(glob: &str, path: &str) -> bool {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if let Some(anchored) = glob.strip_prefix('/') {
+        let pattern: Vec<&str> = anchored.split('/').collect();
+        return segments_match(&pattern, &segments);
+    }
+
+    // Unanchored globs match any trailing run of segments
+    let pattern: Vec<&str> = glob.split('/').filter(|s| !s.is_empty()).collect();
+    (0..segments.len()).any(|start| segments_match(&pattern, &segments[start..]))
+}
+
+fn segments_match(pattern: &[&str], segments: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => segments.is_empty(),
+        Some((&"**", rest)) => (0..=segments.len()).any(|skip| segments_match(rest, &segments[skip..])),
+        Some((first, rest)) => {
+            segments.first().is_some_and(|segment| wildcard_matches(first, segment))
+                && segments_match(rest, &segments[1..])
+        }
+    }
+}
+
+/// Match one path segment against `*` and `?` wildcards.
+fn wildcard_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*`, and where its match currently ends
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if let Some((after, matched)) = star {
+            p = after;
+            t = matched + 1;
+            star = Some((after, t));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::Lines;
+    use rag_core::ChunkerRoute;
+
+    /// Chunker labelling its single chunk with a name.
+    struct Named(&'static str);
+
+    impl Chunker for Named {
+        fn chunk(&self, content: &str, _content_type: ContentType, config: &ChunkConfig) -> Result<Vec<ChunkData>> {
+            Ok(vec![ChunkData {
+                heading_path: Some(self.0.to_string()),
+                ..Lines::new(content).chunk_bytes(0..content.len(), config.max_tokens)
+            }])
+        }
+
+        fn supported_types(&self) -> Vec<ContentType> {
+            Vec::new()
+        }
+    }
+
+    fn chunked_by(registry: &ChunkerRegistry, doc: &Document) -> (String, usize) {
+        let chunks = registry.chunk(doc).unwrap();
+        (chunks[0].heading_path.clone().unwrap_or_default(), chunks[0].token_count)
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*.proto", "/repo/api/user.proto"));
+        assert!(glob_matches("api/*.proto", "/repo/api/user.proto"));
+        assert!(glob_matches("docs/**/*.txt", "docs/a/b/notes.txt"));
+        assert!(glob_matches("docs/**/*.txt", "docs/notes.txt"));
+        assert!(glob_matches("/repo/**", "/repo/api/user.proto"));
+        assert!(glob_matches("user.?roto", "user.proto"));
+        assert!(!glob_matches("/api/*.proto", "/repo/api/user.proto"));
+        assert!(!glob_matches("*.proto", "user.proto.bak"));
+        assert!(!glob_matches("api/*.proto", "api/v1/user.proto"));
+    }
+
+    #[test]
+    fn test_routing_order() {
+        let mut config = ChunkingConfig {
+            routes: vec![
+                ChunkerRoute {
+                    glob: Some("**/*.proto".to_string()),
+                    chunker: "proto".to_string(),
+                    ..Default::default()
+                },
+                ChunkerRoute {
+                    mime_type: Some("text/*".to_string()),
+                    chunker: "text".to_string(),
+                    ..Default::default()
+                },
+                ChunkerRoute {
+                    content_type: Some(ContentType::Json),
+                    chunker: "json".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        config.collections.insert(
+            "logs".to_string(),
+            CollectionChunkingConfig {
+                chunker: Some("logs".to_string()),
+                max_tokens: Some(64),
+                ..Default::default()
+            },
+        );
+        let registry = ["proto", "text", "json", "logs"]
+            .into_iter()
+            .fold(ChunkerRegistry::from_config(&config), |registry, name| {
+                registry.with_chunker(name, Arc::new(Named(name)))
+            });
+
+        let doc = |collection: &str, uri: &str, content_type| Document::new(collection, uri, "x", content_type);
+        assert_eq!(chunked_by(&registry, &doc("logs", "file:///a/b.proto", ContentType::Unknown)).0, "proto");
+        assert_eq!(chunked_by(&registry, &doc("code", "file:///a/b.rs", ContentType::Rust)).0, "text");
+        assert_eq!(chunked_by(&registry, &doc("logs", "file:///a/b.json", ContentType::Json)), ("logs".into(), 64));
+        assert_eq!(chunked_by(&registry, &doc("code", "file:///a/b.json", ContentType::Json)), ("json".into(), 512));
+        assert_eq!(chunked_by(&registry, &doc("code", "data:text/x-rust,x", ContentType::Json)).0, "text");
+
+        // Unrouted documents go to the adaptive chunker
+        let chunks = registry.chunk(&doc("code", "file:///a/b.bin", ContentType::Unknown)).unwrap();
+        assert_eq!(chunks[0].heading_path, None);
+    }
+
+    #[test]
+    fn test_semantic_route() {
+        let mut config = ChunkingConfig {
+            routes: vec![ChunkerRoute {
+                glob: Some("*.log".to_string()),
+                chunker: "recursive".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        config.semantic.collections = vec!["essays".to_string()];
+        let registry = ChunkerRegistry::from_config(&config);
+
+        let doc = |collection: &str, uri: &str, content_type| Document::new(collection, uri, "x", content_type);
+        assert_eq!(registry.route(&doc("essays", "file:///a.md", ContentType::Markdown)), SEMANTIC_CHUNKER);
+        assert_eq!(registry.route(&doc("essays", "file:///a.txt", ContentType::PlainText)), SEMANTIC_CHUNKER);
+        assert_eq!(registry.route(&doc("essays", "file:///a.rs", ContentType::Rust)), DEFAULT_CHUNKER);
+        assert_eq!(registry.route(&doc("essays", "file:///a.log", ContentType::PlainText)), "recursive");
+        assert_eq!(registry.route(&doc("notes", "file:///a.md", ContentType::Markdown)), DEFAULT_CHUNKER);
+
+        // The registry cannot chunk semantically on its own
+        let err = registry.chunk(&doc("essays", "file:///a.md", ContentType::Markdown)).unwrap_err();
+        assert!(err.to_string().contains("embedder"));
+    }
+
+    #[test]
+    fn test_chunk_spans() {
+        let config = ChunkingConfig {
+            max_tokens: 24,
+            min_tokens: 4,
+            overlap_tokens: 6,
+            hierarchical: true,
+            ..Default::default()
+        };
+        let registry = ChunkerRegistry::from_config(&config);
+        let long_line = "let values = [".to_string() + &"1, 2, 3, 4, 5, 6, 7, 8, ".repeat(20) + "];";
+        let samples = [
+            (ContentType::Rust, format!("use std::io;\n\nimpl Point {{\n    fn norm() {{\n{}\n    }}\n}}\n", long_line)),
+            (ContentType::Markdown, "# Install\r\n\r\nUnpack it.\r\n\r\n## Linux\r\n\r\n- apt\r\n- dnf\r\n".repeat(3)),
+            (ContentType::Json, "{\"name\": \"app\", \"deps\": {\"serde\": \"1\"}, \"tags\": [1, 2]}".into()),
+            (ContentType::Yaml, "name: app\nservices:\n  web:\n    image: nginx\n    ports:\n      - 80\n".into()),
+            (ContentType::Toml, "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"1\"\n".into()),
+            (ContentType::PlainText, "Sentence number one is here. ".repeat(40)),
+            (ContentType::Chat, "alice: hi there\nbob: hello, how are you?\n  still typing\nalice: fine\n".repeat(4)),
+            (
+                ContentType::Chat,
+                "{\"role\": \"user\", \"content\": \"Hi\"}\n{\"role\": \"assistant\", \"content\": \"Hello\"}\n".repeat(4),
+            ),
+            (
+                ContentType::Html,
+                "<html><body><main>\n<h1>Title</h1>\n<p>Some text &amp; more.</p>\n<ul>\n<li>One</li>\n<li>Two</li>\n</ul>\n"
+                    .to_string()
+                    + &"<p>Another paragraph of text here.</p>\n".repeat(6)
+                    + "</main></body></html>",
+            ),
+        ];
+        let names = ["adaptive", "recursive", "ast", "markdown", "html", "structured", "chat"];
+
+        for (content_type, source) in &samples {
+            let lines = Lines::new(source);
+            let jsonl = source.starts_with('{') && *content_type == ContentType::Chat;
+            for name in names {
+                let chunks = registry.get(name).unwrap().chunk(source, *content_type, &registry.config).unwrap();
+                // Rendered chunks cover the source they were rendered from
+                let rendered = name == "html"
+                    || (name == "adaptive" && *content_type == ContentType::Html)
+                    || (matches!(name, "adaptive" | "chat") && jsonl);
+
+                for chunk in &chunks {
+                    let context = format!("{} on {:?}: {:?}", name, content_type, chunk.content);
+                    let range = chunk.start_byte..chunk.end_byte;
+                    if rendered {
+                        // The range spans whole source lines holding every
+                        // word rendered into the chunk
+                        let span = &source[range.clone()];
+                        let (first, last) = (lines.row(range.start), lines.row(range.end.saturating_sub(1)));
+                        assert_eq!(lines.range(first, last), range, "{}", context);
+                        for word in chunk.content.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+                            assert!(span.contains(word), "{} lacks {:?}", context, word);
+                        }
+                    } else {
+                        assert_eq!(&source[range.clone()], chunk.content, "{}", context);
+                    }
+
+                    let mut located = chunk.clone();
+                    lines.locate(&mut located, range.clone());
+                    assert_eq!(
+                        (located.start_line, located.start_column, located.end_column),
+                        (chunk.start_line, chunk.start_column, chunk.end_column),
+                        "{}",
+                        context
+                    );
+                    let end_row = chunk.end_line as usize - 1;
+                    assert!(lines.range(end_row, end_row).contains(&range.end.saturating_sub(1)), "{}", context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_chunker() {
+        let registry = ChunkerRegistry::default().route_content_type(ContentType::Rust, "missing");
+        let doc = Document::new("code", "file://main.rs", "fn main() {}", ContentType::Rust);
+
+        assert!(registry.chunk(&doc).is_err());
+        assert!(registry.get("markdown").is_some());
+    }
+}
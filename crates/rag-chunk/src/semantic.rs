@@ -0,0 +1,473 @@
+//! Embedding-based chunker for prose.
+//!
+//! Splits text into sentences, embeds them, and starts a new chunk where the
+//! meaning shifts between adjacent sentences.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use rag_core::{BreakpointThreshold, ChunkConfig, ChunkData, Chunker, ContentType, Embedder, Result};
+
+use crate::lines::Lines;
+use crate::recursive::trim;
+use crate::RecursiveChunker;
+
+/// Chunker that splits prose where adjacent sentences are least similar.
+///
+/// - Each sentence is embedded together with `buffer` neighbours on either
+///   side, which keeps short sentences from causing spurious breaks.
+/// - A breakpoint goes wherever the cosine distance between adjacent
+///   sentences exceeds the [`BreakpointThreshold`].
+/// - Sections larger than `max_tokens` are split again at their largest
+///   distance; a single sentence that is still too large is split with
+///   [`RecursiveChunker`].
+/// - Sections below `min_tokens` are merged into the neighbour they are
+///   closest to, as long as the result fits in `max_tokens`.
+/// - Tokens are counted with the embedder's tokenizer.
+///
+/// Unlike the other chunkers, chunking is async, since it embeds the text.
+pub struct SemanticChunker {
+    /// Embedder for sentences and token counts.
+    embedder: Arc<dyn Embedder>,
+
+    /// Rule for placing breakpoints.
+    threshold: BreakpointThreshold,
+
+    /// Neighbouring sentences embedded with each sentence.
+    buffer: usize,
+
+    /// Fallback for oversized sentences.
+    recursive: RecursiveChunker,
+}
+
+impl SemanticChunker {
+    /// Create a semantic chunker using an embedder.
+    pub fn new(embedder: Arc<dyn Embedder>) -> Self {
+        let counter = embedder.clone();
+
+        Self {
+            embedder,
+            threshold: BreakpointThreshold::default(),
+            buffer: 1,
+            recursive: RecursiveChunker::with_token_counter(move |s| {
+                counter.count_tokens(s).unwrap_or_else(|_| (s.len() / 4).max(1))
+            }),
+        }
+    }
+
+    /// Set the rule for placing breakpoints.
+    pub fn with_threshold(mut self, threshold: BreakpointThreshold) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set how many neighbouring sentences on either side are embedded with
+    /// each sentence.
+    pub fn with_buffer(mut self, buffer: usize) -> Self {
+        self.buffer = buffer;
+        self
+    }
+
+    /// Check whether a content type is prose this chunker handles.
+    pub fn supports(content_type: ContentType) -> bool {
+        matches!(content_type, ContentType::PlainText | ContentType::Markdown)
+    }
+
+    /// Chunk text at its topic shifts.
+    pub async fn chunk(
+        &self,
+        content: &str,
+        content_type: ContentType,
+        config: &ChunkConfig,
+    ) -> Result<Vec<ChunkData>> {
+        let sentences = Self::sentences(content, content_type);
+        if sentences.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let distances = self.distances(content, &sentences).await?;
+        let tokens = |section: &Range<usize>| {
+            self.count_tokens(&content[sentences[section.start].start..sentences[section.end - 1].end])
+        };
+
+        // Split at breakpoints, then again wherever a section is too large
+        let cutoff = Self::cutoff(&distances, self.threshold);
+        let mut sections = Vec::new();
+        let mut start = 0;
+        for (i, &distance) in distances.iter().enumerate() {
+            if cutoff.is_some_and(|cutoff| distance > cutoff) {
+                sections.push(start..i + 1);
+                start = i + 1;
+            }
+        }
+        sections.push(start..sentences.len());
+
+        let mut fitted = Vec::with_capacity(sections.len());
+        for section in sections {
+            Self::fit(section, &distances, config.max_tokens, &tokens, &mut fitted);
+        }
+        let sections = Self::merge_small(fitted, &distances, config, &tokens);
+
+        let lines = Lines::new(content);
+        let mut chunks = Vec::with_capacity(sections.len());
+        for section in sections {
+            let range = sentences[section.start].start..sentences[section.end - 1].end;
+            let text = &content[range.clone()];
+            let token_count = self.count_tokens(text);
+
+            if token_count > config.max_tokens && section.len() == 1 {
+                // A single oversized sentence is split as text
+                let parts = self.recursive.chunk(text, ContentType::PlainText, config)?;
+                chunks.extend(parts.into_iter().map(|part| lines.shift(part, range.start)));
+                continue;
+            }
+
+            chunks.push(lines.chunk_bytes(range, token_count));
+        }
+
+        Ok(chunks)
+    }
+
+    /// Count tokens with the embedder's tokenizer.
+    fn count_tokens(&self, text: &str) -> usize {
+        self.embedder
+            .count_tokens(text)
+            .unwrap_or_else(|_| (text.len() / 4).max(1))
+    }
+
+    /// Byte ranges of the sentences of a text.
+    ///
+    /// Sentences end at `.`, `!` or `?` followed by whitespace, and at
+    /// paragraph breaks. In Markdown, headings, list items, quotes, fences
+    /// and table rows are sentences of their own.
+    fn sentences(content: &str, content_type: ContentType) -> Vec<Range<usize>> {
+        let markdown = content_type == ContentType::Markdown;
+        let bytes = content.as_bytes();
+        let line_at = |start: usize| &content[start..content[start..].find('\n').map_or(content.len(), |n| start + n)];
+
+        let mut ends = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'.' | b'!' | b'?' => {
+                    let mut end = i + 1;
+                    while end < bytes.len() && matches!(bytes[end], b'"' | b'\'' | b')' | b']') {
+                        end += 1;
+                    }
+                    if end == bytes.len() || bytes[end].is_ascii_whitespace() {
+                        ends.push(end);
+                    }
+                    i = end;
+                    continue;
+                }
+                b'\n' => {
+                    let current = line_at(content[..i].rfind('\n').map_or(0, |n| n + 1));
+                    let next = line_at(i + 1);
+                    if next.trim().is_empty() || (markdown && (is_block_line(current) || is_block_line(next))) {
+                        ends.push(i);
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        ends.push(content.len());
+
+        let mut sentences = Vec::with_capacity(ends.len());
+        let mut start = 0;
+        for end in ends {
+            if end <= start {
+                continue;
+            }
+            let sentence = trim(content, start..end);
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            start = end;
+        }
+
+        sentences
+    }
+
+    /// Cosine distances between the embeddings of adjacent sentences.
+    async fn distances(&self, content: &str, sentences: &[Range<usize>]) -> Result<Vec<f32>> {
+        if sentences.len() < 2 {
+            return Ok(Vec::new());
+        }
+
+        let last = sentences.len() - 1;
+        let texts: Vec<&str> = (0..sentences.len())
+            .map(|i| {
+                let first = i.saturating_sub(self.buffer);
+                let end = (i + self.buffer).min(last);
+                &content[sentences[first].start..sentences[end].end]
+            })
+            .collect();
+        let embeddings = self.embedder.embed_documents(&texts).await?;
+
+        Ok(embeddings
+            .windows(2)
+            .map(|pair| 1.0 - cosine_similarity(&pair[0], &pair[1]))
+            .collect())
+    }
+
+    /// Distance above which adjacent sentences are split, if any.
+    fn cutoff(distances: &[f32], threshold: BreakpointThreshold) -> Option<f32> {
+        if distances.is_empty() {
+            return None;
+        }
+
+        match threshold {
+            BreakpointThreshold::Percentile(percentile) => {
+                let mut sorted = distances.to_vec();
+                sorted.sort_by(|a, b| a.total_cmp(b));
+                let rank = (percentile.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f32;
+                let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+                Some(sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f32))
+            }
+            BreakpointThreshold::StdDev(deviations) => {
+                let n = distances.len() as f32;
+                let mean = distances.iter().sum::<f32>() / n;
+                let variance = distances.iter().map(|d| (d - mean).powi(2)).sum::<f32>() / n;
+                Some(mean + deviations * variance.sqrt())
+            }
+        }
+    }
+
+    /// Split a section of sentences at its largest distances until every
+    /// part fits in `max_tokens` or is a single sentence.
+    fn fit<F>(
+        section: Range<usize>,
+        distances: &[f32],
+        max_tokens: usize,
+        tokens: &F,
+        out: &mut Vec<Range<usize>>,
+    ) where
+        F: Fn(&Range<usize>) -> usize,
+    {
+        if section.len() <= 1 || tokens(&section) <= max_tokens {
+            out.push(section);
+            return;
+        }
+
+        // distances[i] lies between sentences i and i + 1
+        let split = (section.start..section.end - 1)
+            .max_by(|&a, &b| distances[a].total_cmp(&distances[b]))
+            .map_or(section.start + 1, |i| i + 1);
+        Self::fit(section.start..split, distances, max_tokens, tokens, out);
+        Self::fit(split..section.end, distances, max_tokens, tokens, out);
+    }
+
+    /// Merge sections below `min_tokens` into their closest neighbour, if
+    /// the result fits in `max_tokens`.
+    fn merge_small<F>(
+        mut sections: Vec<Range<usize>>,
+        distances: &[f32],
+        config: &ChunkConfig,
+        tokens: &F,
+    ) -> Vec<Range<usize>>
+    where
+        F: Fn(&Range<usize>) -> usize,
+    {
+        // Token count of each section, and whether it has no neighbour to
+        // merge with
+        let mut counts: Vec<usize> = sections.iter().map(tokens).collect();
+        let mut stuck = vec![false; sections.len()];
+
+        // Smallest section that may still be merged
+        let smallest = |counts: &[usize], stuck: &[bool]| {
+            (0..counts.len())
+                .filter(|&i| !stuck[i] && counts[i] < config.min_tokens)
+                .min_by_key(|&i| counts[i])
+        };
+
+        while let Some(i) = smallest(&counts, &stuck) {
+            let mut neighbours = Vec::with_capacity(2);
+            if i > 0 {
+                neighbours.push((i - 1, distances[sections[i].start - 1]));
+            }
+            if i + 1 < sections.len() {
+                neighbours.push((i + 1, distances[sections[i].end - 1]));
+            }
+            neighbours.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+            let merge = neighbours.into_iter().find_map(|(j, _)| {
+                let count = tokens(&(sections[i.min(j)].start..sections[i.max(j)].end));
+                (count <= config.max_tokens).then_some((j, count))
+            });
+            match merge {
+                Some((j, count)) => {
+                    let (first, second) = (i.min(j), i.max(j));
+                    sections[first] = sections[first].start..sections[second].end;
+                    counts[first] = count;
+                    stuck[first] = false;
+                    sections.remove(second);
+                    counts.remove(second);
+                    stuck.remove(second);
+                }
+                None => stuck[i] = true,
+            }
+        }
+
+        sections
+    }
+}
+
+/// Whether a Markdown line starts a block of its own.
+fn is_block_line(line: &str) -> bool {
+    let line = line.trim_start();
+    let ordered = line
+        .split_once(". ")
+        .is_some_and(|(number, _)| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()));
+
+    ordered
+        || ["#", "- ", "* ", "+ ", ">", "```", "~~~", "|"]
+            .iter()
+            .any(|marker| line.starts_with(marker))
+}
+
+/// Cosine similarity of two vectors (0 if either is zero).
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    /// Embeds text by how many of its words belong to each topic, and
+    /// counts one token per word.
+    struct TopicEmbedder;
+
+    const TOPICS: &[&[&str]] = &[
+        &["cats", "purr", "nap", "mice", "fur"],
+        &["sql", "tables", "rows", "indexes", "queries"],
+        &["rain", "clouds", "wind", "storms", "snow"],
+    ];
+
+    #[async_trait]
+    impl Embedder for TopicEmbedder {
+        async fn embed_documents(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let words: Vec<String> = text
+                        .split(|c: char| !c.is_alphanumeric())
+                        .map(str::to_lowercase)
+                        .collect();
+                    TOPICS
+                        .iter()
+                        .map(|topic| words.iter().filter(|w| topic.contains(&w.as_str())).count() as f32)
+                        .collect()
+                })
+                .collect())
+        }
+
+        async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+            Ok(self.embed_documents(&[text]).await?.remove(0))
+        }
+
+        fn count_tokens(&self, text: &str) -> Result<usize> {
+            Ok(text.split_whitespace().count())
+        }
+
+        fn dimension(&self) -> usize {
+            TOPICS.len()
+        }
+
+        fn max_tokens(&self) -> usize {
+            512
+        }
+    }
+
+    fn chunker() -> SemanticChunker {
+        SemanticChunker::new(Arc::new(TopicEmbedder)).with_buffer(0)
+    }
+
+    fn config(max_tokens: usize, min_tokens: usize) -> ChunkConfig {
+        ChunkConfig {
+            max_tokens,
+            min_tokens,
+            overlap_tokens: 0,
+        }
+    }
+
+    const TEXT: &str = "Cats purr. Cats nap on fur.\nCats chase mice.\n\nSQL tables hold rows. SQL indexes speed queries.\nRain falls from clouds. Wind brings storms.";
+
+    #[test]
+    fn test_sentences() {
+        let sentences: Vec<&str> = SemanticChunker::sentences(TEXT, ContentType::PlainText)
+            .into_iter()
+            .map(|range| &TEXT[range])
+            .collect();
+        assert_eq!(sentences.len(), 7);
+        assert_eq!(sentences[2], "Cats chase mice.");
+        assert_eq!(sentences[3], "SQL tables hold rows.");
+
+        let markdown = "# Cats\nThey purr\n- nap\n- hunt";
+        let sentences: Vec<&str> = SemanticChunker::sentences(markdown, ContentType::Markdown)
+            .into_iter()
+            .map(|range| &markdown[range])
+            .collect();
+        assert_eq!(sentences, vec!["# Cats", "They purr", "- nap", "- hunt"]);
+    }
+
+    #[tokio::test]
+    async fn test_breaks_at_topic_shifts() {
+        let chunker = chunker().with_threshold(BreakpointThreshold::Percentile(50.0));
+        let chunks = chunker.chunk(TEXT, ContentType::PlainText, &config(100, 1)).await.unwrap();
+
+        let contents: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec![
+                "Cats purr. Cats nap on fur.\nCats chase mice.",
+                "SQL tables hold rows. SQL indexes speed queries.",
+                "Rain falls from clouds. Wind brings storms.",
+            ]
+        );
+        let lines: Vec<_> = chunks.iter().map(|c| (c.start_line, c.end_line)).collect();
+        assert_eq!(lines, vec![(1, 2), (4, 4), (5, 5)]);
+
+        // One standard deviation above the mean only catches the two shifts too
+        let chunks = chunker
+            .with_threshold(BreakpointThreshold::StdDev(1.0))
+            .chunk(TEXT, ContentType::PlainText, &config(100, 1))
+            .await
+            .unwrap();
+        assert_eq!(chunks.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_respects_token_limits() {
+        // No section may exceed 6 words, even within a topic
+        let chunks = chunker().chunk(TEXT, ContentType::PlainText, &config(6, 1)).await.unwrap();
+        assert!(chunks.iter().all(|c| c.token_count <= 6), "{:?}", chunks);
+        assert!(chunks.iter().all(|c| TEXT.contains(&c.content)));
+
+        // A lone sentence below min_tokens joins its closest neighbour
+        let text = "Cats purr and nap. Cats chase mice all day.\nSnow.\nSQL tables hold rows of data.";
+        let chunks = chunker()
+            .with_threshold(BreakpointThreshold::Percentile(0.0))
+            .chunk(text, ContentType::PlainText, &config(100, 3))
+            .await
+            .unwrap();
+        assert!(chunks.iter().all(|c| c.token_count >= 3), "{:?}", chunks);
+        assert!(!chunks.iter().any(|c| c.content == "Snow."));
+
+        // An oversized sentence is split on words
+        let text = "Cats purr and nap and chase mice and sleep on fur all day long.";
+        let chunks = chunker().chunk(text, ContentType::PlainText, &config(4, 1)).await.unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.token_count <= 4 && c.start_line == 1));
+    }
+}
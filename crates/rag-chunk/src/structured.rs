@@ -0,0 +1,943 @@
+//! Structural chunker for JSON, YAML and TOML.
+//!
+//! Outlines a document as a tree of keyed values with the lines they span,
+//! and chunks it at value boundaries, so each chunk is a subtree (or a run
+//! of sibling subtrees) labelled with its key path.
+
+use std::fmt::Write;
+
+use toml_edit::{ImDocument, InlineTable, Item, Table, Value};
+
+use rag_core::{ChunkConfig, ChunkData, Chunker, ContentType, Result};
+
+use crate::lines::Lines;
+use crate::merge::merge_undersized;
+use crate::RecursiveChunker;
+
+/// Chunker that splits structured data at object and array boundaries.
+///
+/// - Top-level values are chunk boundaries; adjacent small values are merged
+///   up to `max_tokens`.
+/// - A value larger than `max_tokens` is split into its members or items,
+///   recursively, and the pieces are merged again up to `max_tokens`. The
+///   items of a large array thus end up in batches.
+/// - Each chunk records the key path it covers as its heading path, e.g.
+///   `dependencies.tokio.features`, or `servers[0-9]` for a batch of items.
+/// - Chunks below `min_tokens` are merged into a neighbour if the result
+///   fits, and kept as they are otherwise.
+/// - Chunks always consist of whole lines, with exact line numbers.
+///
+/// Documents that fail to parse, and values on a single line that exceed
+/// `max_tokens` (e.g. minified JSON), are split with [`RecursiveChunker`].
+pub struct StructuredChunker {
+    /// Fallback for unparseable documents and oversized lines; also counts
+    /// tokens.
+    recursive: RecursiveChunker,
+}
+
+/// One step of a key path.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    /// Object, mapping or table key.
+    Key(String),
+
+    /// Array items `first..=last`.
+    Index(usize, usize),
+}
+
+/// A value and the lines it spans, including its key.
+#[derive(Debug)]
+struct Node {
+    /// Path from the enclosing value (several steps for dotted keys and
+    /// TOML table headers).
+    steps: Vec<Step>,
+
+    /// First line (0-based).
+    start_row: usize,
+
+    /// Last line (0-based, inclusive).
+    end_row: usize,
+
+    /// Members or items.
+    children: Vec<Node>,
+}
+
+/// A run of whole lines and the key path they share.
+#[derive(Clone)]
+struct Piece {
+    start_row: usize,
+    end_row: usize,
+    path: Vec<Step>,
+
+    /// Whether the lines only frame the children of a value (its key,
+    /// brackets or table header), so they don't narrow the key path of
+    /// the pieces they are merged with.
+    frame: bool,
+}
+
+impl StructuredChunker {
+    /// Create a new structured chunker with default token estimation.
+    pub fn new() -> Self {
+        Self {
+            recursive: RecursiveChunker::new(),
+        }
+    }
+
+    /// Create a chunker with a custom token counter.
+    pub fn with_token_counter<F>(counter: F) -> Self
+    where
+        F: Fn(&str) -> usize + Send + Sync + 'static,
+    {
+        Self {
+            recursive: RecursiveChunker::with_token_counter(counter),
+        }
+    }
+
+    /// Check whether a content type is structured data.
+    pub fn supports(content_type: ContentType) -> bool {
+        matches!(content_type, ContentType::Json | ContentType::Yaml | ContentType::Toml)
+    }
+
+    /// Split a value into pieces that each fit in `max_tokens`, descending
+    /// into its members or items as needed.
+    fn pieces(&self, node: &Node, parent: &[Step], lines: &Lines<'_>, config: &ChunkConfig, out: &mut Vec<Piece>) {
+        let mut path = parent.to_vec();
+        path.extend(node.steps.iter().cloned());
+
+        let text = lines.text(node.start_row, node.end_row);
+        if node.children.is_empty() || self.recursive.count_tokens(text) <= config.max_tokens {
+            out.push(Piece {
+                start_row: node.start_row,
+                end_row: node.end_row,
+                path,
+                frame: false,
+            });
+            return;
+        }
+
+        let (header, footer) = frames(node, &path);
+        out.extend(header);
+        for child in &node.children {
+            self.pieces(child, &path, lines, config, out);
+        }
+        out.extend(footer);
+    }
+
+    /// Merge consecutive pieces up to `max_tokens` and emit them as chunks.
+    fn merge(&self, pieces: &[Piece], lines: &Lines<'_>, config: &ChunkConfig, chunks: &mut Vec<ChunkData>) {
+        let runs = pieces.iter().map(|piece| (piece.start_row, piece.end_row, (piece.path.clone(), piece.frame)));
+        let join = |(path, frame): (Vec<Step>, bool), (next, next_frame): (Vec<Step>, bool)| {
+            let path = if frame {
+                next
+            } else if !next_frame {
+                common_path(&path, &next)
+            } else {
+                path
+            };
+            (path, frame && next_frame)
+        };
+        lines.merge(runs, join, |(path, _)| key_path(path), &self.recursive, config, chunks);
+    }
+}
+
+impl Default for StructuredChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunker for StructuredChunker {
+    fn chunk(
+        &self,
+        content: &str,
+        content_type: ContentType,
+        config: &ChunkConfig,
+    ) -> Result<Vec<ChunkData>> {
+        if content.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let lines = Lines::new(content);
+        let root = match content_type {
+            ContentType::Json => parse_json(&lines, content),
+            ContentType::Yaml => parse_yaml(&lines, content),
+            ContentType::Toml => parse_toml(&lines, content),
+            _ => None,
+        };
+        let Some(root) = root else {
+            return self.recursive.chunk(content, content_type, config);
+        };
+
+        let mut groups: Vec<Vec<Piece>> = root
+            .children
+            .iter()
+            .map(|child| {
+                let mut pieces = Vec::new();
+                self.pieces(child, &root.steps, &lines, config, &mut pieces);
+                pieces
+            })
+            .collect();
+        let (header, footer) = frames(&root, &root.steps);
+        if let (Some(header), Some(first)) = (header, groups.first_mut()) {
+            first.insert(0, header);
+        }
+        if let (Some(footer), Some(last)) = (footer, groups.last_mut()) {
+            last.push(footer);
+        }
+
+        // Small top-level values are merged with each other; an oversized
+        // one is split on its own
+        let mut chunks = Vec::new();
+        let mut small = Vec::new();
+        for mut pieces in groups {
+            if pieces.iter().filter(|piece| !piece.frame).count() <= 1 {
+                small.append(&mut pieces);
+            } else {
+                self.merge(&small, &lines, config, &mut chunks);
+                small.clear();
+                self.merge(&pieces, &lines, config, &mut chunks);
+            }
+        }
+        self.merge(&small, &lines, config, &mut chunks);
+
+        // Comments and blank lines between values, or a document without
+        // any keys
+        lines.fill_gaps(&mut chunks, &self.recursive, config);
+
+        Ok(merge_undersized(chunks, &lines, config, |text| self.recursive.count_tokens(text)))
+    }
+
+    fn supported_types(&self) -> Vec<ContentType> {
+        vec![ContentType::Json, ContentType::Yaml, ContentType::Toml]
+    }
+}
+
+/// Pieces for the lines of a value before its first child and after its
+/// last one.
+fn frames(node: &Node, path: &[Step]) -> (Option<Piece>, Option<Piece>) {
+    let frame = |start_row, end_row| Piece {
+        start_row,
+        end_row,
+        path: path.to_vec(),
+        frame: true,
+    };
+    let (Some(first), Some(last)) = (node.children.first(), node.children.last()) else {
+        return (None, None);
+    };
+
+    let header = (node.start_row < first.start_row).then(|| frame(node.start_row, first.start_row - 1));
+    let footer = (node.end_row > last.end_row).then(|| frame(last.end_row + 1, node.end_row));
+    (header, footer)
+}
+
+/// Key path of merged pieces: the steps they share, with the array items
+/// they cover where they first differ.
+fn common_path(a: &[Step], b: &[Step]) -> Vec<Step> {
+    let common = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let mut path = a[..common].to_vec();
+    if let (Some(Step::Index(a_first, a_last)), Some(Step::Index(b_first, b_last))) = (a.get(common), b.get(common)) {
+        path.push(Step::Index(*a_first.min(b_first), *a_last.max(b_last)));
+    }
+    path
+}
+
+/// Render a key path, e.g. `servers[0-9].ports`.
+fn key_path(path: &[Step]) -> Option<String> {
+    if path.is_empty() {
+        return None;
+    }
+
+    let mut rendered = String::new();
+    for step in path {
+        match step {
+            Step::Key(key) => {
+                if !rendered.is_empty() {
+                    rendered.push('.');
+                }
+                rendered.push_str(key);
+            }
+            Step::Index(first, last) if first == last => {
+                let _ = write!(rendered, "[{}]", first);
+            }
+            Step::Index(first, last) => {
+                let _ = write!(rendered, "[{}-{}]", first, last);
+            }
+        }
+    }
+    Some(rendered)
+}
+
+/// Parse JSON, or JSON Lines, whose values become items of the root.
+fn parse_json(lines: &Lines<'_>, content: &str) -> Option<Node> {
+    let mut flow = Flow::new(lines, content, false);
+    let mut values = Vec::new();
+
+    loop {
+        flow.skip_space();
+        if flow.peek().is_none() {
+            break;
+        }
+        let row = flow.row();
+        values.push(flow.value(vec![Step::Index(values.len(), values.len())], row)?);
+    }
+
+    match values.len() {
+        0 => None,
+        1 => values.pop().map(|root| Node {
+            steps: Vec::new(),
+            ..root
+        }),
+        _ => Some(Node {
+            steps: Vec::new(),
+            start_row: values[0].start_row,
+            end_row: values[values.len() - 1].end_row,
+            children: values,
+        }),
+    }
+}
+
+/// Parse TOML into its top-level keys followed by its tables. Tables are
+/// not nested under their parent tables; `[a.b]` is a table with the key
+/// path `a.b`.
+fn parse_toml(lines: &Lines<'_>, content: &str) -> Option<Node> {
+    let document = ImDocument::parse(content).ok()?;
+    let mut keys = Vec::new();
+    let mut tables = Vec::new();
+    toml_table(document.as_table(), &[], &[], lines, &mut keys, &mut tables)?;
+
+    keys.sort_by_key(|key| key.start_row);
+    tables.sort_by_key(|table| table.start_row);
+    keys.append(&mut tables);
+    Some(Node {
+        steps: Vec::new(),
+        start_row: 0,
+        end_row: keys.last()?.end_row,
+        children: keys,
+    })
+}
+
+/// Add the keys of a TOML table to `keys` and its tables, at any depth, to
+/// `tables`. `path` leads to the table with the enclosing header, and
+/// `dotted` from there to `table`.
+fn toml_table(
+    table: &Table,
+    path: &[Step],
+    dotted: &[Step],
+    lines: &Lines<'_>,
+    keys: &mut Vec<Node>,
+    tables: &mut Vec<Node>,
+) -> Option<()> {
+    for (name, item) in table.iter() {
+        let mut steps = dotted.to_vec();
+        steps.push(Step::Key(name.to_string()));
+
+        match item {
+            Item::Value(value) => {
+                let row = lines.row(table.key(name)?.span()?.start);
+                keys.push(toml_value(value, steps, row, lines)?);
+            }
+            Item::Table(child) if child.is_dotted() => toml_table(child, path, &steps, lines, keys, tables)?,
+            Item::Table(child) => toml_header(child, [path, &steps].concat(), lines, tables)?,
+            Item::ArrayOfTables(array) => {
+                for (index, child) in array.iter().enumerate() {
+                    let mut steps = [path, &steps].concat();
+                    steps.push(Step::Index(index, index));
+                    toml_header(child, steps, lines, tables)?;
+                }
+            }
+            Item::None => {}
+        }
+    }
+    Some(())
+}
+
+/// Add a TOML table with the key path `steps` to `tables`, unless it has no
+/// header, followed by the tables inside it.
+fn toml_header(table: &Table, steps: Vec<Step>, lines: &Lines<'_>, tables: &mut Vec<Node>) -> Option<()> {
+    let mut keys = Vec::new();
+    toml_table(table, &steps, &[], lines, &mut keys, tables)?;
+    if table.is_implicit() {
+        return Some(());
+    }
+
+    keys.sort_by_key(|key| key.start_row);
+    let start_row = lines.row(table.span()?.start);
+    tables.push(Node {
+        steps,
+        start_row,
+        end_row: keys.last().map_or(start_row, |key| key.end_row),
+        children: keys,
+    });
+    Some(())
+}
+
+/// A TOML value whose key starts on `start_row`, with the items of arrays
+/// and the members of inline tables as children.
+fn toml_value(value: &Value, steps: Vec<Step>, start_row: usize, lines: &Lines<'_>) -> Option<Node> {
+    let span = value.span()?;
+    let mut children = Vec::new();
+    match value {
+        Value::Array(array) => {
+            for (index, item) in array.iter().enumerate() {
+                let row = lines.row(item.span()?.start);
+                children.push(toml_value(item, vec![Step::Index(index, index)], row, lines)?);
+            }
+        }
+        Value::InlineTable(table) => toml_inline_table(table, &[], lines, &mut children)?,
+        _ => {}
+    }
+
+    Some(Node {
+        steps,
+        start_row,
+        end_row: lines.row(span.end.saturating_sub(1).max(span.start)),
+        children,
+    })
+}
+
+/// Add the members of a TOML inline table to `members`, prefixing their
+/// key paths with `dotted`.
+fn toml_inline_table(table: &InlineTable, dotted: &[Step], lines: &Lines<'_>, members: &mut Vec<Node>) -> Option<()> {
+    for (name, value) in table.iter() {
+        let mut steps = dotted.to_vec();
+        steps.push(Step::Key(name.to_string()));
+
+        match value {
+            Value::InlineTable(child) if child.is_dotted() => toml_inline_table(child, &steps, lines, members)?,
+            _ => {
+                let row = lines.row(table.key(name)?.span()?.start);
+                members.push(toml_value(value, steps, row, lines)?);
+            }
+        }
+    }
+    members.sort_by_key(|member| member.start_row);
+    Some(())
+}
+
+/// An open YAML mapping entry or sequence item.
+struct Open {
+    node: Node,
+
+    /// Column of the key or dash.
+    col: usize,
+
+    /// Whether this is a key without a value on its line, whose sequence
+    /// may start at the same column (`key:\n- item`).
+    bare_key: bool,
+}
+
+/// Parse the block structure of YAML from indentation. Flow collections
+/// (`[a, b]`, `{a: 1}`) are parsed like JSON; block scalars and plain
+/// multi-line scalars are leaves.
+fn parse_yaml(lines: &Lines<'_>, content: &str) -> Option<Node> {
+    let mut stack: Vec<Open> = Vec::new();
+    let mut root_children: Vec<Node> = Vec::new();
+    let mut last_row = 0;
+    // Lines up to this row belong to a flow value or quoted scalar
+    let mut skip_to: Option<usize> = None;
+    // Lines indented more than this belong to a block scalar
+    let mut scalar_col: Option<usize> = None;
+
+    fn close(stack: &mut Vec<Open>, root_children: &mut Vec<Node>, end_row: usize) {
+        if let Some(mut open) = stack.pop() {
+            open.node.end_row = open.node.end_row.max(end_row);
+            match stack.last_mut() {
+                Some(parent) => parent.node.children.push(open.node),
+                None => root_children.push(open.node),
+            }
+        }
+    }
+
+    for row in 0..lines.len() {
+        let line = lines.text(row, row);
+        let trimmed = line.trim();
+        let indent = line.len() - line.trim_start().len();
+
+        if skip_to.is_some_and(|end| row <= end) {
+            last_row = row;
+            continue;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(col) = scalar_col {
+            if indent > col {
+                last_row = row;
+                continue;
+            }
+            scalar_col = None;
+        }
+        if trimmed.starts_with('#')
+            || trimmed.starts_with('%')
+            || trimmed == "---"
+            || trimmed.starts_with("--- ")
+            || trimmed == "..."
+        {
+            continue;
+        }
+
+        let mut col = indent;
+        loop {
+            let rest = &line[col..];
+            let item = rest == "-" || rest.starts_with("- ");
+            let (key, value_col) = if item {
+                let after = &rest[1..];
+                (None, col + 1 + after.len() - after.trim_start().len())
+            } else {
+                match mapping_key(rest) {
+                    Some((key, value_offset)) => (Some(key), col + value_offset),
+                    None => break,
+                }
+            };
+
+            // Close the entries this one is not nested in
+            while let Some(open) = stack.last() {
+                let nested = col > open.col || (item && open.bare_key && col == open.col);
+                if nested {
+                    break;
+                }
+                close(&mut stack, &mut root_children, last_row);
+            }
+
+            let siblings = match stack.last() {
+                Some(parent) => &parent.node.children,
+                None => &root_children,
+            };
+            let steps = match &key {
+                Some(key) => vec![Step::Key(key.clone())],
+                None => {
+                    let index = siblings
+                        .iter()
+                        .filter(|node| matches!(node.steps.first(), Some(Step::Index(..))))
+                        .count();
+                    vec![Step::Index(index, index)]
+                }
+            };
+
+            let value = line[value_col.min(line.len())..].trim();
+            let mut node = Node {
+                steps,
+                start_row: row,
+                end_row: row,
+                children: Vec::new(),
+            };
+            match value.bytes().next() {
+                Some(b'|' | b'>') => scalar_col = Some(col),
+                Some(b'[' | b'{' | b'"' | b'\'') => {
+                    let mut flow = Flow::new(lines, content, true);
+                    flow.pos = lines.range(row, row).start + value_col;
+                    if let Some(value) = flow.value(Vec::new(), row) {
+                        node.end_row = value.end_row;
+                        node.children = value.children;
+                        skip_to = Some(value.end_row);
+                    }
+                }
+                _ => {}
+            }
+
+            stack.push(Open {
+                node,
+                col,
+                bare_key: key.is_some() && value.is_empty(),
+            });
+
+            // `- key: value` opens a mapping inside the item
+            if !item || value_col >= line.len() {
+                break;
+            }
+            col = value_col;
+        }
+        last_row = last_row.max(row);
+    }
+
+    while !stack.is_empty() {
+        close(&mut stack, &mut root_children, last_row);
+    }
+
+    if root_children.is_empty() {
+        return None;
+    }
+
+    // Comments above the first entry belong to the document
+    Some(Node {
+        steps: Vec::new(),
+        start_row: 0,
+        end_row: last_row,
+        children: root_children,
+    })
+}
+
+/// Key of a YAML mapping entry at the start of `text`, and the offset of
+/// its value.
+fn mapping_key(text: &str) -> Option<(String, usize)> {
+    let (key, after) = match text.bytes().next()? {
+        quote @ (b'"' | b'\'') => {
+            let end = text[1..].find(quote as char)? + 1;
+            (text[1..end].to_string(), end + 1)
+        }
+        b'[' | b'{' | b'#' | b'&' | b'*' | b'!' | b'|' | b'>' | b'?' => return None,
+        _ => {
+            let end = text
+                .match_indices(':')
+                .map(|(i, _)| i)
+                .find(|&i| text[i + 1..].is_empty() || text[i + 1..].starts_with([' ', '\t']))?;
+            let key = text[..end].trim_end();
+            if key.contains(" #") {
+                return None;
+            }
+            (key.to_string(), end)
+        }
+    };
+
+    let rest = &text[after..];
+    let colon = after + rest.len() - rest.trim_start().len();
+    if !text[colon..].starts_with(':') {
+        return None;
+    }
+    Some((key, colon + 1))
+}
+
+/// Scanner for JSON values and the flow values of YAML.
+struct Flow<'a> {
+    lines: &'a Lines<'a>,
+    text: &'a [u8],
+    pos: usize,
+
+    /// Whether `#` starts a comment.
+    comments: bool,
+}
+
+impl<'a> Flow<'a> {
+    fn new(lines: &'a Lines<'a>, content: &'a str, comments: bool) -> Self {
+        Self {
+            lines,
+            text: content.as_bytes(),
+            pos: 0,
+            comments,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    /// Line of the next byte.
+    fn row(&self) -> usize {
+        self.lines.row(self.pos)
+    }
+
+    /// Line of the last byte consumed.
+    fn last_row(&self) -> usize {
+        self.lines.row(self.pos.saturating_sub(1))
+    }
+
+    /// Skip whitespace, newlines and comments.
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                b' ' | b'\t' | b'\r' | b'\n' => self.pos += 1,
+                b'#' if self.comments => {
+                    while self.peek().is_some_and(|c| c != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Skip spaces and tabs.
+    fn skip_inline_space(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Parse a value whose key starts on `start_row`.
+    fn value(&mut self, steps: Vec<Step>, start_row: usize) -> Option<Node> {
+        self.skip_space();
+        let children = match self.peek()? {
+            b'{' => {
+                self.pos += 1;
+                self.object()?
+            }
+            b'[' => {
+                self.pos += 1;
+                self.array()?
+            }
+            b'"' | b'\'' => {
+                self.string()?;
+                Vec::new()
+            }
+            _ => {
+                self.scalar()?;
+                Vec::new()
+            }
+        };
+
+        Some(Node {
+            steps,
+            start_row,
+            end_row: self.last_row(),
+            children,
+        })
+    }
+
+    /// Members of an object or inline table, after its `{`.
+    fn object(&mut self) -> Option<Vec<Node>> {
+        let mut members = Vec::new();
+        loop {
+            self.skip_space();
+            match self.peek()? {
+                b'}' => {
+                    self.pos += 1;
+                    return Some(members);
+                }
+                b',' => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let row = self.row();
+            let key = self.key()?;
+            self.skip_space();
+            if self.peek()? != b':' {
+                return None;
+            }
+            self.pos += 1;
+            members.push(self.value(vec![Step::Key(key)], row)?);
+        }
+    }
+
+    /// Items of an array, after its `[`.
+    fn array(&mut self) -> Option<Vec<Node>> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_space();
+            match self.peek()? {
+                b']' => {
+                    self.pos += 1;
+                    return Some(items);
+                }
+                b',' => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let row = self.row();
+            let index = items.len();
+            items.push(self.value(vec![Step::Index(index, index)], row)?);
+        }
+    }
+
+    /// A quoted string.
+    fn string(&mut self) -> Option<String> {
+        let quote = self.peek()?;
+        self.pos += 1;
+
+        let start = self.pos;
+        loop {
+            let c = self.peek()?;
+            if c == b'\\' && quote == b'"' {
+                self.pos += 2;
+            } else if c == quote {
+                let string = String::from_utf8_lossy(&self.text[start..self.pos]).into_owned();
+                self.pos += 1;
+                return Some(string);
+            } else {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// A number, literal or unquoted string, up to the end of the value.
+    fn scalar(&mut self) -> Option<()> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            let comment =
+                c == b'#' && self.comments && self.pos > start && self.text[self.pos - 1].is_ascii_whitespace();
+            if matches!(c, b',' | b']' | b'}' | b'\n') || comment {
+                break;
+            }
+            self.pos += 1;
+        }
+        (self.pos > start).then_some(())
+    }
+
+    /// A key, quoted or not.
+    fn key(&mut self) -> Option<String> {
+        self.skip_inline_space();
+        if matches!(self.peek()?, b'"' | b'\'') {
+            return self.string();
+        }
+
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_whitespace() || matches!(c, b':' | b',' | b'{' | b'}' | b'[' | b']' | b'#') {
+                break;
+            }
+            self.pos += 1;
+        }
+        (self.pos > start).then(|| String::from_utf8_lossy(&self.text[start..self.pos]).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_exact_lines, config, count_lines};
+
+    /// Chunker counting one token per line.
+    fn line_chunker() -> StructuredChunker {
+        StructuredChunker::with_token_counter(count_lines)
+    }
+
+    fn summary(chunks: &[ChunkData]) -> Vec<(u32, u32, Option<&str>)> {
+        chunks
+            .iter()
+            .map(|c| (c.start_line, c.end_line, c.heading_path.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn test_toml_tables_and_key_paths() {
+        let source = r#"[package]
+name = "demo"
+version = "0.1.0"
+
+[dependencies]
+serde = "1"
+tokio = { version = "1", features = ["full"] }
+
+[dependencies.reqwest]
+version = "0.12"
+features = [
+    "json",
+    "rustls-tls",
+]
+"#;
+        let chunks = line_chunker().chunk(source, ContentType::Toml, &config(4)).unwrap();
+        assert_exact_lines(source, &chunks);
+        assert_eq!(
+            summary(&chunks),
+            vec![
+                (1, 3, Some("package")),
+                (5, 7, Some("dependencies")),
+                (9, 10, Some("dependencies.reqwest.version")),
+                (11, 14, Some("dependencies.reqwest.features")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_toml_dotted_keys_and_arrays_of_tables() {
+        let source = r#"title = "demo"
+owner.name = "ops"
+description = """
+Multi-line
+[not a table]
+"""
+
+[[bin]]
+name = "a"
+path = "src/a.rs"
+
+[[bin]]
+name = "b"
+path = "src/b.rs"
+"#;
+        let chunks = line_chunker().chunk(source, ContentType::Toml, &config(4)).unwrap();
+        assert_exact_lines(source, &chunks);
+        assert_eq!(
+            summary(&chunks),
+            vec![
+                (1, 2, None),
+                (3, 6, Some("description")),
+                (8, 10, Some("bin[0]")),
+                (12, 14, Some("bin[1]")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_array_batches() {
+        let records: Vec<String> = (0..6)
+            .map(|i| format!("    {{\n      \"id\": {},\n      \"name\": \"user{}\"\n    }}", i, i))
+            .collect();
+        let source = format!("{{\n  \"version\": 2,\n  \"users\": [\n{}\n  ]\n}}\n", records.join(",\n"));
+
+        let chunks = line_chunker().chunk(&source, ContentType::Json, &config(10)).unwrap();
+        assert_exact_lines(&source, &chunks);
+        assert_eq!(
+            summary(&chunks),
+            vec![
+                (1, 2, Some("version")),
+                (3, 11, Some("users[0-1]")),
+                (12, 19, Some("users[2-3]")),
+                (20, 29, Some("users[4-5]")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_yaml_mappings_and_sequences() {
+        let source = r#"# Service definitions
+name: demo
+services:
+  web:
+    image: nginx
+    ports:
+      - "80:80"
+      - "443:443"
+  db:
+    image: postgres
+    environment:
+      POSTGRES_PASSWORD: secret
+script: |
+  echo one: two
+  echo done
+steps:
+- run: build
+  shell: bash
+- run: test
+"#;
+        let chunks = line_chunker().chunk(source, ContentType::Yaml, &config(6)).unwrap();
+        assert_exact_lines(source, &chunks);
+        assert_eq!(
+            summary(&chunks),
+            vec![
+                (1, 2, Some("name")),
+                (3, 8, Some("services.web")),
+                (9, 12, Some("services.db")),
+                (13, 15, Some("script")),
+                (16, 19, Some("steps")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_and_minified_documents() {
+        let chunker = StructuredChunker::with_token_counter(|s: &str| s.split_whitespace().count());
+
+        // Not JSON: chunked as text
+        let chunks = chunker.chunk("{ \"a\": ", ContentType::Json, &config(10)).unwrap();
+        assert_eq!(chunks.len(), 1);
+
+        // One long line is split, with every piece on line 1
+        let source = format!("[{}]", (0..40).map(|i| i.to_string()).collect::<Vec<_>>().join(", "));
+        let chunks = chunker.chunk(&source, ContentType::Json, &config(10)).unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.start_line == 1 && c.end_line == 1 && c.token_count <= 10));
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let source = "{\"id\": 1}\n{\"id\": 2}\n{\"id\": 3}\n";
+        let chunks = line_chunker().chunk(source, ContentType::Json, &config(2)).unwrap();
+        assert_eq!(summary(&chunks), vec![(1, 2, Some("[0-1]")), (3, 3, Some("[2]"))]);
+    }
+}
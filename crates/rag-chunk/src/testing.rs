@@ -0,0 +1,26 @@
+//! Fixtures shared by the chunker tests.
+
+use rag_core::{ChunkConfig, ChunkData};
+
+/// Config with `max_tokens`, chunks of any size above it and no overlap.
+pub(crate) fn config(max_tokens: usize) -> ChunkConfig {
+    ChunkConfig {
+        max_tokens,
+        min_tokens: 1,
+        overlap_tokens: 0,
+    }
+}
+
+/// Token counter counting one token per line.
+pub(crate) fn count_lines(text: &str) -> usize {
+    text.lines().count()
+}
+
+/// Check that each chunk is exactly the source lines it claims to cover.
+pub(crate) fn assert_exact_lines(source: &str, chunks: &[ChunkData]) {
+    let lines: Vec<&str> = source.lines().collect();
+    for chunk in chunks {
+        let expected = lines[chunk.start_line as usize - 1..chunk.end_line as usize].join("\n");
+        assert_eq!(chunk.content, expected);
+    }
+}
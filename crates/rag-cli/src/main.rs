@@ -1,14 +1,33 @@
 //! RAG CLI - Command-line interface for the RAG knowledge base.
 
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
 use clap::{Parser, Subcommand};
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
+use rag_core::{decode_text, ContentType, DecodedText, Detection, RagConfig, Resolution};
 use rag_mcp::{CollectionParams, IngestParams, RagMcpServer, SearchParams};
 
+/// Bytes read from a file of unknown type to find a modeline or `#!` line.
+const SNIFF_BYTES: u64 = 8192;
+
+/// Directories never ingested recursively, besides hidden ones such as `.git`.
+const SKIPPED_DIRS: &[&str] = &["CVS", "_darcs", "node_modules"];
+
+/// Generated lock files, not worth searching.
+const LOCK_FILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "pnpm-lock.yaml",
+    "yarn.lock",
+    "poetry.lock",
+    "Gemfile.lock",
+    "composer.lock",
+];
+
 /// RAG - Local Retrieval-Augmented Generation knowledge base
 #[derive(Parser)]
 #[command(name = "rag")]
@@ -18,6 +37,10 @@ struct Cli {
     #[arg(short, long, global = true)]
     database: Option<PathBuf>,
 
+    /// Config file path (default: ~/.config/rag-mcp/config.toml or ./rag-mcp.toml)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
     /// Enable verbose logging
     #[arg(short, long, global = true)]
     verbose: bool,
@@ -40,6 +63,11 @@ enum Commands {
         /// Collection to search (searches all if not specified)
         #[arg(short, long)]
         collection: Option<String>,
+
+        /// Return the enclosing function or section of each hit, up to
+        /// this many tokens (needs `chunking.hierarchical` at ingest time)
+        #[arg(long, value_name = "TOKENS")]
+        parent_budget: Option<usize>,
     },
 
     /// Ingest a file or directory into the knowledge base
@@ -56,6 +84,14 @@ enum Commands {
         recursive: bool,
     },
 
+    /// Recompute chunk headers and embeddings of a collection, after
+    /// changing `chunking.context_header` or the embedding settings
+    Reembed {
+        /// Collection to re-embed
+        #[arg(short, long)]
+        collection: String,
+    },
+
     /// Manage collections
     Collection {
         #[command(subcommand)]
@@ -71,6 +107,86 @@ enum Commands {
 
     /// Initialize the database
     Init,
+
+    /// Multi-node synchronization
+    Sync {
+        #[command(subcommand)]
+        action: SyncAction,
+    },
+
+    /// Manage this node's identity
+    Node {
+        #[command(subcommand)]
+        action: NodeAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum NodeAction {
+    /// Give a copied database a new random node ID
+    ResetId,
+}
+
+#[derive(Subcommand)]
+enum SyncAction {
+    /// Show replication status for each peer
+    Status,
+
+    /// Sync with configured peers now
+    Now {
+        /// Only sync with this peer
+        #[arg(long)]
+        peer: Option<String>,
+
+        /// Compare range digests instead of pulling from the watermark
+        #[arg(long)]
+        full: bool,
+    },
+
+    /// List conflicts between documents edited on two nodes
+    Conflicts {
+        /// Maximum number of conflicts to show
+        #[arg(long, default_value = "20")]
+        limit: u32,
+    },
+
+    /// Resolve a logged conflict again
+    Resolve {
+        /// Conflict ID (from `rag sync conflicts`)
+        id: i64,
+
+        /// Version to keep: local, remote or both
+        #[arg(long)]
+        keep: Resolution,
+    },
+
+    /// Export changes to a bundle file for offline sync
+    Export {
+        /// Only export changes after this HLC (defaults to the previous export)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Bundle file to write
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+
+    /// Apply a bundle file exported by another node
+    Import {
+        /// Bundle file to read
+        bundle: PathBuf,
+    },
+
+    /// Generate a self-signed TLS certificate for the sync server
+    GenCert {
+        /// Directory to write the certificate and key to
+        #[arg(long)]
+        dir: PathBuf,
+
+        /// Host name or IP address the certificate is valid for (repeatable)
+        #[arg(long = "host", default_value = "localhost")]
+        hosts: Vec<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -105,6 +221,13 @@ fn get_db_path(db: Option<PathBuf>) -> PathBuf {
     home.join(".rag").join("db.sqlite")
 }
 
+fn load_config(path: Option<PathBuf>) -> Result<RagConfig, Box<dyn std::error::Error>> {
+    match path {
+        Some(path) => Ok(RagConfig::load(&path)?),
+        None => Ok(RagConfig::load_default()?),
+    }
+}
+
 fn setup_logging(verbose: bool) {
     let level = if verbose { Level::DEBUG } else { Level::WARN };
     let subscriber = FmtSubscriber::builder()
@@ -121,29 +244,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     setup_logging(cli.verbose);
 
     let db_path = get_db_path(cli.database);
+    let config = load_config(cli.config)?;
 
     match cli.command {
         Commands::Init => {
-            init_database(&db_path)?;
+            init_database(&db_path, &config)?;
         }
         Commands::Search {
             query,
             top_k,
             collection,
+            parent_budget,
         } => {
-            let server = get_server(&db_path)?;
-            search(&server, &query, top_k, collection).await;
+            let server = get_server(&db_path, &config)?;
+            search(&server, &query, top_k, collection, parent_budget).await;
         }
         Commands::Ingest {
             path,
             collection,
             recursive,
         } => {
-            let server = get_server(&db_path)?;
+            let server = get_server(&db_path, &config)?
+                .with_chunking(config.chunking)
+                .with_embedding(config.embedding);
             ingest(&server, &path, &collection, recursive).await?;
         }
+        Commands::Reembed { collection } => {
+            let server = get_server(&db_path, &config)?
+                .with_chunking(config.chunking)
+                .with_embedding(config.embedding);
+            reembed(&server, &collection).await;
+        }
         Commands::Collection { action } => {
-            let server = get_server(&db_path)?;
+            let server = get_server(&db_path, &config)?;
             match action {
                 CollectionAction::List => {
                     list_collections(&server).await;
@@ -157,27 +290,62 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
         Commands::Stats { collection } => {
-            let server = get_server(&db_path)?;
+            let server = get_server(&db_path, &config)?;
             stats(&server, collection.as_deref()).await;
         }
+        Commands::Sync { action } => match action {
+            SyncAction::Status => {
+                let server = get_server(&db_path, &config)?;
+                sync_status(&server).await;
+            }
+            SyncAction::Now { peer, full } => {
+                let server = get_server(&db_path, &config)?;
+                sync_now(&server, &config, peer.as_deref(), full).await;
+            }
+            SyncAction::Conflicts { limit } => {
+                let server = get_server(&db_path, &config)?;
+                list_conflicts(&server, limit).await;
+            }
+            SyncAction::Resolve { id, keep } => {
+                let server = get_server(&db_path, &config)?;
+                resolve_conflict(&server, id, keep).await;
+            }
+            SyncAction::Export { since, output } => {
+                let server = get_server(&db_path, &config)?;
+                sync_export(&server, since.as_deref(), &output).await;
+            }
+            SyncAction::Import { bundle } => {
+                let server = get_server(&db_path, &config)?;
+                sync_import(&server, &config, &bundle).await;
+            }
+            SyncAction::GenCert { dir, hosts } => {
+                gen_cert(&dir, &hosts)?;
+            }
+        },
+        Commands::Node { action } => match action {
+            NodeAction::ResetId => {
+                let server = get_server(&db_path, &config)?;
+                reset_node_id(&server);
+            }
+        },
     }
 
     Ok(())
 }
 
-fn init_database(db_path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+fn init_database(db_path: &PathBuf, config: &RagConfig) -> Result<(), Box<dyn std::error::Error>> {
     // Create parent directory if needed
     if let Some(parent) = db_path.parent() {
         fs::create_dir_all(parent)?;
     }
 
     // Create the database by opening the server
-    let _server = RagMcpServer::new(db_path)?;
+    let _server = RagMcpServer::open(db_path, &config.sync)?;
     println!("Initialized database at: {}", db_path.display());
     Ok(())
 }
 
-fn get_server(db_path: &PathBuf) -> Result<RagMcpServer, Box<dyn std::error::Error>> {
+fn get_server(db_path: &PathBuf, config: &RagConfig) -> Result<RagMcpServer, Box<dyn std::error::Error>> {
     // Check if database directory exists
     if let Some(parent) = db_path.parent() {
         if !parent.exists() {
@@ -188,14 +356,21 @@ fn get_server(db_path: &PathBuf) -> Result<RagMcpServer, Box<dyn std::error::Err
         }
     }
 
-    Ok(RagMcpServer::new(db_path)?)
+    Ok(RagMcpServer::open(db_path, &config.sync)?)
 }
 
-async fn search(server: &RagMcpServer, query: &str, top_k: u32, collection: Option<String>) {
+async fn search(
+    server: &RagMcpServer,
+    query: &str,
+    top_k: u32,
+    collection: Option<String>,
+    parent_token_budget: Option<usize>,
+) {
     let params = SearchParams {
         query: query.to_string(),
         top_k,
         collection,
+        parent_token_budget,
     };
 
     let result = server.search(params).await;
@@ -230,14 +405,18 @@ async fn ingest(
     let mut error_count = 0;
 
     for file_path in files {
-        let content = match fs::read_to_string(&file_path) {
-            Ok(c) => c,
+        let bytes = match fs::read(&file_path) {
+            Ok(b) => b,
             Err(e) => {
                 eprintln!("  Error reading {}: {}", file_path.display(), e);
                 error_count += 1;
                 continue;
             }
         };
+        let Some(DecodedText { text: content, .. }) = decode_text(&bytes) else {
+            println!("  {} - Skipped (binary)", file_path.display());
+            continue;
+        };
 
         let source_uri = format!("file://{}", file_path.canonicalize()?.display());
 
@@ -245,7 +424,7 @@ async fn ingest(
             collection: collection.to_string(),
             source_uri,
             content,
-            content_type: detect_content_type(&file_path),
+            content_type: None,
         };
 
         let result = server.ingest(params).await;
@@ -277,10 +456,18 @@ fn collect_files(path: &PathBuf, recursive: bool) -> Result<Vec<PathBuf>, std::i
         for entry in fs::read_dir(path)? {
             let entry = entry?;
             let entry_path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
 
-            if entry_path.is_file() && is_supported_file(&entry_path) {
-                files.push(entry_path);
-            } else if entry_path.is_dir() && recursive {
+            if entry_path.is_file() {
+                if !LOCK_FILES.contains(&name.as_ref()) && is_supported_file(&entry_path) {
+                    files.push(entry_path);
+                }
+            } else if entry_path.is_dir()
+                && recursive
+                && !name.starts_with('.')
+                && !SKIPPED_DIRS.contains(&name.as_ref())
+            {
                 files.extend(collect_files(&entry_path, recursive)?);
             }
         }
@@ -289,49 +476,17 @@ fn collect_files(path: &PathBuf, recursive: bool) -> Result<Vec<PathBuf>, std::i
     Ok(files)
 }
 
+/// Check whether a file has a known content type: by its name, such as
+/// `main.rs`, `Makefile` or `LICENSE`, or for other files by a modeline or
+/// `#!` line.
 fn is_supported_file(path: &PathBuf) -> bool {
-    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-    matches!(
-        ext,
-        "rs" | "py"
-            | "js"
-            | "ts"
-            | "tsx"
-            | "jsx"
-            | "go"
-            | "md"
-            | "txt"
-            | "json"
-            | "yaml"
-            | "yml"
-            | "toml"
-            | "html"
-            | "css"
-            | "c"
-            | "cpp"
-            | "h"
-            | "hpp"
-            | "java"
-            | "rb"
-            | "sh"
-    )
-}
+    if ContentType::from_path(&path.to_string_lossy()) != ContentType::Unknown {
+        return true;
+    }
 
-fn detect_content_type(path: &PathBuf) -> Option<String> {
-    let ext = path.extension().and_then(|e| e.to_str())?;
-    let content_type = match ext {
-        "rs" => "rust",
-        "py" => "python",
-        "js" => "javascript",
-        "ts" | "tsx" => "typescript",
-        "go" => "go",
-        "md" => "markdown",
-        "json" => "json",
-        "yaml" | "yml" => "yaml",
-        "toml" => "toml",
-        _ => return None,
-    };
-    Some(content_type.to_string())
+    let mut head = Vec::new();
+    let read = fs::File::open(path).and_then(|file| file.take(SNIFF_BYTES).read_to_end(&mut head));
+    read.is_ok() && decode_text(&head).is_some_and(|decoded| Detection::declared(&decoded.text).is_some())
 }
 
 async fn list_collections(server: &RagMcpServer) {
@@ -369,6 +524,16 @@ async fn delete_collection(server: &RagMcpServer, name: &str) {
     }
 }
 
+async fn reembed(server: &RagMcpServer, collection: &str) {
+    let result = server.reembed(collection).await;
+    if result.success {
+        println!("{}", result.message);
+    } else {
+        eprintln!("Error: {}", result.message);
+        std::process::exit(1);
+    }
+}
+
 async fn stats(server: &RagMcpServer, collection: Option<&str>) {
     let result = server.stats(collection).await;
     if result.success {
@@ -378,3 +543,83 @@ async fn stats(server: &RagMcpServer, collection: Option<&str>) {
         std::process::exit(1);
     }
 }
+
+async fn sync_status(server: &RagMcpServer) {
+    let result = server.sync_status().await;
+    if result.success {
+        println!("{}", result.message);
+    } else {
+        eprintln!("Error: {}", result.message);
+        std::process::exit(1);
+    }
+}
+
+async fn sync_now(server: &RagMcpServer, config: &RagConfig, peer: Option<&str>, full: bool) {
+    let result = server.sync_now(&config.sync, peer, full).await;
+    if result.success {
+        println!("{}", result.message);
+    } else {
+        eprintln!("Error: {}", result.message);
+        std::process::exit(1);
+    }
+}
+
+async fn list_conflicts(server: &RagMcpServer, limit: u32) {
+    let result = server.list_conflicts(limit).await;
+    if result.success {
+        println!("{}", result.message);
+    } else {
+        eprintln!("Error: {}", result.message);
+        std::process::exit(1);
+    }
+}
+
+async fn resolve_conflict(server: &RagMcpServer, id: i64, keep: Resolution) {
+    let result = server.resolve_conflict(id, keep).await;
+    if result.success {
+        println!("{}", result.message);
+    } else {
+        eprintln!("Error: {}", result.message);
+        std::process::exit(1);
+    }
+}
+
+async fn sync_export(server: &RagMcpServer, since: Option<&str>, output: &Path) {
+    let result = server.sync_export(since, output).await;
+    if result.success {
+        println!("{}", result.message);
+    } else {
+        eprintln!("Error: {}", result.message);
+        std::process::exit(1);
+    }
+}
+
+async fn sync_import(server: &RagMcpServer, config: &RagConfig, bundle: &Path) {
+    let result = server.sync_import(&config.sync, bundle).await;
+    if result.success {
+        println!("{}", result.message);
+    } else {
+        eprintln!("Error: {}", result.message);
+        std::process::exit(1);
+    }
+}
+
+fn reset_node_id(server: &RagMcpServer) {
+    let result = server.reset_node_id();
+    if result.success {
+        println!("{}", result.message);
+    } else {
+        eprintln!("Error: {}", result.message);
+        std::process::exit(1);
+    }
+}
+
+fn gen_cert(dir: &Path, hosts: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (cert, key) = rag_sync::tls::write_self_signed(dir, hosts)?;
+    println!("Certificate: {}", cert.display());
+    println!("Private key: {}", key.display());
+    println!();
+    println!("Set sync.tls_cert and sync.tls_key to these paths, and give peers");
+    println!("the certificate as their ca_cert.");
+    Ok(())
+}
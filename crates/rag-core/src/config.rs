@@ -1,8 +1,12 @@
 //! Configuration types for the RAG system.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+use crate::conflict::ConflictPolicy;
+use crate::types::{ContentType, Document};
+
 /// Main configuration for the RAG system.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RagConfig {
@@ -45,8 +49,10 @@ pub struct DatabaseConfig {
     /// Path to SQLite database file.
     pub path: PathBuf,
 
-    /// Node ID for distributed sync (0-65535).
-    pub node_id: u16,
+    /// Fixed node ID for distributed sync (1-65535). By default a random ID
+    /// is generated when the database is created and stored in it.
+    #[serde(default)]
+    pub node_id: Option<u16>,
 
     /// Enable WAL mode (recommended).
     #[serde(default = "default_true")]
@@ -65,7 +71,7 @@ impl Default for DatabaseConfig {
     fn default() -> Self {
         Self {
             path: default_database_path(),
-            node_id: 1,
+            node_id: None,
             wal_mode: true,
             cache_size: -64000, // 64MB
             busy_timeout_ms: 30000,
@@ -90,6 +96,12 @@ pub struct EmbeddingConfig {
     /// Number of threads for CPU inference.
     #[serde(default = "default_num_threads")]
     pub num_threads: usize,
+
+    /// Embed chunks in the context of their whole document (late chunking)
+    /// instead of one by one. Contextual headers are not embedded then, as
+    /// the document itself gives each chunk its context.
+    #[serde(default)]
+    pub late_chunking: bool,
 }
 
 impl Default for EmbeddingConfig {
@@ -99,6 +111,7 @@ impl Default for EmbeddingConfig {
             batch_size: 32,
             use_gpu: false,
             num_threads: 4,
+            late_chunking: false,
         }
     }
 }
@@ -121,6 +134,27 @@ pub struct ChunkingConfig {
     /// Use AST-aware chunking for code.
     #[serde(default = "default_true")]
     pub ast_aware: bool,
+
+    /// Also store the enclosing items and sections of chunks, so search can
+    /// return a parent instead of the matching chunk.
+    #[serde(default)]
+    pub hierarchical: bool,
+
+    /// Embedding-based chunking of prose.
+    #[serde(default)]
+    pub semantic: SemanticChunkingConfig,
+
+    /// Header prepended to chunks when embedding and keyword indexing them.
+    #[serde(default)]
+    pub context_header: ContextHeaderConfig,
+
+    /// Rules sending documents to a specific chunker.
+    #[serde(default)]
+    pub routes: Vec<ChunkerRoute>,
+
+    /// Chunker and chunk size overrides by collection name.
+    #[serde(default)]
+    pub collections: HashMap<String, CollectionChunkingConfig>,
 }
 
 impl Default for ChunkingConfig {
@@ -130,7 +164,176 @@ impl Default for ChunkingConfig {
             min_tokens: 50,
             overlap_tokens: 0,
             ast_aware: true,
+            hierarchical: false,
+            semantic: SemanticChunkingConfig::default(),
+            context_header: ContextHeaderConfig::default(),
+            routes: Vec::new(),
+            collections: HashMap::new(),
+        }
+    }
+}
+
+/// Which fields go into the contextual header of a chunk. The header is
+/// embedded and keyword-indexed with the chunk, so a chunk that never names
+/// its file or section can still be found by them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextHeaderConfig {
+    /// Document title (`title` metadata or first Markdown heading).
+    #[serde(default = "default_true")]
+    pub title: bool,
+
+    /// Source path or URI of the document.
+    #[serde(default = "default_true")]
+    pub path: bool,
+
+    /// Language of the document, for code and markup.
+    #[serde(default = "default_true")]
+    pub language: bool,
+
+    /// Enclosing symbol or heading path of the chunk.
+    #[serde(default = "default_true")]
+    pub section: bool,
+}
+
+impl Default for ContextHeaderConfig {
+    fn default() -> Self {
+        Self {
+            title: true,
+            path: true,
+            language: true,
+            section: true,
+        }
+    }
+}
+
+impl ContextHeaderConfig {
+    /// Build the header of a chunk of `doc` whose heading path is `section`,
+    /// or `None` if no enabled field has a value.
+    pub fn header(&self, doc: &Document, section: Option<&str>) -> Option<String> {
+        let mut lines = Vec::new();
+
+        if self.title {
+            if let Some(title) = doc.title() {
+                lines.push(format!("Title: {}", title));
+            }
+        }
+        if self.path && !doc.source_uri.starts_with("data:") {
+            let path = doc.source_uri.strip_prefix("file://").unwrap_or(&doc.source_uri);
+            lines.push(format!("Path: {}", path));
+        }
+        let language = !matches!(
+            doc.content_type,
+            ContentType::Chat | ContentType::PlainText | ContentType::Unknown
+        );
+        if self.language && language {
+            lines.push(format!("Language: {}", doc.content_type));
+        }
+        if self.section {
+            if let Some(section) = section {
+                lines.push(format!("Section: {}", section));
+            }
         }
+
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+}
+
+/// Rule sending the documents it matches to a named chunker.
+///
+/// Glob rules are tried first, then MIME type rules, then the collection's
+/// chunker, then content type rules, each in order; a rule with several
+/// criteria is tried for each of them.
+///
+/// ```toml
+/// [[chunking.routes]]
+/// glob = "docs/**/*.txt"
+/// chunker = "markdown"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkerRoute {
+    /// Glob matched against the path of the source URI, e.g. `*.proto` or
+    /// `docs/**/*.txt`.
+    #[serde(default)]
+    pub glob: Option<String>,
+
+    /// MIME type of the document, e.g. `text/html` or `application/*`.
+    #[serde(default)]
+    pub mime_type: Option<String>,
+
+    /// Content type of the document.
+    #[serde(default)]
+    pub content_type: Option<ContentType>,
+
+    /// Name of the chunker: one of the built-in chunkers (`adaptive`,
+    /// `recursive`, `ast`, `markdown`, `html`, `structured`, `chat`) or one
+    /// registered by the application.
+    pub chunker: String,
+}
+
+/// Chunking overrides for one collection; unset fields keep the global
+/// value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CollectionChunkingConfig {
+    /// Chunker for documents no glob or MIME type rule matches.
+    #[serde(default)]
+    pub chunker: Option<String>,
+
+    /// Maximum tokens per chunk.
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+
+    /// Minimum tokens per chunk.
+    #[serde(default)]
+    pub min_tokens: Option<usize>,
+
+    /// Token overlap for sliding window.
+    #[serde(default)]
+    pub overlap_tokens: Option<usize>,
+}
+
+/// Semantic chunking configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SemanticChunkingConfig {
+    /// Collections whose plain text and Markdown documents are split where
+    /// the topic shifts, judged by sentence embeddings.
+    #[serde(default)]
+    pub collections: Vec<String>,
+
+    /// Where to place breakpoints.
+    #[serde(default)]
+    pub threshold: BreakpointThreshold,
+}
+
+impl SemanticChunkingConfig {
+    /// Check whether a collection uses semantic chunking.
+    pub fn applies_to(&self, collection: &str) -> bool {
+        self.collections.iter().any(|name| name == collection)
+    }
+}
+
+/// Rule for placing a breakpoint between two sentences, based on the
+/// cosine distance between their embeddings.
+///
+/// Written as `{ percentile = 95.0 }` or `{ std_dev = 1.5 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakpointThreshold {
+    /// Break where the distance is above this percentile (0-100) of all
+    /// distances in the document.
+    Percentile(f32),
+
+    /// Break where the distance is more than this many standard deviations
+    /// above the mean.
+    StdDev(f32),
+}
+
+impl Default for BreakpointThreshold {
+    fn default() -> Self {
+        Self::Percentile(95.0)
     }
 }
 
@@ -193,6 +396,31 @@ pub struct SyncConfig {
     /// HTTP bind address for sync server.
     #[serde(default = "default_bind_address")]
     pub bind_address: String,
+
+    /// Maximum milliseconds a remote HLC may be ahead of local time before it is rejected.
+    #[serde(default = "default_max_clock_drift")]
+    pub max_clock_drift_ms: u64,
+
+    /// Upper bound in seconds for the retry backoff of a failing peer.
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff_secs: u64,
+
+    /// Seconds between scheduled reconciles by range digest, which repair
+    /// divergence the watermarks miss (0 disables them).
+    #[serde(default = "default_reconcile_interval")]
+    pub reconcile_interval_secs: u64,
+
+    /// PEM certificate for serving sync over TLS (requires `tls_key`).
+    #[serde(default)]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for `tls_cert`.
+    #[serde(default)]
+    pub tls_key: Option<PathBuf>,
+
+    /// How documents edited concurrently on two nodes are resolved.
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
 }
 
 impl Default for SyncConfig {
@@ -202,6 +430,12 @@ impl Default for SyncConfig {
             interval_secs: 60,
             peers: Vec::new(),
             bind_address: "0.0.0.0:8765".to_string(),
+            max_clock_drift_ms: crate::hlc::DEFAULT_MAX_DRIFT_MS,
+            max_backoff_secs: 3600,
+            reconcile_interval_secs: 86400,
+            tls_cert: None,
+            tls_key: None,
+            conflict_policy: ConflictPolicy::default(),
         }
     }
 }
@@ -214,6 +448,92 @@ pub struct PeerConfig {
 
     /// Peer endpoint URL.
     pub endpoint: String,
+
+    /// Collections replicated with this peer.
+    #[serde(default)]
+    pub collections: CollectionFilter,
+
+    /// What to do with local copies of collections the filter excludes.
+    #[serde(default)]
+    pub on_excluded: ExcludedPolicy,
+
+    /// Shared secret for signing requests to and from this peer.
+    ///
+    /// The sync server rejects unsigned requests as soon as any peer has one.
+    #[serde(default)]
+    pub secret: Option<String>,
+
+    /// PEM certificate to trust for an `https://` endpoint, e.g. the peer's
+    /// self-signed certificate.
+    #[serde(default)]
+    pub ca_cert: Option<PathBuf>,
+}
+
+/// Include/exclude list of collection names.
+///
+/// An empty `include` list allows every collection; `exclude` always wins.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollectionFilter {
+    /// Collections to replicate (empty = all).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    /// Collections never to replicate.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl CollectionFilter {
+    /// A filter allowing every collection.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Check whether the filter allows every collection.
+    pub fn is_all(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Check whether a collection is replicated.
+    pub fn allows(&self, collection: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|c| c == collection))
+            && !self.exclude.iter().any(|c| c == collection)
+    }
+
+    /// Filter allowing only the collections both filters allow.
+    pub fn intersect(&self, other: &CollectionFilter) -> CollectionFilter {
+        let mut exclude = self.exclude.clone();
+        exclude.extend(other.exclude.iter().filter(|c| !self.exclude.contains(c)).cloned());
+
+        let include = if self.include.is_empty() {
+            other.include.clone()
+        } else if other.include.is_empty() {
+            self.include.clone()
+        } else {
+            let common: Vec<String> = self.include.iter().filter(|c| other.include.contains(c)).cloned().collect();
+            if common.is_empty() {
+                // An empty include list would allow every collection
+                exclude.extend(self.include.iter().cloned());
+                self.include.clone()
+            } else {
+                common
+            }
+        };
+        CollectionFilter { include, exclude }
+    }
+}
+
+/// Handling of collections that a peer's filter excludes after they were
+/// already replicated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExcludedPolicy {
+    /// Keep the local copy but stop replicating changes to it.
+    #[default]
+    Stop,
+
+    /// Remove the local copy, unless another peer still replicates it.
+    Purge,
 }
 
 // Default value functions
@@ -270,6 +590,18 @@ fn default_bind_address() -> String {
     "0.0.0.0:8765".to_string()
 }
 
+fn default_max_clock_drift() -> u64 {
+    crate::hlc::DEFAULT_MAX_DRIFT_MS
+}
+
+fn default_max_backoff() -> u64 {
+    3600
+}
+
+fn default_reconcile_interval() -> u64 {
+    86400
+}
+
 fn default_database_path() -> PathBuf {
     dirs::data_local_dir()
         .unwrap_or_else(|| PathBuf::from("."))
@@ -330,10 +662,120 @@ mod tests {
         assert_eq!(config.chunking.max_tokens, 512);
     }
 
+    #[test]
+    fn test_collection_filter() {
+        assert!(CollectionFilter::all().allows("notes"));
+
+        let filter = CollectionFilter {
+            include: vec!["notes".to_string(), "vendor-docs".to_string()],
+            exclude: vec!["vendor-docs".to_string()],
+        };
+        assert!(filter.allows("notes"));
+        assert!(!filter.allows("vendor-docs"));
+        assert!(!filter.allows("other"));
+
+        let only = |names: &[&str]| CollectionFilter {
+            include: names.iter().map(|n| n.to_string()).collect(),
+            exclude: Vec::new(),
+        };
+        let both = filter.intersect(&CollectionFilter::all());
+        assert!(both.allows("notes") && !both.allows("vendor-docs"));
+        let both = only(&["notes", "code"]).intersect(&only(&["code"]));
+        assert!(both.allows("code") && !both.allows("notes"));
+        let both = only(&["notes"]).intersect(&only(&["code"]));
+        assert!(!both.allows("notes") && !both.allows("code") && !both.allows("other"));
+    }
+
+    #[test]
+    fn test_peer_config_filters() {
+        let config: SyncConfig = toml::from_str(
+            r#"
+            [[peers]]
+            id = "server"
+            endpoint = "http://server:8765"
+            on_excluded = "purge"
+
+            [peers.collections]
+            exclude = ["vendor-docs"]
+            "#,
+        )
+        .unwrap();
+
+        let peer = &config.peers[0];
+        assert_eq!(peer.on_excluded, ExcludedPolicy::Purge);
+        assert!(!peer.collections.allows("vendor-docs"));
+        assert!(peer.collections.allows("notes"));
+    }
+
+    #[test]
+    fn test_semantic_chunking_config() {
+        let config: ChunkingConfig = toml::from_str(
+            r#"
+            [semantic]
+            collections = ["essays"]
+            threshold = { std_dev = 1.5 }
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.semantic.applies_to("essays"));
+        assert!(!config.semantic.applies_to("code"));
+        assert_eq!(config.semantic.threshold, BreakpointThreshold::StdDev(1.5));
+        assert_eq!(ChunkingConfig::default().semantic.threshold, BreakpointThreshold::Percentile(95.0));
+    }
+
+    #[test]
+    fn test_chunker_routes_config() {
+        let config: ChunkingConfig = toml::from_str(
+            r#"
+            [[routes]]
+            glob = "**/*.proto"
+            chunker = "proto"
+
+            [[routes]]
+            content_type = "chat"
+            chunker = "recursive"
+
+            [collections.transcripts]
+            chunker = "chat"
+            max_tokens = 256
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.routes.len(), 2);
+        assert_eq!(config.routes[0].glob.as_deref(), Some("**/*.proto"));
+        assert_eq!(config.routes[1].content_type, Some(ContentType::Chat));
+        let transcripts = &config.collections["transcripts"];
+        assert_eq!(transcripts.chunker.as_deref(), Some("chat"));
+        assert_eq!((transcripts.max_tokens, transcripts.min_tokens), (Some(256), None));
+    }
+
+    #[test]
+    fn test_context_header() {
+        let doc = Document::new(
+            "docs",
+            "file://docs/install.md",
+            "# Install guide\n\n## Linux\n\nRun it",
+            ContentType::Markdown,
+        );
+        let config = ContextHeaderConfig::default();
+        assert_eq!(
+            config.header(&doc, Some("Install guide > Linux")).as_deref(),
+            Some("Title: Install guide\nPath: docs/install.md\nLanguage: Markdown\nSection: Install guide > Linux")
+        );
+
+        let config: ContextHeaderConfig = toml::from_str("path = false\nlanguage = false").unwrap();
+        assert_eq!(config.header(&doc, None).as_deref(), Some("Title: Install guide"));
+
+        let note = Document::new("notes", "data:", "plain words", ContentType::PlainText);
+        assert_eq!(config.header(&note, None), None);
+    }
+
     #[test]
     fn test_database_config_default() {
         let config = DatabaseConfig::default();
         assert!(config.wal_mode);
-        assert_eq!(config.node_id, 1);
+        assert_eq!(config.node_id, None);
     }
 }
@@ -0,0 +1,279 @@
+//! Resolution of documents edited concurrently on different nodes.
+//!
+//! Documents are immutable; editing one means re-ingesting its source URI,
+//! which replaces the old document with a new one. Two nodes that re-ingest
+//! the same URI without having seen each other's version end up with two
+//! live documents for it. When the second one arrives through sync, a
+//! [`ConflictResolver`] decides which version keeps the URI.
+//!
+//! Every resolved conflict is recorded with both versions, so it can be
+//! listed and resolved differently later.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::RagError;
+use crate::types::Document;
+
+/// How a conflict between a local and a remote document was resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// The local version keeps the URI; the remote one is not stored.
+    KeepLocal,
+
+    /// The remote version replaces the local one.
+    TakeRemote,
+
+    /// Both are kept; the older one moves to a [`conflict_uri`].
+    KeepBoth,
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::KeepLocal => "local",
+            Self::TakeRemote => "remote",
+            Self::KeepBoth => "both",
+        })
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = RagError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(Self::KeepLocal),
+            "remote" => Ok(Self::TakeRemote),
+            "both" => Ok(Self::KeepBoth),
+            _ => Err(RagError::invalid_argument(format!(
+                "Unknown resolution '{}' (expected local, remote or both)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Decides which of two conflicting versions of a document wins.
+pub trait ConflictResolver: Send + Sync {
+    /// Policy name recorded in the conflict log.
+    fn name(&self) -> String;
+
+    /// Resolve a conflict between the local document and an incoming one.
+    fn resolve(&self, local: &Document, remote: &Document) -> Resolution;
+}
+
+/// The version with the higher HLC wins. Every node reaches the same result.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LastWriterWins;
+
+impl ConflictResolver for LastWriterWins {
+    fn name(&self) -> String {
+        "lww".to_string()
+    }
+
+    fn resolve(&self, local: &Document, remote: &Document) -> Resolution {
+        if remote.hlc > local.hlc {
+            Resolution::TakeRemote
+        } else {
+            Resolution::KeepLocal
+        }
+    }
+}
+
+/// The local version always wins, so nodes may keep different versions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreferLocal;
+
+impl ConflictResolver for PreferLocal {
+    fn name(&self) -> String {
+        "prefer-local".to_string()
+    }
+
+    fn resolve(&self, _local: &Document, _remote: &Document) -> Resolution {
+        Resolution::KeepLocal
+    }
+}
+
+/// The version written on the given node wins; otherwise last writer wins.
+#[derive(Debug, Clone, Copy)]
+pub struct PreferNode(pub u16);
+
+impl ConflictResolver for PreferNode {
+    fn name(&self) -> String {
+        format!("prefer-node:{}", self.0)
+    }
+
+    fn resolve(&self, local: &Document, remote: &Document) -> Resolution {
+        match (local.hlc.node_id == self.0, remote.hlc.node_id == self.0) {
+            (true, false) => Resolution::KeepLocal,
+            (false, true) => Resolution::TakeRemote,
+            _ => LastWriterWins.resolve(local, remote),
+        }
+    }
+}
+
+/// Both versions are kept. The newer one keeps the URI and the older one
+/// moves to a [`conflict_uri`], the same way on every node.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeepBoth;
+
+impl ConflictResolver for KeepBoth {
+    fn name(&self) -> String {
+        "keep-both".to_string()
+    }
+
+    fn resolve(&self, _local: &Document, _remote: &Document) -> Resolution {
+        Resolution::KeepBoth
+    }
+}
+
+/// URI a document moves to when it loses a keep-both conflict.
+pub fn conflict_uri(doc: &Document) -> String {
+    format!("{}#conflict-{}", doc.source_uri, doc.id)
+}
+
+/// Conflict policy as configured in `[sync]`.
+///
+/// Written as `"lww"`, `"prefer-local"`, `"prefer-node:<id>"` or `"keep-both"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ConflictPolicy {
+    /// See [`LastWriterWins`].
+    #[default]
+    LastWriterWins,
+
+    /// See [`PreferLocal`].
+    PreferLocal,
+
+    /// See [`PreferNode`].
+    PreferNode(u16),
+
+    /// See [`KeepBoth`].
+    KeepBoth,
+}
+
+impl ConflictPolicy {
+    /// Resolver implementing this policy.
+    pub fn resolver(&self) -> Arc<dyn ConflictResolver> {
+        match *self {
+            Self::LastWriterWins => Arc::new(LastWriterWins),
+            Self::PreferLocal => Arc::new(PreferLocal),
+            Self::PreferNode(node_id) => Arc::new(PreferNode(node_id)),
+            Self::KeepBoth => Arc::new(KeepBoth),
+        }
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.resolver().name())
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = RagError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lww" => Ok(Self::LastWriterWins),
+            "prefer-local" => Ok(Self::PreferLocal),
+            "keep-both" => Ok(Self::KeepBoth),
+            _ => s
+                .strip_prefix("prefer-node:")
+                .and_then(|id| id.parse().ok())
+                .map(Self::PreferNode)
+                .ok_or_else(|| {
+                    RagError::invalid_argument(format!(
+                        "Unknown conflict policy '{}' (expected lww, prefer-local, prefer-node:<id> or keep-both)",
+                        s
+                    ))
+                }),
+        }
+    }
+}
+
+impl TryFrom<String> for ConflictPolicy {
+    type Error = RagError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ConflictPolicy> for String {
+    fn from(policy: ConflictPolicy) -> Self {
+        policy.to_string()
+    }
+}
+
+/// A resolved conflict from the conflict log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictRecord {
+    /// Log entry ID.
+    pub id: i64,
+
+    /// Collection of both documents.
+    pub collection: String,
+
+    /// Source URI both documents were ingested from.
+    pub source_uri: String,
+
+    /// Version that was stored locally.
+    pub local: Document,
+
+    /// Version that arrived through sync.
+    pub remote: Document,
+
+    /// Name of the policy that resolved it, or `manual`.
+    pub policy: String,
+
+    /// How it was resolved.
+    pub resolution: Resolution,
+
+    /// When it was last resolved (Unix millis).
+    pub resolved_at: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hlc::HybridLogicalClock;
+    use crate::types::ContentType;
+
+    fn doc(content: &str, wall_time: u64, node_id: u16) -> Document {
+        let mut doc = Document::new("notes", "file://a.md", content, ContentType::Markdown);
+        doc.hlc = HybridLogicalClock::from_parts(wall_time, 0, node_id);
+        doc
+    }
+
+    #[test]
+    fn test_builtin_resolvers() {
+        let local = doc("mine", 10, 1);
+        let remote = doc("theirs", 20, 2);
+
+        assert_eq!(LastWriterWins.resolve(&local, &remote), Resolution::TakeRemote);
+        assert_eq!(LastWriterWins.resolve(&remote, &local), Resolution::KeepLocal);
+        assert_eq!(PreferLocal.resolve(&local, &remote), Resolution::KeepLocal);
+        assert_eq!(PreferNode(1).resolve(&local, &remote), Resolution::KeepLocal);
+        assert_eq!(PreferNode(2).resolve(&local, &remote), Resolution::TakeRemote);
+        assert_eq!(PreferNode(3).resolve(&local, &remote), Resolution::TakeRemote);
+        assert_eq!(KeepBoth.resolve(&local, &remote), Resolution::KeepBoth);
+        assert!(conflict_uri(&local).starts_with("file://a.md#conflict-"));
+    }
+
+    #[test]
+    fn test_conflict_policy_parse() {
+        for policy in ["lww", "prefer-local", "prefer-node:7", "keep-both"] {
+            assert_eq!(policy.parse::<ConflictPolicy>().unwrap().to_string(), policy);
+        }
+        assert_eq!("prefer-node:7".parse::<ConflictPolicy>().unwrap(), ConflictPolicy::PreferNode(7));
+        assert!("prefer-node:x".parse::<ConflictPolicy>().is_err());
+        assert!("newest".parse::<ConflictPolicy>().is_err());
+        assert_eq!("both".parse::<Resolution>().unwrap(), Resolution::KeepBoth);
+    }
+}
@@ -0,0 +1,428 @@
+//! Content type and text encoding detection.
+//!
+//! The content type of a document comes from its path when that is telling
+//! (file name or extension), and is otherwise sniffed from its content:
+//! `#!` lines, editor modelines and the signatures of HTML, JSON, YAML,
+//! TOML and Markdown. Each detection carries a confidence, so callers can
+//! tell a guess from a certainty.
+
+use serde_json::Value;
+
+use crate::types::ContentType;
+
+/// Confidence of a type given explicitly by the caller.
+pub const EXPLICIT_CONFIDENCE: f32 = 1.0;
+
+/// Confidence of an editor modeline, e.g. `# vim: set ft=python:`.
+const MODELINE_CONFIDENCE: f32 = 0.95;
+
+/// Confidence of a known file name or extension.
+const PATH_CONFIDENCE: f32 = 0.9;
+
+/// Confidence of a `#!` line.
+const SHEBANG_CONFIDENCE: f32 = 0.85;
+
+/// Confidence of text that matched no signature.
+const PLAIN_TEXT_CONFIDENCE: f32 = 0.2;
+
+/// Lines at the start and end of a file searched for modelines.
+const MODELINE_LINES: usize = 5;
+
+/// Bytes inspected when telling text from binary data.
+const SNIFF_BYTES: usize = 8192;
+
+/// A detected content type and how sure the detection is, from 0 (no
+/// idea) to 1 (given explicitly).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    /// Detected content type.
+    pub content_type: ContentType,
+
+    /// Confidence in the detection.
+    pub confidence: f32,
+}
+
+/// Text encoding of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8, with or without byte order mark.
+    Utf8,
+
+    /// UTF-16, little endian.
+    Utf16Le,
+
+    /// UTF-16, big endian.
+    Utf16Be,
+
+    /// ISO-8859-1, read as its Windows-1252 superset.
+    Latin1,
+}
+
+/// File content decoded as text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedText {
+    /// Decoded text, without byte order mark.
+    pub text: String,
+
+    /// Encoding the text was decoded from.
+    pub encoding: Encoding,
+}
+
+impl Detection {
+    /// A content type given by the caller.
+    pub fn explicit(content_type: ContentType) -> Self {
+        Self {
+            content_type,
+            confidence: EXPLICIT_CONFIDENCE,
+        }
+    }
+
+    /// Detect the content type of a document from its path and content,
+    /// keeping the most confident of the path, a modeline, a `#!` line and
+    /// content signatures.
+    pub fn detect(path: &str, content: &str) -> Self {
+        let from_path = match ContentType::from_path(path) {
+            ContentType::Unknown => None,
+            content_type => Some(Self::new(content_type, PATH_CONFIDENCE)),
+        };
+
+        [from_path, modeline(content), shebang(content)]
+            .into_iter()
+            .flatten()
+            .chain(std::iter::once(Self::sniff(content)))
+            .fold(Self::new(ContentType::Unknown, 0.0), |best, detection| {
+                if detection.confidence > best.confidence {
+                    detection
+                } else {
+                    best
+                }
+            })
+    }
+
+    /// Content type the text declares itself, with a modeline or a `#!`
+    /// line.
+    pub fn declared(content: &str) -> Option<Self> {
+        modeline(content).or_else(|| shebang(content))
+    }
+
+    /// Detect the content type of text from its signature alone.
+    pub fn sniff(content: &str) -> Self {
+        let text = content.trim_start_matches('\u{feff}').trim_start();
+        if text.is_empty() {
+            return Self::new(ContentType::Unknown, 0.0);
+        }
+
+        [html(text), json(text), yaml(text), toml(text), markdown(text)]
+            .into_iter()
+            .flatten()
+            .fold(Self::new(ContentType::PlainText, PLAIN_TEXT_CONFIDENCE), |best, detection| {
+                if detection.confidence > best.confidence {
+                    detection
+                } else {
+                    best
+                }
+            })
+    }
+
+    fn new(content_type: ContentType, confidence: f32) -> Self {
+        Self {
+            content_type,
+            confidence,
+        }
+    }
+}
+
+/// Decode file content as text, or return `None` for binary data.
+///
+/// A byte order mark selects UTF-8 or UTF-16. Without one, text with NUL
+/// bytes at every other position is read as UTF-16, valid UTF-8 as UTF-8,
+/// and anything else as Latin-1, unless control characters show it is not
+/// text at all.
+pub fn decode_text(bytes: &[u8]) -> Option<DecodedText> {
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        if let Ok(text) = std::str::from_utf8(rest) {
+            return Some(DecodedText {
+                text: text.to_string(),
+                encoding: Encoding::Utf8,
+            });
+        }
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return Some(decode_utf16(rest, Encoding::Utf16Le));
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        return Some(decode_utf16(rest, Encoding::Utf16Be));
+    }
+
+    let sample = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    if sample.contains(&0) {
+        // ASCII text in UTF-16 has a NUL in every other byte
+        let zeros = |parity: usize| sample.iter().skip(parity).step_by(2).filter(|&&b| b == 0).count();
+        let (even, odd) = (zeros(0), zeros(1));
+        let half = sample.len() / 2;
+        return if odd * 5 >= half * 2 && even * 20 <= half {
+            Some(decode_utf16(bytes, Encoding::Utf16Le))
+        } else if even * 5 >= half * 2 && odd * 20 <= half {
+            Some(decode_utf16(bytes, Encoding::Utf16Be))
+        } else {
+            None
+        };
+    }
+
+    // Text has few control characters besides whitespace and escapes
+    let controls = sample
+        .iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B)) || b == 0x7F)
+        .count();
+    if controls * 10 > sample.len() {
+        return None;
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some(DecodedText {
+            text: text.to_string(),
+            encoding: Encoding::Utf8,
+        }),
+        Err(_) => Some(DecodedText {
+            text: bytes.iter().map(|&b| latin1_char(b)).collect(),
+            encoding: Encoding::Latin1,
+        }),
+    }
+}
+
+fn decode_utf16(bytes: &[u8], encoding: Encoding) -> DecodedText {
+    let units = bytes.chunks_exact(2).map(|pair| match encoding {
+        Encoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+        _ => u16::from_le_bytes([pair[0], pair[1]]),
+    });
+    let text = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    DecodedText { text, encoding }
+}
+
+/// Windows-1252 character of a byte; it only differs from Latin-1 in
+/// `0x80..=0x9F`, which Latin-1 reserves for control characters.
+fn latin1_char(byte: u8) -> char {
+    const HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}', '\u{90}',
+        '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+    ];
+    match byte {
+        0x80..=0x9F => HIGH[usize::from(byte - 0x80)],
+        _ => char::from(byte),
+    }
+}
+
+/// Type named by a vim or Emacs modeline near the start or end of the
+/// content, e.g. `vim: set ft=python:` or `-*- mode: ruby -*-`.
+fn modeline(content: &str) -> Option<Detection> {
+    let lines: Vec<&str> = content.lines().collect();
+    let tail = lines.len().saturating_sub(MODELINE_LINES).max(MODELINE_LINES.min(lines.len()));
+    let candidates = lines[..MODELINE_LINES.min(lines.len())].iter().chain(&lines[tail..]);
+
+    candidates
+        .filter_map(|line| vim_modeline(line).or_else(|| emacs_modeline(line)))
+        .map(|name| match name.to_lowercase().as_str() {
+            "make" => ContentType::Makefile,
+            "text" => ContentType::PlainText,
+            name => ContentType::from_name(name),
+        })
+        .find(|content_type| *content_type != ContentType::Unknown)
+        .map(|content_type| Detection::new(content_type, MODELINE_CONFIDENCE))
+}
+
+/// `vim: set ft=python:` or `vi: filetype=python`.
+fn vim_modeline(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(i, _)| *i == 0 || line[..*i].ends_with(char::is_whitespace))
+            .map(|(i, marker)| i + marker.len())
+    })?;
+
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            ["ft=", "filetype=", "syntax=", "syn="]
+                .iter()
+                .find_map(|key| option.strip_prefix(key))
+        })
+        .filter(|name| !name.is_empty())
+}
+
+/// `-*- mode: python -*-` or `-*- python -*-`.
+fn emacs_modeline(line: &str) -> Option<&str> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let vars = line[start..end].trim();
+
+    if !vars.contains(':') {
+        return Some(vars).filter(|mode| !mode.is_empty());
+    }
+    vars.split(';').find_map(|var| {
+        let (key, value) = var.split_once(':')?;
+        (key.trim().eq_ignore_ascii_case("mode")).then(|| value.trim())
+    })
+}
+
+fn shebang(content: &str) -> Option<Detection> {
+    let first_line = content.trim_start_matches('\u{feff}').lines().next()?;
+    match ContentType::from_shebang(first_line) {
+        ContentType::Unknown => None,
+        content_type => Some(Detection::new(content_type, SHEBANG_CONFIDENCE)),
+    }
+}
+
+fn html(text: &str) -> Option<Detection> {
+    let head: String = text.chars().take(1024).collect::<String>().to_ascii_lowercase();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        return Some(Detection::new(ContentType::Html, 0.8));
+    }
+    let tags = ["<head", "<body", "<div", "<p>", "<title", "<meta", "<script"];
+    (head.starts_with('<') && tags.iter().any(|tag| head.contains(tag)))
+        .then(|| Detection::new(ContentType::Html, 0.5))
+}
+
+/// A JSON document, JSON Lines, or JSONL chat messages.
+fn json(text: &str) -> Option<Detection> {
+    if !text.starts_with(['{', '[']) {
+        return None;
+    }
+    let message = |record: &Value| {
+        let record = record.get("message").filter(|m| m.is_object()).unwrap_or(record);
+        ["role", "speaker", "author"].iter().any(|key| record.get(key).is_some())
+            && ["content", "text"].iter().any(|key| record.get(key).is_some())
+    };
+
+    // One object per line, mostly chat messages
+    let records: Option<Vec<Value>> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).ok())
+        .collect();
+    if let Some(records) = &records {
+        if records.iter().filter(|record| message(record)).count() * 2 > records.len() {
+            return Some(Detection::new(ContentType::Chat, 0.75));
+        }
+    }
+
+    if serde_json::from_str::<Value>(text).is_ok() {
+        Some(Detection::new(ContentType::Json, 0.8))
+    } else {
+        records.map(|_| Detection::new(ContentType::Json, 0.7))
+    }
+}
+
+fn yaml(text: &str) -> Option<Detection> {
+    if text.starts_with("%YAML") || text.starts_with("---\n") || text.starts_with("---\r\n") {
+        return Some(Detection::new(ContentType::Yaml, 0.6));
+    }
+
+    // Mostly `key: value` and `- item` lines
+    let lines: Vec<&str> = significant_lines(text, '#').collect();
+    let mapping = |line: &&&str| {
+        let line = line.trim_start();
+        line.starts_with("- ")
+            || line.split_once(':').is_some_and(|(key, rest)| {
+                !key.is_empty()
+                    && !key.contains(char::is_whitespace)
+                    && (rest.is_empty() || rest.starts_with(' '))
+            })
+    };
+    (lines.len() >= 3 && lines.iter().filter(mapping).count() * 10 >= lines.len() * 9)
+        .then(|| Detection::new(ContentType::Yaml, 0.4))
+}
+
+fn toml(text: &str) -> Option<Detection> {
+    let lines: Vec<&str> = significant_lines(text, '#').collect();
+    let table = |line: &&str| line.starts_with('[') && line.ends_with(']');
+    let assignment = |line: &&str| {
+        line.split_once(" = ")
+            .is_some_and(|(key, _)| !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || "_-.\"'".contains(c)))
+    };
+    let matching = lines.iter().filter(|line| table(line) || assignment(line)).count();
+    (lines.iter().any(table) && lines.iter().any(assignment) && matching * 10 >= lines.len() * 9)
+        .then(|| Detection::new(ContentType::Toml, 0.5))
+}
+
+fn markdown(text: &str) -> Option<Detection> {
+    let headings = text
+        .lines()
+        .filter(|line| {
+            let hashes = line.len() - line.trim_start_matches('#').len();
+            (1..=6).contains(&hashes) && line[hashes..].starts_with(' ')
+        })
+        .count();
+    let fences = text.lines().filter(|line| line.trim_start().starts_with("```")).count();
+    (headings > 0 || fences >= 2).then(|| Detection::new(ContentType::Markdown, 0.4))
+}
+
+/// Trimmed lines that are neither blank nor comments.
+fn significant_lines(text: &str, comment: char) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim)
+        .filter(move |line| !line.is_empty() && !line.starts_with(comment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detected(path: &str, content: &str) -> (ContentType, f32) {
+        let detection = Detection::detect(path, content);
+        (detection.content_type, detection.confidence)
+    }
+
+    #[test]
+    fn test_detect_from_path_and_content() {
+        assert_eq!(detected("src/lib.rs", "fn main() {}"), (ContentType::Rust, PATH_CONFIDENCE));
+        assert_eq!(detected("bin/deploy", "#!/usr/bin/env bash\nset -e"), (ContentType::Shell, SHEBANG_CONFIDENCE));
+        assert_eq!(detected("notes.txt", "# vim: set ft=markdown:\n"), (ContentType::Markdown, MODELINE_CONFIDENCE));
+        assert_eq!(detected("build", "# -*- mode: ruby; coding: utf-8 -*-\n"), (ContentType::Ruby, MODELINE_CONFIDENCE));
+        assert_eq!(detected("LICENSE", "MIT License\n\nCopyright").0, ContentType::PlainText);
+        assert_eq!(detected("v1.2/notes", "").0, ContentType::Unknown);
+        assert_eq!(Detection::declared("#!/bin/sh\n").map(|d| d.content_type), Some(ContentType::Shell));
+        assert_eq!(Detection::declared("ref: refs/heads/main\n"), None);
+        assert_eq!(detected("https://example.com/docs/", "<!DOCTYPE html><p>Hi").0, ContentType::Html);
+        assert_eq!(detected("https://example.com/a.md?raw=1", "text").0, ContentType::Markdown);
+    }
+
+    #[test]
+    fn test_sniff_signatures() {
+        let sniffed = |content: &str| Detection::sniff(content).content_type;
+        assert_eq!(sniffed("<html><body>hi</body></html>"), ContentType::Html);
+        assert_eq!(sniffed("{\"a\": [1, 2]}"), ContentType::Json);
+        assert_eq!(sniffed("{\"a\": 1}\n{\"a\": 2}\n"), ContentType::Json);
+        assert_eq!(sniffed("{\"role\": \"user\", \"content\": \"hi\"}\n"), ContentType::Chat);
+        assert_eq!(sniffed("---\nname: app\n"), ContentType::Yaml);
+        assert_eq!(sniffed("name: app\nports:\n  - 80\n  - 443\n"), ContentType::Yaml);
+        assert_eq!(sniffed("[package]\nname = \"app\"\nversion = \"1.0\"\n"), ContentType::Toml);
+        assert_eq!(sniffed("# Title\n\nSome text.\n"), ContentType::Markdown);
+        assert_eq!(sniffed("Dear team: thanks. See you: soon\nBye"), ContentType::PlainText);
+        assert!(Detection::sniff("{not json").confidence <= PLAIN_TEXT_CONFIDENCE);
+    }
+
+    #[test]
+    fn test_decode_text() {
+        let decoded = decode_text("héllo".as_bytes()).unwrap();
+        assert_eq!((decoded.text.as_str(), decoded.encoding), ("héllo", Encoding::Utf8));
+
+        let decoded = decode_text(b"\xEF\xBB\xBFbom").unwrap();
+        assert_eq!(decoded.text, "bom");
+
+        let utf16le: Vec<u8> = "hé\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let decoded = decode_text(&[&[0xFF, 0xFE], &utf16le[..]].concat()).unwrap();
+        assert_eq!((decoded.text.as_str(), decoded.encoding), ("hé\n", Encoding::Utf16Le));
+        let decoded = decode_text(&utf16le).unwrap();
+        assert_eq!((decoded.text.as_str(), decoded.encoding), ("hé\n", Encoding::Utf16Le));
+
+        let utf16be: Vec<u8> = "plain text".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(decode_text(&utf16be).unwrap().encoding, Encoding::Utf16Be);
+
+        let decoded = decode_text(b"caf\xE9 \x93quoted\x94").unwrap();
+        assert_eq!((decoded.text.as_str(), decoded.encoding), ("café “quoted”", Encoding::Latin1));
+
+        assert_eq!(decode_text(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\x00\x00"), None);
+        assert_eq!(decode_text(&[0x01, 0x02, 0x03, 0x04, b'a']), None);
+    }
+}
@@ -52,6 +52,18 @@ pub enum RagError {
     #[error("Sync error: {message}")]
     Sync { message: String },
 
+    /// Remote HLC is too far ahead of local wall time.
+    #[error("Clock drift: HLC {hlc} is {drift_ms}ms ahead of local time (max {max_drift_ms}ms)")]
+    ClockDrift {
+        hlc: String,
+        drift_ms: u64,
+        max_drift_ms: u64,
+    },
+
+    /// Request could not be authenticated.
+    #[error("Unauthorized: {message}")]
+    Unauthorized { message: String },
+
     /// IO error.
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
@@ -105,6 +117,13 @@ impl RagError {
         }
     }
 
+    /// Create an unauthorized error.
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::Unauthorized {
+            message: message.into(),
+        }
+    }
+
     /// Create an internal error.
     pub fn internal(message: impl Into<String>) -> Self {
         Self::Internal {
@@ -126,6 +145,8 @@ impl RagError {
             Self::Embedding { .. } => "EMBEDDING_ERROR",
             Self::Chunking { .. } => "CHUNKING_ERROR",
             Self::Sync { .. } => "SYNC_ERROR",
+            Self::ClockDrift { .. } => "CLOCK_DRIFT",
+            Self::Unauthorized { .. } => "UNAUTHORIZED",
             Self::Io(_) => "IO_ERROR",
             Self::Serialization(_) => "SERIALIZATION_ERROR",
             Self::Config { .. } => "CONFIG_ERROR",
@@ -5,8 +5,66 @@
 
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::error::{RagError, Result};
+
+/// Default maximum amount a remote HLC may run ahead of local wall time (1 minute).
+pub const DEFAULT_MAX_DRIFT_MS: u64 = 60_000;
+
+/// Source of physical time for the HLC.
+///
+/// Injected so that clock skew and backwards steps can be tested deterministically.
+pub trait Clock: Send + Sync {
+    /// Current wall time in milliseconds since Unix epoch.
+    fn now_millis(&self) -> u64;
+}
+
+/// Clock backed by the system wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    }
+}
+
+/// Manually controlled clock for tests and simulations.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    /// Create a clock starting at the given time.
+    pub fn new(now_millis: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now_millis),
+        }
+    }
+
+    /// Set the current time (may move backwards).
+    pub fn set(&self, now_millis: u64) {
+        self.now.store(now_millis, AtomicOrdering::SeqCst);
+    }
+
+    /// Advance the current time.
+    pub fn advance(&self, millis: u64) {
+        self.now.fetch_add(millis, AtomicOrdering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.now.load(AtomicOrdering::SeqCst)
+    }
+}
+
 /// Hybrid Logical Clock for causality tracking.
 ///
 /// Format: 14 bytes
@@ -28,13 +86,13 @@ pub struct HybridLogicalClock {
 impl HybridLogicalClock {
     /// Create a new HLC with the current wall time.
     pub fn new(node_id: u16) -> Self {
-        let wall_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
+        Self::new_with_clock(node_id, &SystemClock)
+    }
 
+    /// Create a new HLC with the time reported by `clock`.
+    pub fn new_with_clock(node_id: u16, clock: &dyn Clock) -> Self {
         Self {
-            wall_time,
+            wall_time: clock.now_millis(),
             logical: 0,
             node_id,
         }
@@ -62,10 +120,15 @@ impl HybridLogicalClock {
     ///
     /// Returns a new HLC that is guaranteed to be greater than the current one.
     pub fn tick(&self) -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
+        self.tick_with_clock(&SystemClock)
+    }
+
+    /// Tick the clock using the time reported by `clock`.
+    ///
+    /// If the clock has stepped backwards, the logical counter is advanced instead
+    /// so the result is still greater than `self`.
+    pub fn tick_with_clock(&self, clock: &dyn Clock) -> Self {
+        let now = clock.now_millis();
 
         if now > self.wall_time {
             Self {
@@ -74,40 +137,70 @@ impl HybridLogicalClock {
                 node_id: self.node_id,
             }
         } else {
-            Self {
-                wall_time: self.wall_time,
-                logical: self.logical + 1,
-                node_id: self.node_id,
-            }
+            Self::after(self.wall_time, self.logical, self.node_id)
+        }
+    }
+
+    /// The next HLC after `wall_time` and `logical`, moving on to the next
+    /// millisecond once the logical counter is exhausted.
+    fn after(wall_time: u64, logical: u32, node_id: u16) -> Self {
+        match logical.checked_add(1) {
+            Some(logical) => Self {
+                wall_time,
+                logical,
+                node_id,
+            },
+            None => Self {
+                wall_time: wall_time.saturating_add(1),
+                logical: 0,
+                node_id,
+            },
         }
     }
 
     /// Merge with a received HLC (on message receive).
     ///
     /// Returns a new HLC that is greater than both self and the received HLC.
-    pub fn merge(&self, other: &Self) -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
+    /// Fails if the received HLC is more than [`DEFAULT_MAX_DRIFT_MS`] ahead of
+    /// the system clock.
+    pub fn merge(&self, other: &Self) -> Result<Self> {
+        self.merge_with_clock(other, &SystemClock, DEFAULT_MAX_DRIFT_MS)
+    }
+
+    /// Merge with a received HLC using the time reported by `clock`.
+    ///
+    /// Rejects `other` with [`RagError::ClockDrift`] if its wall time is more than
+    /// `max_drift_ms` ahead of `clock`, so a peer with a runaway clock cannot drag
+    /// every local timestamp into the future.
+    pub fn merge_with_clock(&self, other: &Self, clock: &dyn Clock, max_drift_ms: u64) -> Result<Self> {
+        let now = clock.now_millis();
+
+        let drift_ms = other.wall_time.saturating_sub(now);
+        if drift_ms > max_drift_ms {
+            return Err(RagError::ClockDrift {
+                hlc: other.to_hex(),
+                drift_ms,
+                max_drift_ms,
+            });
+        }
 
         let max_wall = now.max(self.wall_time).max(other.wall_time);
 
         let logical = if max_wall == self.wall_time && max_wall == other.wall_time {
-            self.logical.max(other.logical) + 1
+            self.logical.max(other.logical)
         } else if max_wall == self.wall_time {
-            self.logical + 1
+            self.logical
         } else if max_wall == other.wall_time {
-            other.logical + 1
+            other.logical
         } else {
-            0
+            return Ok(Self {
+                wall_time: max_wall,
+                logical: 0,
+                node_id: self.node_id,
+            });
         };
 
-        Self {
-            wall_time: max_wall,
-            logical,
-            node_id: self.node_id,
-        }
+        Ok(Self::after(max_wall, logical, self.node_id))
     }
 
     /// Convert to big-endian bytes for storage/comparison.
@@ -224,12 +317,75 @@ mod tests {
         let local = HybridLogicalClock::from_parts(1000, 5, 1);
         let remote = HybridLogicalClock::from_parts(1000, 10, 2);
 
-        let merged = local.merge(&remote);
+        let merged = local.merge(&remote).unwrap();
         assert!(merged > local);
         assert!(merged > remote);
         assert_eq!(merged.node_id, 1); // Keeps local node_id
     }
 
+    #[test]
+    fn test_hlc_tick_clock_backwards() {
+        let clock = ManualClock::new(10_000);
+        let hlc1 = HybridLogicalClock::new_with_clock(1, &clock);
+
+        // Clock steps backwards; ticks must still be monotonic
+        clock.set(5_000);
+        let hlc2 = hlc1.tick_with_clock(&clock);
+        let hlc3 = hlc2.tick_with_clock(&clock);
+
+        assert!(hlc2 > hlc1);
+        assert!(hlc3 > hlc2);
+        assert_eq!(hlc3.wall_time, 10_000);
+        assert_eq!(hlc3.logical, 2);
+
+        // Once the clock catches up, wall time resumes
+        clock.set(20_000);
+        let hlc4 = hlc3.tick_with_clock(&clock);
+        assert_eq!(hlc4.wall_time, 20_000);
+        assert_eq!(hlc4.logical, 0);
+    }
+
+    #[test]
+    fn test_hlc_logical_overflow() {
+        let clock = ManualClock::new(1_000);
+        let hlc = HybridLogicalClock::from_parts(1_000, u32::MAX, 1);
+
+        let next = hlc.tick_with_clock(&clock);
+        assert!(next > hlc);
+        assert_eq!((next.wall_time, next.logical), (1_001, 0));
+
+        let remote = HybridLogicalClock::from_parts(1_000, u32::MAX, 2);
+        let merged = hlc.merge_with_clock(&remote, &clock, 1_000).unwrap();
+        assert!(merged > remote);
+        assert_eq!((merged.wall_time, merged.logical, merged.node_id), (1_001, 0, 1));
+    }
+
+    #[test]
+    fn test_hlc_merge_with_clock() {
+        let clock = ManualClock::new(1_000);
+        let local = HybridLogicalClock::new_with_clock(1, &clock);
+        let remote = HybridLogicalClock::from_parts(1_500, 3, 2);
+
+        let merged = local.merge_with_clock(&remote, &clock, 1_000).unwrap();
+        assert_eq!(merged.wall_time, 1_500);
+        assert_eq!(merged.logical, 4);
+        assert_eq!(merged.node_id, 1);
+    }
+
+    #[test]
+    fn test_hlc_merge_rejects_drift() {
+        let clock = ManualClock::new(1_000);
+        let local = HybridLogicalClock::new_with_clock(1, &clock);
+        let remote = HybridLogicalClock::from_parts(1_000 + 5_001, 0, 2);
+
+        let err = local.merge_with_clock(&remote, &clock, 5_000).unwrap_err();
+        assert_eq!(err.error_code(), "CLOCK_DRIFT");
+
+        // Exactly at the limit is accepted
+        let remote = HybridLogicalClock::from_parts(1_000 + 5_000, 0, 2);
+        assert!(local.merge_with_clock(&remote, &clock, 5_000).is_ok());
+    }
+
     #[test]
     fn test_hlc_bytes_roundtrip() {
         let hlc = HybridLogicalClock::from_parts(1234567890, 42, 7);
@@ -4,13 +4,22 @@
 //! used throughout the rag-mcp system.
 
 pub mod config;
+pub mod conflict;
+pub mod detect;
+pub mod digest;
 pub mod error;
 pub mod hlc;
 pub mod traits;
 pub mod types;
 
 pub use config::*;
+pub use conflict::{
+    conflict_uri, ConflictPolicy, ConflictRecord, ConflictResolver, KeepBoth, LastWriterWins, PreferLocal,
+    PreferNode, Resolution,
+};
+pub use detect::{decode_text, DecodedText, Detection, Encoding};
+pub use digest::{KeyRange, RangeDigest, SyncItem};
 pub use error::{RagError, Result};
-pub use hlc::HybridLogicalClock;
+pub use hlc::{Clock, HybridLogicalClock, ManualClock, SystemClock};
 pub use traits::*;
 pub use types::*;
@@ -1,9 +1,16 @@
 //! Core traits defining the interfaces between components.
 
+use std::collections::HashMap;
+use std::ops::Range;
+
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
-use crate::error::Result;
+use crate::config::CollectionFilter;
+use crate::conflict::{ConflictRecord, ConflictResolver, LastWriterWins, Resolution};
+use crate::digest::{self, KeyRange, RangeDigest, SyncItem};
+use crate::error::{RagError, Result};
 use crate::hlc::HybridLogicalClock;
 use crate::types::{Chunk, Collection, ContentType, Document, Stats};
 
@@ -29,7 +36,14 @@ pub trait Store: Send + Sync {
     async fn get_chunk(&self, id: Ulid) -> Result<Option<Chunk>>;
     async fn delete_chunks_for_document(&self, doc_id: Ulid) -> Result<()>;
 
+    /// Replace the contextual headers of existing chunks.
+    async fn update_chunk_contexts(&self, contexts: &[(Ulid, Option<String>)]) -> Result<()>;
+
     // Embedding operations
+
+    /// Store embeddings for chunks, replacing any they already have.
+    ///
+    /// The chunks count as changed, so their new embeddings replicate.
     async fn insert_embeddings(&self, chunk_ids: &[Ulid], embeddings: &[Vec<f32>]) -> Result<()>;
 
     // Search operations
@@ -51,20 +65,110 @@ pub trait Store: Send + Sync {
     async fn get_stats(&self, collection: Option<&str>) -> Result<Stats>;
 
     // Sync operations
+    /// ID of this node, embedded in every HLC it issues.
+    fn node_id(&self) -> u16;
     async fn get_watermark(&self) -> Result<HybridLogicalClock>;
-    async fn get_changes_since(&self, hlc: &HybridLogicalClock) -> Result<Vec<SyncChange>>;
-    async fn apply_changes(&self, changes: &[SyncChange]) -> Result<()>;
+    /// Changes after `hlc` in collections the filter allows, in HLC order.
+    ///
+    /// Document and chunk tombstones do not record their collection and are
+    /// always included.
+    async fn get_changes_since(
+        &self,
+        hlc: &HybridLogicalClock,
+        filter: &CollectionFilter,
+    ) -> Result<Vec<SyncChange>>;
+
+    /// The first `limit` changes of [`get_changes_since`](Self::get_changes_since),
+    /// and whether more follow.
+    ///
+    /// Changes sharing the HLC of the last change in the page are kept
+    /// together, so a cursor of "last HLC seen" never skips part of a group.
+    async fn get_changes_page(
+        &self,
+        hlc: &HybridLogicalClock,
+        filter: &CollectionFilter,
+        limit: usize,
+    ) -> Result<(Vec<SyncChange>, bool)>;
+
+    /// Apply remote changes, resolving conflicting documents with `resolver`.
+    async fn apply_changes_with(&self, changes: &[SyncChange], resolver: &dyn ConflictResolver) -> Result<()>;
+
+    /// Apply remote changes with [`LastWriterWins`].
+    async fn apply_changes(&self, changes: &[SyncChange]) -> Result<()> {
+        self.apply_changes_with(changes, &LastWriterWins).await
+    }
+
+    /// Remove a collection and its contents without recording a tombstone, so
+    /// the removal stays local. Returns whether the collection existed.
+    async fn purge_collection(&self, name: &str) -> Result<bool>;
+
+    // Conflict log
+    /// Most recently resolved conflicts first.
+    async fn list_conflicts(&self, limit: u32) -> Result<Vec<ConflictRecord>>;
+    async fn get_conflict(&self, id: i64) -> Result<Option<ConflictRecord>>;
+
+    /// Record that a user resolved a conflict differently.
+    async fn set_conflict_resolution(&self, id: i64, resolution: Resolution) -> Result<()>;
+
+    // Sync state
+    async fn get_sync_state(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    async fn set_sync_state(&self, key: &str, value: &[u8]) -> Result<()>;
+    async fn list_sync_state(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>>;
+
+    // Anti-entropy
+    /// Version summaries of the rows and tombstones in a key range that the
+    /// filter allows, sorted by key.
+    async fn sync_items(&self, range: &KeyRange, filter: &CollectionFilter) -> Result<Vec<SyncItem>>;
+
+    /// Digest of a key range.
+    async fn range_digest(&self, range: &KeyRange, filter: &CollectionFilter) -> Result<RangeDigest> {
+        Ok(RangeDigest::from_items(&self.sync_items(range, filter).await?))
+    }
+
+    /// Current version of each key as a change (upserts for rows, deletes for
+    /// tombstones). Keys that do not exist are skipped.
+    async fn get_changes_for_keys(&self, keys: &[String]) -> Result<Vec<SyncChange>>;
 }
 
 /// A change record for sync.
-#[derive(Debug, Clone)]
+///
+/// Deletions carry the HLC at which the row was deleted so they can be
+/// ordered against concurrent upserts (last-writer-wins).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SyncChange {
     UpsertCollection(Collection),
-    DeleteCollection(String),
+    DeleteCollection(String, HybridLogicalClock),
     UpsertDocument(Document),
-    DeleteDocument(Ulid),
+    DeleteDocument(Ulid, HybridLogicalClock),
     UpsertChunk(Chunk, Vec<f32>), // Chunk with embedding
-    DeleteChunk(Ulid),
+    DeleteChunk(Ulid, HybridLogicalClock),
+}
+
+impl SyncChange {
+    /// HLC of the change.
+    pub fn hlc(&self) -> HybridLogicalClock {
+        match self {
+            Self::UpsertCollection(collection) => collection.hlc,
+            Self::UpsertDocument(doc) => doc.hlc,
+            Self::UpsertChunk(chunk, _) => chunk.hlc,
+            Self::DeleteCollection(_, hlc)
+            | Self::DeleteDocument(_, hlc)
+            | Self::DeleteChunk(_, hlc) => *hlc,
+        }
+    }
+
+    /// Sync key of the row (for upserts) or tombstone (for deletes).
+    pub fn key(&self) -> String {
+        match self {
+            Self::UpsertCollection(collection) => digest::collection_key(&collection.name),
+            Self::DeleteCollection(name, _) => digest::tombstone_key(&digest::collection_key(name)),
+            Self::UpsertDocument(doc) => digest::document_key(doc.id),
+            Self::DeleteDocument(id, _) => digest::tombstone_key(&digest::document_key(*id)),
+            Self::UpsertChunk(chunk, _) => digest::chunk_key(chunk.id),
+            Self::DeleteChunk(id, _) => digest::tombstone_key(&digest::chunk_key(*id)),
+        }
+    }
 }
 
 /// Embedding model trait.
@@ -80,6 +184,17 @@ pub trait Embedder: Send + Sync {
     /// Prefixes with "search_query: " for asymmetric retrieval.
     async fn embed_query(&self, text: &str) -> Result<Vec<f32>>;
 
+    /// Embed the chunks of one document in the context of the whole
+    /// document ("late chunking").
+    ///
+    /// `spans` are the byte ranges of the chunks in `document`. Models that
+    /// expose token embeddings run the document once and pool each chunk
+    /// over its own tokens; the default embeds each chunk on its own.
+    async fn embed_chunks_in_context(&self, document: &str, spans: &[Range<usize>]) -> Result<Vec<Vec<f32>>> {
+        let texts = span_texts(document, spans)?;
+        self.embed_documents(&texts).await
+    }
+
     /// Count tokens in text.
     fn count_tokens(&self, text: &str) -> Result<usize>;
 
@@ -90,6 +205,25 @@ pub trait Embedder: Send + Sync {
     fn max_tokens(&self) -> usize;
 }
 
+/// Text of each byte range of a document.
+///
+/// Fails if a range is out of bounds or does not fall on character
+/// boundaries.
+pub fn span_texts<'a>(document: &'a str, spans: &[Range<usize>]) -> Result<Vec<&'a str>> {
+    spans
+        .iter()
+        .map(|span| {
+            document.get(span.clone()).ok_or_else(|| {
+                RagError::invalid_argument(format!(
+                    "Chunk span {:?} is not within the {} byte document",
+                    span,
+                    document.len()
+                ))
+            })
+        })
+        .collect()
+}
+
 /// Chunking configuration.
 #[derive(Debug, Clone)]
 pub struct ChunkConfig {
@@ -123,10 +257,34 @@ pub trait Chunker: Send + Sync {
         config: &ChunkConfig,
     ) -> Result<Vec<ChunkData>>;
 
+    /// Chunk text content, also returning document metadata found while
+    /// parsing it, such as the title of an HTML page.
+    fn chunk_with_metadata(
+        &self,
+        content: &str,
+        content_type: ContentType,
+        config: &ChunkConfig,
+    ) -> Result<ChunkedDocument> {
+        Ok(ChunkedDocument {
+            chunks: self.chunk(content, content_type, config)?,
+            metadata: HashMap::new(),
+        })
+    }
+
     /// Get supported content types.
     fn supported_types(&self) -> Vec<ContentType>;
 }
 
+/// Chunks of a document with the metadata found in it.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkedDocument {
+    /// Chunks, parents before their children.
+    pub chunks: Vec<ChunkData>,
+
+    /// Metadata to add to the document, e.g. `title` and `canonical_url`.
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
 /// Raw chunk data before ID assignment.
 #[derive(Debug, Clone)]
 pub struct ChunkData {
@@ -141,6 +299,52 @@ pub struct ChunkData {
 
     /// End line (1-based, inclusive).
     pub end_line: u32,
+
+    /// Byte offset of the start of the chunk in the chunked text.
+    ///
+    /// `content` is the slice `start_byte..end_byte` of that text, except
+    /// for content rendered from it (HTML pages, JSON Lines chats), where
+    /// the range covers the whole source lines it was rendered from.
+    pub start_byte: usize,
+
+    /// Byte offset of the end of the chunk (exclusive).
+    pub end_byte: usize,
+
+    /// Start column on `start_line` (1-based, in bytes).
+    pub start_column: u32,
+
+    /// End column on `end_line` (1-based, in bytes, exclusive).
+    pub end_column: u32,
+
+    /// Headings or code items enclosing the chunk, outermost first
+    /// (e.g. `Install > Linux > Troubleshooting` or `impl Point`).
+    pub heading_path: Option<String>,
+
+    /// Speakers of a chat window, in order of appearance.
+    pub speakers: Vec<String>,
+
+    /// Times of the first and last message of a chat window, as written in
+    /// the transcript.
+    pub time_range: Option<(String, String)>,
+
+    /// Index of the enclosing chunk in the same output, if any.
+    pub parent: Option<usize>,
+
+    /// Depth in the chunk hierarchy (0 for top-level chunks).
+    pub level: u32,
+}
+
+/// One page of changes pulled from a peer.
+#[derive(Debug, Clone)]
+pub struct ChangePage {
+    /// Changes in the page, in HLC order.
+    pub changes: Vec<SyncChange>,
+
+    /// HLC to pull the next page after.
+    pub watermark: HybridLogicalClock,
+
+    /// Whether more changes follow.
+    pub has_more: bool,
 }
 
 /// Sync peer trait for multi-node synchronization.
@@ -152,12 +356,48 @@ pub trait SyncPeer: Send + Sync {
     /// Get the peer's endpoint URL.
     fn endpoint(&self) -> &str;
 
+    /// Collections replicated with this peer.
+    fn collections(&self) -> &CollectionFilter {
+        static ALL: CollectionFilter = CollectionFilter {
+            include: Vec::new(),
+            exclude: Vec::new(),
+        };
+        &ALL
+    }
+
+    /// Node ID the peer reported in its last watermark response, if known.
+    fn node_id(&self) -> Option<u16> {
+        None
+    }
+
     /// Fetch the peer's current watermark (highest HLC).
     async fn get_watermark(&self) -> Result<HybridLogicalClock>;
 
     /// Pull changes from peer since the given HLC.
     async fn pull_changes(&self, since: &HybridLogicalClock) -> Result<Vec<SyncChange>>;
 
+    /// Pull the next page of changes since the given HLC.
+    ///
+    /// The default pulls every change as a single page.
+    async fn pull_page(&self, since: &HybridLogicalClock) -> Result<ChangePage> {
+        let changes = self.pull_changes(since).await?;
+        let watermark = changes.iter().map(SyncChange::hlc).fold(*since, HybridLogicalClock::max);
+        Ok(ChangePage {
+            changes,
+            watermark,
+            has_more: false,
+        })
+    }
+
     /// Push changes to peer.
     async fn push_changes(&self, changes: &[SyncChange]) -> Result<()>;
+
+    /// Digests of the given key ranges on the peer.
+    async fn range_digests(&self, ranges: &[KeyRange]) -> Result<Vec<RangeDigest>>;
+
+    /// Version summaries of the rows in a key range on the peer.
+    async fn range_items(&self, range: &KeyRange) -> Result<Vec<SyncItem>>;
+
+    /// Fetch the peer's current version of each key.
+    async fn fetch_changes(&self, keys: &[String]) -> Result<Vec<SyncChange>>;
 }
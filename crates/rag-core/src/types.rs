@@ -16,50 +16,333 @@ pub enum ContentType {
     JavaScript,
     Go,
     Java,
+    Kotlin,
+    CSharp,
     Cpp,
     C,
     Ruby,
+    Shell,
+    Sql,
+    Css,
+    Dockerfile,
+    Makefile,
     Markdown,
     Html,
     Json,
     Yaml,
     Toml,
+    Chat,
     PlainText,
     Unknown,
 }
 
+/// File extensions (lowercase) of each content type.
+const EXTENSIONS: &[(&str, ContentType)] = &[
+    ("rs", ContentType::Rust),
+    ("py", ContentType::Python),
+    ("pyi", ContentType::Python),
+    ("pyw", ContentType::Python),
+    ("ts", ContentType::TypeScript),
+    ("tsx", ContentType::TypeScript),
+    ("mts", ContentType::TypeScript),
+    ("cts", ContentType::TypeScript),
+    ("js", ContentType::JavaScript),
+    ("jsx", ContentType::JavaScript),
+    ("mjs", ContentType::JavaScript),
+    ("cjs", ContentType::JavaScript),
+    ("go", ContentType::Go),
+    ("java", ContentType::Java),
+    ("kt", ContentType::Kotlin),
+    ("kts", ContentType::Kotlin),
+    ("cs", ContentType::CSharp),
+    ("csx", ContentType::CSharp),
+    ("cpp", ContentType::Cpp),
+    ("cc", ContentType::Cpp),
+    ("cxx", ContentType::Cpp),
+    ("c++", ContentType::Cpp),
+    ("hpp", ContentType::Cpp),
+    ("hh", ContentType::Cpp),
+    ("hxx", ContentType::Cpp),
+    ("h++", ContentType::Cpp),
+    ("ipp", ContentType::Cpp),
+    ("c", ContentType::C),
+    ("h", ContentType::C),
+    ("rb", ContentType::Ruby),
+    ("rake", ContentType::Ruby),
+    ("gemspec", ContentType::Ruby),
+    ("sh", ContentType::Shell),
+    ("bash", ContentType::Shell),
+    ("zsh", ContentType::Shell),
+    ("ksh", ContentType::Shell),
+    ("sql", ContentType::Sql),
+    ("css", ContentType::Css),
+    ("dockerfile", ContentType::Dockerfile),
+    ("mk", ContentType::Makefile),
+    ("mak", ContentType::Makefile),
+    ("md", ContentType::Markdown),
+    ("markdown", ContentType::Markdown),
+    ("html", ContentType::Html),
+    ("htm", ContentType::Html),
+    ("xhtml", ContentType::Html),
+    ("json", ContentType::Json),
+    ("yaml", ContentType::Yaml),
+    ("yml", ContentType::Yaml),
+    ("toml", ContentType::Toml),
+    ("chat", ContentType::Chat),
+    ("txt", ContentType::PlainText),
+    ("text", ContentType::PlainText),
+];
+
+/// Names of files whose type their extension doesn't tell.
+const FILE_NAMES: &[(&str, ContentType)] = &[
+    ("Dockerfile", ContentType::Dockerfile),
+    ("Containerfile", ContentType::Dockerfile),
+    ("Makefile", ContentType::Makefile),
+    ("makefile", ContentType::Makefile),
+    ("GNUmakefile", ContentType::Makefile),
+    ("Gemfile", ContentType::Ruby),
+    ("Rakefile", ContentType::Ruby),
+    ("Guardfile", ContentType::Ruby),
+    ("Podfile", ContentType::Ruby),
+    ("Vagrantfile", ContentType::Ruby),
+    ("Brewfile", ContentType::Ruby),
+    (".bashrc", ContentType::Shell),
+    (".bash_profile", ContentType::Shell),
+    (".bash_aliases", ContentType::Shell),
+    (".bash_logout", ContentType::Shell),
+    (".profile", ContentType::Shell),
+    (".zshrc", ContentType::Shell),
+    (".zshenv", ContentType::Shell),
+    (".zprofile", ContentType::Shell),
+    ("Cargo.lock", ContentType::Toml),
+    ("Pipfile", ContentType::Toml),
+    ("LICENSE", ContentType::PlainText),
+    ("LICENCE", ContentType::PlainText),
+    ("COPYING", ContentType::PlainText),
+    ("NOTICE", ContentType::PlainText),
+    ("README", ContentType::PlainText),
+    ("AUTHORS", ContentType::PlainText),
+    ("CONTRIBUTORS", ContentType::PlainText),
+    ("CHANGELOG", ContentType::PlainText),
+    ("CHANGES", ContentType::PlainText),
+    ("INSTALL", ContentType::PlainText),
+];
+
+/// Interpreters named on `#!` lines, without version suffixes.
+const INTERPRETERS: &[(&str, ContentType)] = &[
+    ("sh", ContentType::Shell),
+    ("bash", ContentType::Shell),
+    ("zsh", ContentType::Shell),
+    ("ksh", ContentType::Shell),
+    ("dash", ContentType::Shell),
+    ("ash", ContentType::Shell),
+    ("python", ContentType::Python),
+    ("node", ContentType::JavaScript),
+    ("nodejs", ContentType::JavaScript),
+    ("deno", ContentType::TypeScript),
+    ("ts-node", ContentType::TypeScript),
+    ("tsx", ContentType::TypeScript),
+    ("ruby", ContentType::Ruby),
+    ("make", ContentType::Makefile),
+    ("kotlin", ContentType::Kotlin),
+    ("kscript", ContentType::Kotlin),
+    ("dotnet-script", ContentType::CSharp),
+];
+
 impl ContentType {
+    /// Every content type.
+    pub const ALL: &'static [ContentType] = &[
+        Self::Rust,
+        Self::Python,
+        Self::TypeScript,
+        Self::JavaScript,
+        Self::Go,
+        Self::Java,
+        Self::Kotlin,
+        Self::CSharp,
+        Self::Cpp,
+        Self::C,
+        Self::Ruby,
+        Self::Shell,
+        Self::Sql,
+        Self::Css,
+        Self::Dockerfile,
+        Self::Makefile,
+        Self::Markdown,
+        Self::Html,
+        Self::Json,
+        Self::Yaml,
+        Self::Toml,
+        Self::Chat,
+        Self::PlainText,
+        Self::Unknown,
+    ];
+
     /// Detect content type from file extension.
     pub fn from_extension(ext: &str) -> Self {
-        match ext.to_lowercase().as_str() {
-            "rs" => Self::Rust,
-            "py" | "pyi" => Self::Python,
-            "ts" | "tsx" => Self::TypeScript,
-            "js" | "jsx" | "mjs" | "cjs" => Self::JavaScript,
-            "go" => Self::Go,
-            "java" => Self::Java,
-            "cpp" | "cc" | "cxx" | "hpp" | "hxx" => Self::Cpp,
-            "c" | "h" => Self::C,
-            "rb" => Self::Ruby,
-            "md" | "markdown" => Self::Markdown,
-            "html" | "htm" => Self::Html,
-            "json" => Self::Json,
-            "yaml" | "yml" => Self::Yaml,
-            "toml" => Self::Toml,
-            "txt" => Self::PlainText,
+        let ext = ext.to_lowercase();
+        EXTENSIONS
+            .iter()
+            .find(|(known, _)| *known == ext)
+            .map_or(Self::Unknown, |(_, content_type)| *content_type)
+    }
+
+    /// Detect content type from the file name of a path: well-known names
+    /// such as `Makefile` or `.bashrc` first, then the extension.
+    pub fn from_path(path: &str) -> Self {
+        // `https://host/page.md?raw=1#intro`
+        let path = if path.contains("://") && !path.starts_with("file://") {
+            path.split(['?', '#']).next().unwrap_or(path)
+        } else {
+            path
+        };
+        let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+        if let Some((_, content_type)) = FILE_NAMES.iter().find(|(known, _)| *known == name) {
+            return *content_type;
+        }
+        // `Dockerfile.dev`
+        if name.starts_with("Dockerfile.") || name.starts_with("Containerfile.") {
+            return Self::Dockerfile;
+        }
+
+        match name.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() => Self::from_extension(ext),
             _ => Self::Unknown,
         }
     }
 
-    /// Detect content type from file path.
-    pub fn from_path(path: &str) -> Self {
-        path.rsplit('.')
-            .next()
-            .map(Self::from_extension)
-            .unwrap_or(Self::Unknown)
+    /// Detect content type from a `#!` line, e.g. `#!/usr/bin/env python3`.
+    pub fn from_shebang(line: &str) -> Self {
+        let Some(command) = line.strip_prefix("#!") else {
+            return Self::Unknown;
+        };
+        let mut words = command.split_whitespace();
+        let mut interpreter = words.next().and_then(|path| path.rsplit('/').next()).unwrap_or("");
+        if interpreter == "env" {
+            // `env -S node --flag`
+            interpreter = words.find(|word| !word.starts_with('-') && !word.contains('=')).unwrap_or("");
+        }
+        let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+        INTERPRETERS
+            .iter()
+            .find(|(known, _)| *known == interpreter)
+            .map_or(Self::Unknown, |(_, content_type)| *content_type)
+    }
+
+    /// Detect content type from a path and content; see
+    /// [`Detection::detect`](crate::detect::Detection::detect).
+    pub fn detect(path: &str, content: &str) -> Self {
+        crate::detect::Detection::detect(path, content).content_type
+    }
+
+    /// Parse a content type name, as serialized (`typescript`), displayed
+    /// (`C++`) or as a file extension (`ts`), ignoring case.
+    pub fn from_name(name: &str) -> Self {
+        let name = name.trim().to_lowercase();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|content_type| content_type.name() == name || content_type.to_string().to_lowercase() == name)
+            .unwrap_or_else(|| Self::from_extension(&name))
+    }
+
+    /// Serialized name, e.g. `typescript`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::Python => "python",
+            Self::TypeScript => "typescript",
+            Self::JavaScript => "javascript",
+            Self::Go => "go",
+            Self::Java => "java",
+            Self::Kotlin => "kotlin",
+            Self::CSharp => "csharp",
+            Self::Cpp => "cpp",
+            Self::C => "c",
+            Self::Ruby => "ruby",
+            Self::Shell => "shell",
+            Self::Sql => "sql",
+            Self::Css => "css",
+            Self::Dockerfile => "dockerfile",
+            Self::Makefile => "makefile",
+            Self::Markdown => "markdown",
+            Self::Html => "html",
+            Self::Json => "json",
+            Self::Yaml => "yaml",
+            Self::Toml => "toml",
+            Self::Chat => "chat",
+            Self::PlainText => "plaintext",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// Detect content type from a MIME type, ignoring parameters such as
+    /// `charset`.
+    pub fn from_mime(mime: &str) -> Self {
+        let essence = mime.split(';').next().unwrap_or("").trim().to_lowercase();
+        match essence.as_str() {
+            "text/x-rust" => Self::Rust,
+            "text/x-python" | "application/x-python" => Self::Python,
+            "text/x-typescript" | "application/typescript" => Self::TypeScript,
+            "text/javascript" | "application/javascript" => Self::JavaScript,
+            "text/x-go" => Self::Go,
+            "text/x-java" => Self::Java,
+            "text/x-kotlin" => Self::Kotlin,
+            "text/x-csharp" => Self::CSharp,
+            "text/x-c++" => Self::Cpp,
+            "text/x-c" => Self::C,
+            "text/x-ruby" => Self::Ruby,
+            "text/x-shellscript" | "application/x-sh" => Self::Shell,
+            "application/sql" | "text/x-sql" => Self::Sql,
+            "text/css" => Self::Css,
+            "text/x-dockerfile" => Self::Dockerfile,
+            "text/x-makefile" => Self::Makefile,
+            "text/markdown" | "text/x-markdown" => Self::Markdown,
+            "text/html" | "application/xhtml+xml" => Self::Html,
+            "application/json" => Self::Json,
+            "application/yaml" | "application/x-yaml" | "text/yaml" => Self::Yaml,
+            "application/toml" => Self::Toml,
+            "text/x-chat" => Self::Chat,
+            "text/plain" => Self::PlainText,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// MIME type of this content type.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Rust => "text/x-rust",
+            Self::Python => "text/x-python",
+            Self::TypeScript => "text/x-typescript",
+            Self::JavaScript => "text/javascript",
+            Self::Go => "text/x-go",
+            Self::Java => "text/x-java",
+            Self::Kotlin => "text/x-kotlin",
+            Self::CSharp => "text/x-csharp",
+            Self::Cpp => "text/x-c++",
+            Self::C => "text/x-c",
+            Self::Ruby => "text/x-ruby",
+            Self::Shell => "text/x-shellscript",
+            Self::Sql => "application/sql",
+            Self::Css => "text/css",
+            Self::Dockerfile => "text/x-dockerfile",
+            Self::Makefile => "text/x-makefile",
+            Self::Markdown => "text/markdown",
+            Self::Html => "text/html",
+            Self::Json => "application/json",
+            Self::Yaml => "application/yaml",
+            Self::Toml => "application/toml",
+            Self::Chat => "text/x-chat",
+            Self::PlainText => "text/plain",
+            Self::Unknown => "application/octet-stream",
+        }
     }
 
     /// Check if this content type supports AST-aware chunking.
+    ///
+    /// Kotlin and SQL have no bundled tree-sitter grammar yet, so they are
+    /// chunked as text.
     pub fn supports_ast_chunking(&self) -> bool {
         matches!(
             self,
@@ -69,9 +352,12 @@ impl ContentType {
                 | Self::JavaScript
                 | Self::Go
                 | Self::Java
+                | Self::CSharp
                 | Self::Cpp
                 | Self::C
                 | Self::Ruby
+                | Self::Shell
+                | Self::Css
         )
     }
 
@@ -90,14 +376,22 @@ impl std::fmt::Display for ContentType {
             Self::JavaScript => "JavaScript",
             Self::Go => "Go",
             Self::Java => "Java",
+            Self::Kotlin => "Kotlin",
+            Self::CSharp => "C#",
             Self::Cpp => "C++",
             Self::C => "C",
             Self::Ruby => "Ruby",
+            Self::Shell => "Shell",
+            Self::Sql => "SQL",
+            Self::Css => "CSS",
+            Self::Dockerfile => "Dockerfile",
+            Self::Makefile => "Makefile",
             Self::Markdown => "Markdown",
             Self::Html => "HTML",
             Self::Json => "JSON",
             Self::Yaml => "YAML",
             Self::Toml => "TOML",
+            Self::Chat => "Chat",
             Self::PlainText => "Plain Text",
             Self::Unknown => "Unknown",
         };
@@ -165,6 +459,22 @@ impl Document {
         }
     }
 
+    /// Title of the document: the `title` metadata if set, else the first
+    /// top-level heading of Markdown content.
+    pub fn title(&self) -> Option<String> {
+        if let Some(title) = self.metadata.get("title").and_then(|v| v.as_str()) {
+            return Some(title.to_string());
+        }
+        if self.content_type != ContentType::Markdown {
+            return None;
+        }
+        self.raw_content
+            .as_deref()?
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .map(|title| title.trim().to_string())
+    }
+
     /// Check if content has changed by comparing hashes.
     pub fn content_changed(&self, new_content: &str) -> bool {
         let new_hash = blake3::hash(new_content.as_bytes());
@@ -198,6 +508,51 @@ pub struct Chunk {
     /// End line in source (1-based, inclusive).
     pub end_line: u32,
 
+    /// Byte offset of the start of the chunk in the source.
+    #[serde(default)]
+    pub start_byte: u32,
+
+    /// Byte offset of the end of the chunk in the source (exclusive).
+    #[serde(default)]
+    pub end_byte: u32,
+
+    /// Start column on `start_line` (1-based, in bytes).
+    #[serde(default)]
+    pub start_column: u32,
+
+    /// End column on `end_line` (1-based, in bytes, exclusive).
+    #[serde(default)]
+    pub end_column: u32,
+
+    /// Headings or code items enclosing the chunk, outermost first
+    /// (e.g. `Install > Linux`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading_path: Option<String>,
+
+    /// Speakers of a chat window, in order of appearance.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub speakers: Vec<String>,
+
+    /// Times of the first and last message of a chat window, as written in
+    /// the transcript.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_range: Option<(String, String)>,
+
+    /// Header describing where the chunk comes from (document title, path,
+    /// section...), embedded and keyword-indexed along with the content but
+    /// not shown as part of it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+
+    /// Enclosing chunk (e.g. the function or section), for small-to-big
+    /// retrieval. Chunks that are the parent of another are not searched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Ulid>,
+
+    /// Depth in the chunk hierarchy (0 for top-level chunks).
+    #[serde(default)]
+    pub level: u32,
+
     /// Blake3 hash of chunk content.
     #[serde(with = "serde_bytes_opt")]
     pub content_hash: Option<[u8; 32]>,
@@ -226,10 +581,65 @@ impl Chunk {
             token_count,
             start_line,
             end_line,
+            start_byte: 0,
+            end_byte: 0,
+            start_column: 0,
+            end_column: 0,
+            heading_path: None,
+            speakers: Vec::new(),
+            time_range: None,
+            context: None,
+            parent_id: None,
+            level: 0,
             content_hash: Some(*content_hash.as_bytes()),
             hlc: HybridLogicalClock::new(0),
         }
     }
+
+    /// Set the byte range of the chunk in the source and the columns it
+    /// starts and ends at.
+    pub fn with_span(mut self, start_byte: u32, end_byte: u32, start_column: u32, end_column: u32) -> Self {
+        self.start_byte = start_byte;
+        self.end_byte = end_byte;
+        self.start_column = start_column;
+        self.end_column = end_column;
+        self
+    }
+
+    /// Set the heading path of the section the chunk belongs to.
+    pub fn with_heading_path(mut self, heading_path: Option<String>) -> Self {
+        self.heading_path = heading_path;
+        self
+    }
+
+    /// Set the speakers and time range of a chat window.
+    pub fn with_chat(mut self, speakers: Vec<String>, time_range: Option<(String, String)>) -> Self {
+        self.speakers = speakers;
+        self.time_range = time_range;
+        self
+    }
+
+    /// Place the chunk under a parent chunk, at the given depth.
+    pub fn with_parent(mut self, parent_id: Option<Ulid>, level: u32) -> Self {
+        self.parent_id = parent_id;
+        self.level = level;
+        self
+    }
+
+    /// Set the contextual header of the chunk.
+    pub fn with_context(mut self, context: Option<String>) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Text to embed for this chunk: the content, preceded by its contextual
+    /// header if it has one.
+    pub fn embedding_text(&self) -> String {
+        match &self.context {
+            Some(context) => format!("{}\n\n{}", context, self.content),
+            None => self.content.clone(),
+        }
+    }
 }
 
 /// A collection of documents.
@@ -375,6 +785,42 @@ mod tests {
         assert_eq!(ContentType::from_path("src/lib.rs"), ContentType::Rust);
         assert_eq!(ContentType::from_path("README.md"), ContentType::Markdown);
         assert_eq!(ContentType::from_path("no_extension"), ContentType::Unknown);
+        assert_eq!(ContentType::from_path("v1.2/notes"), ContentType::Unknown);
+        assert_eq!(ContentType::from_path("file:///repo/Dockerfile.dev"), ContentType::Dockerfile);
+        assert_eq!(ContentType::from_path("src/Makefile"), ContentType::Makefile);
+        assert_eq!(ContentType::from_path("LICENSE"), ContentType::PlainText);
+        assert_eq!(ContentType::from_path("/home/me/.bashrc"), ContentType::Shell);
+        assert_eq!(ContentType::from_path("App.KT"), ContentType::Kotlin);
+    }
+
+    #[test]
+    fn test_content_type_from_shebang() {
+        assert_eq!(ContentType::from_shebang("#!/bin/bash"), ContentType::Shell);
+        assert_eq!(ContentType::from_shebang("#!/usr/bin/env python3.11"), ContentType::Python);
+        assert_eq!(ContentType::from_shebang("#!/usr/bin/env -S NODE_ENV=test node --trace"), ContentType::JavaScript);
+        assert_eq!(ContentType::from_shebang("#!/usr/bin/make -f"), ContentType::Makefile);
+        assert_eq!(ContentType::from_shebang("# not a shebang"), ContentType::Unknown);
+
+        assert_eq!(ContentType::detect("bin/deploy", "#!/bin/sh\nset -e"), ContentType::Shell);
+        assert_eq!(ContentType::detect("deploy.py", "#!/bin/sh"), ContentType::Python);
+    }
+
+    #[test]
+    fn test_content_type_names() {
+        for content_type in ContentType::ALL {
+            assert_eq!(ContentType::from_name(content_type.name()), *content_type);
+            assert_eq!(ContentType::from_name(&content_type.to_string()), *content_type);
+            assert_eq!(serde_json::to_value(content_type).unwrap(), content_type.name());
+        }
+        assert_eq!(ContentType::from_name("ts"), ContentType::TypeScript);
+    }
+
+    #[test]
+    fn test_content_type_mime() {
+        assert_eq!(ContentType::from_mime("text/html; charset=utf-8"), ContentType::Html);
+        assert_eq!(ContentType::from_mime("Application/X-YAML"), ContentType::Yaml);
+        assert_eq!(ContentType::from_mime("image/png"), ContentType::Unknown);
+        assert_eq!(ContentType::from_mime(ContentType::Markdown.mime_type()), ContentType::Markdown);
     }
 
     #[test]
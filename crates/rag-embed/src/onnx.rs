@@ -1,5 +1,6 @@
 //! ONNX-based embedding model implementation.
 
+use std::ops::Range;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
@@ -8,10 +9,10 @@ use ndarray::ArrayViewD;
 use ort::session::builder::GraphOptimizationLevel;
 use ort::session::Session;
 use ort::value::Tensor;
-use tokenizers::Tokenizer;
+use tokenizers::{Encoding, Tokenizer};
 use tracing::{debug, info};
 
-use rag_core::{Embedder, RagError, Result};
+use rag_core::{span_texts, Embedder, RagError, Result};
 
 /// nomic-embed-text-v1.5 configuration.
 const EMBEDDING_DIM: usize = 768;
@@ -31,6 +32,10 @@ pub struct OnnxEmbedder {
     /// Tokenizer for the model.
     tokenizer: Arc<Tokenizer>,
 
+    /// The tokenizer without truncation, for the offsets of whole documents
+    /// in late chunking.
+    untruncated: Arc<Tokenizer>,
+
     /// Embedding dimension.
     dimension: usize,
 
@@ -62,9 +67,13 @@ impl OnnxEmbedder {
 
         info!("Loading tokenizer from {:?}", tokenizer_path);
 
-        // Load tokenizer
+        // Load tokenizer, and a copy without truncation for late chunking
         let tokenizer = Tokenizer::from_file(tokenizer_path)
             .map_err(|e| RagError::embedding(format!("Failed to load tokenizer: {}", e)))?;
+        let mut untruncated = tokenizer.clone();
+        untruncated
+            .with_truncation(None)
+            .map_err(|e| RagError::embedding(format!("Failed to configure tokenizer: {}", e)))?;
 
         info!(
             "Embedder initialized: dim={}, max_tokens={}",
@@ -74,6 +83,7 @@ impl OnnxEmbedder {
         Ok(Self {
             session: Mutex::new(session),
             tokenizer: Arc::new(tokenizer),
+            untruncated: Arc::new(untruncated),
             dimension: EMBEDDING_DIM,
             max_tokens: MAX_TOKENS,
         })
@@ -108,6 +118,54 @@ impl OnnxEmbedder {
             .encode_batch(prefixed_refs, true)
             .map_err(|e| RagError::embedding(format!("Tokenization failed: {}", e)))?;
 
+        self.run(&encodings, |view, max_len| {
+            let shape_dims: Vec<usize> = view.shape().to_vec();
+
+            // Handle different output shapes
+            if shape_dims.len() == 3 {
+                // (batch_size, seq_len, hidden_dim) - need mean pooling
+                // over the attended tokens of each text
+                let spans: Vec<(usize, Range<usize>)> = encodings
+                    .iter()
+                    .enumerate()
+                    .map(|(i, encoding)| {
+                        let valid_len = encoding
+                            .get_attention_mask()
+                            .iter()
+                            .take(max_len)
+                            .filter(|&&m| m == 1)
+                            .count();
+                        (i, 0..valid_len)
+                    })
+                    .collect();
+                Ok(self.mean_pool_3d_ndarray(view, &spans))
+            } else if shape_dims.len() == 2 {
+                // (batch_size, hidden_dim) - already pooled
+                let hidden_dim = shape_dims[1];
+                Ok((0..encodings.len())
+                    .map(|i| {
+                        let embedding: Vec<f32> = (0..hidden_dim)
+                            .map(|j| view[[i, j]])
+                            .collect();
+                        self.l2_normalize(embedding)
+                    })
+                    .collect())
+            } else {
+                Err(RagError::embedding(format!(
+                    "Unexpected output shape: {:?}",
+                    shape_dims
+                )))
+            }
+        })
+    }
+
+    /// Run the model on a batch of encodings, truncated to `max_tokens`, and
+    /// pass its first output and the padded sequence length to `f`.
+    fn run<T>(
+        &self,
+        encodings: &[Encoding],
+        f: impl FnOnce(&ArrayViewD<'_, f32>, usize) -> Result<T>,
+    ) -> Result<T> {
         // Get max length for padding
         let max_len = encodings
             .iter()
@@ -166,7 +224,6 @@ impl OnnxEmbedder {
 
         // Extract embeddings from output
         // Models typically output (batch_size, seq_len, hidden_dim)
-        // We need to do mean pooling over the sequence dimension
 
         // Get the first output (different models name outputs differently)
         let output_names: Vec<_> = outputs.iter().map(|(k, _)| k.to_string()).collect();
@@ -182,77 +239,72 @@ impl OnnxEmbedder {
             .try_extract_array::<f32>()
             .map_err(|e| RagError::embedding(format!("Failed to extract tensor: {}", e)))?;
 
-        let shape_dims: Vec<usize> = view.shape().to_vec();
-        debug!("Output shape: {:?}", shape_dims);
-
-        // Handle different output shapes
-        let embeddings = if shape_dims.len() == 3 {
-            // (batch_size, seq_len, hidden_dim) - need mean pooling
-            self.mean_pool_3d_ndarray(&view, &encodings, max_len)?
-        } else if shape_dims.len() == 2 {
-            // (batch_size, hidden_dim) - already pooled
-            let hidden_dim = shape_dims[1];
-            (0..batch_size)
-                .map(|i| {
-                    let embedding: Vec<f32> = (0..hidden_dim)
-                        .map(|j| view[[i, j]])
+        debug!("Output shape: {:?}", view.shape());
+
+        f(&view, max_len)
+    }
+
+    /// Embed the chunks of a document with late chunking.
+    fn embed_late(&self, document: &str, spans: &[Range<usize>]) -> Result<Vec<Vec<f32>>> {
+        let tokenizers = LateTokenizers {
+            tokenizer: &self.tokenizer,
+            untruncated: &self.untruncated,
+            max_tokens: self.max_tokens,
+        };
+        tokenizers.embed(
+            document,
+            spans,
+            |encoding, token_spans| {
+                self.run(std::slice::from_ref(encoding), |view, max_len| {
+                    if view.shape().len() != 3 {
+                        return Ok(None);
+                    }
+                    let token_spans: Vec<_> = token_spans
+                        .iter()
+                        .map(|tokens| (0, tokens.start.min(max_len)..tokens.end.min(max_len)))
                         .collect();
-                    self.l2_normalize(embedding)
+                    Ok(Some(self.mean_pool_3d_ndarray(view, &token_spans)))
                 })
-                .collect()
-        } else {
-            return Err(RagError::embedding(format!(
-                "Unexpected output shape: {:?}",
-                shape_dims
-            )));
-        };
-
-        Ok(embeddings)
+            },
+            |texts| self.embed_batch(texts, DOCUMENT_PREFIX),
+        )
     }
 
-    /// Mean pooling over sequence dimension with attention mask.
+    /// Mean pooling over spans of the sequence dimension.
     ///
-    /// Works with ndarray view of shape [batch, seq, hidden]
+    /// Works with ndarray view of shape [batch, seq, hidden]. Each span is a
+    /// batch row and the range of its tokens to average, e.g. all attended
+    /// tokens of a text, or the tokens of one chunk of a document.
     fn mean_pool_3d_ndarray(
         &self,
         tensor: &ArrayViewD<'_, f32>,
-        encodings: &[tokenizers::Encoding],
-        max_len: usize,
-    ) -> Result<Vec<Vec<f32>>> {
+        spans: &[(usize, Range<usize>)],
+    ) -> Vec<Vec<f32>> {
         let shape = tensor.shape();
-        let batch_size = shape[0];
         let seq_len = shape[1];
         let hidden_dim = shape[2];
 
-        let mut embeddings = Vec::with_capacity(batch_size);
-
-        for (i, encoding) in encodings.iter().enumerate() {
-            let attention_mask = encoding.get_attention_mask();
-            let valid_len = attention_mask.iter().take(max_len).filter(|&&m| m == 1).count();
-
-            if valid_len == 0 {
-                embeddings.push(vec![0.0; hidden_dim]);
-                continue;
-            }
+        spans
+            .iter()
+            .map(|(row, tokens)| {
+                let tokens = tokens.start.min(seq_len)..tokens.end.min(seq_len);
+                if tokens.is_empty() {
+                    return vec![0.0; hidden_dim];
+                }
 
-            // Sum embeddings for valid tokens
-            let mut sum = vec![0.0f32; hidden_dim];
-            for j in 0..valid_len.min(max_len).min(seq_len) {
-                if j < attention_mask.len() && attention_mask[j] == 1 {
-                    for k in 0..hidden_dim {
-                        sum[k] += tensor[[i, j, k]];
+                // Sum embeddings for the span's tokens
+                let mut sum = vec![0.0f32; hidden_dim];
+                for j in tokens.clone() {
+                    for (k, value) in sum.iter_mut().enumerate() {
+                        *value += tensor[[*row, j, k]];
                     }
                 }
-            }
-
-            // Compute mean
-            let embedding: Vec<f32> = sum.iter().map(|s| s / valid_len as f32).collect();
 
-            // L2 normalize
-            embeddings.push(self.l2_normalize(embedding));
-        }
-
-        Ok(embeddings)
+                // Compute mean, then L2 normalize
+                let embedding: Vec<f32> = sum.iter().map(|s| s / tokens.len() as f32).collect();
+                self.l2_normalize(embedding)
+            })
+            .collect()
     }
 
     /// L2 normalize a vector.
@@ -275,6 +327,10 @@ impl Embedder for OnnxEmbedder {
         self.embed_batch(texts, DOCUMENT_PREFIX)
     }
 
+    async fn embed_chunks_in_context(&self, document: &str, spans: &[Range<usize>]) -> Result<Vec<Vec<f32>>> {
+        self.embed_late(document, spans)
+    }
+
     async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
         let texts = [text];
         let results = self.embed_batch(&texts, QUERY_PREFIX)?;
@@ -301,6 +357,136 @@ impl Embedder for OnnxEmbedder {
     }
 }
 
+/// Tokenizers and context length used for late chunking.
+struct LateTokenizers<'a> {
+    /// Tokenizer for the model.
+    tokenizer: &'a Tokenizer,
+
+    /// The tokenizer without truncation.
+    untruncated: &'a Tokenizer,
+
+    /// Maximum token count.
+    max_tokens: usize,
+}
+
+impl LateTokenizers<'_> {
+    /// Embed the chunks of a document with late chunking.
+    ///
+    /// The document is run through the model once, or in windows of
+    /// consecutive chunks if it does not fit in `max_tokens`, and each chunk
+    /// is pooled over its own tokens. `pool` runs the model on a window and
+    /// pools the given token ranges, or returns `None` if the model has no
+    /// token embeddings; `embed` embeds texts on their own.
+    fn embed<P, E>(&self, document: &str, spans: &[Range<usize>], mut pool: P, embed: E) -> Result<Vec<Vec<f32>>>
+    where
+        P: FnMut(&Encoding, &[Range<usize>]) -> Result<Option<Vec<Vec<f32>>>>,
+        E: Fn(&[&str]) -> Result<Vec<Vec<f32>>>,
+    {
+        if spans.is_empty() {
+            return Ok(Vec::new());
+        }
+        let texts = span_texts(document, spans)?;
+
+        // Offsets of the whole document, which may be longer than the model's
+        // context
+        let encoding = self
+            .untruncated
+            .encode(document, false)
+            .map_err(|e| RagError::embedding(format!("Tokenization failed: {}", e)))?;
+        let offsets = encoding.get_offsets();
+        let prefix_tokens = self
+            .tokenizer
+            .encode(DOCUMENT_PREFIX, true)
+            .map_err(|e| RagError::embedding(format!("Tokenization failed: {}", e)))?
+            .get_ids()
+            .len();
+        let budget = self.max_tokens.saturating_sub(prefix_tokens).max(1);
+
+        let mut order: Vec<usize> = (0..spans.len()).collect();
+        order.sort_by_key(|&i| (spans[i].start, spans[i].end));
+
+        // Group consecutive chunks into windows that fit the context
+        let mut windows: Vec<(Range<usize>, Vec<usize>)> = Vec::new();
+        for i in order {
+            let span = &spans[i];
+            if let Some((window, members)) = windows.last_mut() {
+                let extended = window.start..window.end.max(span.end);
+                if token_range(offsets, &extended).len() <= budget {
+                    *window = extended;
+                    members.push(i);
+                    continue;
+                }
+            }
+            windows.push((span.clone(), vec![i]));
+        }
+
+        debug!("Late chunking: {} chunks in {} windows", spans.len(), windows.len());
+
+        let mut embeddings: Vec<Option<Vec<f32>>> = vec![None; spans.len()];
+        for (window, members) in windows {
+            let prefixed = format!("{}{}", DOCUMENT_PREFIX, &document[window.clone()]);
+            let encoding = self
+                .tokenizer
+                .encode(prefixed, true)
+                .map_err(|e| RagError::embedding(format!("Tokenization failed: {}", e)))?;
+
+            // Token ranges of the chunks within this window, skipping special
+            // tokens such as [CLS] and [SEP]
+            let offsets: Vec<(usize, usize)> = encoding
+                .get_offsets()
+                .iter()
+                .zip(encoding.get_special_tokens_mask())
+                .map(|(&offset, &special)| if special == 1 { (0, 0) } else { offset })
+                .collect();
+            let token_spans: Vec<Range<usize>> = members
+                .iter()
+                .map(|&i| {
+                    let span = &spans[i];
+                    let start = span.start - window.start + DOCUMENT_PREFIX.len();
+                    let end = span.end - window.start + DOCUMENT_PREFIX.len();
+                    token_range(&offsets, &(start..end))
+                })
+                .collect();
+
+            // Without token embeddings, fall back to embedding chunks one by one
+            let Some(pooled) = pool(&encoding, &token_spans)? else {
+                return embed(&texts);
+            };
+            for ((&i, tokens), embedding) in members.iter().zip(&token_spans).zip(pooled) {
+                if !tokens.is_empty() && tokens.end <= self.max_tokens {
+                    embeddings[i] = Some(embedding);
+                }
+            }
+        }
+
+        // Chunks without tokens of their own in the window (e.g. cut off by
+        // truncation) are embedded on their own
+        let missing: Vec<usize> = (0..spans.len()).filter(|&i| embeddings[i].is_none()).collect();
+        if !missing.is_empty() {
+            let texts: Vec<&str> = missing.iter().map(|&i| texts[i]).collect();
+            for (i, embedding) in missing.into_iter().zip(embed(&texts)?) {
+                embeddings[i] = Some(embedding);
+            }
+        }
+
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+}
+
+/// Range of tokens overlapping a byte range, given the tokens' byte offsets.
+///
+/// Tokens with empty offsets (special tokens) are never part of a range.
+fn token_range(offsets: &[(usize, usize)], bytes: &Range<usize>) -> Range<usize> {
+    let overlaps = |&(start, end): &(usize, usize)| start < end && start < bytes.end && end > bytes.start;
+    match offsets.iter().position(overlaps) {
+        Some(first) => {
+            let last = offsets.iter().rposition(overlaps).unwrap_or(first);
+            first..last + 1
+        }
+        None => 0..0,
+    }
+}
+
 /// A mock embedder for testing that doesn't require actual models.
 pub struct MockEmbedder {
     dimension: usize,
@@ -374,6 +560,10 @@ impl Embedder for MockEmbedder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokenizers::models::wordlevel::WordLevel;
+    use tokenizers::pre_tokenizers::whitespace::WhitespaceSplit;
+    use tokenizers::processors::template::TemplateProcessing;
+    use tokenizers::TruncationParams;
 
     #[tokio::test]
     async fn test_mock_embedder() {
@@ -446,4 +636,135 @@ mod tests {
         let embedding = embedder.embed_query("test").await.unwrap();
         assert_eq!(embedding.len(), 384);
     }
+
+    #[tokio::test]
+    async fn test_chunks_in_context_default() {
+        let embedder = MockEmbedder::new();
+        let document = "The handle is opened.\nIt returns the handle.";
+
+        let in_context = embedder
+            .embed_chunks_in_context(document, &[0..21, 22..44])
+            .await
+            .unwrap();
+        let separate = embedder
+            .embed_documents(&["The handle is opened.", "It returns the handle."])
+            .await
+            .unwrap();
+
+        assert_eq!(in_context, separate);
+
+        // Spans outside the document or inside a character are rejected
+        for span in [30..50, 2..4] {
+            let err = embedder.embed_chunks_in_context("héllo world", &[span]).await.unwrap_err();
+            assert!(matches!(err, RagError::InvalidArgument { .. }));
+        }
+    }
+
+    #[test]
+    fn test_token_range() {
+        // [CLS] "fn" " main" "()" [SEP]
+        let offsets = [(0, 0), (0, 2), (2, 7), (7, 9), (0, 0)];
+
+        assert_eq!(token_range(&offsets, &(0..2)), 1..2);
+        assert_eq!(token_range(&offsets, &(3..9)), 2..4);
+        assert_eq!(token_range(&offsets, &(0..9)), 1..4);
+        assert_eq!(token_range(&offsets, &(9..12)), 0..0);
+    }
+
+    /// Word-level tokenizer adding [CLS] and [SEP] and truncating to
+    /// `max_length` tokens.
+    fn word_tokenizer(words: &[String], max_length: usize) -> Tokenizer {
+        let vocab = ["[UNK]", "[CLS]", "[SEP]", DOCUMENT_PREFIX.trim()]
+            .into_iter()
+            .chain(words.iter().map(String::as_str))
+            .enumerate()
+            .map(|(id, word)| (word.to_string(), id as u32))
+            .collect();
+        let model = WordLevel::builder().vocab(vocab).unk_token("[UNK]".to_string()).build().unwrap();
+        let template = TemplateProcessing::builder()
+            .try_single("[CLS] $A [SEP]")
+            .unwrap()
+            .special_tokens(vec![("[CLS]", 1), ("[SEP]", 2)])
+            .build()
+            .unwrap();
+
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(WhitespaceSplit).with_post_processor(template);
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length,
+                ..Default::default()
+            }))
+            .unwrap();
+        tokenizer
+    }
+
+    /// Mean of context-free token embeddings, L2 normalized. Special and
+    /// prefix tokens have zero embeddings, so they do not change the result.
+    fn pool_tokens(ids: &[u32]) -> Vec<f32> {
+        let mut sum = vec![0.0f32; 3];
+        for &id in ids.iter().filter(|&&id| id > 3) {
+            sum[0] += id as f32;
+            sum[1] += 1.0;
+            sum[2] += (id % 3) as f32;
+        }
+        let norm: f32 = sum.iter().map(|x| x * x).sum::<f32>().sqrt();
+        sum.into_iter().map(|x| x / norm).collect()
+    }
+
+    #[test]
+    fn test_late_chunking_windows() {
+        // Twelve words in chunks of two, with a context of eight tokens
+        let words: Vec<String> = (0..12).map(|i| format!("w{}", i)).collect();
+        let document = words.join(" ");
+        let spans: Vec<Range<usize>> = (0..12)
+            .step_by(2)
+            .map(|i| {
+                let start = words[..i].iter().map(|w| w.len() + 1).sum::<usize>();
+                start..start + words[i].len() + 1 + words[i + 1].len()
+            })
+            .collect();
+
+        let tokenizer = word_tokenizer(&words, 8);
+        let mut untruncated = tokenizer.clone();
+        untruncated.with_truncation(None).unwrap();
+        let tokenizers = LateTokenizers {
+            tokenizer: &tokenizer,
+            untruncated: &untruncated,
+            max_tokens: 8,
+        };
+        let fallbacks = std::cell::Cell::new(0);
+        let embed = |texts: &[&str]| {
+            fallbacks.set(fallbacks.get() + texts.len());
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let encoding = tokenizer.encode(format!("{}{}", DOCUMENT_PREFIX, text), true).unwrap();
+                    pool_tokens(encoding.get_ids())
+                })
+                .collect())
+        };
+
+        let mut windows = 0;
+        let late = tokenizers
+            .embed(
+                &document,
+                &spans,
+                |encoding, token_spans| {
+                    windows += 1;
+                    let ids = encoding.get_ids();
+                    Ok(Some(token_spans.iter().map(|tokens| pool_tokens(&ids[tokens.clone()])).collect()))
+                },
+                embed,
+            )
+            .unwrap();
+
+        // Each window holds two chunks pooled over their own tokens
+        assert_eq!(windows, 3);
+        assert_eq!(fallbacks.get(), 0);
+
+        let separate = tokenizers.embed(&document, &spans, |_, _| Ok(None), embed).unwrap();
+        assert_eq!(fallbacks.get(), spans.len());
+        assert_eq!(late, separate);
+    }
 }
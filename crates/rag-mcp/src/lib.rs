@@ -11,6 +11,7 @@
 //! - `rag_create_collection` - Create a new collection
 //! - `rag_delete_collection` - Delete a collection
 //! - `rag_stats` - Get statistics about the knowledge base
+//! - `rag_reembed` - Recompute chunk headers and embeddings of a collection
 
 mod server;
 
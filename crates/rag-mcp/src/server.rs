@@ -1,16 +1,22 @@
 //! MCP server implementation.
 
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use tracing::{info, warn};
 
-use rag_chunk::{AdaptiveChunker, ChunkConfig, Chunker};
-use rag_core::{Collection, ContentType, Document, Store};
+use rag_chunk::{byte_ranges, ChunkerRegistry, Chunker, SemanticChunker, SEMANTIC_CHUNKER};
+use rag_core::{
+    conflict_uri, Chunk, ChunkData, ChunkedDocument, ChunkingConfig, Clock, Collection, ConflictRecord, ContentType,
+    Detection, Document, EmbeddingConfig, Resolution, Store, SyncConfig, SystemClock,
+};
 use rag_embed::{Embedder, MockEmbedder};
 use rag_query::{QueryConfig, QueryEngine};
 use rag_store::SqliteStore;
+use rag_sync::protocol::parse_hlc;
+use rag_sync::{bundle, load_peer_statuses, PeerStatus, SyncManager, SyncResult};
 
 /// RAG MCP Server state.
 pub struct RagMcpServer {
@@ -20,8 +26,14 @@ pub struct RagMcpServer {
     /// Embedder (mock for now).
     embedder: Arc<MockEmbedder>,
 
-    /// Chunker.
-    chunker: Arc<AdaptiveChunker>,
+    /// Chunkers and the rules picking one for each document.
+    chunkers: ChunkerRegistry,
+
+    /// Chunk sizes used when ingesting.
+    chunking: ChunkingConfig,
+
+    /// Embedding settings used by ingestion.
+    embedding: EmbeddingConfig,
 
     /// Query engine.
     engine: Arc<QueryEngine<SqliteStore, MockEmbedder>>,
@@ -39,6 +51,11 @@ pub struct SearchParams {
 
     /// Collection to search (optional).
     pub collection: Option<String>,
+
+    /// Return the largest enclosing chunk of at most this many tokens
+    /// instead of each matching chunk (optional).
+    #[serde(default)]
+    pub parent_token_budget: Option<usize>,
 }
 
 fn default_top_k() -> u32 {
@@ -57,7 +74,7 @@ pub struct IngestParams {
     /// Document content.
     pub content: String,
 
-    /// Content type (optional, auto-detected if not specified).
+    /// Content type or MIME type (optional, auto-detected if not specified).
     pub content_type: Option<String>,
 }
 
@@ -107,18 +124,25 @@ impl ToolResult {
 impl RagMcpServer {
     /// Create a new RAG MCP server with the given database path.
     pub fn new(db_path: impl Into<PathBuf>) -> Result<Self, rag_core::RagError> {
+        Self::open(db_path, &SyncConfig::default())
+    }
+
+    /// Create a new RAG MCP server with the given database path, applying the
+    /// `[sync]` settings that the store enforces.
+    pub fn open(db_path: impl Into<PathBuf>, sync: &SyncConfig) -> Result<Self, rag_core::RagError> {
         let db_path = db_path.into();
         info!("Initializing RAG MCP server with database at {:?}", db_path);
 
-        let store = Arc::new(SqliteStore::open(&db_path, 1)?);
+        let store = Arc::new(SqliteStore::open_auto(&db_path)?.with_max_clock_drift(sync.max_clock_drift_ms));
         let embedder = Arc::new(MockEmbedder::new());
-        let chunker = Arc::new(AdaptiveChunker::new());
         let engine = Arc::new(QueryEngine::new(store.clone(), embedder.clone()));
 
         Ok(Self {
             store,
             embedder,
-            chunker,
+            chunkers: ChunkerRegistry::default(),
+            chunking: ChunkingConfig::default(),
+            embedding: EmbeddingConfig::default(),
             engine,
         })
     }
@@ -129,17 +153,37 @@ impl RagMcpServer {
 
         let store = Arc::new(SqliteStore::open_memory(1)?);
         let embedder = Arc::new(MockEmbedder::new());
-        let chunker = Arc::new(AdaptiveChunker::new());
         let engine = Arc::new(QueryEngine::new(store.clone(), embedder.clone()));
 
         Ok(Self {
             store,
             embedder,
-            chunker,
+            chunkers: ChunkerRegistry::default(),
+            chunking: ChunkingConfig::default(),
+            embedding: EmbeddingConfig::default(),
             engine,
         })
     }
 
+    /// Use the `[chunking]` configuration for ingestion.
+    pub fn with_chunking(mut self, config: ChunkingConfig) -> Self {
+        self.chunkers = ChunkerRegistry::from_config(&config);
+        self.chunking = config;
+        self
+    }
+
+    /// Register a chunker that `[chunking]` routes can refer to by name.
+    pub fn with_chunker(mut self, name: impl Into<String>, chunker: Arc<dyn Chunker>) -> Self {
+        self.chunkers = self.chunkers.with_chunker(name, chunker);
+        self
+    }
+
+    /// Use the `[embedding]` configuration for ingestion.
+    pub fn with_embedding(mut self, config: EmbeddingConfig) -> Self {
+        self.embedding = config;
+        self
+    }
+
     /// Get the server info.
     pub fn info() -> ServerInfo {
         ServerInfo {
@@ -176,6 +220,14 @@ impl RagMcpServer {
                 name: "rag_stats".to_string(),
                 description: "Get statistics about the knowledge base".to_string(),
             },
+            ToolInfo {
+                name: "rag_reembed".to_string(),
+                description: "Recompute chunk headers and embeddings of a collection".to_string(),
+            },
+            ToolInfo {
+                name: "rag_sync_status".to_string(),
+                description: "Show replication status for each sync peer".to_string(),
+            },
         ]
     }
 
@@ -188,13 +240,19 @@ impl RagMcpServer {
             let config = QueryConfig {
                 top_k: params.top_k,
                 collection: params.collection,
+                parent_token_budget: params.parent_token_budget,
                 ..Default::default()
             };
             self.engine.search(&params.query, config).await
         } else {
-            self.engine
+            let results = self
+                .engine
                 .keyword_only_search(&params.query, params.top_k, params.collection.as_deref())
-                .await
+                .await;
+            match (results, params.parent_token_budget) {
+                (Ok(results), Some(budget)) => self.engine.expand_to_parents(results, budget).await,
+                (results, _) => results,
+            }
         };
 
         match results {
@@ -209,6 +267,19 @@ impl RagMcpServer {
                         "---\n[{}] {} (score: {:.3})\n",
                         result.rank, result.source_uri, result.score
                     ));
+                    if let Some(path) = &result.chunk.heading_path {
+                        output.push_str(&format!("Section: {}\n", path));
+                    }
+                    if !result.chunk.speakers.is_empty() {
+                        output.push_str(&format!("Speakers: {}\n", result.chunk.speakers.join(", ")));
+                    }
+                    match &result.chunk.time_range {
+                        Some((start, end)) if start != end => {
+                            output.push_str(&format!("Time: {} – {}\n", start, end))
+                        }
+                        Some((start, _)) => output.push_str(&format!("Time: {}\n", start)),
+                        None => {}
+                    }
                     output.push_str(&format!(
                         "Lines {}-{}:\n```\n{}\n```\n\n",
                         result.chunk.start_line, result.chunk.end_line, result.chunk.content
@@ -240,81 +311,246 @@ impl RagMcpServer {
             Ok(Some(_)) => {}
         }
 
-        // Determine content type
-        let content_type = params
-            .content_type
-            .as_ref()
-            .map(|ct| ContentType::from_path(ct))
-            .unwrap_or_else(|| ContentType::from_path(&params.source_uri));
+        // Determine content type; an explicit one overrides detection, and a
+        // MIME type is also kept for chunker routing
+        let (explicit, mime_type) = match params.content_type.as_deref() {
+            None => (ContentType::Unknown, None),
+            Some(value) => match explicit_content_type(value) {
+                Some(resolved) => resolved,
+                None => return ToolResult::error(format!("Unknown content type: {}", value)),
+            },
+        };
+
+        // Re-ingesting a URI replaces the previous version once the new one
+        // is stored
+        let previous = match self.store.get_document_by_uri(&params.source_uri).await {
+            Ok(Some(old)) if old.collection == params.collection => Some(old.id),
+            Ok(_) => None,
+            Err(e) => return ToolResult::error(format!("Database error: {}", e)),
+        };
+
+        let detection = match explicit {
+            ContentType::Unknown => Detection::detect(&params.source_uri, &params.content),
+            content_type => Detection::explicit(content_type),
+        };
+        let content_type = detection.content_type;
 
         // Create document
-        let doc = Document::new(
+        let mut doc = Document::new(
             &params.collection,
             &params.source_uri,
             &params.content,
             content_type,
         );
-        let doc_id = doc.id;
+        if let Some(mime) = mime_type {
+            doc.metadata.insert("mime_type".to_string(), serde_json::Value::String(mime.to_string()));
+        }
+        doc.metadata.insert(
+            "content_type_confidence".to_string(),
+            serde_json::json!(f64::from(detection.confidence)),
+        );
+
+        // Chunk the content; the chunker may find metadata such as a title
+        let chunked = match self.chunk_document(&doc).await {
+            Ok(chunked) => chunked,
+            Err(e) => return ToolResult::error(format!("Chunking failed: {}", e)),
+        };
+        doc.metadata.extend(chunked.metadata);
 
         // Insert document
-        if let Err(e) = self.store.insert_document(doc).await {
+        if let Err(e) = self.store.insert_document(doc.clone()).await {
             return ToolResult::error(format!("Failed to insert document: {}", e));
         }
 
-        // Chunk the content
-        let chunk_config = ChunkConfig {
-            max_tokens: 512,
-            min_tokens: 50,
-            overlap_tokens: 0,
+        let num_chunks = match self.index_chunks(&doc, &params.content, chunked.chunks).await {
+            Ok(num_chunks) => num_chunks,
+            Err(message) => {
+                // Keep the previous version rather than a partial new one
+                if let Err(e) = self.store.delete_document(doc.id).await {
+                    warn!("Failed to remove partially ingested document {}: {}", doc.id, e);
+                }
+                return ToolResult::error(message);
+            }
         };
 
-        let chunk_data = match self
-            .chunker
-            .chunk(&params.content, content_type, &chunk_config)
-        {
-            Ok(data) => data,
-            Err(e) => return ToolResult::error(format!("Chunking failed: {}", e)),
-        };
+        if let Some(old_id) = previous {
+            if let Err(e) = self.store.delete_document(old_id).await {
+                return ToolResult::error(format!("Failed to replace previous version: {}", e));
+            }
+        }
 
-        // Create chunks
-        let mut chunks = Vec::with_capacity(chunk_data.len());
-        for (idx, data) in chunk_data.into_iter().enumerate() {
-            chunks.push(rag_core::Chunk::new(
-                doc_id,
-                idx as u32,
-                &data.content,
-                data.token_count as u32,
-                data.start_line,
-                data.end_line,
+        ToolResult::success(format!(
+            "Successfully ingested '{}' with {} chunks ({}, confidence {:.2}).",
+            params.source_uri, num_chunks, content_type, detection.confidence
+        ))
+    }
+
+    /// Recompute the contextual headers of every chunk in a collection and
+    /// embed the chunks again, e.g. after changing `[chunking.context_header]`
+    /// or the embedding model.
+    pub async fn reembed(&self, collection: &str) -> ToolResult {
+        info!("Re-embedding collection: {}", collection);
+
+        match self.store.get_collection(collection).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return ToolResult::error(format!("Collection '{}' not found.", collection)),
+            Err(e) => return ToolResult::error(format!("Failed to get collection: {}", e)),
+        }
+
+        const PAGE_SIZE: u32 = 100;
+        let (mut num_documents, mut num_chunks, mut num_late) = (0, 0, 0);
+        loop {
+            let documents = match self.store.list_documents(collection, PAGE_SIZE, num_documents).await {
+                Ok(documents) => documents,
+                Err(e) => return ToolResult::error(format!("Failed to list documents: {}", e)),
+            };
+
+            for doc in &documents {
+                let mut chunks = match self.store.get_chunks_for_document(doc.id).await {
+                    Ok(chunks) => chunks,
+                    Err(e) => return ToolResult::error(format!("Failed to get chunks: {}", e)),
+                };
+
+                for chunk in &mut chunks {
+                    chunk.context = self.chunking.context_header.header(doc, chunk.heading_path.as_deref());
+                }
+                let contexts: Vec<_> = chunks.iter().map(|c| (c.id, c.context.clone())).collect();
+                if let Err(e) = self.store.update_chunk_contexts(&contexts).await {
+                    return ToolResult::error(format!("Failed to update chunks: {}", e));
+                }
+
+                if let Err(message) = self.embed_chunks(doc.raw_content.as_deref(), &chunks).await {
+                    return ToolResult::error(message);
+                }
+                num_chunks += chunks.len();
+                if self.embedding.late_chunking && doc.raw_content.is_some() {
+                    num_late += 1;
+                }
+            }
+
+            num_documents += documents.len() as u32;
+            if documents.len() < PAGE_SIZE as usize {
+                break;
+            }
+        }
+
+        let mut output = format!(
+            "Re-embedded {} chunks in {} documents of '{}'.",
+            num_chunks, num_documents, collection
+        );
+        if num_late > 0 {
+            output.push_str(&format!(
+                " {} documents were embedded with late chunking, which does not embed contextual headers.",
+                num_late
             ));
         }
+        ToolResult::success(output)
+    }
+
+    /// Chunk a document's content with its collection's chunker.
+    async fn chunk_document(&self, doc: &Document) -> rag_core::Result<ChunkedDocument> {
+        if self.chunkers.route(doc) == SEMANTIC_CHUNKER {
+            let content = doc.raw_content.as_deref().unwrap_or("");
+            let chunks = SemanticChunker::new(self.embedder.clone())
+                .with_threshold(self.chunking.semantic.threshold)
+                .chunk(content, doc.content_type, &self.chunkers.config_for(&doc.collection))
+                .await?;
+            return Ok(ChunkedDocument {
+                chunks,
+                ..Default::default()
+            });
+        }
 
-        let num_chunks = chunks.len();
+        self.chunkers.chunk_with_metadata(doc)
+    }
 
-        // Insert chunks
-        if let Err(e) = self.store.insert_chunks(&chunks).await {
-            return ToolResult::error(format!("Failed to insert chunks: {}", e));
+    /// Store and embed the chunks of a stored document chunked from the text
+    /// `content`, returning their number.
+    async fn index_chunks(&self, doc: &Document, content: &str, chunk_data: Vec<ChunkData>) -> Result<usize, String> {
+        // Create chunks; parents always precede their children
+        let mut chunks: Vec<Chunk> = Vec::with_capacity(chunk_data.len());
+        for (idx, data) in chunk_data.into_iter().enumerate() {
+            let parent_id = data.parent.and_then(|parent| chunks.get(parent)).map(|c| c.id);
+            let context = self.chunking.context_header.header(doc, data.heading_path.as_deref());
+            chunks.push(
+                Chunk::new(
+                    doc.id,
+                    idx as u32,
+                    &data.content,
+                    data.token_count as u32,
+                    data.start_line,
+                    data.end_line,
+                )
+                .with_span(data.start_byte as u32, data.end_byte as u32, data.start_column, data.end_column)
+                .with_heading_path(data.heading_path)
+                .with_chat(data.speakers, data.time_range)
+                .with_parent(parent_id, data.level)
+                .with_context(context),
+            );
         }
 
-        // Generate embeddings
-        let chunk_texts: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
-        let embeddings = match self.embedder.embed_documents(&chunk_texts).await {
-            Ok(e) => e,
-            Err(e) => return ToolResult::error(format!("Embedding failed: {}", e)),
+        // Insert chunks
+        self.store
+            .insert_chunks(&chunks)
+            .await
+            .map_err(|e| format!("Failed to insert chunks: {}", e))?;
+
+        self.embed_chunks(Some(content), &chunks).await?;
+        Ok(chunks.len())
+    }
+
+    /// Embed the chunks of a document and store the embeddings. Parent
+    /// chunks are only returned, not searched, so they are skipped.
+    ///
+    /// With late chunking, chunks are embedded within `content`, the text
+    /// they were chunked from, when it is known; their contextual headers are
+    /// then stored but not embedded.
+    async fn embed_chunks(&self, content: Option<&str>, chunks: &[Chunk]) -> Result<(), String> {
+        let parent_ids: HashSet<_> = chunks.iter().filter_map(|c| c.parent_id).collect();
+        let leaves: Vec<&Chunk> = chunks.iter().filter(|c| !parent_ids.contains(&c.id)).collect();
+
+        let embeddings = match content.filter(|_| self.embedding.late_chunking) {
+            Some(content) => {
+                let data: Vec<ChunkData> = leaves
+                    .iter()
+                    .map(|c| ChunkData {
+                        content: c.content.clone(),
+                        token_count: c.token_count as usize,
+                        start_line: c.start_line,
+                        end_line: c.end_line,
+                        start_byte: c.start_byte as usize,
+                        end_byte: c.end_byte as usize,
+                        start_column: c.start_column,
+                        end_column: c.end_column,
+                        heading_path: None,
+                        speakers: Vec::new(),
+                        time_range: None,
+                        parent: None,
+                        level: c.level,
+                    })
+                    .collect();
+                self.embedder
+                    .embed_chunks_in_context(content, &byte_ranges(content, &data))
+                    .await
+            }
+            None => {
+                let chunk_texts: Vec<String> = leaves.iter().map(|c| c.embedding_text()).collect();
+                let chunk_texts: Vec<&str> = chunk_texts.iter().map(String::as_str).collect();
+                self.embedder.embed_documents(&chunk_texts).await
+            }
         };
+        let embeddings = embeddings.map_err(|e| format!("Embedding failed: {}", e))?;
 
         // Insert embeddings if available
         if self.store.vec_enabled() {
-            let chunk_ids: Vec<_> = chunks.iter().map(|c| c.id).collect();
-            if let Err(e) = self.store.insert_embeddings(&chunk_ids, &embeddings).await {
-                return ToolResult::error(format!("Failed to insert embeddings: {}", e));
-            }
+            let chunk_ids: Vec<_> = leaves.iter().map(|c| c.id).collect();
+            self.store
+                .insert_embeddings(&chunk_ids, &embeddings)
+                .await
+                .map_err(|e| format!("Failed to insert embeddings: {}", e))?;
         }
 
-        ToolResult::success(format!(
-            "Successfully ingested '{}' with {} chunks.",
-            params.source_uri, num_chunks
-        ))
+        Ok(())
     }
 
     /// List all collections.
@@ -394,6 +630,290 @@ impl RagMcpServer {
             Err(e) => ToolResult::error(format!("Failed to get stats: {}", e)),
         }
     }
+
+    /// Get replication status for every peer this node has synced with.
+    pub async fn sync_status(&self) -> ToolResult {
+        match load_peer_statuses(self.store.as_ref()).await {
+            Ok(statuses) => {
+                if statuses.is_empty() {
+                    return ToolResult::success("No sync peers have been contacted yet.");
+                }
+
+                let now = SystemClock.now_millis();
+                let mut output = format!("Sync status for {} peer(s):\n\n", statuses.len());
+                for status in &statuses {
+                    output.push_str(&format_peer_status(status, now));
+                }
+
+                ToolResult::success(output)
+            }
+            Err(e) => ToolResult::error(format!("Failed to get sync status: {}", e)),
+        }
+    }
+
+    /// Sync with the configured peers now, ignoring any retry backoff.
+    ///
+    /// With `full`, runs range-digest reconciliation instead of a watermark sync.
+    pub async fn sync_now(&self, config: &SyncConfig, peer: Option<&str>, full: bool) -> ToolResult {
+        if config.peers.is_empty() {
+            return ToolResult::error("No sync peers configured.");
+        }
+
+        let manager = match SyncManager::from_config(self.store.clone(), config.clone()) {
+            Ok(manager) => manager,
+            Err(e) => return ToolResult::error(format!("Failed to set up sync: {}", e)),
+        };
+
+        let results = match peer {
+            Some(peer_id) => {
+                let result = if full {
+                    manager.reconcile_with(peer_id).await
+                } else {
+                    manager.sync_with(peer_id).await
+                };
+                vec![SyncResult {
+                    peer_id: peer_id.to_string(),
+                    result,
+                }]
+            }
+            None if full => manager.reconcile_all().await,
+            None => manager.sync_all().await,
+        };
+
+        let mut output = String::new();
+        let mut failed = 0;
+        for result in &results {
+            match &result.result {
+                Ok(stats) => output.push_str(&format!(
+                    "- {}: pulled {}, pushed {} in {}ms\n",
+                    result.peer_id, stats.pulled, stats.pushed, stats.duration_ms
+                )),
+                Err(e) => {
+                    failed += 1;
+                    output.push_str(&format!("- {}: failed: {}\n", result.peer_id, e));
+                }
+            }
+        }
+
+        if failed == 0 {
+            ToolResult::success(output)
+        } else {
+            ToolResult::error(output)
+        }
+    }
+
+    /// List the most recently resolved sync conflicts.
+    pub async fn list_conflicts(&self, limit: u32) -> ToolResult {
+        match self.store.list_conflicts(limit).await {
+            Ok(conflicts) if conflicts.is_empty() => ToolResult::success("No sync conflicts."),
+            Ok(conflicts) => {
+                let now = SystemClock.now_millis();
+                let mut output = format!("{} conflict(s):\n\n", conflicts.len());
+                for conflict in &conflicts {
+                    output.push_str(&format_conflict(conflict, now));
+                }
+                ToolResult::success(output)
+            }
+            Err(e) => ToolResult::error(format!("Failed to list conflicts: {}", e)),
+        }
+    }
+
+    /// Resolve a logged conflict again, restoring versions that were dropped.
+    pub async fn resolve_conflict(&self, id: i64, resolution: Resolution) -> ToolResult {
+        let conflict = match self.store.get_conflict(id).await {
+            Ok(Some(conflict)) => conflict,
+            Ok(None) => return ToolResult::error(format!("No conflict with ID {}", id)),
+            Err(e) => return ToolResult::error(format!("Database error: {}", e)),
+        };
+
+        if let Err(e) = self.apply_resolution(&conflict, resolution).await {
+            return ToolResult::error(format!("Failed to resolve conflict {}: {}", id, e));
+        }
+        match self.store.set_conflict_resolution(id, resolution).await {
+            Ok(()) => ToolResult::success(format!(
+                "Resolved conflict {} on '{}' as {}.",
+                id, conflict.source_uri, resolution
+            )),
+            Err(e) => ToolResult::error(format!("Failed to record resolution: {}", e)),
+        }
+    }
+
+    /// Make the versions chosen by `resolution` live and delete the others.
+    ///
+    /// Missing versions are re-ingested from their stored content as new
+    /// local writes, so the outcome replicates like any other edit.
+    async fn apply_resolution(&self, conflict: &ConflictRecord, resolution: Resolution) -> rag_core::Result<()> {
+        let keep: Vec<&Document> = match resolution {
+            Resolution::KeepLocal => vec![&conflict.local],
+            Resolution::TakeRemote => vec![&conflict.remote],
+            Resolution::KeepBoth => vec![&conflict.local, &conflict.remote],
+        };
+
+        for &version in &keep {
+            if self.store.get_document(version.id).await?.is_some() {
+                continue;
+            }
+            let Some(content) = version.raw_content.clone() else {
+                return Err(rag_core::RagError::invalid_argument(format!(
+                    "Version {} has no stored content",
+                    version.id
+                )));
+            };
+            let source_uri = match resolution {
+                Resolution::KeepBoth => conflict_uri(version),
+                _ => conflict.source_uri.clone(),
+            };
+
+            let result = self
+                .ingest(IngestParams {
+                    collection: conflict.collection.clone(),
+                    source_uri,
+                    content,
+                    content_type: Some(version.content_type.to_string()),
+                })
+                .await;
+            if !result.success {
+                return Err(rag_core::RagError::internal(result.message));
+            }
+        }
+
+        // Versions are only deleted once the kept ones are in place
+        for version in [&conflict.local, &conflict.remote] {
+            let kept = keep.iter().any(|k| k.id == version.id);
+            if !kept && self.store.get_document(version.id).await?.is_some() {
+                self.store.delete_document(version.id).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write changes after `since` (hex HLC) to a bundle file for offline sync.
+    ///
+    /// Without `since`, continues from the previous export.
+    pub async fn sync_export(&self, since: Option<&str>, output: &Path) -> ToolResult {
+        let since = match since.map(parse_hlc).transpose() {
+            Ok(since) => since,
+            Err(e) => return ToolResult::error(e.to_string()),
+        };
+
+        match bundle::export_bundle(self.store.as_ref(), since, output).await {
+            Ok(stats) => ToolResult::success(format!(
+                "Exported {} changes since {} to {}\nWatermark: {}",
+                stats.changes,
+                stats.since,
+                output.display(),
+                stats.watermark
+            )),
+            Err(e) => ToolResult::error(format!("Failed to export bundle: {}", e)),
+        }
+    }
+
+    /// Apply a bundle file written by `sync_export` on another node.
+    pub async fn sync_import(&self, config: &SyncConfig, path: &Path) -> ToolResult {
+        let resolver = config.conflict_policy.resolver();
+        match bundle::import_bundle(self.store.as_ref(), path, resolver.as_ref()).await {
+            Ok(stats) if stats.already_imported => ToolResult::success(format!(
+                "Bundle from node {} was already imported; nothing to do.",
+                stats.node_id
+            )),
+            Ok(stats) => ToolResult::success(format!(
+                "Imported {} changes from node {} (watermark {})",
+                stats.changes, stats.node_id, stats.watermark
+            )),
+            Err(e) => ToolResult::error(format!("Failed to import bundle: {}", e)),
+        }
+    }
+
+    /// Give this database a new random node ID, e.g. after copying the file.
+    pub fn reset_node_id(&self) -> ToolResult {
+        let old = self.store.node_id();
+        match self.store.reset_node_id() {
+            Ok(new) => ToolResult::success(format!("Node ID changed from {} to {}", old, new)),
+            Err(e) => ToolResult::error(format!("Failed to reset node ID: {}", e)),
+        }
+    }
+}
+
+/// Top-level MIME types, telling a MIME type such as `text/x-proto` apart from
+/// a path such as `src/main.rs`.
+const MIME_TYPES: &[&str] = &[
+    "application", "audio", "font", "image", "message", "model", "multipart", "text", "video",
+];
+
+/// Resolve an explicit `content_type`: a MIME type, a content type name such
+/// as `rust`, or a file name or path such as `foo.rs`. Returns the content type
+/// and the MIME type to keep for chunker routing, or `None` when the value is
+/// not recognised.
+fn explicit_content_type(value: &str) -> Option<(ContentType, Option<&str>)> {
+    let value = value.trim();
+    if value.contains('/') {
+        let content_type = ContentType::from_mime(value);
+        if content_type != ContentType::Unknown {
+            return Some((content_type, Some(value)));
+        }
+    }
+
+    let content_type = ContentType::from_name(value);
+    if content_type != ContentType::Unknown || value.eq_ignore_ascii_case("unknown") {
+        return Some((content_type, None));
+    }
+    let content_type = ContentType::from_path(value);
+    if content_type != ContentType::Unknown {
+        return Some((content_type, None));
+    }
+
+    // An unrecognised MIME type can still route to a registered chunker
+    let top_level = value.split_once('/').map(|(top_level, _)| top_level.to_lowercase());
+    top_level
+        .filter(|top_level| MIME_TYPES.contains(&top_level.as_str()))
+        .map(|_| (ContentType::Unknown, Some(value)))
+}
+
+/// Render one conflict as a bullet list entry.
+fn format_conflict(conflict: &ConflictRecord, now: u64) -> String {
+    let mut output = format!(
+        "- #{} {} in {} ({}s ago)\n",
+        conflict.id,
+        conflict.source_uri,
+        conflict.collection,
+        now.saturating_sub(conflict.resolved_at) / 1000
+    );
+    output.push_str(&format!("  Resolution: {} ({})\n", conflict.resolution, conflict.policy));
+    output.push_str(&format!(
+        "  Local: {} written by node {}\n",
+        conflict.local.id, conflict.local.hlc.node_id
+    ));
+    output.push_str(&format!(
+        "  Remote: {} written by node {}\n",
+        conflict.remote.id, conflict.remote.hlc.node_id
+    ));
+    output
+}
+
+/// Render one peer's status as a bullet list entry.
+fn format_peer_status(status: &PeerStatus, now: u64) -> String {
+    let ago = |ts: Option<u64>| match ts {
+        Some(ts) => format!("{}s ago", now.saturating_sub(ts) / 1000),
+        None => "never".to_string(),
+    };
+
+    let mut output = format!("- {} ({})\n", status.peer_id, status.endpoint);
+    output.push_str(&format!("  Last success: {}\n", ago(status.last_success)));
+    output.push_str(&format!("  Last attempt: {}\n", ago(status.last_attempt)));
+    match status.lag_ms() {
+        Some(lag) => output.push_str(&format!("  Lag: {}ms\n", lag)),
+        None => output.push_str("  Lag: unknown\n"),
+    }
+    if let Some(error) = &status.last_error {
+        output.push_str(&format!(
+            "  Error: {} ({} consecutive failures, retry in {}s)\n",
+            error,
+            status.consecutive_failures,
+            status.next_attempt.saturating_sub(now) / 1000
+        ));
+    }
+    output
 }
 
 /// Server info.
@@ -414,6 +934,7 @@ pub struct ToolInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rag_core::ChunkerRoute;
 
     #[tokio::test]
     async fn test_server_creation() {
@@ -478,11 +999,226 @@ mod tests {
             query: "hello".to_string(),
             top_k: 5,
             collection: Some("code".to_string()),
+            parent_token_budget: None,
         };
         let result = server.search(search_params).await;
         assert!(result.success, "Search failed: {}", result.message);
     }
 
+    #[tokio::test]
+    async fn test_reembed() {
+        let server = RagMcpServer::new_memory().unwrap();
+        server
+            .create_collection(CollectionParams {
+                name: "code".to_string(),
+                description: None,
+            })
+            .await;
+        let ingest_params = IngestParams {
+            collection: "code".to_string(),
+            source_uri: "file://src/cache.rs".to_string(),
+            content: "fn get() {}".to_string(),
+            content_type: Some("rust".to_string()),
+        };
+        assert!(server.ingest(ingest_params).await.success);
+
+        let result = server.reembed("code").await;
+        assert!(result.success, "Re-embed failed: {}", result.message);
+        assert!(result.message.contains("1 chunks in 1 documents"));
+        assert!(!result.message.contains("late chunking"));
+
+        assert!(!server.reembed("missing").await.success);
+
+        let server = server.with_embedding(EmbeddingConfig {
+            late_chunking: true,
+            ..Default::default()
+        });
+        let result = server.reembed("code").await;
+        assert!(result.success, "Re-embed failed: {}", result.message);
+        assert!(result.message.contains("1 documents were embedded with late chunking"));
+    }
+
+    #[tokio::test]
+    async fn test_ingest_html_metadata() {
+        let server = RagMcpServer::new_memory().unwrap();
+        server
+            .create_collection(CollectionParams {
+                name: "docs".to_string(),
+                description: None,
+            })
+            .await;
+        let ingest_params = IngestParams {
+            collection: "docs".to_string(),
+            source_uri: "https://example.com/install?ref=nav".to_string(),
+            content: "<title>Install</title><link rel=canonical href=https://example.com/install>\
+                      <main><h1>Install</h1><p>Download it.</p></main>"
+                .to_string(),
+            content_type: Some("html".to_string()),
+        };
+        assert!(server.ingest(ingest_params).await.success);
+
+        let doc = server
+            .store
+            .get_document_by_uri("https://example.com/install?ref=nav")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(doc.title().as_deref(), Some("Install"));
+        assert_eq!(doc.metadata["canonical_url"], "https://example.com/install");
+    }
+
+    #[tokio::test]
+    async fn test_ingest_detects_content_type() {
+        let server = RagMcpServer::new_memory().unwrap();
+        server
+            .create_collection(CollectionParams {
+                name: "notes".to_string(),
+                description: None,
+            })
+            .await;
+        let ingest_params = |uri: &str, content_type: Option<&str>| IngestParams {
+            collection: "notes".to_string(),
+            source_uri: uri.to_string(),
+            content: "{\"role\": \"user\", \"content\": \"hi\"}\n{\"role\": \"assistant\", \"content\": \"hello\"}\n"
+                .to_string(),
+            content_type: content_type.map(str::to_string),
+        };
+
+        let result = server.ingest(ingest_params("session-1", None)).await;
+        assert!(result.success);
+        assert!(result.message.contains("(Chat, confidence 0.75)"));
+        let doc = server.store.get_document_by_uri("session-1").await.unwrap().unwrap();
+        assert_eq!(doc.content_type, ContentType::Chat);
+
+        let result = server.ingest(ingest_params("session-2", Some("json"))).await;
+        assert!(result.message.contains("(JSON, confidence 1.00)"));
+        let doc = server.store.get_document_by_uri("session-2").await.unwrap().unwrap();
+        assert_eq!(doc.content_type, ContentType::Json);
+        assert_eq!(doc.metadata["content_type_confidence"], 1.0);
+
+        // A file name or path gives the type by its extension
+        let result = server.ingest(ingest_params("session-3", Some("src/foo.json"))).await;
+        assert!(result.message.contains("(JSON, confidence 1.00)"));
+
+        let result = server.ingest(ingest_params("session-2", Some("klingon"))).await;
+        assert!(!result.success);
+        assert!(result.message.contains("Unknown content type: klingon"));
+        assert!(server.store.get_document_by_uri("session-2").await.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_explicit_content_type() {
+        assert_eq!(explicit_content_type("rust"), Some((ContentType::Rust, None)));
+        assert_eq!(explicit_content_type("foo.rs"), Some((ContentType::Rust, None)));
+        assert_eq!(explicit_content_type("src/foo.rs"), Some((ContentType::Rust, None)));
+        assert_eq!(explicit_content_type("Makefile"), Some((ContentType::Makefile, None)));
+        assert_eq!(explicit_content_type("text/html"), Some((ContentType::Html, Some("text/html"))));
+        assert_eq!(
+            explicit_content_type("application/x-protobuf"),
+            Some((ContentType::Unknown, Some("application/x-protobuf")))
+        );
+        assert_eq!(explicit_content_type("unknown"), Some((ContentType::Unknown, None)));
+        assert_eq!(explicit_content_type("klingon"), None);
+        assert_eq!(explicit_content_type("src/foo.klingon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_routes_to_registered_chunker() {
+        let config = ChunkingConfig {
+            routes: vec![ChunkerRoute {
+                glob: Some("*.proto".to_string()),
+                chunker: "proto".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let server = RagMcpServer::new_memory().unwrap().with_chunking(config);
+        server
+            .create_collection(CollectionParams {
+                name: "api".to_string(),
+                description: None,
+            })
+            .await;
+        let ingest_params = || IngestParams {
+            collection: "api".to_string(),
+            source_uri: "file://api/user.proto".to_string(),
+            content: "message User {\n  string name = 1;\n}".to_string(),
+            content_type: None,
+        };
+
+        let result = server.ingest(ingest_params()).await;
+        assert!(!result.success);
+        assert!(result.message.contains("Unknown chunker 'proto'"));
+
+        let server = server.with_chunker("proto", Arc::new(rag_chunk::RecursiveChunker::new()));
+        assert!(server.ingest(ingest_params()).await.success);
+    }
+
+    /// Chunker that fails on content containing "broken".
+    struct BrittleChunker;
+
+    impl Chunker for BrittleChunker {
+        fn chunk(
+            &self,
+            content: &str,
+            content_type: ContentType,
+            config: &rag_core::ChunkConfig,
+        ) -> rag_core::Result<Vec<ChunkData>> {
+            if content.contains("broken") {
+                return Err(rag_core::RagError::chunking("cannot chunk broken content"));
+            }
+            rag_chunk::RecursiveChunker::new().chunk(content, content_type, config)
+        }
+
+        fn supported_types(&self) -> Vec<ContentType> {
+            vec![ContentType::PlainText]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_reingest_keeps_previous_version() {
+        let config = ChunkingConfig {
+            routes: vec![ChunkerRoute {
+                glob: Some("*.txt".to_string()),
+                chunker: "brittle".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let server = RagMcpServer::new_memory()
+            .unwrap()
+            .with_chunking(config)
+            .with_chunker("brittle", Arc::new(BrittleChunker));
+        server
+            .create_collection(CollectionParams {
+                name: "notes".to_string(),
+                description: None,
+            })
+            .await;
+        let ingest_params = |content: &str| IngestParams {
+            collection: "notes".to_string(),
+            source_uri: "notes.txt".to_string(),
+            content: content.to_string(),
+            content_type: None,
+        };
+
+        assert!(server.ingest(ingest_params("first version")).await.success);
+        let first = server.store.get_document_by_uri("notes.txt").await.unwrap().unwrap();
+
+        let result = server.ingest(ingest_params("broken version")).await;
+        assert!(!result.success);
+        assert!(result.message.contains("Chunking failed"));
+        let documents = server.store.list_documents("notes", 10, 0).await.unwrap();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].id, first.id);
+        assert!(!server.store.get_chunks_for_document(first.id).await.unwrap().is_empty());
+
+        assert!(server.ingest(ingest_params("second version")).await.success);
+        let documents = server.store.list_documents("notes", 10, 0).await.unwrap();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].raw_content.as_deref(), Some("second version"));
+    }
+
     #[tokio::test]
     async fn test_stats() {
         let server = RagMcpServer::new_memory().unwrap();
@@ -492,6 +1228,18 @@ mod tests {
         assert!(result.message.contains("Collections:"));
     }
 
+    #[tokio::test]
+    async fn test_sync_status_without_peers() {
+        let server = RagMcpServer::new_memory().unwrap();
+
+        let result = server.sync_status().await;
+        assert!(result.success);
+        assert!(result.message.contains("No sync peers"));
+
+        let result = server.sync_now(&SyncConfig::default(), None, false).await;
+        assert!(!result.success);
+    }
+
     #[tokio::test]
     async fn test_tools_list() {
         let tools = RagMcpServer::tools();
@@ -33,6 +33,11 @@ pub struct QueryConfig {
 
     /// Collection to search (None for all collections).
     pub collection: Option<String>,
+
+    /// Replace each hit with its largest enclosing chunk (function, section,
+    /// ...) of at most this many tokens. Adjacent chunks are not added when
+    /// set.
+    pub parent_token_budget: Option<usize>,
 }
 
 impl Default for QueryConfig {
@@ -44,6 +49,7 @@ impl Default for QueryConfig {
             expand_context: true,
             context_chunks: 1,
             collection: None,
+            parent_token_budget: None,
         }
     }
 }
@@ -139,8 +145,10 @@ where
             });
         }
 
-        // Optionally expand context
-        if config.expand_context && config.context_chunks > 0 {
+        // Optionally replace hits with their parents, or expand context
+        if let Some(budget) = config.parent_token_budget {
+            results = self.parents_within_budget(results, budget).await?;
+        } else if config.expand_context && config.context_chunks > 0 {
             results = self
                 .expand_context(results, config.context_chunks, &mut seen_chunks)
                 .await?;
@@ -186,6 +194,44 @@ where
             .await
     }
 
+    /// Replace results with their largest parent of at most `budget` tokens.
+    ///
+    /// Results that end up with the same parent are merged, keeping the
+    /// best ranked one.
+    pub async fn expand_to_parents(&self, results: SearchResults, budget: usize) -> Result<SearchResults> {
+        let start = Instant::now();
+        let expanded = self.parents_within_budget(results.results, budget).await?;
+
+        Ok(SearchResults {
+            query: results.query,
+            total_results: expanded.len(),
+            latency_ms: results.latency_ms + start.elapsed().as_millis() as u64,
+            results: expanded,
+        })
+    }
+
+    /// Walk each result up its parents while they fit in `budget` tokens.
+    async fn parents_within_budget(&self, results: Vec<SearchResult>, budget: usize) -> Result<Vec<SearchResult>> {
+        let mut expanded = Vec::with_capacity(results.len());
+        let mut seen: HashSet<Ulid> = HashSet::new();
+
+        for mut result in results {
+            while let Some(parent_id) = result.chunk.parent_id {
+                match self.store.get_chunk(parent_id).await? {
+                    Some(parent) if parent.token_count as usize <= budget => result.chunk = parent,
+                    _ => break,
+                }
+            }
+
+            if seen.insert(result.chunk.id) {
+                result.rank = expanded.len() as u32 + 1;
+                expanded.push(result);
+            }
+        }
+
+        Ok(expanded)
+    }
+
     /// Expand results with adjacent chunks for more context.
     async fn expand_context(
         &self,
@@ -196,7 +242,11 @@ where
         let mut expanded = Vec::with_capacity(results.len() * 2);
 
         for result in results {
-            let doc_chunks = self.store.get_chunks_for_document(result.chunk.doc_id).await?;
+            let mut doc_chunks = self.store.get_chunks_for_document(result.chunk.doc_id).await?;
+
+            // Parent chunks overlap their children; only leaves are neighbours
+            let parents: HashSet<Ulid> = doc_chunks.iter().filter_map(|c| c.parent_id).collect();
+            doc_chunks.retain(|c| !parents.contains(&c.id));
 
             // Find the index of the current chunk
             let current_idx = doc_chunks
@@ -307,6 +357,35 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use async_trait::async_trait;
+    use rag_core::{Chunk, Collection, ContentType, Document};
+    use rag_store::SqliteStore;
+
+    /// Embedder for keyword-only tests.
+    struct NoEmbedder;
+
+    #[async_trait]
+    impl Embedder for NoEmbedder {
+        async fn embed_documents(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|_| Vec::new()).collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f32>> {
+            Ok(Vec::new())
+        }
+
+        fn count_tokens(&self, text: &str) -> Result<usize> {
+            Ok(text.split_whitespace().count())
+        }
+
+        fn dimension(&self) -> usize {
+            0
+        }
+
+        fn max_tokens(&self) -> usize {
+            512
+        }
+    }
 
     #[test]
     fn test_query_config_default() {
@@ -315,4 +394,39 @@ mod tests {
         assert!(config.vector_weight > 0.0);
         assert!(config.keyword_weight > 0.0);
     }
+
+    #[tokio::test]
+    async fn test_expand_to_parents() {
+        let store = Arc::new(SqliteStore::open_memory(1).unwrap());
+        store.create_collection(Collection::new("docs", None)).await.unwrap();
+        let content = "# Setup\n\nInstall the tool.\n\nConfigure the tool.";
+        let doc = Document::new("docs", "setup.md", content, ContentType::Markdown);
+        store.insert_document(doc.clone()).await.unwrap();
+
+        let section = Chunk::new(doc.id, 0, content, 8, 1, 5);
+        let install = Chunk::new(doc.id, 1, "Install the tool.", 3, 3, 3).with_parent(Some(section.id), 1);
+        let configure = Chunk::new(doc.id, 2, "Configure the tool.", 3, 5, 5).with_parent(Some(section.id), 1);
+        store
+            .insert_chunks(&[section.clone(), install, configure])
+            .await
+            .unwrap();
+
+        let engine = QueryEngine::new(store, Arc::new(NoEmbedder));
+
+        // Parents are not searched themselves
+        let results = engine.keyword_only_search("tool", 10, None).await.unwrap();
+        assert_eq!(results.total_results, 2);
+        assert!(results.results.iter().all(|r| r.chunk.parent_id == Some(section.id)));
+
+        // Both hits share the section, which fits the budget
+        let parents = engine.expand_to_parents(results.clone(), 8).await.unwrap();
+        assert_eq!(parents.total_results, 1);
+        assert_eq!(parents.results[0].chunk.id, section.id);
+        assert_eq!(parents.results[0].rank, 1);
+
+        // A section over the budget leaves the hits as they are
+        let leaves = engine.expand_to_parents(results, 7).await.unwrap();
+        assert_eq!(leaves.total_results, 2);
+        assert!(leaves.results.iter().all(|r| r.chunk.level == 1));
+    }
 }
@@ -38,33 +38,73 @@ CREATE TABLE IF NOT EXISTS chunks (
     start_line INTEGER NOT NULL,
     end_line INTEGER NOT NULL,
     content_hash BLOB,
-    hlc BLOB NOT NULL
+    hlc BLOB NOT NULL,
+    heading_path TEXT,
+    parent_id TEXT,
+    level INTEGER NOT NULL DEFAULT 0,
+    context TEXT,
+    start_byte INTEGER NOT NULL DEFAULT 0,
+    end_byte INTEGER NOT NULL DEFAULT 0,
+    start_column INTEGER NOT NULL DEFAULT 0,
+    end_column INTEGER NOT NULL DEFAULT 0,
+    speakers TEXT,
+    time_start TEXT,
+    time_end TEXT
 );
 
 CREATE INDEX IF NOT EXISTS idx_chunks_doc_id ON chunks(doc_id);
 CREATE INDEX IF NOT EXISTS idx_chunks_hlc ON chunks(hlc);
 
--- FTS5 virtual table for keyword search
+-- FTS5 virtual table for keyword search, over the chunk text and its
+-- contextual header
 CREATE VIRTUAL TABLE IF NOT EXISTS chunks_fts USING fts5(
     content,
+    context,
     content=chunks,
     content_rowid=rowid
 );
 
 -- Triggers to keep FTS5 in sync with chunks table
 CREATE TRIGGER IF NOT EXISTS chunks_ai AFTER INSERT ON chunks BEGIN
-    INSERT INTO chunks_fts(rowid, content) VALUES (NEW.rowid, NEW.content);
+    INSERT INTO chunks_fts(rowid, content, context) VALUES (NEW.rowid, NEW.content, NEW.context);
 END;
 
 CREATE TRIGGER IF NOT EXISTS chunks_ad AFTER DELETE ON chunks BEGIN
-    INSERT INTO chunks_fts(chunks_fts, rowid, content) VALUES ('delete', OLD.rowid, OLD.content);
+    INSERT INTO chunks_fts(chunks_fts, rowid, content, context)
+    VALUES ('delete', OLD.rowid, OLD.content, OLD.context);
 END;
 
 CREATE TRIGGER IF NOT EXISTS chunks_au AFTER UPDATE ON chunks BEGIN
-    INSERT INTO chunks_fts(chunks_fts, rowid, content) VALUES ('delete', OLD.rowid, OLD.content);
-    INSERT INTO chunks_fts(rowid, content) VALUES (NEW.rowid, NEW.content);
+    INSERT INTO chunks_fts(chunks_fts, rowid, content, context)
+    VALUES ('delete', OLD.rowid, OLD.content, OLD.context);
+    INSERT INTO chunks_fts(rowid, content, context) VALUES (NEW.rowid, NEW.content, NEW.context);
 END;
 
+-- Tombstones for deleted rows, so deletions replicate with an HLC
+CREATE TABLE IF NOT EXISTS tombstones (
+    kind TEXT NOT NULL,
+    id TEXT NOT NULL,
+    hlc BLOB NOT NULL,
+    PRIMARY KEY (kind, id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tombstones_hlc ON tombstones(hlc);
+
+-- Conflicts between documents edited concurrently on two nodes
+CREATE TABLE IF NOT EXISTS sync_conflicts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    collection TEXT NOT NULL,
+    source_uri TEXT NOT NULL,
+    local_id TEXT NOT NULL,
+    remote_id TEXT NOT NULL,
+    local_version TEXT NOT NULL,
+    remote_version TEXT NOT NULL,
+    policy TEXT NOT NULL,
+    resolution TEXT NOT NULL,
+    resolved_at INTEGER NOT NULL,
+    UNIQUE (local_id, remote_id)
+);
+
 -- Sync metadata table for tracking replication state
 CREATE TABLE IF NOT EXISTS sync_state (
     key TEXT PRIMARY KEY,
@@ -4,17 +4,34 @@ use std::path::Path;
 use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
+use rand::Rng;
 use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
 use tracing::{debug, info, warn};
 use ulid::Ulid;
 
+use rag_core::hlc::DEFAULT_MAX_DRIFT_MS;
+use rag_core::digest::{
+    CHUNK_KEY_PREFIX, COLLECTION_KEY_PREFIX, DOCUMENT_KEY_PREFIX, TOMBSTONE_KEY_PREFIX,
+};
 use rag_core::{
-    Collection, Chunk, ContentType, Document, HybridLogicalClock, RagError, Result,
-    Stats, Store, SyncChange,
+    conflict_uri, Clock, Collection, CollectionFilter, Chunk, ConflictRecord, ConflictResolver,
+    ContentType, Document, HybridLogicalClock, KeyRange, RagError, Resolution,
+    Result, Stats, Store, SyncChange, SyncItem, SystemClock,
 };
 
 use crate::schema::{SCHEMA, VEC_SCHEMA};
 
+/// `sync_state` key under which the last issued HLC is persisted.
+const HLC_STATE_KEY: &str = "hlc";
+
+/// `sync_state` key under which the node ID is persisted.
+const NODE_ID_STATE_KEY: &str = "node_id";
+
+/// Tombstone kinds.
+const TOMBSTONE_COLLECTION: &str = "collection";
+const TOMBSTONE_DOCUMENT: &str = "document";
+const TOMBSTONE_CHUNK: &str = "chunk";
+
 /// SQLite-based store implementation.
 ///
 /// Uses a blocking Mutex for thread-safe access and runs SQLite operations
@@ -23,12 +40,15 @@ pub struct SqliteStore {
     /// Connection wrapped in blocking Mutex.
     conn: Arc<Mutex<Connection>>,
 
-    /// Node ID for HLC.
-    node_id: u16,
-
     /// Current HLC state.
     hlc: Arc<Mutex<HybridLogicalClock>>,
 
+    /// Physical clock driving the HLC.
+    clock: Arc<dyn Clock>,
+
+    /// Maximum milliseconds a remote HLC may be ahead of local time.
+    max_clock_drift_ms: u64,
+
     /// Whether sqlite-vec extension is loaded.
     vec_enabled: bool,
 }
@@ -40,8 +60,24 @@ unsafe impl Sync for SqliteStore {}
 impl SqliteStore {
     /// Open or create a database at the given path.
     pub fn open(path: impl AsRef<Path>, node_id: u16) -> Result<Self> {
+        Self::open_with_clock(path, node_id, Arc::new(SystemClock))
+    }
+
+    /// Open or create a database at the given path, driving the HLC from `clock`.
+    pub fn open_with_clock(path: impl AsRef<Path>, node_id: u16, clock: Arc<dyn Clock>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::init(Self::connect(path)?, Some(node_id), clock, path)
+    }
+
+    /// Open or create a database at the given path with the node ID stored
+    /// in it, generating a random one when the database is created.
+    pub fn open_auto(path: impl AsRef<Path>) -> Result<Self> {
         let path = path.as_ref();
+        Self::init(Self::connect(path)?, None, Arc::new(SystemClock), path)
+    }
 
+    /// Open a connection to a database file.
+    fn connect(path: &Path) -> Result<Connection> {
         // Ensure parent directory exists
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
@@ -56,25 +92,37 @@ impl SqliteStore {
         )
         .map_err(|e| RagError::database(format!("Failed to open database: {}", e)))?;
 
-        Self::init(conn, node_id, path)
+        Ok(conn)
     }
 
     /// Open an in-memory database (for testing).
     pub fn open_memory(node_id: u16) -> Result<Self> {
+        Self::open_memory_with_clock(node_id, Arc::new(SystemClock))
+    }
+
+    /// Open an in-memory database, driving the HLC from `clock`.
+    pub fn open_memory_with_clock(node_id: u16, clock: Arc<dyn Clock>) -> Result<Self> {
         let conn = Connection::open_in_memory()
             .map_err(|e| RagError::database(format!("Failed to open in-memory database: {}", e)))?;
 
-        Self::init(conn, node_id, Path::new(":memory:"))
+        Self::init(conn, Some(node_id), clock, Path::new(":memory:"))
+    }
+
+    /// Set the maximum milliseconds a remote HLC may be ahead of local time.
+    pub fn with_max_clock_drift(mut self, max_clock_drift_ms: u64) -> Self {
+        self.max_clock_drift_ms = max_clock_drift_ms;
+        self
     }
 
     /// Initialize the store with a connection.
-    fn init(conn: Connection, node_id: u16, path: &Path) -> Result<Self> {
+    fn init(conn: Connection, node_id: Option<u16>, clock: Arc<dyn Clock>, path: &Path) -> Result<Self> {
         // Configure SQLite for performance
         Self::configure_connection(&conn)?;
 
         // Initialize schema
         conn.execute_batch(SCHEMA)
             .map_err(|e| RagError::database(format!("Failed to initialize schema: {}", e)))?;
+        Self::migrate(&conn)?;
 
         // Try to load sqlite-vec extension
         let vec_enabled = Self::try_load_vec_extension(&conn);
@@ -87,19 +135,221 @@ impl SqliteStore {
             warn!("sqlite-vec extension not available - vector search disabled");
         }
 
-        // Initialize HLC
-        let hlc = HybridLogicalClock::new(node_id);
+        // An explicit node ID overrides the stored one
+        let node_id = match node_id.or(Self::stored_node_id(&conn)?) {
+            Some(node_id) => node_id,
+            None => {
+                let node_id = Self::random_node_id(None);
+                info!("Generated node ID {}", node_id);
+                node_id
+            }
+        };
+        Self::persist_node_id(&conn, node_id)?;
+
+        // Initialize HLC, never going below anything already issued
+        let hlc = Self::seed_hlc(&conn, node_id, clock.as_ref())?;
+        Self::persist_hlc(&conn, &hlc)?;
 
-        info!("Database opened at {:?}", path);
+        info!("Database opened at {:?} (node {}, hlc {})", path, node_id, hlc);
 
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
-            node_id,
             hlc: Arc::new(Mutex::new(hlc)),
+            clock,
+            max_clock_drift_ms: DEFAULT_MAX_DRIFT_MS,
             vec_enabled,
         })
     }
 
+    /// Compute the initial HLC for this node.
+    ///
+    /// Takes the maximum of the wall clock, the persisted clock and the highest
+    /// HLC stored in any table, so a clock that stepped backwards while the
+    /// database was closed cannot produce HLCs lower than existing rows.
+    fn seed_hlc(conn: &Connection, node_id: u16, clock: &dyn Clock) -> Result<HybridLogicalClock> {
+        let persisted: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT value FROM sync_state WHERE key = ?1",
+                params![HLC_STATE_KEY],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        let persisted = persisted
+            .and_then(|bytes| HybridLogicalClock::from_bytes(&bytes))
+            .unwrap_or_else(HybridLogicalClock::zero);
+        let floor = persisted.max(Self::query_watermark(conn)?);
+
+        let fresh = HybridLogicalClock::new_with_clock(node_id, clock);
+        let seeded = HybridLogicalClock::from_parts(floor.wall_time, floor.logical, node_id);
+
+        if seeded > fresh {
+            warn!(
+                "Wall clock ({}) is behind stored HLC ({}); continuing from stored clock",
+                fresh.wall_time, floor.wall_time
+            );
+        }
+
+        Ok(fresh.max(seeded))
+    }
+
+    /// Node ID stored in `sync_state`, if any.
+    fn stored_node_id(conn: &Connection) -> Result<Option<u16>> {
+        let bytes: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT value FROM sync_state WHERE key = ?1",
+                params![NODE_ID_STATE_KEY],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        Ok(bytes.and_then(|b| b.try_into().ok()).map(u16::from_be_bytes))
+    }
+
+    /// Persist the node ID to `sync_state`.
+    fn persist_node_id(conn: &Connection, node_id: u16) -> Result<()> {
+        conn.execute(
+            "INSERT OR REPLACE INTO sync_state (key, value) VALUES (?1, ?2)",
+            params![NODE_ID_STATE_KEY, node_id.to_be_bytes().as_slice()],
+        )
+        .map_err(|e| RagError::database(format!("Failed to persist node ID: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Random non-zero node ID, different from `current`.
+    fn random_node_id(current: Option<u16>) -> u16 {
+        loop {
+            let node_id = rand::thread_rng().gen_range(1..=u16::MAX);
+            if Some(node_id) != current {
+                return node_id;
+            }
+        }
+    }
+
+    /// Replace this node's ID with a new random one.
+    ///
+    /// Used after copying a database file, since the copy would otherwise
+    /// issue HLCs indistinguishable from the original's. Returns the new ID.
+    pub fn reset_node_id(&self) -> Result<u16> {
+        let mut hlc = self.hlc.lock().map_err(|e| RagError::internal(e.to_string()))?;
+        let node_id = Self::random_node_id(Some(hlc.node_id));
+        // Tick so the clock stays ahead whatever the new ID
+        let next = hlc.tick_with_clock(self.clock.as_ref());
+        let next = HybridLogicalClock::from_parts(next.wall_time, next.logical, node_id);
+
+        self.with_conn(|conn| {
+            Self::persist_node_id(conn, node_id)?;
+            Self::persist_hlc(conn, &next)
+        })?;
+        info!("Node ID changed from {} to {}", hlc.node_id, node_id);
+        *hlc = next;
+        Ok(node_id)
+    }
+
+    /// Persist the HLC to `sync_state`.
+    fn persist_hlc(conn: &Connection, hlc: &HybridLogicalClock) -> Result<()> {
+        conn.execute(
+            "INSERT OR REPLACE INTO sync_state (key, value) VALUES (?1, ?2)",
+            params![HLC_STATE_KEY, hlc.to_bytes().as_slice()],
+        )
+        .map_err(|e| RagError::database(format!("Failed to persist HLC: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Highest HLC stored in any table.
+    fn query_watermark(conn: &Connection) -> Result<HybridLogicalClock> {
+        let result: Option<Vec<u8>> = conn
+            .query_row(
+                r#"
+                SELECT MAX(hlc) FROM (
+                    SELECT hlc FROM collections
+                    UNION ALL
+                    SELECT hlc FROM documents
+                    UNION ALL
+                    SELECT hlc FROM chunks
+                    UNION ALL
+                    SELECT hlc FROM tombstones
+                )
+                "#,
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| RagError::database(e.to_string()))?
+            .flatten();
+
+        match result {
+            Some(bytes) => Ok(HybridLogicalClock::from_bytes(&bytes)
+                .unwrap_or_else(HybridLogicalClock::zero)),
+            None => Ok(HybridLogicalClock::zero()),
+        }
+    }
+
+    /// Add columns introduced after a database was created.
+    fn migrate(conn: &Connection) -> Result<()> {
+        const CHUNK_COLUMNS: &[(&str, &str)] = &[
+            ("heading_path", "heading_path TEXT"),
+            ("parent_id", "parent_id TEXT"),
+            ("level", "level INTEGER NOT NULL DEFAULT 0"),
+            ("context", "context TEXT"),
+            ("start_byte", "start_byte INTEGER NOT NULL DEFAULT 0"),
+            ("end_byte", "end_byte INTEGER NOT NULL DEFAULT 0"),
+            ("start_column", "start_column INTEGER NOT NULL DEFAULT 0"),
+            ("end_column", "end_column INTEGER NOT NULL DEFAULT 0"),
+            ("speakers", "speakers TEXT"),
+            ("time_start", "time_start TEXT"),
+            ("time_end", "time_end TEXT"),
+        ];
+
+        for (name, definition) in CHUNK_COLUMNS {
+            let exists: bool = conn
+                .query_row(
+                    "SELECT COUNT(*) > 0 FROM pragma_table_info('chunks') WHERE name = ?1",
+                    params![name],
+                    |row| row.get(0),
+                )
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            if !exists {
+                conn.execute(&format!("ALTER TABLE chunks ADD COLUMN {}", definition), [])
+                    .map_err(|e| RagError::database(format!("Failed to migrate chunks: {}", e)))?;
+            }
+        }
+
+        conn.execute("CREATE INDEX IF NOT EXISTS idx_chunks_parent_id ON chunks(parent_id)", [])
+            .map_err(|e| RagError::database(format!("Failed to migrate chunks: {}", e)))?;
+
+        // Older full-text indexes only cover `content`; recreate them with
+        // `context` and reindex the existing chunks
+        let fts_has_context: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('chunks_fts') WHERE name = 'context'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        if !fts_has_context {
+            conn.execute_batch(
+                r#"
+                DROP TRIGGER IF EXISTS chunks_ai;
+                DROP TRIGGER IF EXISTS chunks_ad;
+                DROP TRIGGER IF EXISTS chunks_au;
+                DROP TABLE IF EXISTS chunks_fts;
+                "#,
+            )
+            .and_then(|_| conn.execute_batch(SCHEMA))
+            .and_then(|_| conn.execute("INSERT INTO chunks_fts(chunks_fts) VALUES ('rebuild')", []))
+            .map_err(|e| RagError::database(format!("Failed to migrate chunks_fts: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
     /// Configure SQLite connection for optimal performance.
     fn configure_connection(conn: &Connection) -> Result<()> {
         conn.execute_batch(
@@ -147,10 +397,43 @@ impl SqliteStore {
     }
 
     /// Get the next HLC value.
-    fn next_hlc(&self) -> HybridLogicalClock {
-        let mut hlc = self.hlc.lock().unwrap();
-        *hlc = hlc.tick();
-        *hlc
+    fn next_hlc(&self) -> Result<HybridLogicalClock> {
+        let mut hlc = self.hlc.lock().map_err(|e| RagError::internal(e.to_string()))?;
+        let next = hlc.tick_with_clock(self.clock.as_ref());
+        self.with_conn(|conn| Self::persist_hlc(conn, &next))?;
+        *hlc = next;
+        Ok(next)
+    }
+
+    /// Get `count` consecutive HLC values, persisting only the last.
+    fn next_hlcs(&self, count: usize) -> Result<Vec<HybridLogicalClock>> {
+        let mut hlc = self.hlc.lock().map_err(|e| RagError::internal(e.to_string()))?;
+        let mut issued = Vec::with_capacity(count);
+        let mut next = *hlc;
+        for _ in 0..count {
+            next = next.tick_with_clock(self.clock.as_ref());
+            issued.push(next);
+        }
+        self.with_conn(|conn| Self::persist_hlc(conn, &next))?;
+        *hlc = next;
+        Ok(issued)
+    }
+
+    /// Merge a remote HLC into the local clock.
+    ///
+    /// Rejects clocks more than the configured drift ahead of local time, and
+    /// persists the merged clock so it survives restarts.
+    pub fn observe_hlc(&self, remote: &HybridLogicalClock) -> Result<HybridLogicalClock> {
+        let mut hlc = self.hlc.lock().map_err(|e| RagError::internal(e.to_string()))?;
+        let merged = hlc.merge_with_clock(remote, self.clock.as_ref(), self.max_clock_drift_ms)?;
+        self.with_conn(|conn| Self::persist_hlc(conn, &merged))?;
+        *hlc = merged;
+        Ok(merged)
+    }
+
+    /// Current HLC of this node (the last value issued).
+    pub fn current_hlc(&self) -> HybridLogicalClock {
+        *self.hlc.lock().unwrap()
     }
 
     /// Check if vector search is available.
@@ -182,7 +465,7 @@ impl Store for SqliteStore {
     // Collection operations
 
     async fn create_collection(&self, mut collection: Collection) -> Result<()> {
-        collection.hlc = self.next_hlc();
+        collection.hlc = self.next_hlc()?;
 
         self.with_conn(|conn| {
             conn.execute(
@@ -261,8 +544,29 @@ impl Store for SqliteStore {
 
     async fn delete_collection(&self, name: &str) -> Result<()> {
         let name = name.to_string();
+        let hlc = self.next_hlc()?;
+        let vec_enabled = self.vec_enabled;
         self.with_conn(|conn| {
-            let deleted = conn
+            let tx = conn
+                .unchecked_transaction()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            if vec_enabled {
+                tx.execute(
+                    r#"
+                    DELETE FROM vec_chunks WHERE chunk_id IN (
+                        SELECT c.id FROM chunks c
+                        JOIN documents d ON d.id = c.doc_id
+                        WHERE d.collection = ?1
+                    )
+                    "#,
+                    params![name],
+                )
+                .map_err(|e| RagError::database(e.to_string()))?;
+            }
+
+            // Documents and chunks are deleted by CASCADE
+            let deleted = tx
                 .execute("DELETE FROM collections WHERE name = ?1", params![name])
                 .map_err(|e| RagError::database(e.to_string()))?;
 
@@ -270,6 +574,10 @@ impl Store for SqliteStore {
                 return Err(RagError::CollectionNotFound { name });
             }
 
+            Self::record_tombstone(&tx, TOMBSTONE_COLLECTION, &name, &hlc)?;
+            tx.commit()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
             debug!("Deleted collection: {}", name);
             Ok(())
         })
@@ -278,7 +586,7 @@ impl Store for SqliteStore {
     // Document operations
 
     async fn insert_document(&self, mut doc: Document) -> Result<()> {
-        doc.hlc = self.next_hlc();
+        doc.hlc = self.next_hlc()?;
 
         let content_hash = doc.content_hash.map(|h| h.to_vec());
         let metadata = serde_json::to_string(&doc.metadata)?;
@@ -385,10 +693,15 @@ impl Store for SqliteStore {
 
     async fn delete_document(&self, id: Ulid) -> Result<()> {
         let vec_enabled = self.vec_enabled;
+        let hlc = self.next_hlc()?;
         self.with_conn(|conn| {
+            let tx = conn
+                .unchecked_transaction()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
             // Delete embeddings first (if vec enabled)
             if vec_enabled {
-                conn.execute(
+                tx.execute(
                     "DELETE FROM vec_chunks WHERE chunk_id IN (SELECT id FROM chunks WHERE doc_id = ?1)",
                     params![id.to_string()],
                 )
@@ -396,7 +709,7 @@ impl Store for SqliteStore {
             }
 
             // Chunks are deleted by CASCADE
-            let deleted = conn
+            let deleted = tx
                 .execute("DELETE FROM documents WHERE id = ?1", params![id.to_string()])
                 .map_err(|e| RagError::database(e.to_string()))?;
 
@@ -404,6 +717,10 @@ impl Store for SqliteStore {
                 return Err(RagError::DocumentNotFound { id: id.to_string() });
             }
 
+            Self::record_tombstone(&tx, TOMBSTONE_DOCUMENT, &id.to_string(), &hlc)?;
+            tx.commit()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
             debug!("Deleted document: {}", id);
             Ok(())
         })
@@ -412,7 +729,12 @@ impl Store for SqliteStore {
     // Chunk operations
 
     async fn insert_chunks(&self, chunks: &[Chunk]) -> Result<()> {
-        let chunks: Vec<Chunk> = chunks.to_vec();
+        let hlcs = self.next_hlcs(chunks.len())?;
+        let mut chunks: Vec<Chunk> = chunks.to_vec();
+        for (chunk, hlc) in chunks.iter_mut().zip(hlcs) {
+            chunk.hlc = hlc;
+        }
+
         self.with_conn(|conn| {
             let tx = conn
                 .unchecked_transaction()
@@ -423,8 +745,11 @@ impl Store for SqliteStore {
                     .prepare(
                         r#"
                         INSERT INTO chunks (id, doc_id, chunk_index, content, token_count,
-                                           start_line, end_line, content_hash, hlc)
-                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                                           start_line, end_line, content_hash, hlc, heading_path, parent_id,
+                                           level, context, start_byte, end_byte, start_column, end_column,
+                                           speakers, time_start, time_end)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                                ?18, ?19, ?20)
                         "#,
                     )
                     .map_err(|e| RagError::database(e.to_string()))?;
@@ -441,6 +766,17 @@ impl Store for SqliteStore {
                         chunk.end_line,
                         content_hash,
                         chunk.hlc.to_bytes().as_slice(),
+                        chunk.heading_path,
+                        chunk.parent_id.map(|id| id.to_string()),
+                        chunk.level,
+                        chunk.context,
+                        chunk.start_byte,
+                        chunk.end_byte,
+                        chunk.start_column,
+                        chunk.end_column,
+                        Self::speakers_to_json(&chunk.speakers),
+                        chunk.time_range.as_ref().map(|(start, _)| start),
+                        chunk.time_range.as_ref().map(|(_, end)| end),
                     ])
                     .map_err(|e| RagError::database(format!("Failed to insert chunk: {}", e)))?;
                 }
@@ -460,7 +796,8 @@ impl Store for SqliteStore {
                 .prepare(
                     r#"
                     SELECT id, doc_id, chunk_index, content, token_count,
-                           start_line, end_line, content_hash, hlc
+                           start_line, end_line, content_hash, hlc, heading_path, parent_id, level, context,
+                           start_byte, end_byte, start_column, end_column, speakers, time_start, time_end
                     FROM chunks
                     WHERE doc_id = ?1
                     ORDER BY chunk_index
@@ -484,7 +821,8 @@ impl Store for SqliteStore {
                 .prepare(
                     r#"
                     SELECT id, doc_id, chunk_index, content, token_count,
-                           start_line, end_line, content_hash, hlc
+                           start_line, end_line, content_hash, hlc, heading_path, parent_id, level, context,
+                           start_byte, end_byte, start_column, end_column, speakers, time_start, time_end
                     FROM chunks WHERE id = ?1
                     "#,
                 )
@@ -501,22 +839,74 @@ impl Store for SqliteStore {
 
     async fn delete_chunks_for_document(&self, doc_id: Ulid) -> Result<()> {
         let vec_enabled = self.vec_enabled;
+        let hlc = self.next_hlc()?;
         self.with_conn(|conn| {
+            let tx = conn
+                .unchecked_transaction()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
             // Delete embeddings first
             if vec_enabled {
-                conn.execute(
+                tx.execute(
                     "DELETE FROM vec_chunks WHERE chunk_id IN (SELECT id FROM chunks WHERE doc_id = ?1)",
                     params![doc_id.to_string()],
                 )
                 .map_err(|e| RagError::database(e.to_string()))?;
             }
 
-            conn.execute(
+            let chunk_ids: Vec<String> = {
+                let mut stmt = tx
+                    .prepare("SELECT id FROM chunks WHERE doc_id = ?1")
+                    .map_err(|e| RagError::database(e.to_string()))?;
+                let ids = stmt
+                    .query_map(params![doc_id.to_string()], |row| row.get(0))
+                    .map_err(|e| RagError::database(e.to_string()))?
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|e| RagError::database(e.to_string()))?;
+                ids
+            };
+
+            tx.execute(
                 "DELETE FROM chunks WHERE doc_id = ?1",
                 params![doc_id.to_string()],
             )
             .map_err(|e| RagError::database(e.to_string()))?;
 
+            for chunk_id in &chunk_ids {
+                Self::record_tombstone(&tx, TOMBSTONE_CHUNK, chunk_id, &hlc)?;
+            }
+
+            tx.commit()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            Ok(())
+        })
+    }
+
+    async fn update_chunk_contexts(&self, contexts: &[(Ulid, Option<String>)]) -> Result<()> {
+        let hlcs = self.next_hlcs(contexts.len())?;
+        let contexts: Vec<(Ulid, Option<String>)> = contexts.to_vec();
+
+        self.with_conn(|conn| {
+            let tx = conn
+                .unchecked_transaction()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            {
+                let mut stmt = tx
+                    .prepare("UPDATE chunks SET context = ?2, hlc = ?3 WHERE id = ?1")
+                    .map_err(|e| RagError::database(e.to_string()))?;
+
+                for ((id, context), hlc) in contexts.iter().zip(&hlcs) {
+                    stmt.execute(params![id.to_string(), context, hlc.to_bytes().as_slice()])
+                        .map_err(|e| RagError::database(format!("Failed to update chunk: {}", e)))?;
+                }
+            }
+
+            tx.commit()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            debug!("Updated contexts of {} chunks", contexts.len());
             Ok(())
         })
     }
@@ -534,6 +924,8 @@ impl Store for SqliteStore {
             ));
         }
 
+        // Embeddings replicate with their chunks, so the chunks get new HLCs
+        let hlcs = self.next_hlcs(chunk_ids.len())?;
         let chunk_ids: Vec<Ulid> = chunk_ids.to_vec();
         let embeddings: Vec<Vec<f32>> = embeddings.to_vec();
 
@@ -543,14 +935,27 @@ impl Store for SqliteStore {
                 .map_err(|e| RagError::database(e.to_string()))?;
 
             {
+                let mut delete = tx
+                    .prepare("DELETE FROM vec_chunks WHERE chunk_id = ?1")
+                    .map_err(|e| RagError::database(e.to_string()))?;
                 let mut stmt = tx
                     .prepare("INSERT INTO vec_chunks (chunk_id, embedding) VALUES (?1, ?2)")
                     .map_err(|e| RagError::database(e.to_string()))?;
+                let mut touch = tx
+                    .prepare("UPDATE chunks SET hlc = ?2 WHERE id = ?1")
+                    .map_err(|e| RagError::database(e.to_string()))?;
 
-                for (chunk_id, embedding) in chunk_ids.iter().zip(embeddings.iter()) {
+                for ((chunk_id, embedding), hlc) in chunk_ids.iter().zip(embeddings.iter()).zip(&hlcs) {
+                    // vec0 tables don't support upserts
+                    delete
+                        .execute(params![chunk_id.to_string()])
+                        .map_err(|e| RagError::database(e.to_string()))?;
                     let embedding_bytes = Self::vec_to_bytes(embedding);
                     stmt.execute(params![chunk_id.to_string(), embedding_bytes])
                         .map_err(|e| RagError::database(format!("Failed to insert embedding: {}", e)))?;
+                    touch
+                        .execute(params![chunk_id.to_string(), hlc.to_bytes().as_slice()])
+                        .map_err(|e| RagError::database(format!("Failed to update chunk: {}", e)))?;
                 }
             }
 
@@ -666,6 +1071,7 @@ impl Store for SqliteStore {
                         JOIN documents d ON d.id = c.doc_id
                         WHERE chunks_fts MATCH ?1
                         AND d.collection = ?2
+                        AND NOT EXISTS (SELECT 1 FROM chunks child WHERE child.parent_id = c.id)
                         ORDER BY score
                         LIMIT ?3
                         "#,
@@ -697,6 +1103,7 @@ impl Store for SqliteStore {
                         FROM chunks_fts f
                         JOIN chunks c ON c.rowid = f.rowid
                         WHERE chunks_fts MATCH ?1
+                        AND NOT EXISTS (SELECT 1 FROM chunks child WHERE child.parent_id = c.id)
                         ORDER BY score
                         LIMIT ?2
                         "#,
@@ -797,79 +1204,1155 @@ impl Store for SqliteStore {
 
     // Sync operations
 
+    fn node_id(&self) -> u16 {
+        self.current_hlc().node_id
+    }
+
     async fn get_watermark(&self) -> Result<HybridLogicalClock> {
+        self.with_conn(Self::query_watermark)
+    }
+
+    async fn get_changes_since(
+        &self,
+        hlc: &HybridLogicalClock,
+        filter: &CollectionFilter,
+    ) -> Result<Vec<SyncChange>> {
+        let vec_enabled = self.vec_enabled;
         self.with_conn(|conn| {
-            // Get max HLC from all tables
-            let result: Option<Vec<u8>> = conn
-                .query_row(
+            let changes = Self::query_changes(conn, hlc, None, None, filter, vec_enabled)?;
+            debug!("Found {} changes since {}", changes.len(), hlc);
+            Ok(changes)
+        })
+    }
+
+    async fn get_changes_page(
+        &self,
+        hlc: &HybridLogicalClock,
+        filter: &CollectionFilter,
+        limit: usize,
+    ) -> Result<(Vec<SyncChange>, bool)> {
+        let limit = limit.max(1);
+        let vec_enabled = self.vec_enabled;
+        self.with_conn(|conn| {
+            // One change past the page tells whether more follow
+            let mut changes = Self::query_changes(conn, hlc, None, Some(limit + 1), filter, vec_enabled)?;
+            if changes.len() <= limit {
+                return Ok((changes, false));
+            }
+
+            let last = changes[limit - 1].hlc();
+            if changes[limit].hlc() != last {
+                changes.truncate(limit);
+                return Ok((changes, true));
+            }
+
+            // The group sharing the last HLC runs past the page, so take all of it
+            let changes = Self::query_changes(conn, hlc, Some(&last), None, filter, vec_enabled)?;
+            let has_more = !Self::query_changes(conn, &last, None, Some(1), filter, vec_enabled)?.is_empty();
+            Ok((changes, has_more))
+        })
+    }
+
+    async fn apply_changes_with(&self, changes: &[SyncChange], resolver: &dyn ConflictResolver) -> Result<()> {
+        let Some(max_hlc) = changes.iter().map(SyncChange::hlc).max() else {
+            return Ok(());
+        };
+
+        // Reject the whole batch up front if its clock is too far ahead
+        self.observe_hlc(&max_hlc)?;
+
+        // Apply parents before children; within a level, in HLC order so a
+        // delete followed by a re-create resolves the same way on every node.
+        // Document deletes go first: they are last-writer-wins per ID either
+        // way, and a document replaced by a new one at the same URI is then
+        // gone before the new one is checked for conflicts
+        let mut ordered: Vec<&SyncChange> = changes.iter().collect();
+        ordered.sort_by_key(|change| {
+            let upsert_document = matches!(change, SyncChange::UpsertDocument(_));
+            (Self::change_level(change), upsert_document, change.hlc())
+        });
+
+        let vec_enabled = self.vec_enabled;
+        let now = self.clock.now_millis();
+        self.with_conn(|conn| {
+            let tx = conn
+                .unchecked_transaction()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            let mut applied = 0;
+            for change in ordered {
+                let changed = match change {
+                    SyncChange::UpsertCollection(collection) => {
+                        Self::apply_upsert_collection(&tx, collection, vec_enabled)?
+                    }
+                    SyncChange::DeleteCollection(name, hlc) => {
+                        Self::apply_delete_collection(&tx, name, hlc, vec_enabled)?
+                    }
+                    SyncChange::UpsertDocument(doc) => {
+                        Self::apply_upsert_document(&tx, doc, vec_enabled, resolver, now)?
+                    }
+                    SyncChange::DeleteDocument(id, hlc) => {
+                        Self::apply_delete_document(&tx, *id, hlc, vec_enabled)?
+                    }
+                    SyncChange::UpsertChunk(chunk, embedding) => {
+                        Self::apply_upsert_chunk(&tx, chunk, embedding, vec_enabled)?
+                    }
+                    SyncChange::DeleteChunk(id, hlc) => {
+                        Self::apply_delete_chunk(&tx, *id, hlc, vec_enabled)?
+                    }
+                };
+                if changed {
+                    applied += 1;
+                }
+            }
+
+            tx.commit()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            debug!("Applied {} of {} changes", applied, changes.len());
+            Ok(())
+        })
+    }
+
+    async fn purge_collection(&self, name: &str) -> Result<bool> {
+        let vec_enabled = self.vec_enabled;
+
+        self.with_conn(|conn| {
+            // Vectors and rows go together, or not at all
+            let tx = conn
+                .unchecked_transaction()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            if vec_enabled {
+                tx.execute(
                     r#"
-                    SELECT MAX(hlc) FROM (
-                        SELECT hlc FROM collections
-                        UNION ALL
-                        SELECT hlc FROM documents
-                        UNION ALL
-                        SELECT hlc FROM chunks
+                    DELETE FROM vec_chunks WHERE chunk_id IN (
+                        SELECT c.id FROM chunks c
+                        JOIN documents d ON d.id = c.doc_id
+                        WHERE d.collection = ?1
                     )
                     "#,
-                    [],
-                    |row| row.get(0),
+                    params![name],
                 )
-                .optional()
-                .map_err(|e| RagError::database(e.to_string()))?
-                .flatten();
+                .map_err(|e| RagError::database(e.to_string()))?;
+            }
+
+            // Documents and chunks are deleted by CASCADE
+            let deleted = tx
+                .execute("DELETE FROM collections WHERE name = ?1", params![name])
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            tx.commit()
+                .map_err(|e| RagError::database(e.to_string()))?;
 
-            match result {
-                Some(bytes) => Ok(HybridLogicalClock::from_bytes(&bytes)
-                    .unwrap_or_else(HybridLogicalClock::zero)),
-                None => Ok(HybridLogicalClock::zero()),
+            if deleted > 0 {
+                info!("Purged local copy of collection: {}", name);
             }
+            Ok(deleted > 0)
         })
     }
 
-    async fn get_changes_since(&self, _hlc: &HybridLogicalClock) -> Result<Vec<SyncChange>> {
-        // TODO: Implement full sync change retrieval
-        // This would query all tables for rows with HLC > given HLC
-        Ok(Vec::new())
-    }
+    // Conflict log
 
-    async fn apply_changes(&self, _changes: &[SyncChange]) -> Result<()> {
-        // TODO: Implement applying sync changes
-        // This would insert/update rows, handling conflicts via LWW
-        Ok(())
-    }
-}
+    async fn list_conflicts(&self, limit: u32) -> Result<Vec<ConflictRecord>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn
+                .prepare(
+                    r#"
+                    SELECT id, collection, source_uri, local_version, remote_version,
+                           policy, resolution, resolved_at
+                    FROM sync_conflicts ORDER BY resolved_at DESC, id DESC LIMIT ?1
+                    "#,
+                )
+                .map_err(|e| RagError::database(e.to_string()))?;
 
-// Helper methods
-impl SqliteStore {
-    /// Convert a row to a Document.
-    fn row_to_document(row: &rusqlite::Row<'_>) -> rusqlite::Result<Document> {
-        let id_str: String = row.get(0)?;
-        let content_hash: Option<Vec<u8>> = row.get(3)?;
-        let content_type_str: String = row.get(5)?;
-        let metadata_str: String = row.get(6)?;
-        let hlc_bytes: Vec<u8> = row.get(9)?;
+            let conflicts = stmt
+                .query_map(params![limit], Self::row_to_conflict)
+                .map_err(|e| RagError::database(e.to_string()))?
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| RagError::database(e.to_string()))?;
 
-        Ok(Document {
-            id: Ulid::from_string(&id_str).unwrap_or_else(|_| Ulid::nil()),
-            collection: row.get(1)?,
-            source_uri: row.get(2)?,
-            content_hash: content_hash.and_then(|v| v.try_into().ok()),
-            raw_content: row.get(4)?,
-            content_type: ContentType::from_path(&content_type_str),
-            metadata: serde_json::from_str(&metadata_str).unwrap_or_default(),
-            created_at: row.get::<_, i64>(7)? as u64,
-            updated_at: row.get::<_, i64>(8)? as u64,
-            hlc: HybridLogicalClock::from_bytes(&hlc_bytes)
-                .unwrap_or_else(HybridLogicalClock::zero),
+            Ok(conflicts)
         })
     }
 
-    /// Convert a row to a Chunk.
-    fn row_to_chunk(row: &rusqlite::Row<'_>) -> rusqlite::Result<Chunk> {
-        let id_str: String = row.get(0)?;
-        let doc_id_str: String = row.get(1)?;
+    async fn get_conflict(&self, id: i64) -> Result<Option<ConflictRecord>> {
+        self.with_conn(|conn| {
+            conn.query_row(
+                r#"
+                SELECT id, collection, source_uri, local_version, remote_version,
+                       policy, resolution, resolved_at
+                FROM sync_conflicts WHERE id = ?1
+                "#,
+                params![id],
+                Self::row_to_conflict,
+            )
+            .optional()
+            .map_err(|e| RagError::database(e.to_string()))
+        })
+    }
+
+    async fn set_conflict_resolution(&self, id: i64, resolution: Resolution) -> Result<()> {
+        let now = self.clock.now_millis();
+        self.with_conn(|conn| {
+            let updated = conn
+                .execute(
+                    "UPDATE sync_conflicts SET policy = 'manual', resolution = ?2, resolved_at = ?3 WHERE id = ?1",
+                    params![id, resolution.to_string(), now as i64],
+                )
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            if updated == 0 {
+                return Err(RagError::invalid_argument(format!("No conflict with ID {}", id)));
+            }
+            Ok(())
+        })
+    }
+
+    // Sync state
+
+    async fn get_sync_state(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT value FROM sync_state WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| RagError::database(e.to_string()))
+        })
+    }
+
+    async fn set_sync_state(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO sync_state (key, value) VALUES (?1, ?2)",
+                params![key, value],
+            )
+            .map_err(|e| RagError::database(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    async fn list_sync_state(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT key, value FROM sync_state WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key",
+                )
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            let rows = stmt
+                .query_map(params![prefix], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            rows.collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| RagError::database(e.to_string()))
+        })
+    }
+
+    // Anti-entropy
+
+    async fn sync_items(&self, range: &KeyRange, filter: &CollectionFilter) -> Result<Vec<SyncItem>> {
+        let (include, exclude) = Self::filter_params(filter)?;
+
+        self.with_conn(|conn| {
+            let sql = format!(
+                r#"
+                SELECT key, hlc, content_hash, deleted FROM (
+                    SELECT 'c/' || name AS key, hlc, NULL AS content_hash, 0 AS deleted,
+                           name AS collection
+                    FROM collections
+                    UNION ALL
+                    SELECT 'd/' || id, hlc, content_hash, 0, collection FROM documents
+                    UNION ALL
+                    SELECT 'k/' || c.id, c.hlc, c.content_hash, 0, d.collection
+                    FROM chunks c JOIN documents d ON d.id = c.doc_id
+                    UNION ALL
+                    SELECT CASE kind
+                               WHEN 'collection' THEN 't/c/'
+                               WHEN 'document' THEN 't/d/'
+                               ELSE 't/k/'
+                           END || id, hlc, NULL, 1,
+                           CASE kind WHEN 'collection' THEN id END
+                    FROM tombstones WHERE kind IN ('collection', 'document', 'chunk')
+                )
+                WHERE key >= ?1 AND (?2 IS NULL OR key < ?2)
+                  AND (collection IS NULL OR {})
+                ORDER BY key
+                "#,
+                Self::collection_filter_sql("collection", 3)
+            );
+            let mut stmt = conn
+                .prepare(&sql)
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            let items = stmt
+                .query_map(params![range.start, range.end, include, exclude], |row| {
+                    let hlc_bytes: Vec<u8> = row.get(1)?;
+                    let content_hash: Option<Vec<u8>> = row.get(2)?;
+                    Ok(SyncItem {
+                        key: row.get(0)?,
+                        hlc: HybridLogicalClock::from_bytes(&hlc_bytes)
+                            .unwrap_or_else(HybridLogicalClock::zero),
+                        deleted: row.get::<_, i64>(3)? != 0,
+                        content_hash: content_hash.and_then(|v| v.try_into().ok()),
+                    })
+                })
+                .map_err(|e| RagError::database(e.to_string()))?
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| RagError::database(e.to_string()))?;
+
+            Ok(items)
+        })
+    }
+
+    async fn get_changes_for_keys(&self, keys: &[String]) -> Result<Vec<SyncChange>> {
+        let vec_enabled = self.vec_enabled;
+
+        self.with_conn(|conn| {
+            let mut changes = Vec::with_capacity(keys.len());
+            for key in keys {
+                if let Some(change) = Self::change_for_key(conn, key, vec_enabled)? {
+                    changes.push(change);
+                }
+            }
+            Ok(changes)
+        })
+    }
+}
+
+// Sync helpers
+impl SqliteStore {
+    /// SQL parameters for a [`CollectionFilter`]: the include list as a JSON
+    /// array (`None` = all) and the exclude list as a JSON array.
+    fn filter_params(filter: &CollectionFilter) -> Result<(Option<String>, String)> {
+        let include = if filter.include.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&filter.include)?)
+        };
+        Ok((include, serde_json::to_string(&filter.exclude)?))
+    }
+
+    /// Changes in collections the filter allows with an HLC after `since`
+    /// and up to `until`, in HLC order, at most `limit` of them.
+    fn query_changes(
+        conn: &Connection,
+        since: &HybridLogicalClock,
+        until: Option<&HybridLogicalClock>,
+        limit: Option<usize>,
+        filter: &CollectionFilter,
+        vec_enabled: bool,
+    ) -> Result<Vec<SyncChange>> {
+        let since = since.to_bytes();
+        let until = until.map(|hlc| hlc.to_bytes().to_vec());
+        let (include, exclude) = Self::filter_params(filter)?;
+        // SQLite reads a negative limit as no limit
+        let sql_limit = limit.map_or(-1, |limit| limit as i64);
+        let range = |column: &str| format!("{c} > ?1 AND (?4 IS NULL OR {c} <= ?4)", c = column);
+
+        let mut changes = Vec::new();
+
+        let sql = format!(
+            "SELECT name, description, created_at, hlc FROM collections WHERE {} AND {} ORDER BY hlc LIMIT ?5",
+            range("hlc"),
+            Self::collection_filter_sql("name", 2)
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| RagError::database(e.to_string()))?;
+        let collections = stmt
+            .query_map(params![since.as_slice(), include, exclude, until, sql_limit], Self::row_to_collection)
+            .map_err(|e| RagError::database(e.to_string()))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| RagError::database(e.to_string()))?;
+        changes.extend(collections.into_iter().map(SyncChange::UpsertCollection));
+
+        let sql = format!(
+            r#"
+            SELECT id, collection, source_uri, content_hash, raw_content,
+                   content_type, metadata, created_at, updated_at, hlc
+            FROM documents WHERE {} AND {}
+            ORDER BY hlc LIMIT ?5
+            "#,
+            range("hlc"),
+            Self::collection_filter_sql("collection", 2)
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| RagError::database(e.to_string()))?;
+        let documents = stmt
+            .query_map(params![since.as_slice(), include, exclude, until, sql_limit], Self::row_to_document)
+            .map_err(|e| RagError::database(e.to_string()))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| RagError::database(e.to_string()))?;
+        changes.extend(documents.into_iter().map(SyncChange::UpsertDocument));
+
+        let sql = format!(
+            r#"
+            SELECT c.id, c.doc_id, c.chunk_index, c.content, c.token_count,
+                   c.start_line, c.end_line, c.content_hash, c.hlc, c.heading_path, c.parent_id, c.level, c.context,
+                   c.start_byte, c.end_byte, c.start_column, c.end_column, c.speakers, c.time_start, c.time_end
+            FROM chunks c JOIN documents d ON d.id = c.doc_id
+            WHERE {} AND {}
+            ORDER BY c.hlc LIMIT ?5
+            "#,
+            range("c.hlc"),
+            Self::collection_filter_sql("d.collection", 2)
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| RagError::database(e.to_string()))?;
+        let chunks = stmt
+            .query_map(params![since.as_slice(), include, exclude, until, sql_limit], Self::row_to_chunk)
+            .map_err(|e| RagError::database(e.to_string()))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| RagError::database(e.to_string()))?;
+        for chunk in chunks {
+            let embedding = if vec_enabled {
+                Self::get_embedding(conn, chunk.id)?.unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            changes.push(SyncChange::UpsertChunk(chunk, embedding));
+        }
+
+        // Only collection tombstones know their collection
+        let sql = format!(
+            "SELECT kind, id, hlc FROM tombstones WHERE {} AND (kind != '{}' OR {}) ORDER BY hlc LIMIT ?5",
+            range("hlc"),
+            TOMBSTONE_COLLECTION,
+            Self::collection_filter_sql("id", 2)
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| RagError::database(e.to_string()))?;
+        let tombstones = stmt
+            .query_map(params![since.as_slice(), include, exclude, until, sql_limit], |row| {
+                let kind: String = row.get(0)?;
+                let id: String = row.get(1)?;
+                let hlc_bytes: Vec<u8> = row.get(2)?;
+                Ok((kind, id, hlc_bytes))
+            })
+            .map_err(|e| RagError::database(e.to_string()))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| RagError::database(e.to_string()))?;
+        for (kind, id, hlc_bytes) in tombstones {
+            let hlc = HybridLogicalClock::from_bytes(&hlc_bytes)
+                .unwrap_or_else(HybridLogicalClock::zero);
+            let change = match kind.as_str() {
+                TOMBSTONE_COLLECTION => SyncChange::DeleteCollection(id, hlc),
+                TOMBSTONE_DOCUMENT => SyncChange::DeleteDocument(Self::parse_ulid(&id)?, hlc),
+                TOMBSTONE_CHUNK => SyncChange::DeleteChunk(Self::parse_ulid(&id)?, hlc),
+                other => {
+                    warn!("Ignoring tombstone of unknown kind: {}", other);
+                    continue;
+                }
+            };
+            changes.push(change);
+        }
+
+        // Causal order: parents are always written before their children
+        changes.sort_by_key(|change| change.hlc());
+        if let Some(limit) = limit {
+            changes.truncate(limit);
+        }
+        Ok(changes)
+    }
+
+    /// SQL condition on `column` for the filter parameters bound at
+    /// `?{first}` (include) and `?{first + 1}` (exclude).
+    fn collection_filter_sql(column: &str, first: usize) -> String {
+        format!(
+            "(?{include} IS NULL OR {column} IN (SELECT value FROM json_each(?{include}))) \
+             AND {column} NOT IN (SELECT value FROM json_each(?{exclude}))",
+            include = first,
+            exclude = first + 1,
+            column = column,
+        )
+    }
+
+    /// Dependency level of a change: collections, then documents, then chunks.
+    fn change_level(change: &SyncChange) -> u8 {
+        match change {
+            SyncChange::UpsertCollection(_) | SyncChange::DeleteCollection(..) => 0,
+            SyncChange::UpsertDocument(_) | SyncChange::DeleteDocument(..) => 1,
+            SyncChange::UpsertChunk(..) | SyncChange::DeleteChunk(..) => 2,
+        }
+    }
+
+    /// HLC of the tombstone for a deleted row, if any.
+    fn tombstone_hlc(conn: &Connection, kind: &str, id: &str) -> Result<Option<HybridLogicalClock>> {
+        let bytes: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT hlc FROM tombstones WHERE kind = ?1 AND id = ?2",
+                params![kind, id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        Ok(bytes.and_then(|b| HybridLogicalClock::from_bytes(&b)))
+    }
+
+    /// Record a tombstone, keeping the newest HLC if one already exists.
+    fn record_tombstone(conn: &Connection, kind: &str, id: &str, hlc: &HybridLogicalClock) -> Result<()> {
+        conn.execute(
+            r#"
+            INSERT INTO tombstones (kind, id, hlc) VALUES (?1, ?2, ?3)
+            ON CONFLICT(kind, id) DO UPDATE SET hlc = excluded.hlc
+            WHERE excluded.hlc > tombstones.hlc
+            "#,
+            params![kind, id, hlc.to_bytes().as_slice()],
+        )
+        .map_err(|e| RagError::database(format!("Failed to record tombstone: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// HLC of a live row, if it exists.
+    fn row_hlc(conn: &Connection, sql: &str, id: &str) -> Result<Option<HybridLogicalClock>> {
+        let bytes: Option<Vec<u8>> = conn
+            .query_row(sql, params![id], |row| row.get(0))
+            .optional()
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        Ok(bytes.and_then(|b| HybridLogicalClock::from_bytes(&b)))
+    }
+
+    /// Whether an incoming write with `hlc` wins against the local row and tombstone.
+    fn wins(
+        hlc: &HybridLogicalClock,
+        existing: Option<HybridLogicalClock>,
+        tombstone: Option<HybridLogicalClock>,
+    ) -> bool {
+        existing.map_or(true, |e| *hlc > e) && tombstone.map_or(true, |t| *hlc > t)
+    }
+
+    /// Whether a child written at `hlc` belongs to the live incarnation of its parent.
+    ///
+    /// `None` if the parent does not exist. A parent that was deleted and then
+    /// re-created only accepts children written after the re-creation; older
+    /// children belonged to the deleted incarnation and were cascaded away.
+    fn parent_accepts(
+        hlc: &HybridLogicalClock,
+        parent: Option<HybridLogicalClock>,
+        parent_tombstone: Option<HybridLogicalClock>,
+    ) -> bool {
+        match (parent, parent_tombstone) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(parent), Some(_)) => *hlc > parent,
+        }
+    }
+
+    fn apply_upsert_collection(
+        conn: &Connection,
+        collection: &Collection,
+        vec_enabled: bool,
+    ) -> Result<bool> {
+        let existing = Self::row_hlc(conn, "SELECT hlc FROM collections WHERE name = ?1", &collection.name)?;
+        let tombstone = Self::tombstone_hlc(conn, TOMBSTONE_COLLECTION, &collection.name)?;
+        if !Self::wins(&collection.hlc, existing, tombstone) {
+            return Ok(false);
+        }
+
+        conn.execute(
+            r#"
+            INSERT INTO collections (name, description, created_at, hlc) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(name) DO UPDATE SET
+                description = excluded.description,
+                created_at = excluded.created_at,
+                hlc = excluded.hlc
+            "#,
+            params![
+                collection.name,
+                collection.description,
+                collection.created_at as i64,
+                collection.hlc.to_bytes().as_slice(),
+            ],
+        )
+        .map_err(|e| RagError::database(format!("Failed to apply collection: {}", e)))?;
+
+        // A re-creation that wins over a concurrent one orphans the loser's
+        // documents; drop them so every replica agrees with `parent_accepts`.
+        if tombstone.is_some() {
+            Self::drop_orphaned_documents(conn, &collection.name, &collection.hlc, vec_enabled)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Remove documents of a collection older than its current incarnation.
+    fn drop_orphaned_documents(
+        conn: &Connection,
+        name: &str,
+        collection_hlc: &HybridLogicalClock,
+        vec_enabled: bool,
+    ) -> Result<()> {
+        let hlc = collection_hlc.to_bytes();
+        if vec_enabled {
+            conn.execute(
+                r#"
+                DELETE FROM vec_chunks WHERE chunk_id IN (
+                    SELECT c.id FROM chunks c
+                    JOIN documents d ON d.id = c.doc_id
+                    WHERE d.collection = ?1 AND d.hlc < ?2
+                )
+                "#,
+                params![name, hlc.as_slice()],
+            )
+            .map_err(|e| RagError::database(e.to_string()))?;
+        }
+        conn.execute(
+            "DELETE FROM documents WHERE collection = ?1 AND hlc < ?2",
+            params![name, hlc.as_slice()],
+        )
+        .map_err(|e| RagError::database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    fn apply_delete_collection(
+        conn: &Connection,
+        name: &str,
+        hlc: &HybridLogicalClock,
+        vec_enabled: bool,
+    ) -> Result<bool> {
+        Self::record_tombstone(conn, TOMBSTONE_COLLECTION, name, hlc)?;
+
+        let existing = Self::row_hlc(conn, "SELECT hlc FROM collections WHERE name = ?1", name)?;
+        match existing {
+            None => return Ok(false),
+            Some(existing) if existing >= *hlc => {
+                // The delete arrived after a re-creation, which now orphans
+                // documents from before it
+                Self::drop_orphaned_documents(conn, name, &existing, vec_enabled)?;
+                return Ok(false);
+            }
+            Some(_) => {}
+        }
+
+        if vec_enabled {
+            conn.execute(
+                r#"
+                DELETE FROM vec_chunks WHERE chunk_id IN (
+                    SELECT c.id FROM chunks c
+                    JOIN documents d ON d.id = c.doc_id
+                    WHERE d.collection = ?1
+                )
+                "#,
+                params![name],
+            )
+            .map_err(|e| RagError::database(e.to_string()))?;
+        }
+
+        // Documents and chunks are deleted by CASCADE
+        conn.execute("DELETE FROM collections WHERE name = ?1", params![name])
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        Ok(true)
+    }
+
+    fn apply_upsert_document(
+        conn: &Connection,
+        doc: &Document,
+        vec_enabled: bool,
+        resolver: &dyn ConflictResolver,
+        now: u64,
+    ) -> Result<bool> {
+        let id = doc.id.to_string();
+        let existing = Self::row_hlc(conn, "SELECT hlc FROM documents WHERE id = ?1", &id)?;
+        let tombstone = Self::tombstone_hlc(conn, TOMBSTONE_DOCUMENT, &id)?;
+        if !Self::wins(&doc.hlc, existing, tombstone) {
+            return Ok(false);
+        }
+
+        let parent = Self::row_hlc(conn, "SELECT hlc FROM collections WHERE name = ?1", &doc.collection)?;
+        let parent_tombstone = Self::tombstone_hlc(conn, TOMBSTONE_COLLECTION, &doc.collection)?;
+        if !Self::parent_accepts(&doc.hlc, parent, parent_tombstone) {
+            debug!("Skipping document {} without live collection {}", doc.id, doc.collection);
+            return Ok(false);
+        }
+
+        let mut source_uri = doc.source_uri.as_str();
+        let renamed;
+        if existing.is_none() {
+            if let Some(local) = Self::conflicting_document(conn, doc)? {
+                let resolution = resolver.resolve(&local, doc);
+                Self::record_conflict(conn, &local, doc, &resolver.name(), resolution, now)?;
+                info!("Resolved conflict on {} as {} ({})", doc.source_uri, resolution, resolver.name());
+
+                match resolution {
+                    Resolution::KeepLocal => return Ok(false),
+                    Resolution::TakeRemote => Self::remove_document(conn, local.id, vec_enabled)?,
+                    Resolution::KeepBoth if doc.hlc > local.hlc => {
+                        conn.execute(
+                            "UPDATE documents SET source_uri = ?2 WHERE id = ?1",
+                            params![local.id.to_string(), conflict_uri(&local)],
+                        )
+                        .map_err(|e| RagError::database(e.to_string()))?;
+                    }
+                    Resolution::KeepBoth => {
+                        renamed = conflict_uri(doc);
+                        source_uri = &renamed;
+                    }
+                }
+            }
+        }
+
+        let content_hash = doc.content_hash.map(|h| h.to_vec());
+        let metadata = serde_json::to_string(&doc.metadata)?;
+
+        conn.execute(
+            r#"
+            INSERT INTO documents (id, collection, source_uri, content_hash, raw_content,
+                                   content_type, metadata, created_at, updated_at, hlc)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            ON CONFLICT(id) DO UPDATE SET
+                collection = excluded.collection,
+                source_uri = excluded.source_uri,
+                content_hash = excluded.content_hash,
+                raw_content = excluded.raw_content,
+                content_type = excluded.content_type,
+                metadata = excluded.metadata,
+                created_at = excluded.created_at,
+                updated_at = excluded.updated_at,
+                hlc = excluded.hlc
+            "#,
+            params![
+                id,
+                doc.collection,
+                source_uri,
+                content_hash,
+                doc.raw_content,
+                doc.content_type.to_string(),
+                metadata,
+                doc.created_at as i64,
+                doc.updated_at as i64,
+                doc.hlc.to_bytes().as_slice(),
+            ],
+        )
+        .map_err(|e| RagError::database(format!("Failed to apply document: {}", e)))?;
+
+        // Same as for collections: chunks of a losing re-creation are dropped
+        if tombstone.is_some() {
+            let hlc = doc.hlc.to_bytes();
+            if vec_enabled {
+                conn.execute(
+                    "DELETE FROM vec_chunks WHERE chunk_id IN (SELECT id FROM chunks WHERE doc_id = ?1 AND hlc < ?2)",
+                    params![id, hlc.as_slice()],
+                )
+                .map_err(|e| RagError::database(e.to_string()))?;
+            }
+            conn.execute(
+                "DELETE FROM chunks WHERE doc_id = ?1 AND hlc < ?2",
+                params![id, hlc.as_slice()],
+            )
+            .map_err(|e| RagError::database(e.to_string()))?;
+        }
+
+        Ok(true)
+    }
+
+    /// The newest live document another node's `doc` conflicts with: same
+    /// collection and source URI, different ID and content.
+    ///
+    /// A document written earlier by the node that wrote `doc` is not a
+    /// conflict: that node saw it and replaced it, and its tombstone follows.
+    fn conflicting_document(conn: &Connection, doc: &Document) -> Result<Option<Document>> {
+        let mut stmt = conn
+            .prepare(
+                r#"
+                SELECT id, collection, source_uri, content_hash, raw_content,
+                       content_type, metadata, created_at, updated_at, hlc
+                FROM documents
+                WHERE collection = ?1 AND source_uri = ?2 AND id != ?3
+                ORDER BY hlc DESC
+                "#,
+            )
+            .map_err(|e| RagError::database(e.to_string()))?;
+        let locals = stmt
+            .query_map(params![doc.collection, doc.source_uri, doc.id.to_string()], Self::row_to_document)
+            .map_err(|e| RagError::database(e.to_string()))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        let superseded = |local: &Document| local.hlc.node_id == doc.hlc.node_id && local.hlc < doc.hlc;
+        Ok(locals.into_iter().find(|local| !superseded(local)).filter(|local| {
+            match (local.content_hash, doc.content_hash) {
+                (Some(a), Some(b)) => a != b,
+                _ => local.raw_content != doc.raw_content,
+            }
+        }))
+    }
+
+    /// Add a conflict to the log, once per pair of documents.
+    fn record_conflict(
+        conn: &Connection,
+        local: &Document,
+        remote: &Document,
+        policy: &str,
+        resolution: Resolution,
+        now: u64,
+    ) -> Result<()> {
+        conn.execute(
+            r#"
+            INSERT INTO sync_conflicts (collection, source_uri, local_id, remote_id, local_version,
+                                        remote_version, policy, resolution, resolved_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT(local_id, remote_id) DO NOTHING
+            "#,
+            params![
+                local.collection,
+                local.source_uri,
+                local.id.to_string(),
+                remote.id.to_string(),
+                serde_json::to_string(local)?,
+                serde_json::to_string(remote)?,
+                policy,
+                resolution.to_string(),
+                now as i64,
+            ],
+        )
+        .map_err(|e| RagError::database(format!("Failed to record conflict: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Delete a document without a tombstone. Every node resolves the
+    /// conflict that removes it on its own, so the removal is not replicated.
+    fn remove_document(conn: &Connection, id: Ulid, vec_enabled: bool) -> Result<()> {
+        let id = id.to_string();
+        if vec_enabled {
+            conn.execute(
+                "DELETE FROM vec_chunks WHERE chunk_id IN (SELECT id FROM chunks WHERE doc_id = ?1)",
+                params![id],
+            )
+            .map_err(|e| RagError::database(e.to_string()))?;
+        }
+
+        // Chunks are deleted by CASCADE
+        conn.execute("DELETE FROM documents WHERE id = ?1", params![id])
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    fn apply_delete_document(
+        conn: &Connection,
+        id: Ulid,
+        hlc: &HybridLogicalClock,
+        vec_enabled: bool,
+    ) -> Result<bool> {
+        let id = id.to_string();
+        Self::record_tombstone(conn, TOMBSTONE_DOCUMENT, &id, hlc)?;
+
+        let existing = Self::row_hlc(conn, "SELECT hlc FROM documents WHERE id = ?1", &id)?;
+        if !existing.is_some_and(|e| e < *hlc) {
+            return Ok(false);
+        }
+
+        if vec_enabled {
+            conn.execute(
+                "DELETE FROM vec_chunks WHERE chunk_id IN (SELECT id FROM chunks WHERE doc_id = ?1)",
+                params![id],
+            )
+            .map_err(|e| RagError::database(e.to_string()))?;
+        }
+
+        // Chunks are deleted by CASCADE
+        conn.execute("DELETE FROM documents WHERE id = ?1", params![id])
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        Ok(true)
+    }
+
+    fn apply_upsert_chunk(
+        conn: &Connection,
+        chunk: &Chunk,
+        embedding: &[f32],
+        vec_enabled: bool,
+    ) -> Result<bool> {
+        let id = chunk.id.to_string();
+        let existing = Self::row_hlc(conn, "SELECT hlc FROM chunks WHERE id = ?1", &id)?;
+        let tombstone = Self::tombstone_hlc(conn, TOMBSTONE_CHUNK, &id)?;
+        if !Self::wins(&chunk.hlc, existing, tombstone) {
+            return Ok(false);
+        }
+
+        let doc_id = chunk.doc_id.to_string();
+        let parent = Self::row_hlc(conn, "SELECT hlc FROM documents WHERE id = ?1", &doc_id)?;
+        let parent_tombstone = Self::tombstone_hlc(conn, TOMBSTONE_DOCUMENT, &doc_id)?;
+        if !Self::parent_accepts(&chunk.hlc, parent, parent_tombstone) {
+            debug!("Skipping chunk {} without live document {}", chunk.id, chunk.doc_id);
+            return Ok(false);
+        }
+
+        let content_hash = chunk.content_hash.map(|h| h.to_vec());
+        conn.execute(
+            r#"
+            INSERT INTO chunks (id, doc_id, chunk_index, content, token_count,
+                               start_line, end_line, content_hash, hlc, heading_path, parent_id, level, context,
+                               start_byte, end_byte, start_column, end_column, speakers, time_start, time_end)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)
+            ON CONFLICT(id) DO UPDATE SET
+                doc_id = excluded.doc_id,
+                chunk_index = excluded.chunk_index,
+                content = excluded.content,
+                token_count = excluded.token_count,
+                start_line = excluded.start_line,
+                end_line = excluded.end_line,
+                content_hash = excluded.content_hash,
+                hlc = excluded.hlc,
+                heading_path = excluded.heading_path,
+                parent_id = excluded.parent_id,
+                level = excluded.level,
+                context = excluded.context,
+                start_byte = excluded.start_byte,
+                end_byte = excluded.end_byte,
+                start_column = excluded.start_column,
+                end_column = excluded.end_column,
+                speakers = excluded.speakers,
+                time_start = excluded.time_start,
+                time_end = excluded.time_end
+            "#,
+            params![
+                id,
+                doc_id,
+                chunk.chunk_index,
+                chunk.content,
+                chunk.token_count,
+                chunk.start_line,
+                chunk.end_line,
+                content_hash,
+                chunk.hlc.to_bytes().as_slice(),
+                chunk.heading_path,
+                chunk.parent_id.map(|id| id.to_string()),
+                chunk.level,
+                chunk.context,
+                chunk.start_byte,
+                chunk.end_byte,
+                chunk.start_column,
+                chunk.end_column,
+                Self::speakers_to_json(&chunk.speakers),
+                chunk.time_range.as_ref().map(|(start, _)| start),
+                chunk.time_range.as_ref().map(|(_, end)| end),
+            ],
+        )
+        .map_err(|e| RagError::database(format!("Failed to apply chunk: {}", e)))?;
+
+        if vec_enabled && !embedding.is_empty() {
+            conn.execute("DELETE FROM vec_chunks WHERE chunk_id = ?1", params![id])
+                .map_err(|e| RagError::database(e.to_string()))?;
+            conn.execute(
+                "INSERT INTO vec_chunks (chunk_id, embedding) VALUES (?1, ?2)",
+                params![id, Self::vec_to_bytes(embedding)],
+            )
+            .map_err(|e| RagError::database(format!("Failed to apply embedding: {}", e)))?;
+        }
+
+        Ok(true)
+    }
+
+    fn apply_delete_chunk(
+        conn: &Connection,
+        id: Ulid,
+        hlc: &HybridLogicalClock,
+        vec_enabled: bool,
+    ) -> Result<bool> {
+        let id = id.to_string();
+        Self::record_tombstone(conn, TOMBSTONE_CHUNK, &id, hlc)?;
+
+        let existing = Self::row_hlc(conn, "SELECT hlc FROM chunks WHERE id = ?1", &id)?;
+        if !existing.is_some_and(|e| e < *hlc) {
+            return Ok(false);
+        }
+
+        if vec_enabled {
+            conn.execute("DELETE FROM vec_chunks WHERE chunk_id = ?1", params![id])
+                .map_err(|e| RagError::database(e.to_string()))?;
+        }
+
+        conn.execute("DELETE FROM chunks WHERE id = ?1", params![id])
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        Ok(true)
+    }
+
+    /// Read a chunk's embedding from the vec table.
+    fn get_embedding(conn: &Connection, chunk_id: Ulid) -> Result<Option<Vec<f32>>> {
+        let bytes: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT embedding FROM vec_chunks WHERE chunk_id = ?1",
+                params![chunk_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| RagError::database(e.to_string()))?;
+
+        Ok(bytes.map(|b| Self::bytes_to_vec(&b)))
+    }
+
+    /// Parse a stored ULID.
+    fn parse_ulid(s: &str) -> Result<Ulid> {
+        Ulid::from_string(s).map_err(|e| RagError::database(format!("Invalid id {}: {}", s, e)))
+    }
+
+    /// Current version of a sync key as a change, if the row or tombstone exists.
+    fn change_for_key(conn: &Connection, key: &str, vec_enabled: bool) -> Result<Option<SyncChange>> {
+        let (row_key, deleted) = match key.strip_prefix(TOMBSTONE_KEY_PREFIX) {
+            Some(row_key) => (row_key, true),
+            None => (key, false),
+        };
+
+        let (kind, id) = if let Some(name) = row_key.strip_prefix(COLLECTION_KEY_PREFIX) {
+            (TOMBSTONE_COLLECTION, name)
+        } else if let Some(id) = row_key.strip_prefix(DOCUMENT_KEY_PREFIX) {
+            (TOMBSTONE_DOCUMENT, id)
+        } else if let Some(id) = row_key.strip_prefix(CHUNK_KEY_PREFIX) {
+            (TOMBSTONE_CHUNK, id)
+        } else {
+            return Err(RagError::invalid_argument(format!("Invalid sync key: {}", key)));
+        };
+
+        if deleted {
+            let Some(hlc) = Self::tombstone_hlc(conn, kind, id)? else {
+                return Ok(None);
+            };
+            let change = match kind {
+                TOMBSTONE_COLLECTION => SyncChange::DeleteCollection(id.to_string(), hlc),
+                TOMBSTONE_DOCUMENT => SyncChange::DeleteDocument(Self::parse_ulid(id)?, hlc),
+                _ => SyncChange::DeleteChunk(Self::parse_ulid(id)?, hlc),
+            };
+            return Ok(Some(change));
+        }
+
+        let change = match kind {
+            TOMBSTONE_COLLECTION => conn
+                .query_row(
+                    "SELECT name, description, created_at, hlc FROM collections WHERE name = ?1",
+                    params![id],
+                    Self::row_to_collection,
+                )
+                .optional()
+                .map_err(|e| RagError::database(e.to_string()))?
+                .map(SyncChange::UpsertCollection),
+            TOMBSTONE_DOCUMENT => conn
+                .query_row(
+                    r#"
+                    SELECT id, collection, source_uri, content_hash, raw_content,
+                           content_type, metadata, created_at, updated_at, hlc
+                    FROM documents WHERE id = ?1
+                    "#,
+                    params![id],
+                    Self::row_to_document,
+                )
+                .optional()
+                .map_err(|e| RagError::database(e.to_string()))?
+                .map(SyncChange::UpsertDocument),
+            _ => {
+                let chunk = conn
+                    .query_row(
+                        r#"
+                        SELECT id, doc_id, chunk_index, content, token_count,
+                               start_line, end_line, content_hash, hlc, heading_path, parent_id, level, context,
+                               start_byte, end_byte, start_column, end_column, speakers, time_start, time_end
+                        FROM chunks WHERE id = ?1
+                        "#,
+                        params![id],
+                        Self::row_to_chunk,
+                    )
+                    .optional()
+                    .map_err(|e| RagError::database(e.to_string()))?;
+                match chunk {
+                    Some(chunk) => {
+                        let embedding = if vec_enabled {
+                            Self::get_embedding(conn, chunk.id)?.unwrap_or_default()
+                        } else {
+                            Vec::new()
+                        };
+                        Some(SyncChange::UpsertChunk(chunk, embedding))
+                    }
+                    None => None,
+                }
+            }
+        };
+
+        Ok(change)
+    }
+}
+
+// Helper methods
+impl SqliteStore {
+    /// Convert a row to a Collection.
+    fn row_to_collection(row: &rusqlite::Row<'_>) -> rusqlite::Result<Collection> {
+        let hlc_bytes: Vec<u8> = row.get(3)?;
+        Ok(Collection {
+            name: row.get(0)?,
+            description: row.get(1)?,
+            created_at: row.get::<_, i64>(2)? as u64,
+            hlc: HybridLogicalClock::from_bytes(&hlc_bytes)
+                .unwrap_or_else(HybridLogicalClock::zero),
+        })
+    }
+
+    /// Convert a row to a ConflictRecord.
+    fn row_to_conflict(row: &rusqlite::Row<'_>) -> rusqlite::Result<ConflictRecord> {
+        let version = |index: usize| -> rusqlite::Result<Document> {
+            let json: String = row.get(index)?;
+            serde_json::from_str(&json).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e))
+            })
+        };
+        let resolution: String = row.get(6)?;
+
+        Ok(ConflictRecord {
+            id: row.get(0)?,
+            collection: row.get(1)?,
+            source_uri: row.get(2)?,
+            local: version(3)?,
+            remote: version(4)?,
+            policy: row.get(5)?,
+            resolution: resolution.parse().unwrap_or(Resolution::KeepLocal),
+            resolved_at: row.get::<_, i64>(7)? as u64,
+        })
+    }
+
+    /// Convert a row to a Document.
+    fn row_to_document(row: &rusqlite::Row<'_>) -> rusqlite::Result<Document> {
+        let id_str: String = row.get(0)?;
+        let content_hash: Option<Vec<u8>> = row.get(3)?;
+        let content_type_str: String = row.get(5)?;
+        let metadata_str: String = row.get(6)?;
+        let hlc_bytes: Vec<u8> = row.get(9)?;
+
+        Ok(Document {
+            id: Ulid::from_string(&id_str).unwrap_or_else(|_| Ulid::nil()),
+            collection: row.get(1)?,
+            source_uri: row.get(2)?,
+            content_hash: content_hash.and_then(|v| v.try_into().ok()),
+            raw_content: row.get(4)?,
+            content_type: ContentType::from_name(&content_type_str),
+            metadata: serde_json::from_str(&metadata_str).unwrap_or_default(),
+            created_at: row.get::<_, i64>(7)? as u64,
+            updated_at: row.get::<_, i64>(8)? as u64,
+            hlc: HybridLogicalClock::from_bytes(&hlc_bytes)
+                .unwrap_or_else(HybridLogicalClock::zero),
+        })
+    }
+
+    /// Convert a row to a Chunk.
+    fn row_to_chunk(row: &rusqlite::Row<'_>) -> rusqlite::Result<Chunk> {
+        let id_str: String = row.get(0)?;
+        let doc_id_str: String = row.get(1)?;
         let content_hash: Option<Vec<u8>> = row.get(7)?;
         let hlc_bytes: Vec<u8> = row.get(8)?;
+        let parent_id: Option<String> = row.get(10)?;
+        let speakers: Option<String> = row.get(17)?;
+        let time_start: Option<String> = row.get(18)?;
+        let time_end: Option<String> = row.get(19)?;
 
         Ok(Chunk {
             id: Ulid::from_string(&id_str).unwrap_or_else(|_| Ulid::nil()),
@@ -879,17 +2362,43 @@ impl SqliteStore {
             token_count: row.get(4)?,
             start_line: row.get(5)?,
             end_line: row.get(6)?,
+            heading_path: row.get(9)?,
+            parent_id: parent_id.and_then(|id| Ulid::from_string(&id).ok()),
+            level: row.get(11)?,
+            context: row.get(12)?,
+            start_byte: row.get(13)?,
+            end_byte: row.get(14)?,
+            start_column: row.get(15)?,
+            end_column: row.get(16)?,
+            speakers: speakers.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default(),
+            time_range: time_start.zip(time_end),
             content_hash: content_hash.and_then(|v| v.try_into().ok()),
             hlc: HybridLogicalClock::from_bytes(&hlc_bytes)
                 .unwrap_or_else(HybridLogicalClock::zero),
         })
     }
 
+    /// Encode the speakers of a chunk as a JSON array, or NULL if it has none.
+    fn speakers_to_json(speakers: &[String]) -> Option<String> {
+        if speakers.is_empty() {
+            None
+        } else {
+            serde_json::to_string(speakers).ok()
+        }
+    }
+
     /// Convert f32 vector to bytes (little-endian).
     fn vec_to_bytes(v: &[f32]) -> Vec<u8> {
         v.iter().flat_map(|f| f.to_le_bytes()).collect()
     }
 
+    /// Convert little-endian bytes to an f32 vector.
+    fn bytes_to_vec(b: &[u8]) -> Vec<f32> {
+        b.chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect()
+    }
+
     /// Escape FTS5 query special characters.
     fn escape_fts5_query(query: &str) -> String {
         // Simple escaping: wrap each term in quotes if it contains special chars
@@ -910,6 +2419,7 @@ impl SqliteStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rag_core::ManualClock;
 
     #[tokio::test]
     async fn test_open_memory() {
@@ -957,6 +2467,7 @@ mod tests {
         // Read
         let retrieved = store.get_document(doc_id).await.unwrap().unwrap();
         assert_eq!(retrieved.source_uri, "file://test.rs");
+        assert_eq!(retrieved.content_type, ContentType::Rust);
 
         // Read by URI
         let by_uri = store
@@ -999,6 +2510,101 @@ mod tests {
         assert_eq!(retrieved[1].chunk_index, 1);
     }
 
+    #[tokio::test]
+    async fn test_chunk_heading_path_and_migration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.db");
+
+        // A database created before chunks had a heading path, parent,
+        // contextual header, byte offsets or chat metadata
+        {
+            let conn = Connection::open(&path).unwrap();
+            let start = SCHEMA.find(",\n    heading_path TEXT").unwrap();
+            let end = SCHEMA.find("\n);\n\nCREATE INDEX").unwrap();
+            let old_schema = [&SCHEMA[..start], &SCHEMA[end..]]
+                .concat()
+                .replace("    content,\n    context,\n", "    content,\n")
+                .replace(", context)", ")")
+                .replace(", NEW.context)", ")")
+                .replace(", OLD.context)", ")");
+            conn.execute_batch(&old_schema).unwrap();
+        }
+
+        let store = SqliteStore::open(&path, 1).unwrap();
+        store
+            .create_collection(Collection::new("docs", None))
+            .await
+            .unwrap();
+        let doc = Document::new("docs", "README.md", "# Install", ContentType::Markdown);
+        let doc_id = doc.id;
+        store.insert_document(doc).await.unwrap();
+
+        let intro = Chunk::new(doc_id, 0, "Intro", 1, 1, 1);
+        let chunks = vec![
+            intro.clone(),
+            Chunk::new(doc_id, 1, "Run it", 2, 3, 3)
+                .with_heading_path(Some("Install > Linux".to_string()))
+                .with_parent(Some(intro.id), 1)
+                .with_span(14, 20, 1, 7),
+            Chunk::new(doc_id, 2, "alice: hi\nbob: hello", 4, 5, 6).with_chat(
+                vec!["alice".to_string(), "bob".to_string()],
+                Some(("10:00".to_string(), "10:02".to_string())),
+            ),
+        ];
+        store.insert_chunks(&chunks).await.unwrap();
+
+        let retrieved = store.get_chunks_for_document(doc_id).await.unwrap();
+        let span = |c: &Chunk| (c.start_byte, c.end_byte, c.start_column, c.end_column);
+        assert_eq!(span(&retrieved[0]), (0, 0, 0, 0));
+        assert_eq!(span(&retrieved[1]), (14, 20, 1, 7));
+        assert_eq!(retrieved[0].heading_path, None);
+        assert_eq!(retrieved[1].heading_path.as_deref(), Some("Install > Linux"));
+        assert_eq!((retrieved[0].parent_id, retrieved[0].level), (None, 0));
+        assert_eq!((retrieved[1].parent_id, retrieved[1].level), (Some(intro.id), 1));
+        assert_eq!((retrieved[1].speakers.len(), retrieved[1].time_range.as_ref()), (0, None));
+        assert_eq!(retrieved[2].speakers, vec!["alice", "bob"]);
+        assert_eq!(retrieved[2].time_range, Some(("10:00".to_string(), "10:02".to_string())));
+
+        // The full-text index was rebuilt with the contextual header
+        store
+            .update_chunk_contexts(&[(chunks[1].id, Some("Section: Install > Linux".to_string()))])
+            .await
+            .unwrap();
+        let results = store.keyword_search("Linux", 10, None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, chunks[1].id);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_context() {
+        let store = SqliteStore::open_memory(1).unwrap();
+        store
+            .create_collection(Collection::new("test", None))
+            .await
+            .unwrap();
+        let doc = Document::new("test", "file://src/cache.rs", "fn get() {}", ContentType::Rust);
+        let doc_id = doc.id;
+        store.insert_document(doc).await.unwrap();
+
+        let chunk = Chunk::new(doc_id, 0, "fn get() {}", 4, 1, 1)
+            .with_context(Some("Path: src/cache.rs\nLanguage: Rust".to_string()));
+        store.insert_chunks(std::slice::from_ref(&chunk)).await.unwrap();
+
+        // The header is searchable but not part of the content
+        let results = store.keyword_search("cache", 10, None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        let retrieved = store.get_chunk(results[0].0).await.unwrap().unwrap();
+        assert_eq!(retrieved.content, "fn get() {}");
+        assert_eq!(retrieved.embedding_text(), "Path: src/cache.rs\nLanguage: Rust\n\nfn get() {}");
+
+        // Replacing the header reindexes the chunk
+        store.update_chunk_contexts(&[(chunk.id, None)]).await.unwrap();
+        assert!(store.keyword_search("cache", 10, None).await.unwrap().is_empty());
+        let retrieved = store.get_chunk(chunk.id).await.unwrap().unwrap();
+        assert_eq!(retrieved.context, None);
+        assert!(retrieved.hlc > chunk.hlc);
+    }
+
     #[tokio::test]
     async fn test_stats() {
         let store = SqliteStore::open_memory(1).unwrap();
@@ -1044,4 +2650,610 @@ mod tests {
         let results = store.keyword_search("Hello World", 10, None).await.unwrap();
         assert!(!results.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_hlc_survives_clock_stepping_backwards() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rag.db");
+
+        let clock = Arc::new(ManualClock::new(10_000_000));
+        let store = SqliteStore::open_with_clock(&path, 1, clock.clone()).unwrap();
+        store
+            .create_collection(Collection::new("test", None))
+            .await
+            .unwrap();
+        let before = store.get_watermark().await.unwrap();
+        drop(store);
+
+        // Restart with the wall clock an hour behind
+        clock.set(10_000_000 - 3_600_000);
+        let store = SqliteStore::open_with_clock(&path, 1, clock.clone()).unwrap();
+        assert!(store.current_hlc() >= before);
+
+        let doc = Document::new("test", "file://a.rs", "fn a() {}", ContentType::Rust);
+        let doc_id = doc.id;
+        store.insert_document(doc).await.unwrap();
+
+        let doc = store.get_document(doc_id).await.unwrap().unwrap();
+        assert!(doc.hlc > before);
+    }
+
+    #[tokio::test]
+    async fn test_hlc_persisted_without_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rag.db");
+
+        let clock = Arc::new(ManualClock::new(5_000));
+        let store = SqliteStore::open_with_clock(&path, 1, clock.clone()).unwrap();
+        let observed = store
+            .observe_hlc(&HybridLogicalClock::from_parts(6_000, 3, 2))
+            .unwrap();
+        drop(store);
+
+        // No rows carry the merged clock, so only the persisted clock prevents going backwards
+        clock.set(0);
+        let store = SqliteStore::open_with_clock(&path, 1, clock).unwrap();
+        assert_eq!(store.get_watermark().await.unwrap(), HybridLogicalClock::zero());
+        assert!(store.current_hlc() >= observed);
+    }
+
+    #[tokio::test]
+    async fn test_node_id_persisted_and_reset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rag.db");
+
+        let store = SqliteStore::open_auto(&path).unwrap();
+        let node_id = store.node_id();
+        assert_ne!(node_id, 0);
+        drop(store);
+
+        let store = SqliteStore::open_auto(&path).unwrap();
+        assert_eq!(store.node_id(), node_id);
+
+        // A clone re-keyed with a new ID keeps it across restarts
+        let before = store.current_hlc();
+        let reset = store.reset_node_id().unwrap();
+        assert_ne!(reset, node_id);
+        assert!(store.current_hlc() > before);
+        assert_eq!(store.current_hlc().tick().node_id, reset);
+        drop(store);
+
+        assert_eq!(SqliteStore::open_auto(&path).unwrap().node_id(), reset);
+
+        // An exhausted logical counter moves the clock to the next millisecond
+        let clock = Arc::new(ManualClock::new(10_000));
+        let store = SqliteStore::open_memory_with_clock(1, clock).unwrap();
+        store.observe_hlc(&HybridLogicalClock::from_parts(10_000, u32::MAX - 1, 2)).unwrap();
+        store.reset_node_id().unwrap();
+        let hlc = store.current_hlc();
+        assert_eq!((hlc.wall_time, hlc.logical), (10_001, 0));
+    }
+
+    #[tokio::test]
+    async fn test_observe_hlc() {
+        let clock = Arc::new(ManualClock::new(10_000));
+        let store = SqliteStore::open_memory_with_clock(1, clock)
+            .unwrap()
+            .with_max_clock_drift(1_000);
+
+        let remote = HybridLogicalClock::from_parts(10_500, 7, 2);
+        let merged = store.observe_hlc(&remote).unwrap();
+        assert!(merged > remote);
+        assert_eq!(merged.node_id, 1);
+
+        let runaway = HybridLogicalClock::from_parts(20_000, 0, 2);
+        let err = store.observe_hlc(&runaway).unwrap_err();
+        assert!(matches!(err, RagError::ClockDrift { .. }));
+        assert_eq!(store.current_hlc(), merged);
+    }
+
+    /// Comparable view of a store's replicated state.
+    async fn snapshot(store: &SqliteStore) -> Vec<String> {
+        let mut rows = Vec::new();
+        for coll in store.list_collections().await.unwrap() {
+            rows.push(format!("collection {} {}", coll.name, coll.hlc));
+            for doc in store.list_documents(&coll.name, 1000, 0).await.unwrap() {
+                rows.push(format!("document {} {} {}", doc.id, doc.source_uri, doc.hlc));
+                for chunk in store.get_chunks_for_document(doc.id).await.unwrap() {
+                    rows.push(format!("chunk {} {} {}", chunk.id, chunk.content, chunk.hlc));
+                }
+            }
+        }
+        rows.sort();
+        rows
+    }
+
+    /// Exchange all changes in both directions.
+    async fn sync_pair(a: &SqliteStore, b: &SqliteStore) {
+        let zero = HybridLogicalClock::zero();
+        let from_a = a.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap();
+        let from_b = b.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap();
+        b.apply_changes(&from_a).await.unwrap();
+        a.apply_changes(&from_b).await.unwrap();
+    }
+
+    async fn insert_doc(store: &SqliteStore, collection: &str, uri: &str, chunks: &[&str]) -> Ulid {
+        let doc = Document::new(collection, uri, &chunks.join("\n"), ContentType::PlainText);
+        let doc_id = doc.id;
+        store.insert_document(doc).await.unwrap();
+        let chunks: Vec<_> = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, c)| Chunk::new(doc_id, i as u32, c, 1, i as u32 + 1, i as u32 + 1))
+            .collect();
+        store.insert_chunks(&chunks).await.unwrap();
+        doc_id
+    }
+
+    #[tokio::test]
+    async fn test_get_changes_since_ordered() {
+        let store = SqliteStore::open_memory(1).unwrap();
+        store
+            .create_collection(Collection::new("test", None))
+            .await
+            .unwrap();
+        let doc_id = insert_doc(&store, "test", "file://a.txt", &["alpha", "beta"]).await;
+
+        let changes = store
+            .get_changes_since(&HybridLogicalClock::zero(), &CollectionFilter::all())
+            .await
+            .unwrap();
+        assert_eq!(changes.len(), 4);
+        assert!(matches!(changes[0], SyncChange::UpsertCollection(_)));
+        assert!(matches!(&changes[1], SyncChange::UpsertDocument(d) if d.id == doc_id));
+        assert!(changes.windows(2).all(|w| w[0].hlc() < w[1].hlc()));
+
+        // Only changes strictly after the given HLC are returned
+        let since = changes[1].hlc();
+        let later = store.get_changes_since(&since, &CollectionFilter::all()).await.unwrap();
+        assert_eq!(later.len(), 2);
+        assert!(later.iter().all(|c| matches!(c, SyncChange::UpsertChunk(..))));
+
+        // Deletions are returned as tombstones
+        let watermark = store.get_watermark().await.unwrap();
+        store.delete_document(doc_id).await.unwrap();
+        let deleted = store
+            .get_changes_since(&watermark, &CollectionFilter::all())
+            .await
+            .unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert!(matches!(deleted[0], SyncChange::DeleteDocument(id, _) if id == doc_id));
+        assert!(store.get_watermark().await.unwrap() > watermark);
+    }
+
+    #[tokio::test]
+    async fn test_get_changes_page() {
+        let store = SqliteStore::open_memory(1).unwrap();
+        let delete = |name: &str, wall_time| {
+            SyncChange::DeleteCollection(name.to_string(), HybridLogicalClock::from_parts(wall_time, 0, 2))
+        };
+        let changes: Vec<_> = (1..=5).map(|i| delete(&format!("c{}", i), i)).collect();
+        store.apply_changes(&changes).await.unwrap();
+        let (zero, all) = (HybridLogicalClock::zero(), CollectionFilter::all());
+
+        let hlcs = |changes: &[SyncChange]| changes.iter().map(SyncChange::hlc).collect::<Vec<_>>();
+
+        let (page, has_more) = store.get_changes_page(&zero, &all, 2).await.unwrap();
+        assert_eq!(hlcs(&page), hlcs(&changes[..2]));
+        assert!(has_more);
+
+        let (page, has_more) = store.get_changes_page(&changes[1].hlc(), &all, 3).await.unwrap();
+        assert_eq!(hlcs(&page), hlcs(&changes[2..]));
+        assert!(!has_more);
+
+        let (page, has_more) = store.get_changes_page(&zero, &all, 10).await.unwrap();
+        assert_eq!(page.len(), 5);
+        assert!(!has_more);
+    }
+
+    #[tokio::test]
+    async fn test_get_changes_page_keeps_equal_hlcs_together() {
+        let store = SqliteStore::open_memory(1).unwrap();
+        let delete = |name: &str, wall_time| {
+            SyncChange::DeleteCollection(name.to_string(), HybridLogicalClock::from_parts(wall_time, 0, 2))
+        };
+        let changes = vec![delete("a", 1), delete("b", 2), delete("c", 2), delete("d", 2), delete("e", 3)];
+        store.apply_changes(&changes).await.unwrap();
+        let (zero, all) = (HybridLogicalClock::zero(), CollectionFilter::all());
+
+        let (page, has_more) = store.get_changes_page(&zero, &all, 2).await.unwrap();
+        assert_eq!(page.len(), 4);
+        assert!(has_more);
+
+        // A page smaller than the group still holds all of it
+        let (page, has_more) = store.get_changes_page(&changes[0].hlc(), &all, 1).await.unwrap();
+        assert_eq!(page.len(), 3);
+        assert!(has_more);
+
+        let (page, has_more) = store.get_changes_page(&changes[3].hlc(), &all, 1).await.unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].hlc(), changes[4].hlc());
+        assert!(!has_more);
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_replicate_after_sync() {
+        let a = SqliteStore::open_memory(1).unwrap();
+        let b = SqliteStore::open_memory(2).unwrap();
+        // Embeddings need the sqlite-vec extension
+        if !a.vec_enabled {
+            return;
+        }
+
+        // B syncs between A storing a chunk and embedding it
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        let doc_id = insert_doc(&a, "notes", "file://a.txt", &["alpha"]).await;
+        sync_pair(&a, &b).await;
+        let watermark = a.get_watermark().await.unwrap();
+        let chunk = a.get_chunks_for_document(doc_id).await.unwrap().remove(0);
+        a.insert_embeddings(&[chunk.id], &[vec![0.5; 768]]).await.unwrap();
+
+        let changes = a.get_changes_since(&watermark, &CollectionFilter::all()).await.unwrap();
+        assert!(matches!(&changes[..], [SyncChange::UpsertChunk(c, e)] if c.id == chunk.id && e.len() == 768));
+        b.apply_changes(&changes).await.unwrap();
+        assert_eq!(b.vector_search(&[0.5; 768], 1, None).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes_converges() {
+        let a = SqliteStore::open_memory(1).unwrap();
+        let b = SqliteStore::open_memory(2).unwrap();
+
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        insert_doc(&a, "notes", "file://a.txt", &["from a"]).await;
+        sync_pair(&a, &b).await;
+
+        insert_doc(&b, "notes", "file://b.txt", &["from b", "more b"]).await;
+        insert_doc(&a, "notes", "file://c.txt", &["from a again"]).await;
+        sync_pair(&a, &b).await;
+
+        let snap_a = snapshot(&a).await;
+        assert_eq!(snap_a, snapshot(&b).await);
+        assert_eq!(snap_a.iter().filter(|r| r.starts_with("document")).count(), 3);
+        assert_eq!(snap_a.iter().filter(|r| r.starts_with("chunk")).count(), 4);
+
+        // Keyword search works on replicated chunks (FTS kept in sync)
+        let hits = b.keyword_search("again", 10, None).await.unwrap();
+        assert_eq!(hits.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes_idempotent() {
+        let a = SqliteStore::open_memory(1).unwrap();
+        let b = SqliteStore::open_memory(2).unwrap();
+
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        insert_doc(&a, "notes", "file://a.txt", &["one", "two"]).await;
+
+        let changes = a
+            .get_changes_since(&HybridLogicalClock::zero(), &CollectionFilter::all())
+            .await
+            .unwrap();
+        b.apply_changes(&changes).await.unwrap();
+        let once = snapshot(&b).await;
+        b.apply_changes(&changes).await.unwrap();
+        assert_eq!(once, snapshot(&b).await);
+
+        // Applying in reverse order converges to the same state
+        let c = SqliteStore::open_memory(3).unwrap();
+        let reversed: Vec<_> = changes.iter().rev().cloned().collect();
+        c.apply_changes(&reversed).await.unwrap();
+        assert_eq!(once, snapshot(&c).await);
+
+        let stats = b.get_stats(None).await.unwrap();
+        assert_eq!(stats.chunks, 2);
+        assert_eq!(b.keyword_search("two", 10, None).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes_last_writer_wins() {
+        let a = SqliteStore::open_memory(1).unwrap();
+        let b = SqliteStore::open_memory(2).unwrap();
+
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        let doc_id = insert_doc(&a, "notes", "file://a.txt", &["original"]).await;
+        sync_pair(&a, &b).await;
+
+        // Concurrent edits of the same document: the higher HLC wins everywhere
+        let mut doc_a = a.get_document(doc_id).await.unwrap().unwrap();
+        doc_a.source_uri = "file://edited-on-a.txt".to_string();
+        doc_a.hlc = a.current_hlc().tick();
+        let mut doc_b = b.get_document(doc_id).await.unwrap().unwrap();
+        doc_b.source_uri = "file://edited-on-b.txt".to_string();
+        doc_b.hlc = doc_a.hlc.tick();
+        a.apply_changes(&[SyncChange::UpsertDocument(doc_a)]).await.unwrap();
+        b.apply_changes(&[SyncChange::UpsertDocument(doc_b)]).await.unwrap();
+
+        sync_pair(&a, &b).await;
+        assert_eq!(snapshot(&a).await, snapshot(&b).await);
+        let winner = a.get_document(doc_id).await.unwrap().unwrap();
+        assert_eq!(winner.source_uri, "file://edited-on-b.txt");
+        assert_eq!(a.get_chunks_for_document(doc_id).await.unwrap().len(), 1);
+
+        // A stale write does not overwrite a newer one
+        let mut stale = winner.clone();
+        stale.source_uri = "file://stale.txt".to_string();
+        stale.hlc = HybridLogicalClock::from_parts(1, 0, 9);
+        a.apply_changes(&[SyncChange::UpsertDocument(stale)]).await.unwrap();
+        assert_eq!(
+            a.get_document(doc_id).await.unwrap().unwrap().source_uri,
+            "file://edited-on-b.txt"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes_deletes() {
+        let a = SqliteStore::open_memory(1).unwrap();
+        let b = SqliteStore::open_memory(2).unwrap();
+
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        a.create_collection(Collection::new("scratch", None)).await.unwrap();
+        let doc_id = insert_doc(&a, "notes", "file://a.txt", &["one", "two"]).await;
+        let rechunked = insert_doc(&a, "notes", "file://b.txt", &["old chunk"]).await;
+        insert_doc(&a, "scratch", "file://c.txt", &["scratch"]).await;
+        sync_pair(&a, &b).await;
+
+        a.delete_document(doc_id).await.unwrap();
+        a.delete_chunks_for_document(rechunked).await.unwrap();
+        a.delete_collection("scratch").await.unwrap();
+        sync_pair(&a, &b).await;
+
+        assert_eq!(snapshot(&a).await, snapshot(&b).await);
+        assert!(b.get_document(doc_id).await.unwrap().is_none());
+        assert!(b.get_chunks_for_document(rechunked).await.unwrap().is_empty());
+        assert!(b.get_collection("scratch").await.unwrap().is_none());
+        assert!(b.keyword_search("scratch", 10, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_delete_and_insert_converge() {
+        let clock = Arc::new(ManualClock::new(10_000_000));
+        let a = SqliteStore::open_memory_with_clock(1, clock.clone()).unwrap();
+        let b = SqliteStore::open_memory_with_clock(2, clock.clone()).unwrap();
+
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        sync_pair(&a, &b).await;
+
+        // B writes into the collection while A deletes and re-creates it
+        clock.advance(10);
+        insert_doc(&b, "notes", "file://b.txt", &["written on b"]).await;
+        clock.advance(10);
+        a.delete_collection("notes").await.unwrap();
+        clock.advance(10);
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        insert_doc(&a, "notes", "file://a.txt", &["written on a"]).await;
+
+        sync_pair(&a, &b).await;
+        sync_pair(&a, &b).await;
+
+        let snap = snapshot(&a).await;
+        assert_eq!(snap, snapshot(&b).await);
+        assert_eq!(snap.iter().filter(|r| r.starts_with("document")).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_recreate_drops_losing_documents() {
+        let clock = Arc::new(ManualClock::new(10_000_000));
+        let a = SqliteStore::open_memory_with_clock(1, clock.clone()).unwrap();
+        let b = SqliteStore::open_memory_with_clock(2, clock.clone()).unwrap();
+
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        clock.advance(10);
+        a.delete_collection("notes").await.unwrap();
+        sync_pair(&a, &b).await;
+
+        // Both sides re-create the collection; B's re-creation is newer
+        clock.advance(10);
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        insert_doc(&a, "notes", "file://a.txt", &["written on a"]).await;
+        clock.advance(10);
+        b.create_collection(Collection::new("notes", None)).await.unwrap();
+
+        sync_pair(&a, &b).await;
+
+        let snap = snapshot(&a).await;
+        assert_eq!(snap, snapshot(&b).await);
+        assert_eq!(snap.iter().filter(|r| r.starts_with("document")).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_late_collection_delete_drops_orphaned_documents() {
+        let clock = Arc::new(ManualClock::new(10_000_000));
+        let a = SqliteStore::open_memory_with_clock(1, clock.clone()).unwrap();
+        let b = SqliteStore::open_memory_with_clock(2, clock.clone()).unwrap();
+
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        insert_doc(&a, "notes", "file://a.txt", &["old"]).await;
+        sync_pair(&a, &b).await;
+
+        // A deletes and re-creates the collection; B sees the re-creation first
+        let before_delete = a.get_watermark().await.unwrap();
+        clock.advance(10);
+        a.delete_collection("notes").await.unwrap();
+        let delete = a.get_changes_since(&before_delete, &CollectionFilter::all()).await.unwrap();
+        clock.advance(10);
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+
+        let all = a.get_changes_since(&before_delete, &CollectionFilter::all()).await.unwrap();
+        let upserts: Vec<_> = all
+            .into_iter()
+            .filter(|c| matches!(c, SyncChange::UpsertCollection(_)))
+            .collect();
+        b.apply_changes(&upserts).await.unwrap();
+        b.apply_changes(&delete).await.unwrap();
+
+        assert_eq!(snapshot(&a).await, snapshot(&b).await);
+        assert!(b.list_documents("notes", 10, 0).await.unwrap().is_empty());
+    }
+
+    /// Two nodes edit the same URI concurrently, then exchange changes.
+    async fn conflicting_edits(resolver: &dyn ConflictResolver) -> (SqliteStore, SqliteStore) {
+        let clock = Arc::new(ManualClock::new(10_000_000));
+        let a = SqliteStore::open_memory_with_clock(1, clock.clone()).unwrap();
+        let b = SqliteStore::open_memory_with_clock(2, clock.clone()).unwrap();
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        sync_pair(&a, &b).await;
+
+        insert_doc(&a, "notes", "file://plan.md", &["edited on a"]).await;
+        clock.advance(10);
+        insert_doc(&b, "notes", "file://plan.md", &["edited on b"]).await;
+
+        let zero = HybridLogicalClock::zero();
+        let from_a = a.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap();
+        let from_b = b.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap();
+        b.apply_changes_with(&from_a, resolver).await.unwrap();
+        a.apply_changes_with(&from_b, resolver).await.unwrap();
+        (a, b)
+    }
+
+    async fn uri_contents(store: &SqliteStore) -> Vec<(String, String)> {
+        let mut docs: Vec<_> = store
+            .list_documents("notes", 100, 0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.source_uri, d.raw_content.unwrap_or_default()))
+            .collect();
+        docs.sort();
+        docs
+    }
+
+    #[tokio::test]
+    async fn test_conflict_policies() {
+        use rag_core::{KeepBoth, LastWriterWins, PreferLocal, PreferNode};
+
+        let (a, b) = conflicting_edits(&LastWriterWins).await;
+        assert_eq!(snapshot(&a).await, snapshot(&b).await);
+        assert_eq!(
+            uri_contents(&a).await,
+            vec![("file://plan.md".to_string(), "edited on b".to_string())]
+        );
+        let logged = a.list_conflicts(10).await.unwrap();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].resolution, Resolution::TakeRemote);
+        assert_eq!(logged[0].local.raw_content.as_deref(), Some("edited on a"));
+        assert_eq!(b.list_conflicts(10).await.unwrap()[0].resolution, Resolution::KeepLocal);
+
+        let (a, b) = conflicting_edits(&PreferNode(1)).await;
+        assert_eq!(snapshot(&a).await, snapshot(&b).await);
+        assert_eq!(uri_contents(&b).await[0].1, "edited on a");
+
+        let (a, b) = conflicting_edits(&PreferLocal).await;
+        assert_eq!(uri_contents(&a).await[0].1, "edited on a");
+        assert_eq!(uri_contents(&b).await[0].1, "edited on b");
+
+        let (a, b) = conflicting_edits(&KeepBoth).await;
+        assert_eq!(snapshot(&a).await, snapshot(&b).await);
+        let docs = uri_contents(&a).await;
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0], ("file://plan.md".to_string(), "edited on b".to_string()));
+        assert!(docs[1].0.starts_with("file://plan.md#conflict-"));
+        assert_eq!(docs[1].1, "edited on a");
+
+        // Re-resolving is recorded as manual
+        let id = a.list_conflicts(10).await.unwrap()[0].id;
+        a.set_conflict_resolution(id, Resolution::KeepLocal).await.unwrap();
+        let record = a.get_conflict(id).await.unwrap().unwrap();
+        assert_eq!((record.policy.as_str(), record.resolution), ("manual", Resolution::KeepLocal));
+        assert!(a.set_conflict_resolution(id + 100, Resolution::KeepLocal).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sequential_edit_is_not_a_conflict() {
+        use rag_core::{LastWriterWins, PreferLocal};
+
+        let resolvers: [&dyn ConflictResolver; 2] = [&LastWriterWins, &PreferLocal];
+        for resolver in resolvers {
+            let a = SqliteStore::open_memory(1).unwrap();
+            let b = SqliteStore::open_memory(2).unwrap();
+            a.create_collection(Collection::new("notes", None)).await.unwrap();
+            let old = insert_doc(&a, "notes", "file://plan.md", &["first"]).await;
+            sync_pair(&a, &b).await;
+
+            // B replaces the document after seeing A's version, storing the
+            // new one before deleting the old one like `ingest`
+            insert_doc(&b, "notes", "file://plan.md", &["second"]).await;
+            b.delete_document(old).await.unwrap();
+            let zero = HybridLogicalClock::zero();
+            let from_b = b.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap();
+            a.apply_changes_with(&from_b, resolver).await.unwrap();
+
+            assert_eq!(snapshot(&a).await, snapshot(&b).await, "{}", resolver.name());
+            let docs = a.list_documents("notes", 10, 0).await.unwrap();
+            assert_eq!(docs.len(), 1);
+            assert_eq!(docs[0].raw_content.as_deref(), Some("second"));
+            assert!(a.list_conflicts(10).await.unwrap().is_empty(), "{}", resolver.name());
+        }
+
+        // Nor is a node replacing its own document, even when the tombstone
+        // comes in a later batch
+        let a = SqliteStore::open_memory(1).unwrap();
+        let b = SqliteStore::open_memory(2).unwrap();
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        let old = insert_doc(&a, "notes", "file://plan.md", &["v1"]).await;
+        sync_pair(&a, &b).await;
+
+        let watermark = a.get_watermark().await.unwrap();
+        insert_doc(&a, "notes", "file://plan.md", &["v2"]).await;
+        let insert = a.get_changes_since(&watermark, &CollectionFilter::all()).await.unwrap();
+        a.delete_document(old).await.unwrap();
+        b.apply_changes_with(&insert, &PreferLocal).await.unwrap();
+        let all = a.get_changes_since(&watermark, &CollectionFilter::all()).await.unwrap();
+        b.apply_changes_with(&all, &PreferLocal).await.unwrap();
+
+        let docs = b.list_documents("notes", 10, 0).await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].raw_content.as_deref(), Some("v2"));
+        assert!(b.list_conflicts(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_collection_filter_and_purge() {
+        let store = SqliteStore::open_memory(1).unwrap();
+        store.create_collection(Collection::new("notes", None)).await.unwrap();
+        store.create_collection(Collection::new("vendor-docs", None)).await.unwrap();
+        insert_doc(&store, "notes", "file://a.txt", &["alpha"]).await;
+        insert_doc(&store, "vendor-docs", "file://b.txt", &["beta", "gamma"]).await;
+        store.create_collection(Collection::new("old", None)).await.unwrap();
+        store.delete_collection("old").await.unwrap();
+
+        let filter = CollectionFilter {
+            include: Vec::new(),
+            exclude: vec!["vendor-docs".to_string(), "old".to_string()],
+        };
+        let zero = HybridLogicalClock::zero();
+        assert_eq!(store.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap().len(), 8);
+        assert_eq!(store.get_changes_since(&zero, &filter).await.unwrap().len(), 3);
+
+        let only_notes = CollectionFilter {
+            include: vec!["notes".to_string()],
+            exclude: Vec::new(),
+        };
+        let items = store.sync_items(&KeyRange::full(), &only_notes).await.unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items, store.sync_items(&KeyRange::full(), &filter).await.unwrap());
+
+        // Purging leaves no tombstone behind
+        let watermark = store.get_watermark().await.unwrap();
+        assert!(store.purge_collection("vendor-docs").await.unwrap());
+        assert!(!store.purge_collection("vendor-docs").await.unwrap());
+        assert!(store.get_changes_since(&watermark, &CollectionFilter::all()).await.unwrap().is_empty());
+        assert_eq!(store.get_stats(None).await.unwrap().chunks, 1);
+    }
+
+    #[tokio::test]
+    async fn test_sync_state() {
+        let store = SqliteStore::open_memory(1).unwrap();
+        assert!(store.get_sync_state("peer:a").await.unwrap().is_none());
+
+        store.set_sync_state("peer:a", b"one").await.unwrap();
+        store.set_sync_state("peer:b", b"two").await.unwrap();
+        store.set_sync_state("peer:a", b"three").await.unwrap();
+
+        assert_eq!(store.get_sync_state("peer:a").await.unwrap().unwrap(), b"three");
+
+        let peers = store.list_sync_state("peer:").await.unwrap();
+        let keys: Vec<_> = peers.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, vec!["peer:a", "peer:b"]);
+    }
 }
@@ -0,0 +1,243 @@
+//! Request signing for the sync API.
+//!
+//! Requests are signed with HMAC-SHA256 over the method, path and query, a
+//! timestamp, a random nonce and the body, using a secret shared by the two
+//! peers. The server accepts a signature made with any configured peer's
+//! secret, rejects timestamps outside a window around its own clock, and
+//! remembers nonces for that window so a captured request cannot be replayed.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use tracing::debug;
+
+use rag_core::{Clock, RagError, Result, SyncConfig, SystemClock};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Header carrying the request time (Unix millis).
+pub const TIMESTAMP_HEADER: &str = "x-rag-timestamp";
+
+/// Header carrying the random request nonce (hex).
+pub const NONCE_HEADER: &str = "x-rag-nonce";
+
+/// Header carrying the request signature (hex).
+pub const SIGNATURE_HEADER: &str = "x-rag-signature";
+
+/// Default tolerance between the signer's and verifier's clocks.
+pub const DEFAULT_MAX_SKEW_MS: u64 = 5 * 60 * 1000;
+
+/// Longest nonce accepted, in characters.
+const MAX_NONCE_LEN: usize = 64;
+
+fn mac(secret: &[u8], method: &str, path: &str, timestamp: u64, nonce: &str, body: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(method.as_bytes());
+    mac.update(b"\n");
+    mac.update(path.as_bytes());
+    mac.update(b"\n");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b"\n");
+    mac.update(nonce.as_bytes());
+    mac.update(b"\n");
+    mac.update(body);
+    mac
+}
+
+/// Sign a request, returning the hex-encoded signature.
+///
+/// `path` includes the query string, exactly as sent.
+pub fn sign(secret: &[u8], method: &str, path: &str, timestamp: u64, nonce: &str, body: &[u8]) -> String {
+    hex::encode(mac(secret, method, path, timestamp, nonce, body).finalize().into_bytes())
+}
+
+/// Generate a random request nonce.
+pub fn new_nonce() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Authentication headers of an incoming request.
+#[derive(Debug, Clone, Copy)]
+pub struct SignedRequest<'a> {
+    /// HTTP method.
+    pub method: &'a str,
+
+    /// Path and query string.
+    pub path: &'a str,
+
+    /// Value of [`TIMESTAMP_HEADER`].
+    pub timestamp: Option<&'a str>,
+
+    /// Value of [`NONCE_HEADER`].
+    pub nonce: Option<&'a str>,
+
+    /// Value of [`SIGNATURE_HEADER`].
+    pub signature: Option<&'a str>,
+
+    /// Request body.
+    pub body: &'a [u8],
+}
+
+/// Verifies signed requests against the secrets of the configured peers.
+pub struct Authenticator {
+    keys: Vec<(String, Vec<u8>)>,
+    max_skew_ms: u64,
+    clock: Arc<dyn Clock>,
+    /// Nonces seen within the skew window, with their request timestamps.
+    seen: Mutex<HashMap<String, u64>>,
+}
+
+impl Authenticator {
+    /// Create an authenticator for `(peer_id, secret)` pairs.
+    pub fn new(keys: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|(peer_id, secret)| (peer_id, secret.into_bytes()))
+                .collect(),
+            max_skew_ms: DEFAULT_MAX_SKEW_MS,
+            clock: Arc::new(SystemClock),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Create an authenticator from the peers' secrets, or `None` if no peer
+    /// has a secret configured.
+    pub fn from_config(config: &SyncConfig) -> Option<Self> {
+        let keys: Vec<_> = config
+            .peers
+            .iter()
+            .filter_map(|peer| peer.secret.clone().map(|secret| (peer.id.clone(), secret)))
+            .collect();
+        (!keys.is_empty()).then(|| Self::new(keys))
+    }
+
+    /// Use a custom clock (for tests).
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Set the tolerated clock difference, which is also how long nonces are kept.
+    pub fn with_max_skew_ms(mut self, max_skew_ms: u64) -> Self {
+        self.max_skew_ms = max_skew_ms;
+        self
+    }
+
+    /// Verify a request, returning the ID of the peer whose secret signed it.
+    pub fn verify(&self, request: &SignedRequest<'_>) -> Result<String> {
+        let (Some(timestamp), Some(nonce), Some(signature)) =
+            (request.timestamp, request.nonce, request.signature)
+        else {
+            return Err(RagError::unauthorized("Missing signature headers"));
+        };
+
+        let timestamp: u64 = timestamp
+            .parse()
+            .map_err(|_| RagError::unauthorized("Invalid timestamp"))?;
+        let now = self.clock.now_millis();
+        if now.abs_diff(timestamp) > self.max_skew_ms {
+            return Err(RagError::unauthorized(format!(
+                "Timestamp is {}ms away from server time",
+                now.abs_diff(timestamp)
+            )));
+        }
+
+        if nonce.is_empty() || nonce.len() > MAX_NONCE_LEN {
+            return Err(RagError::unauthorized("Invalid nonce"));
+        }
+        let signature = hex::decode(signature).map_err(|_| RagError::unauthorized("Invalid signature"))?;
+
+        let peer_id = self
+            .keys
+            .iter()
+            .find(|(_, secret)| {
+                mac(secret, request.method, request.path, timestamp, nonce, request.body)
+                    .verify_slice(&signature)
+                    .is_ok()
+            })
+            .map(|(peer_id, _)| peer_id.clone())
+            .ok_or_else(|| RagError::unauthorized("Invalid signature"))?;
+
+        // Only signed requests reach the nonce cache, so it cannot be flooded
+        let mut seen = self.seen.lock().unwrap();
+        let window = self.max_skew_ms;
+        seen.retain(|_, ts| now.abs_diff(*ts) <= window);
+        if seen.insert(nonce.to_string(), timestamp).is_some() {
+            return Err(RagError::unauthorized("Replayed request"));
+        }
+
+        debug!("Authenticated request from {}", peer_id);
+        Ok(peer_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rag_core::ManualClock;
+
+    fn request<'a>(timestamp: &'a str, nonce: &'a str, signature: &'a str) -> SignedRequest<'a> {
+        SignedRequest {
+            method: "POST",
+            path: "/sync/changes",
+            timestamp: Some(timestamp),
+            nonce: Some(nonce),
+            signature: Some(signature),
+            body: b"{}",
+        }
+    }
+
+    fn authenticator(clock: Arc<ManualClock>) -> Authenticator {
+        Authenticator::new([
+            ("a".to_string(), "secret-a".to_string()),
+            ("b".to_string(), "secret-b".to_string()),
+        ])
+        .with_clock(clock)
+        .with_max_skew_ms(1000)
+    }
+
+    #[test]
+    fn test_verify_identifies_peer() {
+        let auth = authenticator(Arc::new(ManualClock::new(10_000)));
+        let signature = sign(b"secret-b", "POST", "/sync/changes", 10_000, "n1", b"{}");
+        assert_eq!(auth.verify(&request("10000", "n1", &signature)).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_verify_rejects_wrong_key_and_tampering() {
+        let auth = authenticator(Arc::new(ManualClock::new(10_000)));
+
+        let signature = sign(b"wrong", "POST", "/sync/changes", 10_000, "n1", b"{}");
+        let err = auth.verify(&request("10000", "n1", &signature)).unwrap_err();
+        assert_eq!(err.error_code(), "UNAUTHORIZED");
+
+        let signature = sign(b"secret-a", "POST", "/sync/changes", 10_000, "n2", b"{}");
+        let mut tampered = request("10000", "n2", &signature);
+        tampered.body = b"{\"changes\":[]}";
+        assert!(auth.verify(&tampered).is_err());
+
+        let mut unsigned = request("10000", "n3", "");
+        unsigned.signature = None;
+        assert!(auth.verify(&unsigned).is_err());
+    }
+
+    #[test]
+    fn test_verify_rejects_replay_and_stale_requests() {
+        let clock = Arc::new(ManualClock::new(10_000));
+        let auth = authenticator(clock.clone());
+
+        let signature = sign(b"secret-a", "POST", "/sync/changes", 10_000, "n1", b"{}");
+        auth.verify(&request("10000", "n1", &signature)).unwrap();
+        assert!(auth.verify(&request("10000", "n1", &signature)).is_err());
+
+        clock.advance(5_000);
+        let err = auth.verify(&request("10000", "n1", &signature)).unwrap_err();
+        assert!(err.to_string().contains("away from server time"));
+    }
+}
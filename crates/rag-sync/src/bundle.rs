@@ -0,0 +1,366 @@
+//! Offline sync through change bundle files.
+//!
+//! A bundle is a JSON file holding every change after a given HLC, the
+//! exporting node's ID and watermark, and a checksummed [`ChangeBatch`],
+//! with a checksum over all of them. It can be carried to an air-gapped
+//! node and applied there with [`import_bundle`]. Applying changes is
+//! last-writer-wins, so importing a bundle twice changes nothing; the
+//! importer also remembers each bundle's checksum and reports repeats
+//! without touching the store.
+//!
+//! Each node remembers the watermark of its last export, so `export_bundle`
+//! without an explicit `since` only carries what changed after it.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use rag_core::{
+    Clock, CollectionFilter, ConflictResolver, HybridLogicalClock, RagError, Result, Store, SyncChange, SystemClock,
+};
+
+use crate::protocol::{parse_hlc, ChangeBatch};
+
+/// Format identifier written into every bundle.
+pub const BUNDLE_FORMAT: &str = "rag-sync-bundle/1";
+
+/// Sync state key holding the watermark of the last export.
+const LAST_EXPORT_KEY: &str = "bundle:last_export";
+
+/// Sync state key prefix for checksums of imported bundles.
+const IMPORTED_PREFIX: &str = "bundle:imported:";
+
+/// Sync state key prefix for the latest bundle imported from each node.
+const SOURCE_PREFIX: &str = "bundle:source:";
+
+/// A self-contained set of changes exported from one node.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeBundle {
+    /// Always [`BUNDLE_FORMAT`].
+    pub format: String,
+
+    /// Node that produced the bundle.
+    pub node_id: u16,
+
+    /// Creation time (Unix millis).
+    pub created_at: u64,
+
+    /// Changes after this HLC are included (hex).
+    pub since: String,
+
+    /// Exporting node's watermark when the bundle was written (hex).
+    pub watermark: String,
+
+    /// The changes.
+    pub batch: ChangeBatch,
+
+    /// Hex-encoded BLAKE3 hash of the fields above, covering the changes
+    /// through the batch checksum.
+    pub checksum: String,
+}
+
+impl ChangeBundle {
+    /// Create a bundle of changes exported by a node.
+    pub fn new(
+        node_id: u16,
+        since: &HybridLogicalClock,
+        watermark: &HybridLogicalClock,
+        changes: &[SyncChange],
+    ) -> Result<Self> {
+        let mut bundle = Self {
+            format: BUNDLE_FORMAT.to_string(),
+            node_id,
+            created_at: SystemClock.now_millis(),
+            since: since.to_hex(),
+            watermark: watermark.to_hex(),
+            batch: ChangeBatch::new(changes)?,
+            checksum: String::new(),
+        };
+        bundle.checksum = bundle.header_checksum();
+        Ok(bundle)
+    }
+
+    /// Checksum of every field but `checksum`.
+    fn header_checksum(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        let node_id = self.node_id.to_string();
+        let created_at = self.created_at.to_string();
+        for field in [&self.format, &node_id, &created_at, &self.since, &self.watermark, &self.batch.checksum] {
+            // Length-prefixed, so fields cannot run into each other
+            hasher.update(&(field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+
+    /// Write the bundle to a file.
+    ///
+    /// The bundle is written to a temporary file in the same directory and
+    /// renamed into place, so `path` never holds a partial bundle.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+
+        let written = File::create(&temp).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer(&mut writer, self)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+            std::fs::rename(&temp, path)
+        });
+        if written.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        Ok(written?)
+    }
+
+    /// Read a bundle from a file, checking its format and checksum.
+    ///
+    /// The checksum of the changes is verified when they are decoded.
+    pub fn read(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        let bundle: Self = serde_json::from_slice(&data)
+            .map_err(|e| RagError::invalid_argument(format!("Invalid bundle {}: {}", path.display(), e)))?;
+        if bundle.format != BUNDLE_FORMAT {
+            return Err(RagError::invalid_argument(format!(
+                "Unsupported bundle format '{}' (expected '{}')",
+                bundle.format, BUNDLE_FORMAT
+            )));
+        }
+        if bundle.checksum != bundle.header_checksum() {
+            return Err(RagError::invalid_argument(format!(
+                "Bundle checksum mismatch in {}",
+                path.display()
+            )));
+        }
+        Ok(bundle)
+    }
+}
+
+/// Where the latest bundle imported from a node left off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleSource {
+    /// Node that produced the bundle.
+    pub node_id: u16,
+
+    /// Watermark recorded in the bundle.
+    pub watermark: HybridLogicalClock,
+
+    /// Import time (Unix millis).
+    pub imported_at: u64,
+}
+
+/// Result of exporting a bundle.
+#[derive(Debug, Clone)]
+pub struct ExportStats {
+    /// HLC the export started after.
+    pub since: HybridLogicalClock,
+
+    /// Watermark recorded in the bundle.
+    pub watermark: HybridLogicalClock,
+
+    /// Number of changes written.
+    pub changes: usize,
+}
+
+/// Result of importing a bundle.
+#[derive(Debug, Clone)]
+pub struct ImportStats {
+    /// Node that produced the bundle.
+    pub node_id: u16,
+
+    /// Watermark recorded in the bundle.
+    pub watermark: HybridLogicalClock,
+
+    /// Number of changes in the bundle.
+    pub changes: usize,
+
+    /// Whether the bundle had already been imported (and was skipped).
+    pub already_imported: bool,
+}
+
+/// Export every change after `since` to a bundle file.
+///
+/// Without `since`, continues from the watermark of the previous export.
+pub async fn export_bundle(store: &dyn Store, since: Option<HybridLogicalClock>, path: &Path) -> Result<ExportStats> {
+    let since = match since {
+        Some(since) => since,
+        None => last_export(store).await?.unwrap_or_else(HybridLogicalClock::zero),
+    };
+
+    // Read the watermark first so a concurrent write is exported next time
+    let watermark = store.get_watermark().await?;
+    let changes = store.get_changes_since(&since, &CollectionFilter::all()).await?;
+
+    ChangeBundle::new(store.node_id(), &since, &watermark, &changes)?.write(path)?;
+
+    if watermark > since {
+        store.set_sync_state(LAST_EXPORT_KEY, &watermark.to_bytes()).await?;
+    }
+    info!("Exported {} changes to {}", changes.len(), path.display());
+
+    Ok(ExportStats {
+        since,
+        watermark,
+        changes: changes.len(),
+    })
+}
+
+/// Apply a bundle file to the store, resolving conflicting documents with `resolver`.
+pub async fn import_bundle(
+    store: &dyn Store,
+    path: &Path,
+    resolver: &dyn ConflictResolver,
+) -> Result<ImportStats> {
+    let bundle = ChangeBundle::read(path)?;
+    let node_id = bundle.node_id;
+    let watermark = parse_hlc(&bundle.watermark)?;
+    let imported_key = format!("{}{}", IMPORTED_PREFIX, bundle.checksum);
+    let changes = bundle.batch.into_changes()?;
+
+    let mut stats = ImportStats {
+        node_id,
+        watermark,
+        changes: changes.len(),
+        already_imported: false,
+    };
+    if store.get_sync_state(&imported_key).await?.is_some() {
+        stats.already_imported = true;
+        return Ok(stats);
+    }
+
+    store.apply_changes_with(&changes, resolver).await?;
+
+    let now = SystemClock.now_millis();
+    store.set_sync_state(&imported_key, &now.to_be_bytes()).await?;
+
+    let source_key = format!("{}{}", SOURCE_PREFIX, node_id);
+    let newer = match store.get_sync_state(&source_key).await? {
+        Some(value) => serde_json::from_slice::<BundleSource>(&value)?.watermark < watermark,
+        None => true,
+    };
+    if newer {
+        let source = BundleSource {
+            node_id,
+            watermark,
+            imported_at: now,
+        };
+        store.set_sync_state(&source_key, &serde_json::to_vec(&source)?).await?;
+    }
+
+    info!("Imported {} changes from node {}", changes.len(), node_id);
+    Ok(stats)
+}
+
+/// Watermark of the last bundle exported by this node.
+pub async fn last_export(store: &dyn Store) -> Result<Option<HybridLogicalClock>> {
+    match store.get_sync_state(LAST_EXPORT_KEY).await? {
+        Some(value) => HybridLogicalClock::from_bytes(&value)
+            .map(Some)
+            .ok_or_else(|| RagError::database("Invalid last export watermark")),
+        None => Ok(None),
+    }
+}
+
+/// Latest bundle imported from each node.
+pub async fn bundle_sources(store: &dyn Store) -> Result<Vec<BundleSource>> {
+    store
+        .list_sync_state(SOURCE_PREFIX)
+        .await?
+        .into_iter()
+        .map(|(_, value)| Ok(serde_json::from_slice(&value)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rag_core::{Chunk, Collection, ContentType, Document, LastWriterWins};
+    use rag_store::SqliteStore;
+
+    async fn insert_doc(store: &SqliteStore, uri: &str, text: &str) {
+        let doc = Document::new("notes", uri, text, ContentType::PlainText);
+        let doc_id = doc.id;
+        store.insert_document(doc).await.unwrap();
+        store.insert_chunks(&[Chunk::new(doc_id, 0, text, 1, 1, 1)]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_import_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = SqliteStore::open_memory(1).unwrap();
+        let b = SqliteStore::open_memory(2).unwrap();
+
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        insert_doc(&a, "file://a.txt", "alpha").await;
+
+        let first = dir.path().join("first.bundle");
+        let stats = export_bundle(&a, None, &first).await.unwrap();
+        assert_eq!(stats.changes, 3);
+
+        let imported = import_bundle(&b, &first, &LastWriterWins).await.unwrap();
+        assert_eq!((imported.node_id, imported.changes, imported.already_imported), (1, 3, false));
+        assert_eq!(b.list_documents("notes", 10, 0).await.unwrap().len(), 1);
+
+        // Importing the same bundle again is a no-op
+        let watermark = b.get_watermark().await.unwrap();
+        assert!(import_bundle(&b, &first, &LastWriterWins).await.unwrap().already_imported);
+        assert_eq!(b.get_watermark().await.unwrap(), watermark);
+
+        // The next export only carries newer changes
+        insert_doc(&a, "file://b.txt", "beta").await;
+        let second = dir.path().join("second.bundle");
+        assert_eq!(export_bundle(&a, None, &second).await.unwrap().changes, 2);
+        import_bundle(&b, &second, &LastWriterWins).await.unwrap();
+        assert_eq!(b.list_documents("notes", 10, 0).await.unwrap().len(), 2);
+
+        let sources = bundle_sources(&b).await.unwrap();
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0].watermark, a.get_watermark().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_corrupted_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = SqliteStore::open_memory(1).unwrap();
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        insert_doc(&a, "file://a.txt", "alpha").await;
+
+        let path = dir.path().join("a.bundle");
+        export_bundle(&a, None, &path).await.unwrap();
+        let data = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, data.replace("alpha", "omega")).unwrap();
+
+        let b = SqliteStore::open_memory(2).unwrap();
+        let err = import_bundle(&b, &path, &LastWriterWins).await.unwrap_err();
+        assert!(err.to_string().contains("checksum"), "{}", err);
+        assert!(b.list_collections().await.unwrap().is_empty());
+
+        // The header is covered too
+        export_bundle(&a, Some(HybridLogicalClock::zero()), &path).await.unwrap();
+        let data = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, data.replace("\"node_id\":1,", "\"node_id\":7,")).unwrap();
+        let err = import_bundle(&b, &path, &LastWriterWins).await.unwrap_err();
+        assert!(err.to_string().contains("checksum"), "{}", err);
+        assert!(b.list_sync_state(SOURCE_PREFIX).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_export_replaces_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = SqliteStore::open_memory(1).unwrap();
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+
+        let path = dir.path().join("a.bundle");
+        std::fs::write(&path, "stale").unwrap();
+        export_bundle(&a, None, &path).await.unwrap();
+
+        assert_eq!(ChangeBundle::read(&path).unwrap().batch.count, 1);
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+    }
+}
@@ -0,0 +1,95 @@
+//! Enforcement of per-peer collection filters on incoming changes.
+//!
+//! Peers are asked to filter what they send, but a peer running an older
+//! build (or a misbehaving one) may ignore the request, so pulled changes are
+//! filtered again before they are applied.
+
+use std::collections::HashMap;
+
+use tracing::debug;
+
+use rag_core::{CollectionFilter, Result, Store, SyncChange};
+
+/// Drop changes to collections the filter excludes.
+///
+/// Chunks are attributed through their document, looked up in the batch
+/// first and then in the store. Document and chunk deletions carry no
+/// collection and are kept; applying one for a row that was never
+/// replicated only records its tombstone.
+pub async fn retain_allowed(
+    store: &dyn Store,
+    changes: Vec<SyncChange>,
+    filter: &CollectionFilter,
+) -> Result<Vec<SyncChange>> {
+    if filter.is_all() {
+        return Ok(changes);
+    }
+
+    let mut doc_collections = HashMap::new();
+    for change in &changes {
+        if let SyncChange::UpsertDocument(doc) = change {
+            doc_collections.insert(doc.id, doc.collection.clone());
+        }
+    }
+
+    let total = changes.len();
+    let mut allowed = Vec::with_capacity(total);
+    for change in changes {
+        let keep = match &change {
+            SyncChange::UpsertCollection(collection) => filter.allows(&collection.name),
+            SyncChange::DeleteCollection(name, _) => filter.allows(name),
+            SyncChange::UpsertDocument(doc) => filter.allows(&doc.collection),
+            SyncChange::UpsertChunk(chunk, _) => {
+                let collection = match doc_collections.get(&chunk.doc_id) {
+                    Some(collection) => Some(collection.clone()),
+                    None => store.get_document(chunk.doc_id).await?.map(|doc| doc.collection),
+                };
+                // Unknown documents are rejected by the store anyway
+                collection.map_or(true, |c| filter.allows(&c))
+            }
+            SyncChange::DeleteDocument(..) | SyncChange::DeleteChunk(..) => true,
+        };
+        if keep {
+            allowed.push(change);
+        }
+    }
+
+    if allowed.len() < total {
+        debug!("Filtered out {} changes to excluded collections", total - allowed.len());
+    }
+    Ok(allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rag_core::{Chunk, Collection, ContentType, Document};
+    use rag_store::SqliteStore;
+
+    #[tokio::test]
+    async fn test_retain_allowed() {
+        let store = SqliteStore::open_memory(1).unwrap();
+        store.create_collection(Collection::new("vendor-docs", None)).await.unwrap();
+        let known = Document::new("vendor-docs", "file://known", "text", ContentType::PlainText);
+        let known_id = known.id;
+        store.insert_document(known).await.unwrap();
+
+        let doc = Document::new("vendor-docs", "file://new", "text", ContentType::PlainText);
+        let doc_id = doc.id;
+        let changes = vec![
+            SyncChange::UpsertCollection(Collection::new("notes", None)),
+            SyncChange::UpsertCollection(Collection::new("vendor-docs", None)),
+            SyncChange::UpsertDocument(doc),
+            SyncChange::UpsertChunk(Chunk::new(doc_id, 0, "in batch", 2, 1, 1), Vec::new()),
+            SyncChange::UpsertChunk(Chunk::new(known_id, 0, "in store", 2, 1, 1), Vec::new()),
+        ];
+
+        let filter = CollectionFilter {
+            include: Vec::new(),
+            exclude: vec!["vendor-docs".to_string()],
+        };
+        let allowed = retain_allowed(&store, changes, &filter).await.unwrap();
+        assert_eq!(allowed.len(), 1);
+        assert!(matches!(&allowed[0], SyncChange::UpsertCollection(c) if c.name == "notes"));
+    }
+}
@@ -1,3 +1,32 @@
 //! rag-sync - Multi-node synchronization
 //!
-//! TODO: Implement sync layer
+//! This crate replicates collections, documents and chunks between nodes
+//! over HTTP. Each node runs a [`SyncServer`] exposing its change log, and
+//! talks to other nodes through [`HttpSyncPeer`], which implements
+//! [`rag_core::SyncPeer`]. A [`SyncManager`] drives pull/push rounds with
+//! every configured peer, on a schedule or on demand, and can fall back to
+//! range-digest reconciliation when watermarks are not enough. Each peer can
+//! be limited to a subset of collections, and air-gapped nodes can exchange
+//! change bundle files instead ([`bundle`]). Requests can be signed with a
+//! per-peer shared secret ([`auth`]) and served over TLS ([`tls`]). Conflicts
+//! are resolved by the store using last-writer-wins on hybrid logical clocks.
+
+pub mod auth;
+pub mod bundle;
+pub mod filter;
+pub mod manager;
+pub mod peer;
+pub mod protocol;
+pub mod reconcile;
+pub mod scheduler;
+pub mod server;
+#[cfg(test)]
+mod sim;
+pub mod tls;
+
+pub use auth::Authenticator;
+pub use manager::{load_peer_statuses, PeerStatus, SyncManager, SyncResult, SyncStats};
+pub use peer::{HttpSyncPeer, LocalSyncPeer};
+pub use reconcile::{ReconcileStats, Reconciler};
+pub use scheduler::SchedulerHandle;
+pub use server::{SyncServer, SyncServerHandle};
@@ -0,0 +1,901 @@
+//! Sync orchestration across configured peers.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use rag_core::{
+    Clock, ConflictResolver, ExcludedPolicy, HybridLogicalClock, RagError, Result, Store, SyncChange, SyncConfig,
+    SyncPeer, SystemClock,
+};
+
+use crate::filter::retain_allowed;
+use crate::peer::HttpSyncPeer;
+use crate::protocol::DEFAULT_PAGE_LIMIT;
+use crate::reconcile::Reconciler;
+use crate::scheduler::backoff_delay_ms;
+
+/// `sync_state` key prefix for per-peer status records.
+pub const PEER_STATE_PREFIX: &str = "peer:";
+
+/// Replication status for one peer, persisted in `sync_state`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeerStatus {
+    /// Peer identifier.
+    pub peer_id: String,
+
+    /// Peer endpoint URL.
+    pub endpoint: String,
+
+    /// Highest peer HLC pulled and applied locally.
+    pub pulled_watermark: HybridLogicalClock,
+
+    /// Highest local HLC pushed to the peer.
+    pub pushed_watermark: HybridLogicalClock,
+
+    /// Peer watermark reported at the last successful contact.
+    pub remote_watermark: Option<HybridLogicalClock>,
+
+    /// Last sync attempt (Unix millis).
+    pub last_attempt: Option<u64>,
+
+    /// Last successful sync (Unix millis).
+    pub last_success: Option<u64>,
+
+    /// Error from the last failed attempt, cleared on success.
+    pub last_error: Option<String>,
+
+    /// Failures since the last success.
+    pub consecutive_failures: u32,
+
+    /// Earliest time the scheduler will retry this peer (Unix millis).
+    pub next_attempt: u64,
+
+    /// Time from which the scheduler reconciles with this peer instead of
+    /// exchanging changes (Unix millis, 0 until the first success).
+    #[serde(default)]
+    pub next_reconcile: u64,
+}
+
+impl PeerStatus {
+    fn new(peer: &dyn SyncPeer) -> Self {
+        Self {
+            peer_id: peer.peer_id().to_string(),
+            endpoint: peer.endpoint().to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Milliseconds of peer history not yet pulled, based on wall time.
+    pub fn lag_ms(&self) -> Option<u64> {
+        self.remote_watermark
+            .map(|remote| remote.wall_time.saturating_sub(self.pulled_watermark.wall_time))
+    }
+
+    /// Whether the peer is currently failing.
+    pub fn is_failing(&self) -> bool {
+        self.consecutive_failures > 0
+    }
+}
+
+/// Counters for one sync round with a peer.
+#[derive(Debug, Clone, Default)]
+pub struct SyncStats {
+    /// Changes pulled from the peer.
+    pub pulled: usize,
+
+    /// Changes pushed to the peer.
+    pub pushed: usize,
+
+    /// Wall time spent in milliseconds.
+    pub duration_ms: u64,
+}
+
+/// Outcome of syncing with one peer.
+#[derive(Debug)]
+pub struct SyncResult {
+    /// Peer identifier.
+    pub peer_id: String,
+
+    /// Stats on success, error otherwise.
+    pub result: Result<SyncStats>,
+}
+
+/// Manages synchronization with multiple peers.
+pub struct SyncManager {
+    /// Local store.
+    store: Arc<dyn Store>,
+
+    /// Configured peers.
+    peers: RwLock<Vec<Arc<dyn SyncPeer>>>,
+
+    /// Sync configuration.
+    config: SyncConfig,
+
+    /// Time source for status timestamps and backoff.
+    clock: Arc<dyn Clock>,
+
+    /// Resolves documents edited concurrently here and on a peer.
+    resolver: Arc<dyn ConflictResolver>,
+
+    /// Serializes sync rounds so scheduled and manual syncs do not overlap.
+    round: Mutex<()>,
+}
+
+impl SyncManager {
+    /// Create a manager with no peers.
+    pub fn new(store: Arc<dyn Store>, config: SyncConfig) -> Self {
+        Self::with_peers(store, config, Vec::new())
+    }
+
+    /// Create a manager with the given peers.
+    pub fn with_peers(store: Arc<dyn Store>, config: SyncConfig, peers: Vec<Arc<dyn SyncPeer>>) -> Self {
+        Self {
+            store,
+            peers: RwLock::new(peers),
+            resolver: config.conflict_policy.resolver(),
+            config,
+            clock: Arc::new(SystemClock),
+            round: Mutex::new(()),
+        }
+    }
+
+    /// Create a manager with an [`HttpSyncPeer`] for every configured peer.
+    pub fn from_config(store: Arc<dyn Store>, config: SyncConfig) -> Result<Self> {
+        let peers = config
+            .peers
+            .iter()
+            .map(|peer| HttpSyncPeer::from_config(peer).map(|p| Arc::new(p) as Arc<dyn SyncPeer>))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self::with_peers(store, config, peers))
+    }
+
+    /// Use a custom clock (for tests and simulations).
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Use a custom conflict resolver instead of the configured policy.
+    pub fn with_conflict_resolver(mut self, resolver: Arc<dyn ConflictResolver>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Sync configuration.
+    pub fn config(&self) -> &SyncConfig {
+        &self.config
+    }
+
+    /// Add a sync peer.
+    pub async fn add_peer(&self, peer: Arc<dyn SyncPeer>) {
+        let mut peers = self.peers.write().await;
+        peers.retain(|p| p.peer_id() != peer.peer_id());
+        peers.push(peer);
+    }
+
+    /// Remove a sync peer.
+    pub async fn remove_peer(&self, peer_id: &str) {
+        let mut peers = self.peers.write().await;
+        peers.retain(|p| p.peer_id() != peer_id);
+    }
+
+    /// Manually sync with all peers, ignoring backoff.
+    pub async fn sync_all(&self) -> Vec<SyncResult> {
+        self.sync_peers(|_| Some(false)).await
+    }
+
+    /// Reconcile with all peers by comparing range digests, ignoring backoff.
+    ///
+    /// Slower than [`sync_all`](Self::sync_all) but does not rely on
+    /// watermarks, so it repairs divergence they cannot see.
+    pub async fn reconcile_all(&self) -> Vec<SyncResult> {
+        self.sync_peers(|_| Some(true)).await
+    }
+
+    /// Sync with peers whose backoff has expired, reconciling with those
+    /// due for it every `SyncConfig.reconcile_interval_secs`.
+    pub async fn sync_due(&self) -> Vec<SyncResult> {
+        let now = self.clock.now_millis();
+        let mut due = Vec::new();
+        for peer in self.peers.read().await.iter() {
+            match self.peer_status(peer.as_ref()).await {
+                Ok(status) if status.next_attempt > now => {
+                    debug!("Skipping {} until {}", peer.peer_id(), status.next_attempt);
+                }
+                Ok(status) => {
+                    let reconcile = self.config.reconcile_interval_secs > 0
+                        && status.next_reconcile > 0
+                        && status.next_reconcile <= now;
+                    due.push((peer.peer_id().to_string(), reconcile));
+                }
+                Err(_) => due.push((peer.peer_id().to_string(), false)),
+            }
+        }
+
+        self.sync_peers(|id| due.iter().find(|(d, _)| d == id).map(|(_, full)| *full)).await
+    }
+
+    /// Sync with a specific peer, ignoring backoff.
+    pub async fn sync_with(&self, peer_id: &str) -> Result<SyncStats> {
+        self.sync_one(peer_id, false).await
+    }
+
+    /// Reconcile with a specific peer, ignoring backoff.
+    pub async fn reconcile_with(&self, peer_id: &str) -> Result<SyncStats> {
+        self.sync_one(peer_id, true).await
+    }
+
+    async fn sync_one(&self, peer_id: &str, full: bool) -> Result<SyncStats> {
+        let mut results = self.sync_peers(|id| (id == peer_id).then_some(full)).await;
+        match results.pop() {
+            Some(result) => result.result,
+            None => Err(RagError::sync(format!("Unknown peer: {}", peer_id))),
+        }
+    }
+
+    /// Status of every configured peer.
+    pub async fn status(&self) -> Result<Vec<PeerStatus>> {
+        let mut statuses = Vec::new();
+        for peer in self.peers.read().await.iter() {
+            statuses.push(self.peer_status(peer.as_ref()).await?);
+        }
+        Ok(statuses)
+    }
+
+    /// Sync with the peers `select` returns a mode for: `Some(true)` to
+    /// reconcile, `Some(false)` to exchange changes.
+    async fn sync_peers(&self, select: impl Fn(&str) -> Option<bool>) -> Vec<SyncResult> {
+        let _round = self.round.lock().await;
+        if let Err(e) = self.purge_excluded().await {
+            warn!("Failed to purge excluded collections: {}", e);
+        }
+
+        let peers: Vec<_> = self
+            .peers
+            .read()
+            .await
+            .iter()
+            .filter_map(|p| select(p.peer_id()).map(|full| (p.clone(), full)))
+            .collect();
+
+        let mut results = Vec::with_capacity(peers.len());
+        for (peer, full) in peers {
+            let result = self.sync_peer(peer.as_ref(), full).await;
+            results.push(SyncResult {
+                peer_id: peer.peer_id().to_string(),
+                result,
+            });
+        }
+        results
+    }
+
+    /// Run one round with a peer and record the outcome.
+    async fn sync_peer(&self, peer: &dyn SyncPeer, full: bool) -> Result<SyncStats> {
+        let mut status = self.peer_status(peer).await?;
+        let started = Instant::now();
+        let now = self.clock.now_millis();
+        status.endpoint = peer.endpoint().to_string();
+        status.last_attempt = Some(now);
+
+        let result = if full {
+            self.reconcile(peer, &mut status).await
+        } else {
+            self.exchange(peer, &mut status).await
+        };
+        let interval_ms = self.config.interval_secs.saturating_mul(1000);
+
+        match &result {
+            Ok(stats) => {
+                info!(
+                    "Synced with {}: pulled {}, pushed {}",
+                    status.peer_id, stats.pulled, stats.pushed
+                );
+                status.last_success = Some(now);
+                status.last_error = None;
+                status.consecutive_failures = 0;
+                status.next_attempt = now.saturating_add(interval_ms);
+                if full || status.next_reconcile == 0 {
+                    let reconcile_ms = self.config.reconcile_interval_secs.saturating_mul(1000);
+                    status.next_reconcile = now.saturating_add(reconcile_ms);
+                }
+            }
+            Err(e) => {
+                status.consecutive_failures = status.consecutive_failures.saturating_add(1);
+                let delay = backoff_delay_ms(
+                    interval_ms,
+                    self.config.max_backoff_secs.saturating_mul(1000),
+                    status.consecutive_failures,
+                );
+                warn!(
+                    "Sync with {} failed ({} in a row), retrying in {}s: {}",
+                    status.peer_id,
+                    status.consecutive_failures,
+                    delay / 1000,
+                    e
+                );
+                status.last_error = Some(e.to_string());
+                status.next_attempt = now.saturating_add(delay);
+            }
+        }
+
+        self.save_status(&status).await?;
+
+        result.map(|mut stats| {
+            stats.duration_ms = started.elapsed().as_millis() as u64;
+            stats
+        })
+    }
+
+    /// Refuse to sync with a peer that issues HLCs with this node's ID,
+    /// typically a copy of this database.
+    fn check_node_id(&self, peer: &dyn SyncPeer) -> Result<()> {
+        let node_id = self.store.node_id();
+        if peer.node_id() == Some(node_id) {
+            return Err(RagError::sync(format!(
+                "Peer {} uses the same node ID {} as this node; run `rag node reset-id` on one of them",
+                peer.peer_id(),
+                node_id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Pull then push, advancing the watermarks in `status` as each step completes.
+    async fn exchange(&self, peer: &dyn SyncPeer, status: &mut PeerStatus) -> Result<SyncStats> {
+        let mut stats = SyncStats::default();
+
+        let remote = peer.get_watermark().await?;
+        self.check_node_id(peer)?;
+        status.remote_watermark = Some(remote);
+
+        // Apply and record each page as it arrives, so an interrupted pull
+        // resumes where it stopped
+        let mut cursor = status.pulled_watermark;
+        loop {
+            let page = peer.pull_page(&cursor).await?;
+            let pulled = retain_allowed(self.store.as_ref(), page.changes, peer.collections()).await?;
+            if !pulled.is_empty() {
+                self.store.apply_changes_with(&pulled, self.resolver.as_ref()).await?;
+            }
+            stats.pulled += pulled.len();
+
+            let watermark = if page.has_more { page.watermark } else { page.watermark.max(remote) };
+            status.pulled_watermark = status.pulled_watermark.max(watermark);
+            self.save_status(status).await?;
+
+            if !page.has_more {
+                break;
+            }
+            if page.watermark <= cursor {
+                return Err(RagError::sync(format!("Peer {} did not advance its change cursor", peer.peer_id())));
+            }
+            cursor = page.watermark;
+        }
+
+        // Push in pages too, leaving out what the peer wrote itself
+        let mut cursor = status.pushed_watermark;
+        loop {
+            let (page, has_more) = self
+                .store
+                .get_changes_page(&cursor, peer.collections(), DEFAULT_PAGE_LIMIT)
+                .await?;
+            let Some(last) = page.last().map(SyncChange::hlc) else {
+                break;
+            };
+            let local: Vec<SyncChange> = page
+                .into_iter()
+                .filter(|change| Some(change.hlc().node_id) != peer.node_id())
+                .collect();
+            if !local.is_empty() {
+                peer.push_changes(&local).await?;
+            }
+            stats.pushed += local.len();
+            status.pushed_watermark = status.pushed_watermark.max(last);
+            self.save_status(status).await?;
+
+            if !has_more {
+                break;
+            }
+            cursor = last;
+        }
+
+        Ok(stats)
+    }
+
+    /// Reconcile by range digests, then fast-forward both watermarks.
+    async fn reconcile(&self, peer: &dyn SyncPeer, status: &mut PeerStatus) -> Result<SyncStats> {
+        let remote = peer.get_watermark().await?;
+        self.check_node_id(peer)?;
+        let local = self.store.get_watermark().await?;
+        status.remote_watermark = Some(remote);
+
+        let reconciled = Reconciler::new()
+            .with_conflict_resolver(self.resolver.clone())
+            .reconcile(self.store.as_ref(), peer)
+            .await?;
+
+        // Everything either side held before reconciling is now on both
+        status.pulled_watermark = status.pulled_watermark.max(remote);
+        status.pushed_watermark = status.pushed_watermark.max(local);
+
+        Ok(SyncStats {
+            pulled: reconciled.pulled,
+            pushed: reconciled.pushed,
+            duration_ms: 0,
+        })
+    }
+
+    /// Purge local copies of collections that every configured peer excludes,
+    /// if at least one of those peers uses [`ExcludedPolicy::Purge`].
+    ///
+    /// Purging records no tombstone, so other nodes keep their copies.
+    async fn purge_excluded(&self) -> Result<()> {
+        let peers = &self.config.peers;
+        if !peers.iter().any(|p| p.on_excluded == ExcludedPolicy::Purge) {
+            return Ok(());
+        }
+
+        for collection in self.store.list_collections().await? {
+            let excluded = peers.iter().all(|p| !p.collections.allows(&collection.name));
+            let purge = peers
+                .iter()
+                .any(|p| p.on_excluded == ExcludedPolicy::Purge && !p.collections.allows(&collection.name));
+            if excluded && purge {
+                self.store.purge_collection(&collection.name).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn peer_status(&self, peer: &dyn SyncPeer) -> Result<PeerStatus> {
+        Ok(load_peer_status(self.store.as_ref(), peer.peer_id())
+            .await?
+            .unwrap_or_else(|| PeerStatus::new(peer)))
+    }
+
+    async fn save_status(&self, status: &PeerStatus) -> Result<()> {
+        let key = format!("{}{}", PEER_STATE_PREFIX, status.peer_id);
+        let value = serde_json::to_vec(status)?;
+        self.store.set_sync_state(&key, &value).await
+    }
+}
+
+/// Load the persisted status for one peer.
+pub async fn load_peer_status(store: &dyn Store, peer_id: &str) -> Result<Option<PeerStatus>> {
+    let key = format!("{}{}", PEER_STATE_PREFIX, peer_id);
+    match store.get_sync_state(&key).await? {
+        Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+        None => Ok(None),
+    }
+}
+
+/// Load the persisted status of every peer this node has synced with.
+///
+/// Works without a running [`SyncManager`], e.g. from the CLI.
+pub async fn load_peer_statuses(store: &dyn Store) -> Result<Vec<PeerStatus>> {
+    store
+        .list_sync_state(PEER_STATE_PREFIX)
+        .await?
+        .into_iter()
+        .map(|(_, value)| Ok(serde_json::from_slice(&value)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::peer::LocalSyncPeer;
+    use rag_core::{
+        ChangePage, Collection, CollectionFilter, ContentType, Document, KeyRange, ManualClock, PeerConfig,
+        RangeDigest, SyncItem,
+    };
+    use rag_store::SqliteStore;
+
+    /// In-process peer that can be taken offline.
+    struct StorePeer {
+        inner: LocalSyncPeer,
+        store: Arc<SqliteStore>,
+        down: AtomicBool,
+        /// Changes per pulled page, if paged.
+        page_limit: Option<usize>,
+        /// Pages served before going offline.
+        pages_left: AtomicUsize,
+    }
+
+    impl StorePeer {
+        fn new(id: &str, store: Arc<SqliteStore>) -> Self {
+            Self {
+                inner: LocalSyncPeer::new(id, store.clone()),
+                store,
+                down: AtomicBool::new(false),
+                page_limit: None,
+                pages_left: AtomicUsize::new(usize::MAX),
+            }
+        }
+
+        fn with_page_limit(mut self, page_limit: usize) -> Self {
+            self.page_limit = Some(page_limit);
+            self
+        }
+
+        fn with_collections(mut self, collections: CollectionFilter) -> Self {
+            self.inner = self.inner.with_collections(collections);
+            self
+        }
+
+        fn check(&self) -> Result<()> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(RagError::sync("connection refused"));
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl SyncPeer for StorePeer {
+        fn peer_id(&self) -> &str {
+            self.inner.peer_id()
+        }
+
+        fn endpoint(&self) -> &str {
+            "memory://"
+        }
+
+        fn collections(&self) -> &CollectionFilter {
+            self.inner.collections()
+        }
+
+        fn node_id(&self) -> Option<u16> {
+            self.inner.node_id()
+        }
+
+        async fn get_watermark(&self) -> Result<HybridLogicalClock> {
+            self.check()?;
+            self.inner.get_watermark().await
+        }
+
+        async fn pull_changes(&self, since: &HybridLogicalClock) -> Result<Vec<SyncChange>> {
+            self.check()?;
+            self.inner.pull_changes(since).await
+        }
+
+        async fn pull_page(&self, since: &HybridLogicalClock) -> Result<ChangePage> {
+            self.check()?;
+            let Some(limit) = self.page_limit else {
+                return self.inner.pull_page(since).await;
+            };
+            if self.pages_left.fetch_sub(1, Ordering::SeqCst) == 0 {
+                self.down.store(true, Ordering::SeqCst);
+                return Err(RagError::sync("connection reset"));
+            }
+
+            let (changes, has_more) = self.store.get_changes_page(since, self.collections(), limit).await?;
+            let watermark = match changes.last() {
+                Some(last) if has_more => last.hlc(),
+                _ => self.store.get_watermark().await?.max(*since),
+            };
+            Ok(ChangePage {
+                changes,
+                watermark,
+                has_more,
+            })
+        }
+
+        async fn push_changes(&self, changes: &[SyncChange]) -> Result<()> {
+            self.check()?;
+            self.inner.push_changes(changes).await
+        }
+
+        async fn range_digests(&self, ranges: &[KeyRange]) -> Result<Vec<RangeDigest>> {
+            self.check()?;
+            self.inner.range_digests(ranges).await
+        }
+
+        async fn range_items(&self, range: &KeyRange) -> Result<Vec<SyncItem>> {
+            self.check()?;
+            self.inner.range_items(range).await
+        }
+
+        async fn fetch_changes(&self, keys: &[String]) -> Result<Vec<SyncChange>> {
+            self.check()?;
+            self.inner.fetch_changes(keys).await
+        }
+    }
+
+    fn config() -> SyncConfig {
+        SyncConfig {
+            interval_secs: 10,
+            max_backoff_secs: 60,
+            ..Default::default()
+        }
+    }
+
+    async fn names(store: &SqliteStore) -> Vec<String> {
+        let mut names: Vec<_> = store
+            .list_collections()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_sync_all_exchanges_and_persists_watermarks() {
+        let local = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let remote = Arc::new(SqliteStore::open_memory(2).unwrap());
+        local.create_collection(Collection::new("a", None)).await.unwrap();
+        remote.create_collection(Collection::new("b", None)).await.unwrap();
+
+        let manager = SyncManager::new(local.clone(), config());
+        manager.add_peer(Arc::new(StorePeer::new("remote", remote.clone()))).await;
+
+        let results = manager.sync_all().await;
+        let stats = results[0].result.as_ref().unwrap();
+        assert_eq!(stats.pulled, 1);
+        assert!(stats.pushed >= 1);
+        assert_eq!(names(&local).await, names(&remote).await);
+
+        let status = load_peer_status(local.as_ref(), "remote").await.unwrap().unwrap();
+        assert_eq!(status.pulled_watermark, remote.get_watermark().await.unwrap());
+        assert!(status.pushed_watermark > HybridLogicalClock::zero());
+        assert_eq!(status.lag_ms(), Some(0));
+        assert!(status.last_success.is_some());
+
+        // A second round has nothing new to pull.
+        let stats = manager.sync_with("remote").await.unwrap();
+        assert_eq!(stats.pulled, 0);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_pull_resumes() {
+        let local = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let remote = Arc::new(SqliteStore::open_memory(2).unwrap());
+        for name in ["a", "b", "c", "d", "e"] {
+            remote.create_collection(Collection::new(name, None)).await.unwrap();
+        }
+        let peer = Arc::new(StorePeer::new("remote", remote.clone()).with_page_limit(2));
+        peer.pages_left.store(1, Ordering::SeqCst);
+
+        let manager = SyncManager::new(local.clone(), config());
+        manager.add_peer(peer.clone()).await;
+
+        // The first page is applied and recorded before the connection drops
+        assert!(manager.sync_with("remote").await.is_err());
+        assert_eq!(names(&local).await, ["a", "b"]);
+        let status = load_peer_status(local.as_ref(), "remote").await.unwrap().unwrap();
+        let second = remote.get_collection("b").await.unwrap().unwrap().hlc;
+        assert_eq!(status.pulled_watermark, second);
+
+        peer.pages_left.store(usize::MAX, Ordering::SeqCst);
+        peer.down.store(false, Ordering::SeqCst);
+        let stats = manager.sync_with("remote").await.unwrap();
+        assert_eq!(stats.pulled, 3);
+        assert_eq!(names(&local).await, names(&remote).await);
+    }
+
+    #[tokio::test]
+    async fn test_pulled_changes_are_not_pushed_back() {
+        let local = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let remote = Arc::new(SqliteStore::open_memory(2).unwrap());
+        local.create_collection(Collection::new("a", None)).await.unwrap();
+        remote.create_collection(Collection::new("b", None)).await.unwrap();
+        remote.create_collection(Collection::new("c", None)).await.unwrap();
+
+        let manager = SyncManager::new(local.clone(), config());
+        manager.add_peer(Arc::new(StorePeer::new("remote", remote.clone()))).await;
+
+        let stats = manager.sync_with("remote").await.unwrap();
+        assert_eq!((stats.pulled, stats.pushed), (2, 1));
+        let stats = manager.sync_with("remote").await.unwrap();
+        assert_eq!((stats.pulled, stats.pushed), (0, 0));
+        assert_eq!(names(&local).await, names(&remote).await);
+    }
+
+    #[tokio::test]
+    async fn test_failing_peer_backs_off() {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let local = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let remote = Arc::new(SqliteStore::open_memory(2).unwrap());
+        let peer = Arc::new(StorePeer::new("remote", remote));
+        peer.down.store(true, Ordering::SeqCst);
+
+        let manager = SyncManager::new(local.clone(), config()).with_clock(clock.clone());
+        manager.add_peer(peer.clone()).await;
+
+        assert!(manager.sync_due().await[0].result.is_err());
+        let status = &manager.status().await.unwrap()[0];
+        assert_eq!(status.consecutive_failures, 1);
+        assert_eq!(status.next_attempt, 1_000_000 + 20_000);
+        assert!(status.last_error.as_deref().unwrap().contains("connection refused"));
+
+        // Still backing off.
+        clock.advance(10_000);
+        assert!(manager.sync_due().await.is_empty());
+
+        clock.advance(10_000);
+        assert!(manager.sync_due().await[0].result.is_err());
+        assert_eq!(manager.status().await.unwrap()[0].next_attempt, 1_020_000 + 40_000);
+
+        // Capped by max_backoff_secs.
+        clock.advance(40_000);
+        manager.sync_due().await;
+        assert_eq!(manager.status().await.unwrap()[0].next_attempt, 1_060_000 + 60_000);
+
+        // Manual sync ignores backoff and a success resets it.
+        peer.down.store(false, Ordering::SeqCst);
+        manager.sync_all().await[0].result.as_ref().unwrap();
+        let status = &load_peer_statuses(local.as_ref()).await.unwrap()[0];
+        assert_eq!(status.consecutive_failures, 0);
+        assert!(status.last_error.is_none());
+        assert_eq!(status.next_attempt, 1_060_000 + 10_000);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_repairs_missed_changes() {
+        let local = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let remote = Arc::new(SqliteStore::open_memory(2).unwrap());
+        let manager = SyncManager::new(local.clone(), config());
+        manager.add_peer(Arc::new(StorePeer::new("remote", remote.clone()))).await;
+
+        remote.create_collection(Collection::new("b", None)).await.unwrap();
+        manager.sync_all().await[0].result.as_ref().unwrap();
+
+        // A change relayed from a third node with an HLC below the cursor
+        let mut relayed = Collection::new("relayed", None);
+        relayed.hlc = HybridLogicalClock::from_parts(1, 0, 3);
+        remote.apply_changes(&[SyncChange::UpsertCollection(relayed)]).await.unwrap();
+
+        assert_eq!(manager.sync_with("remote").await.unwrap().pulled, 0);
+        assert_eq!(manager.reconcile_with("remote").await.unwrap().pulled, 1);
+        assert_eq!(names(&local).await, names(&remote).await);
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_reconciles_periodically() {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let local = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let remote = Arc::new(SqliteStore::open_memory(2).unwrap());
+        let config = SyncConfig {
+            reconcile_interval_secs: 100,
+            ..config()
+        };
+        let manager = SyncManager::new(local.clone(), config).with_clock(clock.clone());
+        manager.add_peer(Arc::new(StorePeer::new("remote", remote.clone()))).await;
+
+        remote.create_collection(Collection::new("b", None)).await.unwrap();
+        manager.sync_due().await[0].result.as_ref().unwrap();
+        assert_eq!(manager.status().await.unwrap()[0].next_reconcile, 1_000_000 + 100_000);
+
+        let mut relayed = Collection::new("relayed", None);
+        relayed.hlc = HybridLogicalClock::from_parts(1, 0, 3);
+        remote.apply_changes(&[SyncChange::UpsertCollection(relayed)]).await.unwrap();
+
+        // Rounds exchange changes until the reconcile is due
+        clock.advance(10_000);
+        assert_eq!(manager.sync_due().await[0].result.as_ref().unwrap().pulled, 0);
+        clock.advance(90_000);
+        assert_eq!(manager.sync_due().await[0].result.as_ref().unwrap().pulled, 1);
+        assert_eq!(names(&local).await, names(&remote).await);
+        assert_eq!(manager.status().await.unwrap()[0].next_reconcile, 1_100_000 + 100_000);
+    }
+
+    #[tokio::test]
+    async fn test_node_id_collision_is_refused() {
+        let local = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let clone = Arc::new(SqliteStore::open_memory(1).unwrap());
+        clone.create_collection(Collection::new("b", None)).await.unwrap();
+
+        let manager = SyncManager::new(local.clone(), config());
+        manager.add_peer(Arc::new(StorePeer::new("clone", clone.clone()))).await;
+
+        let err = manager.sync_with("clone").await.unwrap_err();
+        assert!(err.to_string().contains("reset-id"), "{}", err);
+        assert!(manager.reconcile_with("clone").await.is_err());
+        assert!(names(&local).await.is_empty());
+
+        // Re-keying the clone lets it sync
+        clone.reset_node_id().unwrap();
+        manager.sync_with("clone").await.unwrap();
+        assert_eq!(names(&local).await, names(&clone).await);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_peer() {
+        let local = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let manager = SyncManager::new(local, config());
+        assert!(manager.sync_with("nobody").await.is_err());
+    }
+
+    fn exclude(name: &str) -> CollectionFilter {
+        CollectionFilter {
+            include: Vec::new(),
+            exclude: vec![name.to_string()],
+        }
+    }
+
+    async fn add_doc(store: &SqliteStore, collection: &str, uri: &str) {
+        let doc = Document::new(collection, uri, "text", ContentType::PlainText);
+        store.insert_document(doc).await.unwrap();
+    }
+
+    async fn doc_count(store: &SqliteStore, collection: &str) -> usize {
+        store.list_documents(collection, 1000, 0).await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn test_collection_filter_limits_push_and_pull() {
+        let local = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let remote = Arc::new(SqliteStore::open_memory(2).unwrap());
+        local.create_collection(Collection::new("notes", None)).await.unwrap();
+        local.create_collection(Collection::new("vendor-docs", None)).await.unwrap();
+        add_doc(&local, "vendor-docs", "file://local").await;
+        remote.create_collection(Collection::new("archive", None)).await.unwrap();
+        remote.create_collection(Collection::new("vendor-docs", None)).await.unwrap();
+        add_doc(&remote, "vendor-docs", "file://remote").await;
+
+        let peer = StorePeer::new("remote", remote.clone()).with_collections(exclude("vendor-docs"));
+        let manager = SyncManager::new(local.clone(), config());
+        manager.add_peer(Arc::new(peer)).await;
+
+        manager.sync_all().await[0].result.as_ref().unwrap();
+        assert_eq!(names(&local).await, vec!["archive", "notes", "vendor-docs"]);
+        assert_eq!(names(&remote).await, vec!["archive", "notes", "vendor-docs"]);
+        assert_eq!(doc_count(&local, "vendor-docs").await, 1);
+        assert_eq!(doc_count(&remote, "vendor-docs").await, 1);
+
+        // Reconciliation compares only the allowed collections
+        let stats = manager.reconcile_with("remote").await.unwrap();
+        assert_eq!((stats.pulled, stats.pushed), (0, 0));
+        assert_eq!(doc_count(&local, "vendor-docs").await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_excluded_collection_is_purged_by_policy() {
+        let local = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let remote = Arc::new(SqliteStore::open_memory(2).unwrap());
+        remote.create_collection(Collection::new("notes", None)).await.unwrap();
+        remote.create_collection(Collection::new("vendor-docs", None)).await.unwrap();
+        add_doc(&remote, "vendor-docs", "file://remote").await;
+
+        // Replicate everything first
+        let manager = SyncManager::new(local.clone(), config());
+        manager.add_peer(Arc::new(StorePeer::new("remote", remote.clone()))).await;
+        manager.sync_all().await[0].result.as_ref().unwrap();
+        assert_eq!(doc_count(&local, "vendor-docs").await, 1);
+
+        // Stop keeps the local copy
+        let mut config = config();
+        config.peers = vec![PeerConfig {
+            id: "remote".to_string(),
+            endpoint: "memory://".to_string(),
+            collections: exclude("vendor-docs"),
+            on_excluded: ExcludedPolicy::Stop,
+            secret: None,
+            ca_cert: None,
+        }];
+        let peer = Arc::new(StorePeer::new("remote", remote.clone()).with_collections(exclude("vendor-docs")));
+        let manager = SyncManager::with_peers(local.clone(), config.clone(), vec![peer.clone()]);
+        manager.sync_all().await[0].result.as_ref().unwrap();
+        assert_eq!(names(&local).await, vec!["notes", "vendor-docs"]);
+
+        // Purge removes it locally without deleting it on the peer
+        config.peers[0].on_excluded = ExcludedPolicy::Purge;
+        let manager = SyncManager::with_peers(local.clone(), config, vec![peer]);
+        manager.sync_all().await[0].result.as_ref().unwrap();
+        manager.reconcile_all().await[0].result.as_ref().unwrap();
+        assert_eq!(names(&local).await, vec!["notes"]);
+        assert_eq!(names(&remote).await, vec!["notes", "vendor-docs"]);
+        assert_eq!(doc_count(&remote, "vendor-docs").await, 1);
+    }
+}
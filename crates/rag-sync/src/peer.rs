@@ -0,0 +1,590 @@
+//! HTTP client for a remote sync peer.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{Certificate, Client, Method, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::debug;
+
+use rag_core::{
+    ChangePage, Clock, CollectionFilter, HybridLogicalClock, KeyRange, PeerConfig, RagError, RangeDigest, Result,
+    Store, SyncChange, SyncItem, SyncPeer, SystemClock,
+};
+
+use crate::auth::{self, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use crate::protocol::{
+    parse_hlc, ChangeBatch, ChangesResponse, DigestsRequest, DigestsResponse, ErrorResponse, FetchRequest,
+    FetchResponse, ItemsRequest, ItemsResponse, PushChangesRequest, PushChangesResponse,
+    WatermarkResponse, DEFAULT_PAGE_LIMIT,
+};
+
+/// Default request timeout.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// [`SyncPeer`] that talks to a [`SyncServer`](crate::SyncServer) over HTTP.
+pub struct HttpSyncPeer {
+    peer_id: String,
+    endpoint: String,
+    client: Client,
+    page_limit: usize,
+    collections: CollectionFilter,
+    secret: Option<Vec<u8>>,
+    node_id: Mutex<Option<u16>>,
+}
+
+fn build_client(root_certificate: Option<Certificate>) -> Result<Client> {
+    let mut builder = Client::builder().timeout(DEFAULT_TIMEOUT);
+    if let Some(certificate) = root_certificate {
+        builder = builder.add_root_certificate(certificate);
+    }
+    builder
+        .build()
+        .map_err(|e| RagError::sync(format!("Failed to build HTTP client: {}", e)))
+}
+
+impl HttpSyncPeer {
+    /// Create a peer client for the given endpoint (e.g. `http://host:7890`).
+    pub fn new(peer_id: impl Into<String>, endpoint: impl Into<String>) -> Result<Self> {
+        Ok(Self {
+            peer_id: peer_id.into(),
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            client: build_client(None)?,
+            page_limit: DEFAULT_PAGE_LIMIT,
+            collections: CollectionFilter::all(),
+            secret: None,
+            node_id: Mutex::new(None),
+        })
+    }
+
+    /// Create a peer client from configuration.
+    pub fn from_config(config: &PeerConfig) -> Result<Self> {
+        let mut peer = Self::new(&config.id, &config.endpoint)?.with_collections(config.collections.clone());
+        if let Some(secret) = &config.secret {
+            peer = peer.with_secret(secret);
+        }
+        if let Some(path) = &config.ca_cert {
+            let pem = std::fs::read(path).map_err(|e| RagError::Config {
+                message: format!("Failed to read CA certificate {}: {}", path.display(), e),
+            })?;
+            peer = peer.with_root_certificate(&pem)?;
+        }
+        Ok(peer)
+    }
+
+    /// Sign every request with a secret shared with the peer.
+    pub fn with_secret(mut self, secret: impl AsRef<[u8]>) -> Self {
+        self.secret = Some(secret.as_ref().to_vec());
+        self
+    }
+
+    /// Trust a PEM certificate (such as the peer's self-signed one) for TLS.
+    pub fn with_root_certificate(mut self, pem: &[u8]) -> Result<Self> {
+        let certificate = Certificate::from_pem(pem).map_err(|e| RagError::Config {
+            message: format!("Invalid CA certificate: {}", e),
+        })?;
+        self.client = build_client(Some(certificate))?;
+        Ok(self)
+    }
+
+    /// Only replicate the collections the filter allows.
+    pub fn with_collections(mut self, collections: CollectionFilter) -> Self {
+        self.collections = collections;
+        self
+    }
+
+    /// Set the number of changes requested and pushed per round trip.
+    pub fn with_page_limit(mut self, page_limit: usize) -> Self {
+        self.page_limit = page_limit.max(1);
+        self
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T> {
+        self.send(Method::GET, path, query, Vec::new()).await
+    }
+
+    async fn post<T: DeserializeOwned>(&self, path: &str, body: &impl Serialize) -> Result<T> {
+        self.send(Method::POST, path, &[], serde_json::to_vec(body)?).await
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Vec<u8>,
+    ) -> Result<T> {
+        let url = Url::parse_with_params(&format!("{}{}", self.endpoint, path), query)
+            .map_err(|e| RagError::sync(format!("Invalid URL for peer {}: {}", self.peer_id, e)))?;
+
+        let mut request = self.client.request(method.clone(), url.clone());
+        if let Some(secret) = &self.secret {
+            // Sign exactly what the server sees as the request target
+            let target = match url.query() {
+                Some(q) => format!("{}?{}", url.path(), q),
+                None => url.path().to_string(),
+            };
+            let timestamp = SystemClock.now_millis();
+            let nonce = auth::new_nonce();
+            let signature = auth::sign(secret, method.as_str(), &target, timestamp, &nonce, &body);
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(NONCE_HEADER, nonce)
+                .header(SIGNATURE_HEADER, signature);
+        }
+        if !body.is_empty() {
+            request = request.header(reqwest::header::CONTENT_TYPE, "application/json").body(body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| RagError::sync(format!("Request to peer {} failed: {}", self.peer_id, e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = match response.json::<ErrorResponse>().await {
+                Ok(body) => format!("{}: {}", body.code, body.message),
+                Err(_) => status.to_string(),
+            };
+            return Err(RagError::sync(format!("Peer {} returned {}: {}", self.peer_id, status, message)));
+        }
+
+        response
+            .json::<T>()
+            .await
+            .map_err(|e| RagError::sync(format!("Invalid response from peer {}: {}", self.peer_id, e)))
+    }
+}
+
+#[async_trait]
+impl SyncPeer for HttpSyncPeer {
+    fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    fn collections(&self) -> &CollectionFilter {
+        &self.collections
+    }
+
+    fn node_id(&self) -> Option<u16> {
+        *self.node_id.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn get_watermark(&self) -> Result<HybridLogicalClock> {
+        let response: WatermarkResponse = self.get("/sync/watermark", &[]).await?;
+        *self.node_id.lock().unwrap_or_else(|e| e.into_inner()) = Some(response.node_id);
+        parse_hlc(&response.hlc)
+    }
+
+    async fn pull_changes(&self, since: &HybridLogicalClock) -> Result<Vec<SyncChange>> {
+        let mut cursor = *since;
+        let mut changes = Vec::new();
+
+        loop {
+            let page = self.pull_page(&cursor).await?;
+            changes.extend(page.changes);
+
+            if !page.has_more {
+                break;
+            }
+            if page.watermark <= cursor {
+                return Err(RagError::sync(format!("Peer {} did not advance its change cursor", self.peer_id)));
+            }
+            cursor = page.watermark;
+        }
+
+        Ok(changes)
+    }
+
+    async fn pull_page(&self, since: &HybridLogicalClock) -> Result<ChangePage> {
+        let mut query = vec![("since", since.to_hex()), ("limit", self.page_limit.to_string())];
+        if !self.collections.include.is_empty() {
+            query.push(("include", self.collections.include.join(",")));
+        }
+        if !self.collections.exclude.is_empty() {
+            query.push(("exclude", self.collections.exclude.join(",")));
+        }
+        let page: ChangesResponse = self.get("/sync/changes", &query).await?;
+        let watermark = parse_hlc(&page.watermark)?;
+        let has_more = page.has_more;
+        let changes = page.batch.into_changes()?;
+
+        debug!("Pulled {} changes from {} (has_more: {})", changes.len(), self.peer_id, has_more);
+        Ok(ChangePage {
+            changes,
+            watermark,
+            has_more,
+        })
+    }
+
+    async fn push_changes(&self, changes: &[SyncChange]) -> Result<()> {
+        for batch in changes.chunks(self.page_limit) {
+            let source_watermark = batch
+                .iter()
+                .map(SyncChange::hlc)
+                .max()
+                .unwrap_or_else(HybridLogicalClock::zero);
+            let request = PushChangesRequest {
+                batch: ChangeBatch::new(batch)?,
+                source_watermark: source_watermark.to_hex(),
+            };
+
+            let response: PushChangesResponse = self.post("/sync/changes", &request).await?;
+            if !response.accepted {
+                return Err(RagError::sync(format!("Peer {} rejected pushed changes", self.peer_id)));
+            }
+
+            debug!("Pushed {} changes to {}", batch.len(), self.peer_id);
+        }
+
+        Ok(())
+    }
+
+    async fn range_digests(&self, ranges: &[KeyRange]) -> Result<Vec<RangeDigest>> {
+        let request = DigestsRequest {
+            ranges: ranges.to_vec(),
+            collections: self.collections.clone(),
+        };
+        let response: DigestsResponse = self.post("/sync/digests", &request).await?;
+        if response.digests.len() != ranges.len() {
+            return Err(RagError::sync(format!(
+                "Peer {} returned {} digests for {} ranges",
+                self.peer_id,
+                response.digests.len(),
+                ranges.len()
+            )));
+        }
+        Ok(response.digests)
+    }
+
+    async fn range_items(&self, range: &KeyRange) -> Result<Vec<SyncItem>> {
+        let request = ItemsRequest {
+            range: range.clone(),
+            collections: self.collections.clone(),
+        };
+        let response: ItemsResponse = self.post("/sync/items", &request).await?;
+        Ok(response.items)
+    }
+
+    async fn fetch_changes(&self, keys: &[String]) -> Result<Vec<SyncChange>> {
+        let mut changes = Vec::with_capacity(keys.len());
+        for batch in keys.chunks(self.page_limit) {
+            let request = FetchRequest {
+                keys: batch.to_vec(),
+            };
+            let response: FetchResponse = self.post("/sync/fetch", &request).await?;
+            changes.extend(response.batch.into_changes()?);
+        }
+        Ok(changes)
+    }
+}
+
+/// [`SyncPeer`] backed by a store in the same process.
+///
+/// Useful for tests and simulations that exercise sync without HTTP.
+pub struct LocalSyncPeer {
+    peer_id: String,
+    store: Arc<dyn Store>,
+    collections: CollectionFilter,
+}
+
+impl LocalSyncPeer {
+    /// Create a peer for the given store.
+    pub fn new(peer_id: impl Into<String>, store: Arc<dyn Store>) -> Self {
+        Self {
+            peer_id: peer_id.into(),
+            store,
+            collections: CollectionFilter::all(),
+        }
+    }
+
+    /// Only replicate the collections the filter allows.
+    pub fn with_collections(mut self, collections: CollectionFilter) -> Self {
+        self.collections = collections;
+        self
+    }
+}
+
+#[async_trait]
+impl SyncPeer for LocalSyncPeer {
+    fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    fn endpoint(&self) -> &str {
+        "local"
+    }
+
+    fn node_id(&self) -> Option<u16> {
+        Some(self.store.node_id())
+    }
+
+    fn collections(&self) -> &CollectionFilter {
+        &self.collections
+    }
+
+    async fn get_watermark(&self) -> Result<HybridLogicalClock> {
+        self.store.get_watermark().await
+    }
+
+    async fn pull_changes(&self, since: &HybridLogicalClock) -> Result<Vec<SyncChange>> {
+        self.store.get_changes_since(since, &self.collections).await
+    }
+
+    async fn push_changes(&self, changes: &[SyncChange]) -> Result<()> {
+        self.store.apply_changes(changes).await
+    }
+
+    async fn range_digests(&self, ranges: &[KeyRange]) -> Result<Vec<RangeDigest>> {
+        let mut digests = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            digests.push(self.store.range_digest(range, &self.collections).await?);
+        }
+        Ok(digests)
+    }
+
+    async fn range_items(&self, range: &KeyRange) -> Result<Vec<SyncItem>> {
+        self.store.sync_items(range, &self.collections).await
+    }
+
+    async fn fetch_changes(&self, keys: &[String]) -> Result<Vec<SyncChange>> {
+        self.store.get_changes_for_keys(keys).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SyncServer;
+    use rag_core::{Chunk, Collection, ContentType, Document, Store};
+    use rag_store::SqliteStore;
+
+    async fn insert_doc(store: &SqliteStore, collection: &str, uri: &str, chunks: &[&str]) {
+        let doc = Document::new(collection, uri, &chunks.join("\n"), ContentType::PlainText);
+        let doc_id = doc.id;
+        store.insert_document(doc).await.unwrap();
+        let chunks: Vec<_> = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, c)| Chunk::new(doc_id, i as u32, c, 1, i as u32 + 1, i as u32 + 1))
+            .collect();
+        store.insert_chunks(&chunks).await.unwrap();
+    }
+
+    async fn snapshot(store: &SqliteStore) -> Vec<String> {
+        let mut rows = Vec::new();
+        for coll in store.list_collections().await.unwrap() {
+            rows.push(format!("collection {} {}", coll.name, coll.hlc));
+            for doc in store.list_documents(&coll.name, 1000, 0).await.unwrap() {
+                rows.push(format!("document {} {}", doc.id, doc.hlc));
+                for chunk in store.get_chunks_for_document(doc.id).await.unwrap() {
+                    rows.push(format!("chunk {} {} {}", chunk.id, chunk.content, chunk.hlc));
+                }
+            }
+        }
+        rows.sort();
+        rows
+    }
+
+    #[tokio::test]
+    async fn test_http_sync_roundtrip() {
+        let a = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let b = Arc::new(SqliteStore::open_memory(2).unwrap());
+
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        insert_doc(&a, "notes", "file://a.txt", &["alpha", "beta", "gamma"]).await;
+        b.create_collection(Collection::new("code", None)).await.unwrap();
+        insert_doc(&b, "code", "file://b.rs", &["fn main() {}"]).await;
+
+        let server_a = SyncServer::new(a.clone()).spawn("127.0.0.1:0").await.unwrap();
+        let server_b = SyncServer::new(b.clone()).spawn("127.0.0.1:0").await.unwrap();
+
+        // Small pages exercise the pagination loop in both directions.
+        let peer_a = HttpSyncPeer::new("a", server_a.endpoint()).unwrap().with_page_limit(2);
+        let peer_b = HttpSyncPeer::new("b", server_b.endpoint()).unwrap().with_page_limit(2);
+
+        let zero = HybridLogicalClock::zero();
+        let from_a = peer_a.pull_changes(&zero).await.unwrap();
+        assert_eq!(from_a.len(), 5);
+        b.apply_changes(&from_a).await.unwrap();
+
+        let from_b = b.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap();
+        peer_a.push_changes(&from_b).await.unwrap();
+
+        assert_eq!(snapshot(&a).await, snapshot(&b).await);
+        assert_eq!(peer_a.get_watermark().await.unwrap(), a.get_watermark().await.unwrap());
+        assert_eq!(peer_b.get_watermark().await.unwrap(), b.get_watermark().await.unwrap());
+
+        // Nothing new after the exchange.
+        let watermark = peer_a.get_watermark().await.unwrap();
+        assert!(peer_a.pull_changes(&watermark).await.unwrap().is_empty());
+
+        server_a.shutdown().await;
+        server_b.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_http_sync_rejects_bad_since() {
+        let store = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let server = SyncServer::new(store).spawn("127.0.0.1:0").await.unwrap();
+
+        let response = reqwest::get(format!("{}/sync/changes?since=zz", server.endpoint()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        server.shutdown().await;
+    }
+
+    fn secured_server(store: Arc<SqliteStore>) -> SyncServer {
+        let auth = crate::Authenticator::new([
+            ("a".to_string(), "secret-a".to_string()),
+            ("b".to_string(), "secret-b".to_string()),
+        ]);
+        SyncServer::new(store).with_auth(auth)
+    }
+
+    #[tokio::test]
+    async fn test_http_sync_requires_valid_signature() {
+        let store = Arc::new(SqliteStore::open_memory(1).unwrap());
+        store.create_collection(Collection::new("notes", None)).await.unwrap();
+        insert_doc(&store, "notes", "file://a.txt", &["alpha"]).await;
+        let server = secured_server(store.clone()).spawn("127.0.0.1:0").await.unwrap();
+
+        let zero = HybridLogicalClock::zero();
+        let peer = HttpSyncPeer::new("a", server.endpoint()).unwrap().with_secret("secret-a");
+        assert_eq!(peer.pull_changes(&zero).await.unwrap().len(), 3);
+        peer.push_changes(&store.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap())
+            .await
+            .unwrap();
+
+        for peer in [
+            HttpSyncPeer::new("a", server.endpoint()).unwrap(),
+            HttpSyncPeer::new("a", server.endpoint()).unwrap().with_secret("wrong"),
+        ] {
+            let err = peer.pull_changes(&zero).await.unwrap_err();
+            assert!(err.to_string().contains("UNAUTHORIZED"), "{}", err);
+        }
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_http_sync_enforces_peer_filter() {
+        let store = Arc::new(SqliteStore::open_memory(1).unwrap());
+        for name in ["notes", "vendor-docs"] {
+            store.create_collection(Collection::new(name, None)).await.unwrap();
+            insert_doc(&store, name, &format!("file://{}.txt", name), &["alpha"]).await;
+        }
+        let filter = CollectionFilter {
+            include: Vec::new(),
+            exclude: vec!["vendor-docs".to_string()],
+        };
+        let server = secured_server(store.clone())
+            .with_peer_filter("a", filter)
+            .spawn("127.0.0.1:0")
+            .await
+            .unwrap();
+
+        // The client asks for everything, but only gets what its peer entry allows
+        let zero = HybridLogicalClock::zero();
+        let peer = HttpSyncPeer::new("a", server.endpoint()).unwrap().with_secret("secret-a");
+        let pulled = peer.pull_changes(&zero).await.unwrap();
+        assert_eq!(pulled.len(), 3);
+        assert!(pulled.iter().all(|change| !format!("{:?}", change).contains("vendor-docs")));
+
+        let other = Arc::new(SqliteStore::open_memory(2).unwrap());
+        other.create_collection(Collection::new("vendor-docs", None)).await.unwrap();
+        insert_doc(&other, "vendor-docs", "file://pushed.txt", &["beta"]).await;
+        peer.push_changes(&other.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap())
+            .await
+            .unwrap();
+        assert!(store.get_document_by_uri("file://pushed.txt").await.unwrap().is_none());
+
+        // Other peers are not restricted
+        let peer = HttpSyncPeer::new("b", server.endpoint()).unwrap().with_secret("secret-b");
+        assert_eq!(peer.pull_changes(&zero).await.unwrap().len(), 6);
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_http_sync_rejects_replayed_and_tampered_requests() {
+        let store = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let server = secured_server(store).spawn("127.0.0.1:0").await.unwrap();
+
+        let body = serde_json::to_vec(&PushChangesRequest {
+            batch: ChangeBatch::new(&[]).unwrap(),
+            source_watermark: HybridLogicalClock::zero().to_hex(),
+        })
+        .unwrap();
+        let timestamp = SystemClock.now_millis();
+        let signature = auth::sign(b"secret-b", "POST", "/sync/changes", timestamp, "n1", &body);
+        let send = |body: Vec<u8>| {
+            reqwest::Client::new()
+                .post(format!("{}/sync/changes", server.endpoint()))
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(NONCE_HEADER, "n1")
+                .header(SIGNATURE_HEADER, signature.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body)
+                .send()
+        };
+
+        let mut tampered = body.clone();
+        tampered.pop();
+        assert_eq!(send(tampered).await.unwrap().status(), reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(send(body.clone()).await.unwrap().status(), reqwest::StatusCode::OK);
+        assert_eq!(send(body).await.unwrap().status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_https_sync_with_generated_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert, key) = crate::tls::write_self_signed(dir.path(), &["localhost".to_string()]).unwrap();
+
+        let store = Arc::new(SqliteStore::open_memory(1).unwrap());
+        store.create_collection(Collection::new("notes", None)).await.unwrap();
+        let server = secured_server(store)
+            .with_tls(crate::tls::server_config(&cert, &key).unwrap())
+            .spawn("127.0.0.1:0")
+            .await
+            .unwrap();
+        let endpoint = format!("https://localhost:{}", server.local_addr().port());
+        assert!(server.endpoint().starts_with("https://"));
+
+        let peer = HttpSyncPeer::from_config(&PeerConfig {
+            id: "b".to_string(),
+            endpoint: endpoint.clone(),
+            collections: CollectionFilter::all(),
+            on_excluded: Default::default(),
+            secret: Some("secret-b".to_string()),
+            ca_cert: Some(cert),
+        })
+        .unwrap();
+        let changes = peer.pull_changes(&HybridLogicalClock::zero()).await.unwrap();
+        assert_eq!(changes.len(), 1);
+
+        // A client that does not trust the certificate cannot connect
+        let untrusted = HttpSyncPeer::new("b", endpoint).unwrap().with_secret("secret-b");
+        assert!(untrusted.get_watermark().await.is_err());
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_peer() {
+        let peer = HttpSyncPeer::new("gone", "http://127.0.0.1:1").unwrap();
+        let err = peer.get_watermark().await.unwrap_err();
+        assert_eq!(err.error_code(), "SYNC_ERROR");
+    }
+}
@@ -0,0 +1,277 @@
+//! Wire types for the sync HTTP API.
+//!
+//! Change sets are encoded as JSON. HLCs travel as hex strings so that they
+//! compare lexicographically in the same order as the clocks themselves.
+//! Every change list travels as a [`ChangeBatch`] with a checksum over its
+//! exact bytes, so a batch cut short or altered in transit is rejected
+//! before anything is applied.
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use rag_core::{
+    CollectionFilter, HybridLogicalClock, KeyRange, RagError, RangeDigest, Result, SyncChange,
+    SyncItem,
+};
+
+/// Default number of changes per page.
+pub const DEFAULT_PAGE_LIMIT: usize = 1000;
+
+/// Maximum number of changes a client may request per page.
+pub const MAX_PAGE_LIMIT: usize = 10_000;
+
+/// A list of changes with its length and a BLAKE3 checksum of its JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeBatch {
+    /// JSON array of [`SyncChange`]s, kept verbatim for checksumming.
+    pub changes: Box<RawValue>,
+
+    /// Number of changes in the batch.
+    pub count: usize,
+
+    /// Hex-encoded BLAKE3 hash of `changes`.
+    pub checksum: String,
+}
+
+impl ChangeBatch {
+    /// Serialize changes into a batch.
+    pub fn new(changes: &[SyncChange]) -> Result<Self> {
+        let json = serde_json::to_string(changes)?;
+        let checksum = blake3::hash(json.as_bytes()).to_hex().to_string();
+        Ok(Self {
+            changes: RawValue::from_string(json)?,
+            count: changes.len(),
+            checksum,
+        })
+    }
+
+    /// Verify the checksum and count, then deserialize the changes.
+    pub fn into_changes(self) -> Result<Vec<SyncChange>> {
+        let actual = blake3::hash(self.changes.get().as_bytes()).to_hex();
+        if actual.as_str() != self.checksum {
+            return Err(RagError::invalid_argument("Change batch checksum mismatch"));
+        }
+
+        let changes: Vec<SyncChange> = serde_json::from_str(self.changes.get())?;
+        if changes.len() != self.count {
+            return Err(RagError::invalid_argument(format!(
+                "Change batch holds {} changes, expected {}",
+                changes.len(),
+                self.count
+            )));
+        }
+        Ok(changes)
+    }
+}
+
+/// Response for `GET /sync/watermark`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatermarkResponse {
+    /// Highest HLC stored on the node (hex-encoded).
+    pub hlc: String,
+
+    /// Node ID of the responding node.
+    pub node_id: u16,
+}
+
+/// Query parameters for `GET /sync/changes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangesQuery {
+    /// Return changes with an HLC strictly greater than this (hex-encoded).
+    pub since: String,
+
+    /// Maximum number of changes to return.
+    pub limit: Option<usize>,
+
+    /// Comma-separated collections to include (default: all).
+    pub include: Option<String>,
+
+    /// Comma-separated collections to exclude.
+    pub exclude: Option<String>,
+}
+
+impl ChangesQuery {
+    /// Collection filter encoded in the query.
+    pub fn collections(&self) -> CollectionFilter {
+        let split = |list: &Option<String>| -> Vec<String> {
+            list.as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        CollectionFilter {
+            include: split(&self.include),
+            exclude: split(&self.exclude),
+        }
+    }
+}
+
+/// Response for `GET /sync/changes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangesResponse {
+    /// Changes in HLC order.
+    pub batch: ChangeBatch,
+
+    /// Cursor for the next page, or the node's watermark on the last page.
+    pub watermark: String,
+
+    /// Whether more changes are available after `watermark`.
+    pub has_more: bool,
+}
+
+/// Request body for `POST /sync/changes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushChangesRequest {
+    /// Changes to apply.
+    pub batch: ChangeBatch,
+
+    /// Watermark of the pushing node (hex-encoded).
+    pub source_watermark: String,
+}
+
+/// Response for `POST /sync/changes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushChangesResponse {
+    /// Whether the changes were applied.
+    pub accepted: bool,
+
+    /// Watermark of the receiving node after applying (hex-encoded).
+    pub new_watermark: String,
+}
+
+/// Request body for `POST /sync/digests`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestsRequest {
+    /// Key ranges to digest.
+    pub ranges: Vec<KeyRange>,
+
+    /// Collections to include in the digests.
+    #[serde(default)]
+    pub collections: CollectionFilter,
+}
+
+/// Response for `POST /sync/digests`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestsResponse {
+    /// One digest per requested range, in request order.
+    pub digests: Vec<RangeDigest>,
+}
+
+/// Request body for `POST /sync/items`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemsRequest {
+    /// Key range to list.
+    pub range: KeyRange,
+
+    /// Collections to include in the listing.
+    #[serde(default)]
+    pub collections: CollectionFilter,
+}
+
+/// Response for `POST /sync/items`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemsResponse {
+    /// Row versions in key order.
+    pub items: Vec<SyncItem>,
+}
+
+/// Request body for `POST /sync/fetch`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchRequest {
+    /// Sync keys to fetch.
+    pub keys: Vec<String>,
+}
+
+/// Response for `POST /sync/fetch`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchResponse {
+    /// Current version of each key that exists on the node.
+    pub batch: ChangeBatch,
+}
+
+/// Error body returned with non-2xx responses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// Machine-readable error code (see [`RagError::error_code`]).
+    pub code: String,
+
+    /// Human-readable message.
+    pub message: String,
+}
+
+/// Parse a hex-encoded HLC from the wire.
+pub fn parse_hlc(s: &str) -> Result<HybridLogicalClock> {
+    HybridLogicalClock::from_hex(s)
+        .ok_or_else(|| RagError::invalid_argument(format!("Invalid HLC: {}", s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rag_core::Collection;
+
+    fn delete(wall_time: u64) -> SyncChange {
+        SyncChange::DeleteCollection(
+            format!("c{}", wall_time),
+            HybridLogicalClock::from_parts(wall_time, 0, 1),
+        )
+    }
+
+    #[test]
+    fn test_change_batch_roundtrip() {
+        let batch = ChangeBatch::new(&[delete(1), delete(2)]).unwrap();
+        let json = serde_json::to_string(&batch).unwrap();
+        let parsed: ChangeBatch = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.into_changes().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_change_batch_rejects_truncation() {
+        let full = ChangeBatch::new(&[delete(1), delete(2)]).unwrap();
+        let partial = ChangeBatch::new(&[delete(1)]).unwrap();
+
+        // Changes cut short but still valid JSON
+        let truncated = ChangeBatch {
+            changes: partial.changes.clone(),
+            ..full.clone()
+        };
+        let err = truncated.into_changes().unwrap_err();
+        assert_eq!(err.error_code(), "INVALID_ARGUMENT");
+
+        // Consistent checksum but a missing change
+        let miscounted = ChangeBatch {
+            count: 2,
+            ..partial
+        };
+        assert!(miscounted.into_changes().is_err());
+    }
+
+    #[test]
+    fn test_changes_query_collections() {
+        let query = ChangesQuery {
+            since: HybridLogicalClock::zero().to_hex(),
+            limit: None,
+            include: None,
+            exclude: Some("vendor-docs,,archive".to_string()),
+        };
+        let filter = query.collections();
+        assert!(filter.include.is_empty());
+        assert_eq!(filter.exclude, vec!["vendor-docs", "archive"]);
+    }
+
+    #[test]
+    fn test_change_roundtrip() {
+        let mut collection = Collection::new("notes", Some("Notes"));
+        collection.hlc = HybridLogicalClock::from_parts(42, 1, 7);
+        let changes = vec![SyncChange::UpsertCollection(collection), delete(43)];
+
+        let json = serde_json::to_string(&changes).unwrap();
+        let parsed: Vec<SyncChange> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].hlc(), HybridLogicalClock::from_parts(42, 1, 7));
+        assert!(matches!(&parsed[1], SyncChange::DeleteCollection(name, _) if name == "c43"));
+    }
+}
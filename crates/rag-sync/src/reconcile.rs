@@ -0,0 +1,422 @@
+//! Anti-entropy reconciliation using range digests.
+//!
+//! Watermark pulls only see rows written after the cursor, so they miss rows
+//! a peer received late from a third node, and cannot recover after a long
+//! partition. Reconciliation instead compares digests of key ranges with the
+//! peer, recursively splitting ranges that differ until they are small enough
+//! to compare row by row, then transfers only the rows that differ.
+//!
+//! Applying one side's rows can cascade away rows the other side just sent
+//! (e.g. a collection delete removing documents written concurrently), so a
+//! reconciliation repeats passes until one transfers nothing.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use tracing::{debug, info};
+
+use rag_core::{
+    ConflictResolver, KeyRange, LastWriterWins, RangeDigest, Result, Store, SyncItem, SyncPeer,
+};
+
+use crate::filter::retain_allowed;
+
+/// Default number of rows below which a differing range is compared row by row.
+pub const DEFAULT_LEAF_SIZE: usize = 128;
+
+/// Default number of sub-ranges a differing range is split into.
+pub const DEFAULT_FANOUT: usize = 16;
+
+/// Default maximum number of passes per reconciliation.
+pub const DEFAULT_MAX_PASSES: usize = 4;
+
+/// Counters for one reconciliation with a peer.
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileStats {
+    /// Ranges whose digests were compared.
+    pub ranges_compared: usize,
+
+    /// Ranges whose digests differed.
+    pub ranges_differing: usize,
+
+    /// Rows fetched from the peer.
+    pub pulled: usize,
+
+    /// Rows sent to the peer.
+    pub pushed: usize,
+
+    /// Passes run before the replicas stopped differing.
+    pub passes: usize,
+}
+
+/// Range-digest reconciler.
+#[derive(Clone)]
+pub struct Reconciler {
+    leaf_size: usize,
+    fanout: usize,
+    max_passes: usize,
+    resolver: Arc<dyn ConflictResolver>,
+}
+
+impl Default for Reconciler {
+    fn default() -> Self {
+        Self {
+            leaf_size: DEFAULT_LEAF_SIZE,
+            fanout: DEFAULT_FANOUT,
+            max_passes: DEFAULT_MAX_PASSES,
+            resolver: Arc::new(LastWriterWins),
+        }
+    }
+}
+
+impl Reconciler {
+    /// Create a reconciler with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the range size at which rows are compared directly.
+    pub fn with_leaf_size(mut self, leaf_size: usize) -> Self {
+        self.leaf_size = leaf_size.max(1);
+        self
+    }
+
+    /// Set the number of sub-ranges per split.
+    pub fn with_fanout(mut self, fanout: usize) -> Self {
+        self.fanout = fanout.max(2);
+        self
+    }
+
+    /// Set the maximum number of passes.
+    pub fn with_max_passes(mut self, max_passes: usize) -> Self {
+        self.max_passes = max_passes.max(1);
+        self
+    }
+
+    /// Resolve conflicting documents with `resolver` (last writer wins by default).
+    pub fn with_conflict_resolver(mut self, resolver: Arc<dyn ConflictResolver>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Bring `store` and `peer` to the same state in both directions.
+    pub async fn reconcile(&self, store: &dyn Store, peer: &dyn SyncPeer) -> Result<ReconcileStats> {
+        let mut stats = ReconcileStats::default();
+        while stats.passes < self.max_passes {
+            stats.passes += 1;
+            let (pulled, pushed) = self.reconcile_pass(store, peer, &mut stats).await?;
+            stats.pulled += pulled;
+            stats.pushed += pushed;
+            if pulled == 0 && pushed == 0 {
+                break;
+            }
+        }
+
+        info!(
+            "Reconciled with {} in {} passes: {} of {} ranges differed, pulled {}, pushed {}",
+            peer.peer_id(),
+            stats.passes,
+            stats.ranges_differing,
+            stats.ranges_compared,
+            stats.pulled,
+            stats.pushed
+        );
+
+        Ok(stats)
+    }
+
+    /// Compare digests once and transfer the differing rows.
+    ///
+    /// Returns the number of rows pulled and pushed.
+    async fn reconcile_pass(
+        &self,
+        store: &dyn Store,
+        peer: &dyn SyncPeer,
+        stats: &mut ReconcileStats,
+    ) -> Result<(usize, usize)> {
+        let mut to_pull = Vec::new();
+        let mut to_push = Vec::new();
+        let mut pending = vec![KeyRange::full()];
+
+        while !pending.is_empty() {
+            let remote_digests = peer.range_digests(&pending).await?;
+            let mut next = Vec::new();
+
+            for (range, remote) in pending.iter().zip(remote_digests) {
+                stats.ranges_compared += 1;
+                let local_items = store.sync_items(range, peer.collections()).await?;
+                if RangeDigest::from_items(&local_items) == remote {
+                    continue;
+                }
+                stats.ranges_differing += 1;
+
+                if local_items.len() <= self.leaf_size || remote.count as usize <= self.leaf_size {
+                    let remote_items = peer.range_items(range).await?;
+                    diff_items(&local_items, remote_items, &mut to_pull, &mut to_push);
+                } else {
+                    next.extend(split_range(range, &local_items, self.fanout));
+                }
+            }
+
+            debug!("Reconciliation with {}: {} ranges to split", peer.peer_id(), next.len());
+            pending = next;
+        }
+
+        // Key order puts collections before documents before chunks
+        to_pull.sort();
+        to_push.sort();
+
+        let mut pulled = 0;
+        if !to_pull.is_empty() {
+            let changes = peer.fetch_changes(&to_pull).await?;
+            let changes = retain_allowed(store, changes, peer.collections()).await?;
+            pulled = changes.len();
+            store.apply_changes_with(&changes, self.resolver.as_ref()).await?;
+        }
+
+        let mut pushed = 0;
+        if !to_push.is_empty() {
+            let changes = store.get_changes_for_keys(&to_push).await?;
+            pushed = changes.len();
+            peer.push_changes(&changes).await?;
+        }
+
+        Ok((pulled, pushed))
+    }
+}
+
+/// Compare row versions and collect the keys each side is missing or has stale.
+fn diff_items(
+    local: &[SyncItem],
+    remote: Vec<SyncItem>,
+    to_pull: &mut Vec<String>,
+    to_push: &mut Vec<String>,
+) {
+    let mut remote: BTreeMap<String, SyncItem> =
+        remote.into_iter().map(|item| (item.key.clone(), item)).collect();
+
+    for item in local {
+        match remote.remove(&item.key) {
+            Some(theirs) if theirs.hlc > item.hlc => to_pull.push(item.key.clone()),
+            Some(theirs) if theirs.hlc < item.hlc => to_push.push(item.key.clone()),
+            Some(_) => {}
+            None => to_push.push(item.key.clone()),
+        }
+    }
+
+    to_pull.extend(remote.into_keys());
+}
+
+/// Split a range at evenly spaced local keys.
+///
+/// Boundaries are taken from the local rows, so every sub-range holds fewer
+/// local rows than the parent and the recursion terminates.
+fn split_range(range: &KeyRange, items: &[SyncItem], fanout: usize) -> Vec<KeyRange> {
+    let mut bounds: Vec<&str> = (1..fanout)
+        .map(|i| items[i * items.len() / fanout].key.as_str())
+        .collect();
+    bounds.dedup();
+
+    let mut ranges = Vec::with_capacity(bounds.len() + 1);
+    let mut start = range.start.clone();
+    for bound in bounds {
+        ranges.push(KeyRange::new(start, Some(bound.to_string())));
+        start = bound.to_string();
+    }
+    ranges.push(KeyRange::new(start, range.end.clone()));
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::peer::LocalSyncPeer;
+    use rag_core::{
+        Chunk, Collection, CollectionFilter, ContentType, Document, HybridLogicalClock, ManualClock,
+    };
+    use rag_store::SqliteStore;
+
+    fn item(key: &str, wall_time: u64) -> SyncItem {
+        SyncItem {
+            key: key.to_string(),
+            hlc: HybridLogicalClock::from_parts(wall_time, 0, 1),
+            deleted: false,
+            content_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_items() {
+        let local = vec![item("c/a", 1), item("c/b", 5), item("c/c", 1)];
+        let remote = vec![item("c/a", 2), item("c/b", 3), item("c/d", 1)];
+
+        let (mut pull, mut push) = (Vec::new(), Vec::new());
+        diff_items(&local, remote, &mut pull, &mut push);
+
+        assert_eq!(pull, vec!["c/a", "c/d"]);
+        assert_eq!(push, vec!["c/b", "c/c"]);
+    }
+
+    #[test]
+    fn test_split_range_covers_parent() {
+        let items: Vec<_> = (0..10).map(|i| item(&format!("k/{:02}", i), 1)).collect();
+        let range = KeyRange::new("k/", Some("l".to_string()));
+
+        let ranges = split_range(&range, &items, 4);
+        assert_eq!(ranges.first().unwrap().start, "k/");
+        assert_eq!(ranges.last().unwrap().end.as_deref(), Some("l"));
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end.as_deref(), Some(pair[1].start.as_str()));
+        }
+        for item in &items {
+            assert_eq!(ranges.iter().filter(|r| r.contains(&item.key)).count(), 1);
+        }
+    }
+
+    async fn snapshot(store: &SqliteStore) -> Vec<String> {
+        let mut rows = Vec::new();
+        for coll in store.list_collections().await.unwrap() {
+            rows.push(format!("collection {} {}", coll.name, coll.hlc));
+            for doc in store.list_documents(&coll.name, 10_000, 0).await.unwrap() {
+                rows.push(format!("document {} {}", doc.id, doc.hlc));
+                for chunk in store.get_chunks_for_document(doc.id).await.unwrap() {
+                    rows.push(format!("chunk {} {} {}", chunk.id, chunk.content, chunk.hlc));
+                }
+            }
+        }
+        rows.sort();
+        rows
+    }
+
+    /// Apply a random mix of writes and deletes to a store.
+    async fn mutate(store: &SqliteStore, clock: &ManualClock, rng: &mut StdRng, ops: usize) {
+        for _ in 0..ops {
+            clock.advance(1);
+            let collections = store.list_collections().await.unwrap();
+            match rng.gen_range(0..10) {
+                0 => {
+                    let name = format!("coll{}", rng.gen_range(0..4));
+                    let _ = store.create_collection(Collection::new(&name, None)).await;
+                }
+                1 if !collections.is_empty() && rng.gen_bool(0.3) => {
+                    let coll = &collections[rng.gen_range(0..collections.len())];
+                    store.delete_collection(&coll.name).await.unwrap();
+                }
+                2 | 3 => {
+                    let docs = match collections.first() {
+                        Some(coll) => store.list_documents(&coll.name, 10_000, 0).await.unwrap(),
+                        None => Vec::new(),
+                    };
+                    if let Some(doc) = docs.get(rng.gen_range(0..docs.len().max(1))) {
+                        store.delete_document(doc.id).await.unwrap();
+                    }
+                }
+                _ if !collections.is_empty() => {
+                    let coll = &collections[rng.gen_range(0..collections.len())];
+                    let n: u32 = rng.gen();
+                    let doc = Document::new(&coll.name, &format!("file://{}", n), "text", ContentType::PlainText);
+                    let doc_id = doc.id;
+                    store.insert_document(doc).await.unwrap();
+                    let chunks: Vec<_> = (0..rng.gen_range(1..4))
+                        .map(|i| Chunk::new(doc_id, i, &format!("chunk {} {}", n, i), 2, i + 1, i + 1))
+                        .collect();
+                    store.insert_chunks(&chunks).await.unwrap();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Exchange every change in both directions (reference result).
+    async fn full_exchange(a: &SqliteStore, b: &SqliteStore) {
+        let zero = HybridLogicalClock::zero();
+        let from_a = a.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap();
+        let from_b = b.get_changes_since(&zero, &CollectionFilter::all()).await.unwrap();
+        b.apply_changes(&from_a).await.unwrap();
+        a.apply_changes(&from_b).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_randomized_divergent_replicas() {
+        for seed in 0..20u64 {
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            let clock = Arc::new(ManualClock::new(1_000_000));
+            let a = Arc::new(SqliteStore::open_memory_with_clock(1, clock.clone()).unwrap());
+            let b = Arc::new(SqliteStore::open_memory_with_clock(2, clock.clone()).unwrap());
+            let ref_a = SqliteStore::open_memory_with_clock(3, clock.clone()).unwrap();
+            let ref_b = SqliteStore::open_memory_with_clock(4, clock.clone()).unwrap();
+
+            // Shared history, then independent divergence on each side
+            mutate(&a, &clock, &mut rng, 40).await;
+            full_exchange(&a, &b).await;
+            let (ops_a, ops_b) = (rng.gen_range(0..40), rng.gen_range(0..40));
+            mutate(&a, &clock, &mut rng, ops_a).await;
+            mutate(&b, &clock, &mut rng, ops_b).await;
+
+            // Reference replicas converge by exchanging complete change logs
+            let all = CollectionFilter::all();
+            let zero = HybridLogicalClock::zero();
+            ref_a.apply_changes(&a.get_changes_since(&zero, &all).await.unwrap()).await.unwrap();
+            ref_b.apply_changes(&b.get_changes_since(&zero, &all).await.unwrap()).await.unwrap();
+            full_exchange(&ref_a, &ref_b).await;
+            full_exchange(&ref_a, &ref_b).await;
+            assert_eq!(snapshot(&ref_a).await, snapshot(&ref_b).await, "seed {}", seed);
+
+            let peer = LocalSyncPeer::new("b", b.clone());
+            let reconciler = Reconciler::new().with_leaf_size(4).with_fanout(4);
+            reconciler.reconcile(a.as_ref(), &peer).await.unwrap();
+
+            assert_eq!(snapshot(&a).await, snapshot(&b).await, "seed {}", seed);
+            assert_eq!(snapshot(&a).await, snapshot(&ref_a).await, "seed {}", seed);
+            assert_eq!(
+                a.range_digest(&KeyRange::full(), &all).await.unwrap(),
+                b.range_digest(&KeyRange::full(), &all).await.unwrap(),
+                "seed {}",
+                seed
+            );
+
+            // Nothing left to transfer once converged
+            let stats = reconciler.reconcile(a.as_ref(), &peer).await.unwrap();
+            assert_eq!((stats.pulled, stats.pushed, stats.ranges_differing), (0, 0, 0), "seed {}", seed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_transfers_only_differing_ranges() {
+        let a = Arc::new(SqliteStore::open_memory(1).unwrap());
+        let b = Arc::new(SqliteStore::open_memory(2).unwrap());
+        a.create_collection(Collection::new("notes", None)).await.unwrap();
+        for i in 0..50 {
+            let doc = Document::new("notes", &format!("file://{}", i), "text", ContentType::PlainText);
+            a.insert_document(doc).await.unwrap();
+        }
+        full_exchange(&a, &b).await;
+
+        // One new document that a watermark pull from `b` would never see
+        let late = Document::new("notes", "file://late", "text", ContentType::PlainText);
+        b.apply_changes(&[rag_core::SyncChange::UpsertDocument(Document {
+            hlc: HybridLogicalClock::from_parts(1, 0, 9),
+            ..late
+        })])
+        .await
+        .unwrap();
+
+        let peer = LocalSyncPeer::new("b", b.clone());
+        let stats = Reconciler::new()
+            .with_leaf_size(4)
+            .with_fanout(4)
+            .reconcile(a.as_ref(), &peer)
+            .await
+            .unwrap();
+
+        assert_eq!(stats.pulled, 1);
+        assert_eq!(stats.pushed, 0);
+        assert_eq!(snapshot(&a).await, snapshot(&b).await);
+    }
+}
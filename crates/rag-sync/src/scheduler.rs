@@ -0,0 +1,92 @@
+//! Background sync scheduling.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+use crate::manager::SyncManager;
+
+/// Delay before retrying a peer after `failures` consecutive failures.
+///
+/// Doubles the interval per failure, capped at `max_ms`.
+pub fn backoff_delay_ms(interval_ms: u64, max_ms: u64, failures: u32) -> u64 {
+    let factor = 1u64.checked_shl(failures.min(63)).unwrap_or(u64::MAX);
+    interval_ms.saturating_mul(factor).min(max_ms.max(interval_ms))
+}
+
+/// Handle to a running background sync loop.
+pub struct SchedulerHandle {
+    shutdown: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl SchedulerHandle {
+    /// Stop the loop after the current round finishes.
+    pub async fn stop(mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for SchedulerHandle {
+    fn drop(&mut self) {
+        if self.shutdown.is_some() {
+            self.task.abort();
+        }
+    }
+}
+
+/// Start syncing with due peers every `SyncConfig.interval_secs`.
+///
+/// Peers in backoff are skipped until their retry time passes, and each
+/// peer is reconciled by range digest every
+/// `SyncConfig.reconcile_interval_secs`.
+pub fn start(manager: Arc<SyncManager>) -> SchedulerHandle {
+    let interval = Duration::from_secs(manager.config().interval_secs.max(1));
+    let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+
+    let task = tokio::spawn(async move {
+        info!("Background sync every {:?}", interval);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = &mut shutdown_rx => break,
+                _ = ticker.tick() => {
+                    let results = manager.sync_due().await;
+                    debug!("Background sync round touched {} peers", results.len());
+                }
+            }
+        }
+    });
+
+    SchedulerHandle {
+        shutdown: Some(shutdown_tx),
+        task,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay() {
+        assert_eq!(backoff_delay_ms(1000, 60_000, 0), 1000);
+        assert_eq!(backoff_delay_ms(1000, 60_000, 1), 2000);
+        assert_eq!(backoff_delay_ms(1000, 60_000, 3), 8000);
+        assert_eq!(backoff_delay_ms(1000, 60_000, 10), 60_000);
+        assert_eq!(backoff_delay_ms(1000, 60_000, u32::MAX), 60_000);
+    }
+
+    #[test]
+    fn test_backoff_never_below_interval() {
+        assert_eq!(backoff_delay_ms(120_000, 60_000, 1), 120_000);
+    }
+}
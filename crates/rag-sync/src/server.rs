@@ -0,0 +1,379 @@
+//! HTTP server exposing a store's change log to peers.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, Query, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use rag_core::{CollectionFilter, ConflictResolver, LastWriterWins, RagError, Result, Store, SyncConfig};
+
+use crate::auth::{Authenticator, SignedRequest, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use crate::protocol::{
+    parse_hlc, ChangeBatch, ChangesQuery, ChangesResponse, DigestsRequest,
+    DigestsResponse, ErrorResponse, FetchRequest, FetchResponse, ItemsRequest, ItemsResponse,
+    PushChangesRequest, PushChangesResponse, WatermarkResponse, DEFAULT_PAGE_LIMIT,
+    MAX_PAGE_LIMIT,
+};
+use crate::filter::retain_allowed;
+use crate::tls;
+
+/// Largest request body accepted (pushed pages carry embeddings).
+pub const MAX_BODY_BYTES: usize = 256 * 1024 * 1024;
+
+/// Sync HTTP server.
+///
+/// Serves `GET /sync/watermark`, `GET /sync/changes` and `POST /sync/changes`
+/// on top of any [`Store`], plus the `/sync/digests`, `/sync/items` and
+/// `/sync/fetch` endpoints used for anti-entropy reconciliation. With an
+/// [`Authenticator`] every request must be signed by a configured peer, and
+/// that peer's collection filter applies whatever filter its client sends.
+#[derive(Clone)]
+pub struct SyncServer {
+    store: Arc<dyn Store>,
+    auth: Option<Arc<Authenticator>>,
+    tls: Option<Arc<rustls::ServerConfig>>,
+    resolver: Arc<dyn ConflictResolver>,
+    peer_filters: Arc<HashMap<String, CollectionFilter>>,
+}
+
+/// ID of the peer that signed a request.
+#[derive(Clone)]
+struct AuthenticatedPeer(String);
+
+impl SyncServer {
+    /// Create a server for the given store.
+    pub fn new(store: Arc<dyn Store>) -> Self {
+        Self {
+            store,
+            auth: None,
+            tls: None,
+            resolver: Arc::new(LastWriterWins),
+            peer_filters: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// Create a server with the authentication and TLS settings in `config`.
+    pub fn from_config(store: Arc<dyn Store>, config: &SyncConfig) -> Result<Self> {
+        let mut server = Self::new(store).with_conflict_resolver(config.conflict_policy.resolver());
+        for peer in &config.peers {
+            server = server.with_peer_filter(&peer.id, peer.collections.clone());
+        }
+        if let Some(auth) = Authenticator::from_config(config) {
+            server = server.with_auth(auth);
+        }
+        match (&config.tls_cert, &config.tls_key) {
+            (Some(cert), Some(key)) => server = server.with_tls(tls::server_config(cert, key)?),
+            (None, None) => {}
+            _ => {
+                return Err(RagError::Config {
+                    message: "sync.tls_cert and sync.tls_key must be set together".to_string(),
+                })
+            }
+        }
+        Ok(server)
+    }
+
+    /// Require requests to be signed.
+    pub fn with_auth(mut self, auth: Authenticator) -> Self {
+        self.auth = Some(Arc::new(auth));
+        self
+    }
+
+    /// Serve over TLS.
+    pub fn with_tls(mut self, config: Arc<rustls::ServerConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Restrict what an authenticated peer may pull and push to the
+    /// collections `filter` allows.
+    pub fn with_peer_filter(mut self, peer_id: &str, filter: CollectionFilter) -> Self {
+        Arc::make_mut(&mut self.peer_filters).insert(peer_id.to_string(), filter);
+        self
+    }
+
+    /// Collections a request may read or write: the client's filter within
+    /// the one configured for the peer that signed it.
+    fn filter_for(
+        &self,
+        peer: Option<&Extension<AuthenticatedPeer>>,
+        requested: &CollectionFilter,
+    ) -> CollectionFilter {
+        match peer.and_then(|Extension(AuthenticatedPeer(id))| self.peer_filters.get(id)) {
+            Some(filter) => filter.intersect(requested),
+            None => requested.clone(),
+        }
+    }
+
+    /// Resolve conflicts in pushed documents with `resolver`.
+    pub fn with_conflict_resolver(mut self, resolver: Arc<dyn ConflictResolver>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Build the axum router.
+    pub fn router(&self) -> Router {
+        let router = Router::new()
+            .route("/sync/watermark", get(watermark))
+            .route("/sync/changes", get(pull_changes).post(push_changes))
+            .route("/sync/digests", post(range_digests))
+            .route("/sync/items", post(range_items))
+            .route("/sync/fetch", post(fetch_changes))
+            .layer(DefaultBodyLimit::max(MAX_BODY_BYTES));
+
+        let router = match &self.auth {
+            Some(auth) => router.layer(middleware::from_fn_with_state(auth.clone(), authenticate)),
+            None => router,
+        };
+        router.with_state(self.clone())
+    }
+
+    /// Bind to `bind_address` and serve in a background task.
+    pub async fn spawn(&self, bind_address: &str) -> Result<SyncServerHandle> {
+        let listener = TcpListener::bind(bind_address)
+            .await
+            .map_err(|e| RagError::sync(format!("Failed to bind {}: {}", bind_address, e)))?;
+        let local_addr = listener.local_addr()?;
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        if self.auth.is_none() && !local_addr.ip().is_loopback() {
+            warn!("Sync server on {} accepts unauthenticated requests; set a peer secret", local_addr);
+        }
+
+        let router = self.router();
+        let task = match &self.tls {
+            Some(tls) => {
+                let handle = axum_server::Handle::new();
+                let config = RustlsConfig::from_config(tls.clone());
+                let server = axum_server::from_tcp_rustls(listener.into_std()?, config).handle(handle.clone());
+                tokio::spawn(async move {
+                    tokio::spawn(async move {
+                        let _ = shutdown_rx.await;
+                        handle.graceful_shutdown(None);
+                    });
+                    if let Err(e) = server.serve(router.into_make_service()).await {
+                        warn!("Sync server stopped with error: {}", e);
+                    }
+                })
+            }
+            None => tokio::spawn(async move {
+                let result = axum::serve(listener, router)
+                    .with_graceful_shutdown(async {
+                        let _ = shutdown_rx.await;
+                    })
+                    .await;
+                if let Err(e) = result {
+                    warn!("Sync server stopped with error: {}", e);
+                }
+            }),
+        };
+
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        info!("Sync server listening on {}://{}", scheme, local_addr);
+
+        Ok(SyncServerHandle {
+            local_addr,
+            scheme,
+            shutdown: Some(shutdown_tx),
+            task,
+        })
+    }
+}
+
+/// Handle to a running [`SyncServer`].
+pub struct SyncServerHandle {
+    local_addr: SocketAddr,
+    scheme: &'static str,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl SyncServerHandle {
+    /// Address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Base URL for peers to connect to.
+    pub fn endpoint(&self) -> String {
+        format!("{}://{}", self.scheme, self.local_addr)
+    }
+
+    /// Stop accepting connections and wait for in-flight requests to finish.
+    pub async fn shutdown(mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for SyncServerHandle {
+    fn drop(&mut self) {
+        if self.shutdown.is_some() {
+            self.task.abort();
+        }
+    }
+}
+
+/// Error wrapper mapping [`RagError`] to an HTTP response.
+struct ApiError(RagError);
+
+impl From<RagError> for ApiError {
+    fn from(e: RagError) -> Self {
+        Self(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self.0 {
+            RagError::InvalidArgument { .. } | RagError::Serialization(_) => StatusCode::BAD_REQUEST,
+            RagError::ClockDrift { .. } => StatusCode::CONFLICT,
+            RagError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = ErrorResponse {
+            code: self.0.error_code().to_string(),
+            message: self.0.to_string(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Reject requests without a valid signature.
+async fn authenticate(
+    State(auth): State<Arc<Authenticator>>,
+    request: Request,
+    next: Next,
+) -> std::result::Result<Response, ApiError> {
+    let (mut parts, body) = request.into_parts();
+    let body: Bytes = axum::body::to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|e| RagError::invalid_argument(format!("Failed to read request body: {}", e)))?;
+
+    let header = |name: &str| parts.headers.get(name).and_then(|v| v.to_str().ok());
+    let path = parts.uri.path_and_query().map_or(parts.uri.path(), |p| p.as_str());
+    let peer_id = auth.verify(&SignedRequest {
+        method: parts.method.as_str(),
+        path,
+        timestamp: header(TIMESTAMP_HEADER),
+        nonce: header(NONCE_HEADER),
+        signature: header(SIGNATURE_HEADER),
+        body: &body,
+    })
+    .map_err(|e| {
+        warn!("Rejected sync request to {}: {}", path, e);
+        e
+    })?;
+
+    parts.extensions.insert(AuthenticatedPeer(peer_id));
+    Ok(next.run(Request::from_parts(parts, Body::from(body))).await)
+}
+
+async fn watermark(State(server): State<SyncServer>) -> std::result::Result<Json<WatermarkResponse>, ApiError> {
+    let hlc = server.store.get_watermark().await?;
+    Ok(Json(WatermarkResponse {
+        hlc: hlc.to_hex(),
+        node_id: server.store.node_id(),
+    }))
+}
+
+async fn pull_changes(
+    State(server): State<SyncServer>,
+    peer: Option<Extension<AuthenticatedPeer>>,
+    Query(query): Query<ChangesQuery>,
+) -> std::result::Result<Json<ChangesResponse>, ApiError> {
+    let since = parse_hlc(&query.since)?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let filter = server.filter_for(peer.as_ref(), &query.collections());
+
+    let (changes, has_more) = server.store.get_changes_page(&since, &filter, limit).await?;
+
+    let watermark = match changes.last() {
+        Some(last) if has_more => last.hlc(),
+        _ => server.store.get_watermark().await?.max(since),
+    };
+
+    debug!("Serving {} changes since {} (has_more: {})", changes.len(), query.since, has_more);
+
+    Ok(Json(ChangesResponse {
+        batch: ChangeBatch::new(&changes)?,
+        watermark: watermark.to_hex(),
+        has_more,
+    }))
+}
+
+async fn push_changes(
+    State(server): State<SyncServer>,
+    peer: Option<Extension<AuthenticatedPeer>>,
+    Json(request): Json<PushChangesRequest>,
+) -> std::result::Result<Json<PushChangesResponse>, ApiError> {
+    parse_hlc(&request.source_watermark)?;
+    let filter = server.filter_for(peer.as_ref(), &CollectionFilter::all());
+    let changes = retain_allowed(server.store.as_ref(), request.batch.into_changes()?, &filter).await?;
+
+    debug!("Applying {} pushed changes", changes.len());
+    server.store.apply_changes_with(&changes, server.resolver.as_ref()).await?;
+
+    let watermark = server.store.get_watermark().await?;
+    Ok(Json(PushChangesResponse {
+        accepted: true,
+        new_watermark: watermark.to_hex(),
+    }))
+}
+
+async fn range_digests(
+    State(server): State<SyncServer>,
+    peer: Option<Extension<AuthenticatedPeer>>,
+    Json(request): Json<DigestsRequest>,
+) -> std::result::Result<Json<DigestsResponse>, ApiError> {
+    let filter = server.filter_for(peer.as_ref(), &request.collections);
+    let mut digests = Vec::with_capacity(request.ranges.len());
+    for range in &request.ranges {
+        digests.push(server.store.range_digest(range, &filter).await?);
+    }
+    Ok(Json(DigestsResponse { digests }))
+}
+
+async fn range_items(
+    State(server): State<SyncServer>,
+    peer: Option<Extension<AuthenticatedPeer>>,
+    Json(request): Json<ItemsRequest>,
+) -> std::result::Result<Json<ItemsResponse>, ApiError> {
+    let filter = server.filter_for(peer.as_ref(), &request.collections);
+    let items = server.store.sync_items(&request.range, &filter).await?;
+    Ok(Json(ItemsResponse { items }))
+}
+
+async fn fetch_changes(
+    State(server): State<SyncServer>,
+    peer: Option<Extension<AuthenticatedPeer>>,
+    Json(request): Json<FetchRequest>,
+) -> std::result::Result<Json<FetchResponse>, ApiError> {
+    if request.keys.len() > MAX_PAGE_LIMIT {
+        return Err(RagError::invalid_argument(format!(
+            "At most {} keys may be fetched at once",
+            MAX_PAGE_LIMIT
+        ))
+        .into());
+    }
+    let filter = server.filter_for(peer.as_ref(), &CollectionFilter::all());
+    let changes = server.store.get_changes_for_keys(&request.keys).await?;
+    let changes = retain_allowed(server.store.as_ref(), changes, &filter).await?;
+    Ok(Json(FetchResponse {
+        batch: ChangeBatch::new(&changes)?,
+    }))
+}
@@ -0,0 +1,634 @@
+//! Deterministic multi-node sync simulation.
+//!
+//! Runs several in-memory [`SqliteStore`] replicas, each with its own
+//! [`ManualClock`] and [`SyncManager`], connected by a simulated network
+//! that drops, duplicates and reorders messages and can be partitioned. A
+//! seeded random workload interleaves local writes, sync and reconcile
+//! rounds, partitions and clock skew. Afterwards the network is healed and
+//! every node must converge to the same state without breaking causality:
+//!
+//! - every local write gets an HLC above everything the node had seen,
+//! - each collection ends up as its highest-HLC create or delete left it,
+//! - deleted documents never come back,
+//! - no live document or chunk outlives its parent,
+//! - a source URI has at most one live document per collection.
+//!
+//! Every decision comes from one seed, so a failure reproduces with
+//! `RAG_SIM_SEED=<seed> cargo test -p rag-sync sim`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use rag_core::digest::{CHUNK_KEY_PREFIX, DOCUMENT_KEY_PREFIX};
+use rag_core::{
+    Chunk, Collection, CollectionFilter, ContentType, Document, HybridLogicalClock, KeyRange, ManualClock,
+    RagError, RangeDigest, Result, Store, SyncChange, SyncConfig, SyncItem, SyncPeer,
+};
+use rag_store::SqliteStore;
+
+use crate::manager::SyncManager;
+use crate::peer::LocalSyncPeer;
+
+/// Wall time all simulated clocks start from.
+const START_MILLIS: u64 = 1_700_000_000_000;
+
+/// Largest ordinary clock skew, well within the drift limit.
+const MAX_SKEW_MS: i64 = 5_000;
+
+/// Skew of a runaway clock, beyond the drift limit.
+const RUNAWAY_SKEW_MS: i64 = 90_000;
+
+/// Collection names the workload picks from.
+const COLLECTIONS: [&str; 3] = ["alpha", "beta", "gamma"];
+
+/// Number of distinct source URIs per collection.
+const URIS: usize = 5;
+
+/// Message faults injected by the network.
+#[derive(Debug, Clone, Copy)]
+pub struct Faults {
+    /// Probability that a request or its response is lost.
+    pub loss: f64,
+
+    /// Probability that a message is delivered twice.
+    pub duplicate: f64,
+
+    /// Probability that a message's changes arrive out of order.
+    pub reorder: f64,
+}
+
+impl Faults {
+    /// A network that delivers everything once, in order.
+    pub fn none() -> Self {
+        Self {
+            loss: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+        }
+    }
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Self {
+            loss: 0.03,
+            duplicate: 0.1,
+            reorder: 0.2,
+        }
+    }
+}
+
+/// Shared state of the simulated network.
+struct Network {
+    rng: StdRng,
+    faults: Faults,
+    /// Unordered node pairs that cannot reach each other.
+    partitions: HashSet<(usize, usize)>,
+}
+
+impl Network {
+    fn pair(a: usize, b: usize) -> (usize, usize) {
+        (a.min(b), a.max(b))
+    }
+
+    /// Fail if a message between two nodes does not get through.
+    fn send(&mut self, from: usize, to: usize) -> Result<()> {
+        if self.partitions.contains(&Self::pair(from, to)) {
+            return Err(RagError::sync(format!("node {} is partitioned from node {}", from, to)));
+        }
+        if self.rng.gen_bool(self.faults.loss) {
+            return Err(RagError::sync(format!("message from node {} to node {} lost", from, to)));
+        }
+        Ok(())
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        self.rng.gen_bool(p)
+    }
+
+    /// Possibly shuffle and duplicate a list of changes in transit.
+    fn mangle(&mut self, mut changes: Vec<SyncChange>) -> Vec<SyncChange> {
+        if self.chance(self.faults.duplicate) && !changes.is_empty() {
+            let i = self.rng.gen_range(0..changes.len());
+            changes.push(changes[i].clone());
+        }
+        if self.chance(self.faults.reorder) {
+            changes.shuffle(&mut self.rng);
+        }
+        changes
+    }
+}
+
+/// [`SyncPeer`] for node `to` as seen from node `from`, over the simulated network.
+struct SimPeer {
+    from: usize,
+    to: usize,
+    inner: LocalSyncPeer,
+    net: Arc<Mutex<Network>>,
+}
+
+impl SimPeer {
+    fn net(&self) -> std::sync::MutexGuard<'_, Network> {
+        self.net.lock().unwrap()
+    }
+
+    /// Send a request to the peer, then its response back.
+    fn round_trip(&self) -> Result<()> {
+        self.net().send(self.from, self.to)
+    }
+
+    fn response(&self) -> Result<()> {
+        self.net().send(self.to, self.from)
+    }
+}
+
+#[async_trait]
+impl SyncPeer for SimPeer {
+    fn peer_id(&self) -> &str {
+        self.inner.peer_id()
+    }
+
+    fn endpoint(&self) -> &str {
+        "sim"
+    }
+
+    fn node_id(&self) -> Option<u16> {
+        self.inner.node_id()
+    }
+
+    async fn get_watermark(&self) -> Result<HybridLogicalClock> {
+        self.round_trip()?;
+        let watermark = self.inner.get_watermark().await?;
+        self.response()?;
+        Ok(watermark)
+    }
+
+    async fn pull_changes(&self, since: &HybridLogicalClock) -> Result<Vec<SyncChange>> {
+        self.round_trip()?;
+        let changes = self.inner.pull_changes(since).await?;
+        self.response()?;
+        Ok(self.net().mangle(changes))
+    }
+
+    async fn push_changes(&self, changes: &[SyncChange]) -> Result<()> {
+        self.round_trip()?;
+        let (changes, split, duplicate) = {
+            let mut net = self.net();
+            let changes = net.mangle(changes.to_vec());
+            let faults = net.faults;
+            let split = net.chance(faults.reorder);
+            let duplicate = net.chance(faults.duplicate);
+            (changes, split, duplicate)
+        };
+
+        // Deliver as two messages in reverse order, so children can arrive
+        // before their parents
+        if split && changes.len() > 1 {
+            let (first, second) = changes.split_at(changes.len() / 2);
+            self.inner.push_changes(second).await?;
+            self.inner.push_changes(first).await?;
+        } else {
+            self.inner.push_changes(&changes).await?;
+        }
+        if duplicate {
+            self.inner.push_changes(&changes).await?;
+        }
+
+        // The changes are applied even if the acknowledgement is lost
+        self.response()
+    }
+
+    async fn range_digests(&self, ranges: &[KeyRange]) -> Result<Vec<RangeDigest>> {
+        self.round_trip()?;
+        let digests = self.inner.range_digests(ranges).await?;
+        self.response()?;
+        Ok(digests)
+    }
+
+    async fn range_items(&self, range: &KeyRange) -> Result<Vec<SyncItem>> {
+        self.round_trip()?;
+        let items = self.inner.range_items(range).await?;
+        self.response()?;
+        Ok(items)
+    }
+
+    async fn fetch_changes(&self, keys: &[String]) -> Result<Vec<SyncChange>> {
+        self.round_trip()?;
+        let changes = self.inner.fetch_changes(keys).await?;
+        self.response()?;
+        Ok(self.net().mangle(changes))
+    }
+}
+
+/// One simulated replica.
+struct Node {
+    store: Arc<SqliteStore>,
+    clock: Arc<ManualClock>,
+    manager: SyncManager,
+    skew: i64,
+}
+
+/// What the workload did, for checking invariants afterwards.
+#[derive(Default)]
+struct History {
+    /// Every effective collection create (`true`) or delete (`false`).
+    collections: Vec<(String, HybridLogicalClock, bool)>,
+
+    /// Every deleted document.
+    deleted_documents: HashSet<String>,
+
+    writes: usize,
+    syncs: usize,
+    failed_syncs: usize,
+}
+
+/// Seeded simulation of a cluster of replicas.
+pub struct Simulation {
+    seed: u64,
+    rng: StdRng,
+    now: u64,
+    nodes: Vec<Node>,
+    net: Arc<Mutex<Network>>,
+    history: History,
+}
+
+impl Simulation {
+    /// Create a cluster of `nodes` fully connected replicas.
+    pub fn new(seed: u64, nodes: usize, faults: Faults) -> Self {
+        let net = Arc::new(Mutex::new(Network {
+            rng: StdRng::seed_from_u64(seed ^ 0x9e37_79b9_7f4a_7c15),
+            faults,
+            partitions: HashSet::new(),
+        }));
+
+        let stores: Vec<_> = (0..nodes)
+            .map(|i| {
+                let clock = Arc::new(ManualClock::new(START_MILLIS));
+                let store = SqliteStore::open_memory_with_clock(i as u16 + 1, clock.clone()).unwrap();
+                (Arc::new(store), clock)
+            })
+            .collect();
+
+        let nodes = (0..nodes)
+            .map(|i| {
+                let (store, clock) = stores[i].clone();
+                let peers = (0..stores.len())
+                    .filter(|&j| j != i)
+                    .map(|j| {
+                        Arc::new(SimPeer {
+                            from: i,
+                            to: j,
+                            inner: LocalSyncPeer::new(node_name(j), stores[j].0.clone()),
+                            net: net.clone(),
+                        }) as Arc<dyn SyncPeer>
+                    })
+                    .collect();
+                let manager =
+                    SyncManager::with_peers(store.clone(), SyncConfig::default(), peers).with_clock(clock.clone());
+                Node {
+                    store,
+                    clock,
+                    manager,
+                    skew: 0,
+                }
+            })
+            .collect();
+
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            now: START_MILLIS,
+            nodes,
+            net,
+            history: History::default(),
+        }
+    }
+
+    /// Run `steps` random workload steps, then heal the network and check
+    /// that every node converged. Returns a description of the first
+    /// violated invariant.
+    pub async fn run(&mut self, steps: usize) -> std::result::Result<(), String> {
+        for _ in 0..steps {
+            self.step().await?;
+        }
+        self.heal().await;
+        self.check().await
+    }
+
+    /// Advance time and perform one random action.
+    async fn step(&mut self) -> std::result::Result<(), String> {
+        let delay = self.rng.gen_range(0..500);
+        self.advance(delay);
+
+        let node = self.rng.gen_range(0..self.nodes.len());
+        match self.rng.gen_range(0..100) {
+            0..=39 => self.write(node).await?,
+            40..=74 => {
+                let peer = self.other(node);
+                self.sync(node, peer, false).await;
+            }
+            75..=84 => {
+                let peer = self.other(node);
+                self.sync(node, peer, true).await;
+            }
+            85..=92 => {
+                let peer = self.other(node);
+                let pair = Network::pair(node, peer);
+                let mut net = self.net.lock().unwrap();
+                if !net.partitions.remove(&pair) {
+                    net.partitions.insert(pair);
+                }
+            }
+            _ => {
+                // Clocks step in either direction; rarely one runs far ahead
+                self.nodes[node].skew = if self.rng.gen_bool(0.05) {
+                    RUNAWAY_SKEW_MS
+                } else {
+                    self.rng.gen_range(-MAX_SKEW_MS..=MAX_SKEW_MS)
+                };
+                self.advance(0);
+            }
+        }
+        Ok(())
+    }
+
+    /// Move simulated time forward and update every node's clock.
+    fn advance(&mut self, millis: u64) {
+        self.now += millis;
+        for node in &self.nodes {
+            node.clock.set(self.now.saturating_add_signed(node.skew));
+        }
+    }
+
+    fn other(&mut self, node: usize) -> usize {
+        let peer = self.rng.gen_range(0..self.nodes.len() - 1);
+        if peer >= node {
+            peer + 1
+        } else {
+            peer
+        }
+    }
+
+    /// Sync or reconcile `node` with `peer`; failures are expected.
+    async fn sync(&mut self, node: usize, peer: usize, reconcile: bool) {
+        let manager = &self.nodes[node].manager;
+        let result = if reconcile {
+            manager.reconcile_with(&node_name(peer)).await
+        } else {
+            manager.sync_with(&node_name(peer)).await
+        };
+        self.history.syncs += 1;
+        if result.is_err() {
+            self.history.failed_syncs += 1;
+        }
+    }
+
+    /// Perform a random local write on `node`, checking that it happens
+    /// after everything the node has seen.
+    async fn write(&mut self, node: usize) -> std::result::Result<(), String> {
+        let store = self.nodes[node].store.clone();
+        let seen = store.current_hlc();
+        let collections: Vec<String> = store.list_collections().await.unwrap().into_iter().map(|c| c.name).collect();
+        let missing: Vec<&str> = COLLECTIONS
+            .iter()
+            .copied()
+            .filter(|name| !collections.iter().any(|c| c == name))
+            .collect();
+
+        let roll = self.rng.gen_range(0..100);
+        if collections.is_empty() || (roll < 10 && !missing.is_empty()) {
+            let name = *missing.choose(&mut self.rng).unwrap();
+            store.create_collection(Collection::new(name, None)).await.unwrap();
+            let hlc = store.get_collection(name).await.unwrap().unwrap().hlc;
+            self.check_after(node, seen, hlc, "collection create")?;
+            self.history.collections.push((name.to_string(), hlc, true));
+        } else if roll < 13 {
+            let name = collections.choose(&mut self.rng).unwrap().clone();
+            store.delete_collection(&name).await.unwrap();
+            for change in store.get_changes_since(&seen, &CollectionFilter::all()).await.unwrap() {
+                if let SyncChange::DeleteCollection(deleted, hlc) = change {
+                    self.check_after(node, seen, hlc, "collection delete")?;
+                    self.history.collections.push((deleted, hlc, false));
+                }
+            }
+        } else {
+            let collection = collections.choose(&mut self.rng).unwrap().clone();
+            let uri = format!("file://doc-{}.md", self.rng.gen_range(0..URIS));
+            let existing = store
+                .list_documents(&collection, 1000, 0)
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|doc| doc.source_uri == uri);
+
+            // Re-ingesting a URI stores the new document, then deletes the
+            // one it replaces, like `ingest` does
+            if roll >= 30 {
+                let content = format!("{} version {} from node {}", uri, self.history.writes, node);
+                let doc = Document::new(&collection, &uri, &content, ContentType::Markdown);
+                let doc_id = doc.id;
+                store.insert_document(doc).await.unwrap();
+                let chunks: Vec<Chunk> = (0..self.rng.gen_range(1..=3))
+                    .map(|i| Chunk::new(doc_id, i, &format!("{} part {}", content, i), i + 1, i + 1, 4))
+                    .collect();
+                store.insert_chunks(&chunks).await.unwrap();
+
+                let hlc = store.get_document(doc_id).await.unwrap().unwrap().hlc;
+                self.check_after(node, seen, hlc, "document insert")?;
+            }
+            if let Some(existing) = existing {
+                store.delete_document(existing.id).await.unwrap();
+                self.history.deleted_documents.insert(existing.id.to_string());
+            }
+        }
+        self.history.writes += 1;
+        Ok(())
+    }
+
+    fn check_after(
+        &self,
+        node: usize,
+        seen: HybridLogicalClock,
+        hlc: HybridLogicalClock,
+        what: &str,
+    ) -> std::result::Result<(), String> {
+        if hlc <= seen || hlc.node_id != self.nodes[node].store.node_id() {
+            return Err(self.failure(format!(
+                "{} on node {} got HLC {} after having seen {}",
+                what, node, hlc, seen
+            )));
+        }
+        Ok(())
+    }
+
+    /// Remove partitions and faults, bring clocks back in line and run
+    /// anti-entropy between every pair of nodes.
+    async fn heal(&mut self) {
+        {
+            let mut net = self.net.lock().unwrap();
+            net.partitions.clear();
+            net.faults = Faults::none();
+        }
+
+        // Let real time catch up with any runaway clock
+        for node in &mut self.nodes {
+            node.skew = 0;
+        }
+        self.advance(2 * RUNAWAY_SKEW_MS as u64);
+
+        for _ in 0..2 {
+            for node in 0..self.nodes.len() {
+                for peer in 0..self.nodes.len() {
+                    if peer != node {
+                        self.sync(node, peer, true).await;
+                        self.sync(node, peer, false).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Check convergence and the invariants listed in the module docs.
+    async fn check(&self) -> std::result::Result<(), String> {
+        let mut states = Vec::new();
+        for node in &self.nodes {
+            states.push(node.store.sync_items(&KeyRange::full(), &CollectionFilter::all()).await.unwrap());
+        }
+        for (i, state) in states.iter().enumerate().skip(1) {
+            if *state != states[0] {
+                let only = |a: &[SyncItem], b: &[SyncItem]| -> Vec<SyncItem> {
+                    a.iter().filter(|item| !b.contains(item)).cloned().collect()
+                };
+                return Err(self.failure(format!(
+                    "node {} diverged from node 0: only on node 0 {:?}, only on node {} {:?}",
+                    i,
+                    only(&states[0], state),
+                    i,
+                    only(state, &states[0])
+                )));
+            }
+        }
+
+        let store = &self.nodes[0].store;
+
+        // Each collection is in the state of its last create or delete
+        let mut last: HashMap<&str, (HybridLogicalClock, bool)> = HashMap::new();
+        for (name, hlc, live) in &self.history.collections {
+            let entry = last.entry(name.as_str()).or_insert((*hlc, *live));
+            if *hlc > entry.0 {
+                *entry = (*hlc, *live);
+            }
+        }
+        let collections: HashSet<String> = store.list_collections().await.unwrap().into_iter().map(|c| c.name).collect();
+        for (name, (_, live)) in &last {
+            if collections.contains(*name) != *live {
+                return Err(self.failure(format!(
+                    "collection {} should be {}",
+                    name,
+                    if *live { "live" } else { "deleted" }
+                )));
+            }
+        }
+
+        // Live documents and chunks have live parents, and each URI has one document
+        let mut documents = HashSet::new();
+        let mut chunks = HashSet::new();
+        for collection in &collections {
+            let mut uris = BTreeMap::new();
+            for doc in store.list_documents(collection, 10_000, 0).await.unwrap() {
+                if self.history.deleted_documents.contains(&doc.id.to_string()) {
+                    return Err(self.failure(format!("deleted document {} came back", doc.id)));
+                }
+                if let Some(other) = uris.insert(doc.source_uri.clone(), doc.id) {
+                    return Err(self.failure(format!(
+                        "{} in {} has two live documents {} and {}",
+                        doc.source_uri, collection, other, doc.id
+                    )));
+                }
+                for chunk in store.get_chunks_for_document(doc.id).await.unwrap() {
+                    chunks.insert(chunk.id.to_string());
+                }
+                documents.insert(doc.id.to_string());
+            }
+        }
+        for item in &states[0] {
+            if let Some(id) = item.key.strip_prefix(DOCUMENT_KEY_PREFIX) {
+                if !documents.contains(id) {
+                    return Err(self.failure(format!("document {} outlived its collection", id)));
+                }
+            } else if let Some(id) = item.key.strip_prefix(CHUNK_KEY_PREFIX) {
+                if !chunks.contains(id) {
+                    return Err(self.failure(format!("chunk {} outlived its document", id)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn failure(&self, message: String) -> String {
+        format!(
+            "seed {}: {} ({} writes, {} of {} syncs failed)",
+            self.seed, message, self.history.writes, self.history.failed_syncs, self.history.syncs
+        )
+    }
+}
+
+fn node_name(index: usize) -> String {
+    format!("node-{}", index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seeds to run: `RAG_SIM_SEED` alone if set, otherwise a fixed range.
+    fn seeds() -> Vec<u64> {
+        match std::env::var("RAG_SIM_SEED") {
+            Ok(seed) => vec![seed.parse().expect("RAG_SIM_SEED must be a number")],
+            Err(_) => (0..8).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simulation_converges() {
+        for seed in seeds() {
+            Simulation::new(seed, 3, Faults::default()).run(300).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simulation_converges_under_heavy_faults() {
+        let faults = Faults {
+            loss: 0.15,
+            duplicate: 0.5,
+            reorder: 0.8,
+        };
+        for seed in seeds() {
+            Simulation::new(seed, 5, faults).run(200).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simulation_is_deterministic() {
+        async fn trace(seed: u64) -> (usize, usize, usize, Vec<(String, bool)>) {
+            let mut sim = Simulation::new(seed, 3, Faults::default());
+            sim.run(100).await.unwrap();
+            let mut collections: Vec<_> = sim
+                .history
+                .collections
+                .iter()
+                .map(|(name, _, live)| (name.clone(), *live))
+                .collect();
+            collections.sort();
+            (sim.history.writes, sim.history.syncs, sim.history.failed_syncs, collections)
+        }
+
+        assert_eq!(trace(7).await, trace(7).await);
+    }
+}
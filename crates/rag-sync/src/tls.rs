@@ -0,0 +1,111 @@
+//! TLS for the sync server with locally generated certificates.
+//!
+//! Nodes on a private network rarely have certificates from a public CA, so
+//! a node generates a self-signed certificate for its host names and its
+//! peers trust that certificate explicitly (`PeerConfig::ca_cert`).
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+use rag_core::{RagError, Result};
+
+/// File name of the generated certificate.
+pub const CERT_FILE: &str = "sync-cert.pem";
+
+/// File name of the generated private key.
+pub const KEY_FILE: &str = "sync-key.pem";
+
+/// Generate a self-signed certificate for the given host names and IP
+/// addresses, returning the certificate and private key as PEM.
+pub fn generate_self_signed(hosts: &[String]) -> Result<(String, String)> {
+    let certified = rcgen::generate_simple_self_signed(hosts.to_vec())
+        .map_err(|e| RagError::sync(format!("Failed to generate certificate: {}", e)))?;
+    Ok((certified.cert.pem(), certified.key_pair.serialize_pem()))
+}
+
+/// Generate a self-signed certificate into `dir`, returning the certificate
+/// and key paths. The key is created only readable by the current user, and
+/// an existing key is never overwritten.
+pub fn write_self_signed(dir: &Path, hosts: &[String]) -> Result<(PathBuf, PathBuf)> {
+    let (cert, key) = generate_self_signed(hosts)?;
+    std::fs::create_dir_all(dir)?;
+
+    let cert_path = dir.join(CERT_FILE);
+    let key_path = dir.join(KEY_FILE);
+    write_private(&key_path, key.as_bytes())?;
+    std::fs::write(&cert_path, cert)?;
+
+    Ok((cert_path, key_path))
+}
+
+/// Create `path` with owner-only permissions and write `contents` to it,
+/// failing if the file already exists.
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => {
+            RagError::sync(format!("{} already exists; remove it to generate a new key", path.display()))
+        }
+        _ => e.into(),
+    })?;
+    file.write_all(contents)?;
+    Ok(())
+}
+
+/// Load a rustls server configuration from PEM files.
+pub fn server_config(cert_path: &Path, key_path: &Path) -> Result<Arc<rustls::ServerConfig>> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|e| RagError::Config {
+            message: format!("Invalid TLS certificate {}: {}", cert_path.display(), e),
+        })?;
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| RagError::Config {
+        message: format!("Invalid TLS key {}: {}", key_path.display(), e),
+    })?;
+
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| RagError::Config {
+            message: format!("Invalid TLS configuration: {}", e),
+        })?;
+
+    Ok(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_self_signed_protects_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let hosts = vec!["localhost".to_string()];
+        let (_, key) = write_self_signed(dir.path(), &hosts).unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&key).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // An existing key is kept rather than replaced.
+        let before = std::fs::read(&key).unwrap();
+        assert!(write_self_signed(dir.path(), &hosts).is_err());
+        assert_eq!(std::fs::read(&key).unwrap(), before);
+    }
+}